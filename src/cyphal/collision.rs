@@ -0,0 +1,266 @@
+//! Node-ID collision detection.
+
+use super::defines::*;
+use super::uavcan::CyphalDataType;
+use super::uavcan::node::Heartbeat;
+
+use std::collections::{HashMap, HashSet};
+
+/// Heartbeats whose uptime deviates from the expected value by more than this are attributed to another node.
+const UPTIME_TOLERANCE_SEC: i64 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum CyphalCollisionEvent {
+    /// A transfer with our source node-ID that we did not transmit.
+    LocalNodeIdConflict {
+        node_id: CyphalNodeID,
+        transfer_kind: CyphalTransferKind,
+        port_id: CyphalPortID,
+        /// Uptime reported by the other node, if the transfer was a heartbeat.
+        remote_uptime: Option<u32>,
+    },
+    /// Heartbeats from one remote node-ID report two inconsistent uptimes.
+    RemoteNodeIdConflict {
+        node_id: CyphalNodeID,
+        uptimes: (u32, u32),
+    },
+}
+
+/// Uptime progression of one heartbeat source.
+#[derive(Debug, Clone, Copy)]
+struct UptimeTrack {
+    uptime: u32,
+    last_seen_usec: CyphalMicrosecond,
+}
+
+impl UptimeTrack {
+    fn matches(&self, uptime: u32, now_usec: CyphalMicrosecond) -> bool {
+        let elapsed_sec: i64 = (now_usec.saturating_sub(self.last_seen_usec) / 1_000_000) as i64;
+        let deviation: i64 = uptime as i64 - (self.uptime as i64 + elapsed_sec);
+        deviation.abs() <= UPTIME_TOLERANCE_SEC
+    }
+}
+
+/// Detects other nodes using our node-ID, from any received transfer carrying it as source, and remote
+/// node-ID duplicates, by following interleaved uptime sequences in their heartbeats.
+/// The drivers do not loop our own frames back, so every transfer with our node-ID comes from another node.
+/// Each conflict is reported once: a local one until `reset`, a remote one until one of the two nodes
+/// has been silent for the offline timeout.
+/// With silencing enabled, `is_silenced` turns true on the first local conflict so that transmission can be stopped.
+pub struct CyphalCollisionDetector {
+    tracks: HashMap<CyphalNodeID, Vec<UptimeTrack>>,
+    remote_conflicts: HashSet<CyphalNodeID>,
+    local_conflict: bool,
+    silence_on_conflict: bool,
+    silenced: bool,
+    events: Vec<CyphalCollisionEvent>,
+}
+
+impl Default for CyphalCollisionDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CyphalCollisionDetector {
+    pub fn new() -> Self {
+        Self {
+            tracks: HashMap::new(),
+            remote_conflicts: HashSet::new(),
+            local_conflict: false,
+            silence_on_conflict: false,
+            silenced: false,
+            events: vec![],
+        }
+    }
+
+    pub fn set_silence_on_conflict(mut self, silence_on_conflict: bool) -> Self {
+        self.silence_on_conflict = silence_on_conflict;
+        self
+    }
+
+    pub fn is_silenced(&self) -> bool {
+        self.silenced
+    }
+
+    /// Resumes transmission after the conflict has been resolved, e.g. with a new node-ID.
+    pub fn reset(&mut self) {
+        self.silenced = false;
+        self.local_conflict = false;
+    }
+
+    pub fn take_events(&mut self) -> Vec<CyphalCollisionEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Checks a reassembled transfer. Returns true if it revealed a conflict not reported before.
+    pub fn accept(&mut self, local_node_id: CyphalNodeID, frame: &CyphalRxFrame, now_usec: CyphalMicrosecond) -> bool {
+        let source: CyphalNodeID = frame.props.source_node_id;
+        if source > CYPHAL_NODE_ID_MAX {
+            return false;
+        }
+        let heartbeat: Option<Heartbeat> = match (frame.props.transfer_kind, frame.props.port_id) {
+            (CyphalTransferKind::Message, Heartbeat::FIXED_PORT_ID) => Heartbeat::deserialize(&frame.payload).ok(),
+            _ => None,
+        };
+
+        if source == local_node_id {
+            if self.local_conflict {
+                return false;
+            }
+            self.local_conflict = true;
+            self.events.push(CyphalCollisionEvent::LocalNodeIdConflict {
+                node_id: source,
+                transfer_kind: frame.props.transfer_kind,
+                port_id: frame.props.port_id,
+                remote_uptime: heartbeat.map(|x| x.uptime),
+            });
+            self.silenced |= self.silence_on_conflict;
+            return true;
+        }
+
+        match heartbeat {
+            Some(x) => self.accept_remote_uptime(source, x.uptime, now_usec),
+            None => false,
+        }
+    }
+
+    /// A restart starts a second uptime track for the node; the conflict is reported when the old track continues.
+    fn accept_remote_uptime(&mut self, node_id: CyphalNodeID, uptime: u32, now_usec: CyphalMicrosecond) -> bool {
+        let tracks: &mut Vec<UptimeTrack> = self.tracks.entry(node_id).or_default();
+        tracks.retain(|x| now_usec.saturating_sub(x.last_seen_usec) <= Heartbeat::OFFLINE_TIMEOUT_USEC);
+        if tracks.len() < 2 {
+            self.remote_conflicts.remove(&node_id);
+        }
+
+        let matching: Option<usize> = tracks.iter().position(|x| x.matches(uptime, now_usec));
+        let conflict: Option<(u32, u32)> = match matching {
+            // The most recent track continues: normal operation.
+            Some(i) if i + 1 == tracks.len() => None,
+            // An older track continues while a newer one is alive: two nodes share the ID.
+            Some(i) => Some((tracks[i].uptime, tracks[tracks.len() - 1].uptime)),
+            None => None,
+        };
+        match matching {
+            Some(i) => {
+                tracks[i] = UptimeTrack { uptime, last_seen_usec: now_usec };
+                // Keep the active track last.
+                let track: UptimeTrack = tracks.remove(i);
+                tracks.push(track);
+            },
+            None => tracks.push(UptimeTrack { uptime, last_seen_usec: now_usec }),
+        }
+
+        match conflict {
+            Some(uptimes) if self.remote_conflicts.insert(node_id) => {
+                self.events.push(CyphalCollisionEvent::RemoteNodeIdConflict { node_id, uptimes });
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::uavcan::node::{Health, Mode};
+
+    const LOCAL: CyphalNodeID = 10;
+
+    fn heartbeat(source_node_id: CyphalNodeID, uptime: u32) -> CyphalRxFrame {
+        let payload: Vec<u8> = Heartbeat { uptime, health: Health::Nominal, mode: Mode::Operational, vendor_specific_status_code: 0 }
+            .serialize()
+            .unwrap();
+        CyphalRxFrame {
+            xid: 0,
+            payload_size: payload.len(),
+            payload,
+            props: CyphalRxProps {
+                priority: CyphalPriority::Nominal,
+                transfer_kind: CyphalTransferKind::Message,
+                transfer_id: 0,
+                port_id: Heartbeat::FIXED_PORT_ID,
+                source_node_id,
+                destination_node_id: CYPHAL_NODE_ID_UNSET,
+            },
+            timestamp_usec: 0,
+        }
+    }
+
+    #[test]
+    fn transfer_with_our_node_id_is_reported_once_until_reset() {
+        let mut detector: CyphalCollisionDetector = CyphalCollisionDetector::new().set_silence_on_conflict(true);
+        assert!(detector.accept(LOCAL, &heartbeat(LOCAL, 7), 0));
+        assert!(!detector.accept(LOCAL, &heartbeat(LOCAL, 8), 1_000_000));
+        assert!(detector.is_silenced());
+        assert_eq!(detector.take_events(), vec![CyphalCollisionEvent::LocalNodeIdConflict {
+            node_id: LOCAL,
+            transfer_kind: CyphalTransferKind::Message,
+            port_id: Heartbeat::FIXED_PORT_ID,
+            remote_uptime: Some(7),
+        }]);
+
+        detector.reset();
+        assert!(!detector.is_silenced());
+        assert!(detector.accept(LOCAL, &heartbeat(LOCAL, 9), 2_000_000));
+        assert_eq!(detector.take_events().len(), 1);
+    }
+
+    #[test]
+    fn conflict_does_not_silence_unless_enabled() {
+        let mut detector: CyphalCollisionDetector = CyphalCollisionDetector::new();
+        assert!(detector.accept(LOCAL, &heartbeat(LOCAL, 7), 0));
+        assert!(!detector.is_silenced());
+    }
+
+    #[test]
+    fn anonymous_and_other_nodes_are_no_local_conflict() {
+        let mut detector: CyphalCollisionDetector = CyphalCollisionDetector::new();
+        assert!(!detector.accept(LOCAL, &heartbeat(CYPHAL_NODE_ID_UNSET, 7), 0));
+        assert!(!detector.accept(LOCAL, &heartbeat(LOCAL + 1, 7), 0));
+        assert!(detector.take_events().is_empty());
+    }
+
+    #[test]
+    fn remote_restart_is_no_conflict() {
+        let mut detector: CyphalCollisionDetector = CyphalCollisionDetector::new();
+        for (second, uptime) in [(0, 100), (1, 101), (2, 0), (3, 1), (4, 2)] {
+            assert!(!detector.accept(LOCAL, &heartbeat(5, uptime), second * 1_000_000));
+        }
+        assert!(detector.take_events().is_empty());
+    }
+
+    #[test]
+    fn remote_conflict_is_reported_once_while_both_nodes_are_alive() {
+        let mut detector: CyphalCollisionDetector = CyphalCollisionDetector::new();
+        let mut conflicts: usize = 0;
+        for second in 0..10u32 {
+            let now_usec: CyphalMicrosecond = second as CyphalMicrosecond * 1_000_000;
+            conflicts += detector.accept(LOCAL, &heartbeat(5, 100 + second), now_usec) as usize;
+            conflicts += detector.accept(LOCAL, &heartbeat(5, second), now_usec + 500_000) as usize;
+        }
+        assert_eq!(conflicts, 1);
+        assert_eq!(detector.take_events(), vec![CyphalCollisionEvent::RemoteNodeIdConflict { node_id: 5, uptimes: (100, 0) }]);
+    }
+
+    #[test]
+    fn remote_conflict_is_reported_again_after_it_cleared() {
+        let mut detector: CyphalCollisionDetector = CyphalCollisionDetector::new();
+        for second in 0..3u32 {
+            let now_usec: CyphalMicrosecond = second as CyphalMicrosecond * 1_000_000;
+            detector.accept(LOCAL, &heartbeat(5, 100 + second), now_usec);
+            detector.accept(LOCAL, &heartbeat(5, second), now_usec + 500_000);
+        }
+        assert_eq!(detector.take_events().len(), 1);
+
+        // The second node goes silent for longer than the offline timeout, then comes back.
+        for second in 3..10u32 {
+            detector.accept(LOCAL, &heartbeat(5, 100 + second), second as CyphalMicrosecond * 1_000_000);
+        }
+        assert!(detector.take_events().is_empty());
+        detector.accept(LOCAL, &heartbeat(5, 0), 10_500_000);
+        detector.accept(LOCAL, &heartbeat(5, 110), 11_000_000);
+        assert_eq!(detector.take_events().len(), 1);
+    }
+}
@@ -3,7 +3,7 @@ use super::defines::*;
 pub fn crc_add_byte(crc: u16, byte: u8) -> u16 {
     const TOP: TransferCRC = 0x8000;
     const POLY: TransferCRC = 0x1021;
-    let mut out: TransferCRC  = crc ^ ((byte as u16) << BITS_PER_BYTE) as u16;
+    let mut out: TransferCRC  = crc ^ ((byte as u16) << BITS_PER_BYTE);
     // Consider adding a compilation option that replaces this with a CRC table. Adds 512 bytes of ROM.
     // Do not fold this into a loop because a size-optimizing compiler won't unroll it degrading the performance.
     out = (((out << 1) as u16) ^ (if (out & TOP) != 0 { POLY } else  { 0 })) as u16;
@@ -28,3 +28,37 @@ pub fn crc_add(crc: u16, size: usize, data: &[u8]) -> Result<u16, Box<dyn std::e
     }
     Ok(out)
 }
+
+/// CRC-64/WE as used by the Cyphal specification to hash the 128-bit unique-ID of a node.
+pub fn crc64we_add(crc: u64, data: &[u8]) -> u64 {
+    const POLY: u64 = 0x42F0_E1EB_A9EA_3693;
+    const TOP: u64 = 1 << 63;
+    let mut out: u64 = crc;
+    for v in data {
+        out ^= (*v as u64) << 56;
+        for _ in 0..BITS_PER_BYTE {
+            out = (out << 1) ^ (if (out & TOP) != 0 { POLY } else { 0 });
+        }
+    }
+    out
+}
+
+pub fn crc64we(data: &[u8]) -> u64 {
+    crc64we_add(u64::MAX, data) ^ u64::MAX
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc16_matches_the_check_value() {
+        assert_eq!(crc_add(CRC_INITIAL, 9, b"123456789").unwrap(), 0x29B1);
+    }
+
+    #[test]
+    fn crc64we_matches_the_check_value() {
+        assert_eq!(crc64we(b"123456789"), 0x62EC_59E3_F1A4_F00A);
+        assert_eq!(crc64we_add(u64::MAX, b"12345"), crc64we_add(crc64we_add(u64::MAX, b"123"), b"45"));
+    }
+}
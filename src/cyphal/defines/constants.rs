@@ -37,6 +37,10 @@ pub const CYPHAL_NODE_ID_UNSET: u8 = 255;
 /// different values per subscription (i.e., per data specifier) depending on its timing requirements.
 pub const CYPHAL_DEFAULT_TRANSFER_ID_TIMEOUT_USEC: usize = 2000000;
 
+/// Extent of ports without one of their own, i.e. the longest transfer payload kept while reassembling.
+/// It covers every standard data type, uavcan.node.port.List included.
+pub const CYPHAL_DEFAULT_EXTENT_BYTES: usize = 16384;
+
 pub const CYPHAL_NUM_TRANSFER_KINDS: usize = 3;
 
 pub const CAN_DLC_TO_DLEN: [u8;16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 12, 16, 20, 24, 32, 48, 64];
@@ -29,7 +29,7 @@ impl From<u8> for CyphalPriority {
 }
 
 // Transfer kinds as defined by the Cyphal Specification.
-#[derive(PartialEq, Debug, Clone, Copy, serde::Serialize)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, serde::Serialize)]
 pub enum CyphalTransferKind {
     Message,
     Response,
@@ -1,5 +1,6 @@
 use super::*;
 use crate::cyphal::crc_add;
+use std::collections::BTreeSet;
 
 pub struct CyphalInstance<const MTU: usize> {
     pub(crate) mtu_bytes: usize,
@@ -31,11 +32,29 @@ pub struct CyphalTxPacketFrame {
     pub props: CyphalTxProps
 }
 
+/// Fields may be added; construct packets with `new` outside this crate.
+///
+/// ```compile_fail
+/// let packet = cands_transport::cyphal::CyphalTxPacket::<8> { xid: 0, payload: [0; 8], payload_size: 0, deadline_usec: None, marker: 0 };
+/// ```
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct CyphalTxPacket<const MTU: usize> {
     pub xid: u32,
     pub payload: [u8; MTU],
     pub payload_size: usize,
+    /// The packet should be dropped instead of transmitted after this time. None means no deadline.
+    pub deadline_usec: Option<CyphalMicrosecond>,
+    /// Message marker assigned when the transfer was encoded, shared by all of its frames.
+    /// Drivers report it back in `CanTxConfirmation` once the transfer has left the controller.
+    pub marker: u8,
+}
+
+impl <const MTU: usize> CyphalTxPacket<MTU> {
+    /// A packet without deadline and with marker 0.
+    pub fn new(xid: u32, payload: [u8; MTU], payload_size: usize) -> Self {
+        Self { xid, payload, payload_size, deadline_usec: None, marker: 0 }
+    }
 }
 
 
@@ -61,7 +80,9 @@ pub struct CyphalRxPacket<const MTU: usize> {
     pub payload: [u8; MTU],
     pub payload_size: usize,
     pub status: CyphalRxPacketStatus,
-    pub props: CyphalRxProps
+    pub props: CyphalRxProps,
+    /// Reception time in the time base of the driver, or zero when unknown.
+    pub timestamp_usec: CyphalMicrosecond,
 }
 
 #[derive(Debug, Clone)]
@@ -69,7 +90,9 @@ pub struct CyphalRxFrame {
     pub xid: u32,
     pub payload: Vec<u8>,
     pub payload_size: usize,
-    pub props: CyphalRxProps
+    pub props: CyphalRxProps,
+    /// Reception time of the first frame of the transfer, as passed to `CyphalMiddleware::accept`.
+    pub timestamp_usec: CyphalMicrosecond,
 }
 
 impl CyphalRxFrame {
@@ -85,3 +108,54 @@ pub struct CyphalRxData <T> {
   pub props: CyphalRxProps
 } 
 
+
+/// Reassembly state of one (transfer kind, port-ID, source node-ID) session.
+#[derive(Debug, Clone)]
+pub(crate) struct CyphalRxSession {
+    pub(crate) transfer_timestamp_usec: CyphalMicrosecond,
+    pub(crate) transfer_id: CyphalTransferID,
+    pub(crate) toggle: bool,
+    pub(crate) payload: Vec<u8>,
+    pub(crate) crc: TransferCRC,
+}
+
+impl CyphalRxSession {
+    pub(crate) fn new(transfer_timestamp_usec: CyphalMicrosecond, transfer_id: CyphalTransferID) -> Self {
+        Self {
+            transfer_timestamp_usec,
+            transfer_id,
+            toggle: INITIAL_TOGGLE_STATE,
+            payload: vec![],
+            crc: CRC_INITIAL,
+        }
+    }
+
+    pub(crate) fn restart(&mut self, transfer_id: CyphalTransferID) {
+        self.transfer_id = transfer_id & CYPHAL_TRANSFER_ID_MAX;
+        self.toggle = INITIAL_TOGGLE_STATE;
+        self.payload.clear();
+        self.crc = CRC_INITIAL;
+    }
+}
+
+/// Ports used by the local node, as reported by uavcan.node.port.List.
+/// Subjects are recorded on the first publication, services on the first request or response sent;
+/// subscriptions and served services are registered explicitly.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct CyphalPorts {
+    pub publishers: BTreeSet<CyphalPortID>,
+    pub subscribers: BTreeSet<CyphalPortID>,
+    pub clients: BTreeSet<CyphalPortID>,
+    pub servers: BTreeSet<CyphalPortID>,
+}
+
+impl CyphalPorts {
+    /// Records a port used for transmission: messages are publications, requests are client calls, responses are served services.
+    pub(crate) fn record(&mut self, transfer_kind: CyphalTransferKind, port_id: CyphalPortID) {
+        match transfer_kind {
+            CyphalTransferKind::Message => self.publishers.insert(port_id),
+            CyphalTransferKind::Request => self.clients.insert(port_id),
+            CyphalTransferKind::Response => self.servers.insert(port_id),
+        };
+    }
+}
@@ -2,6 +2,6 @@ pub type CyphalPortID = u16;
 pub type CyphalNodeID = u8;
 pub type CyphalTransferID = u8;
 
-// pub type CyphalMicrosecond = u64;
+pub type CyphalMicrosecond = u64;
 
 pub type TransferCRC = u16;
\ No newline at end of file
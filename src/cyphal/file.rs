@@ -0,0 +1,580 @@
+//! uavcan.file.* server and client.
+
+use super::CyphalMiddleware;
+use crate::driver::*;
+use super::defines::*;
+use super::service::{CyphalServiceClient, CyphalServiceError};
+use super::uavcan::CyphalDataType;
+use super::uavcan::file::*;
+
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// File server exposing a local directory through uavcan.file.GetInfo, List, Modify, Read and Write.
+/// Remote paths are resolved relative to the root directory; `..` components and symbolic links
+/// leading outside of it or nowhere are rejected with ACCESS_DENIED.
+pub struct CyphalFileServer {
+    root: PathBuf,
+    read_only: bool,
+}
+
+impl CyphalFileServer {
+    pub fn new<P: AsRef<Path>>(root: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let root: PathBuf = root.as_ref().canonicalize()?;
+        if !root.is_dir() {
+            return Err("FILE SERVER ROOT IS NOT A DIRECTORY".into());
+        }
+        Ok(Self { root, read_only: false })
+    }
+
+    /// Rejects Write and Modify requests, e.g. when the directory only serves firmware images.
+    pub fn set_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns true if the service-ID belongs to one of the uavcan.file services handled here.
+    pub fn is_file_service(port_id: CyphalPortID) -> bool {
+        matches!(
+            port_id,
+            GetInfoRequest::FIXED_PORT_ID | ListRequest::FIXED_PORT_ID | ModifyRequest::FIXED_PORT_ID |
+            ReadRequest::FIXED_PORT_ID | WriteRequest::FIXED_PORT_ID
+        )
+    }
+
+    /// Handles a reassembled request addressed to this node and returns the response packets.
+    /// Transfers that are not uavcan.file requests for this node yield no packets.
+    pub fn respond<const MTU: usize>(
+        &self,
+        middleware: &mut CyphalMiddleware<MTU>,
+        frame: &CyphalRxFrame
+    ) -> Result<Vec<CyphalTxPacket<MTU>>, Box<dyn std::error::Error>> {
+        if frame.props.transfer_kind != CyphalTransferKind::Request ||
+            frame.props.destination_node_id != middleware.node_id() ||
+            !Self::is_file_service(frame.props.port_id)
+        {
+            return Ok(vec![]);
+        }
+        let data: Vec<u8> = self.handle(frame.props.port_id, &frame.payload)?;
+        middleware.create_response_for(&frame.props, &data)
+    }
+
+    /// Handles a serialized request of the given service and returns the serialized response.
+    pub fn handle(&self, port_id: CyphalPortID, payload: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        match port_id {
+            GetInfoRequest::FIXED_PORT_ID => self.get_info(&GetInfoRequest::deserialize(payload)?).serialize(),
+            ListRequest::FIXED_PORT_ID => self.list(&ListRequest::deserialize(payload)?).serialize(),
+            ModifyRequest::FIXED_PORT_ID => self.modify(&ModifyRequest::deserialize(payload)?).serialize(),
+            ReadRequest::FIXED_PORT_ID => self.read(&ReadRequest::deserialize(payload)?).serialize(),
+            WriteRequest::FIXED_PORT_ID => self.write(&WriteRequest::deserialize(payload)?).serialize(),
+            _ => Err("INVALID SERVICE ID".into()),
+        }
+    }
+
+    pub fn get_info(&self, request: &GetInfoRequest) -> GetInfoResponse {
+        let path: PathBuf = match self.resolve(&request.path) {
+            Ok(x) => x,
+            Err(err) => return GetInfoResponse::from_error(err),
+        };
+        let is_link: bool = std::fs::symlink_metadata(&path).map(|x| x.file_type().is_symlink()).unwrap_or(false);
+        let metadata: std::fs::Metadata = match std::fs::metadata(&path) {
+            Ok(x) => x,
+            Err(err) => return GetInfoResponse::from_error(FileError::from(&err)),
+        };
+        let unix_timestamp_of_last_modification: u64 = metadata.modified().ok()
+            .and_then(|x| x.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|x| x.as_secs())
+            .unwrap_or(0);
+        GetInfoResponse {
+            error: FileError::OK,
+            size: if metadata.is_file() { metadata.len() } else { 0 },
+            unix_timestamp_of_last_modification,
+            is_file_not_directory: !metadata.is_dir(),
+            is_link,
+            is_readable: true,
+            is_writeable: !self.read_only && !metadata.permissions().readonly(),
+        }
+    }
+
+    /// Lists the directory in name order; an index past the last entry yields an empty name.
+    pub fn list(&self, request: &ListRequest) -> ListResponse {
+        let empty: ListResponse = ListResponse { entry_base_name: vec![] };
+        let path: PathBuf = match self.resolve(&request.directory_path) {
+            Ok(x) => x,
+            Err(_) => return empty,
+        };
+        let mut names: Vec<String> = match std::fs::read_dir(&path) {
+            Ok(x) => x.filter_map(|e| e.ok())
+                .filter_map(|e| e.file_name().into_string().ok())
+                .filter(|e| e.len() <= PATH_MAX_LENGTH)
+                .collect(),
+            Err(_) => return empty,
+        };
+        names.sort();
+        match names.get(request.entry_index as usize) {
+            Some(x) => ListResponse { entry_base_name: x.as_bytes().to_vec() },
+            None => empty,
+        }
+    }
+
+    pub fn modify(&self, request: &ModifyRequest) -> FileErrorResponse {
+        let error: FileError = if self.read_only {
+            FileError::ACCESS_DENIED
+        } else {
+            match self.modify_impl(request) {
+                Ok(()) => FileError::OK,
+                Err(err) => err,
+            }
+        };
+        FileErrorResponse { error }
+    }
+
+    /// Reads up to DATA_CAPACITY bytes at the requested offset.
+    pub fn read(&self, request: &ReadRequest) -> ReadResponse {
+        match self.read_impl(request) {
+            Ok(data) => ReadResponse { error: FileError::OK, data },
+            Err(error) => ReadResponse { error, data: vec![] },
+        }
+    }
+
+    /// Writes the data at the requested offset, creating the file if needed.
+    /// A request without data truncates the file at the offset, which marks the end of an upload.
+    pub fn write(&self, request: &WriteRequest) -> FileErrorResponse {
+        let error: FileError = if self.read_only {
+            FileError::ACCESS_DENIED
+        } else {
+            match self.write_impl(request) {
+                Ok(()) => FileError::OK,
+                Err(err) => err,
+            }
+        };
+        FileErrorResponse { error }
+    }
+
+    fn read_impl(&self, request: &ReadRequest) -> Result<Vec<u8>, FileError> {
+        let path: PathBuf = self.resolve(&request.path)?;
+        if path.is_dir() {
+            return Err(FileError::IS_DIRECTORY);
+        }
+        let mut file: std::fs::File = std::fs::File::open(&path).map_err(|e| FileError::from(&e))?;
+        file.seek(SeekFrom::Start(request.offset)).map_err(|e| FileError::from(&e))?;
+        let mut data: Vec<u8> = vec![];
+        file.take(DATA_CAPACITY as u64).read_to_end(&mut data).map_err(|e| FileError::from(&e))?;
+        Ok(data)
+    }
+
+    fn write_impl(&self, request: &WriteRequest) -> Result<(), FileError> {
+        let path: PathBuf = self.resolve(&request.path)?;
+        if path == self.root || path.is_dir() {
+            return Err(FileError::IS_DIRECTORY);
+        }
+        let mut file: std::fs::File = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .map_err(|e| FileError::from(&e))?;
+        if request.data.is_empty() {
+            return file.set_len(request.offset).map_err(|e| FileError::from(&e));
+        }
+        file.seek(SeekFrom::Start(request.offset)).map_err(|e| FileError::from(&e))?;
+        file.write_all(&request.data).map_err(|e| FileError::from(&e))
+    }
+
+    fn modify_impl(&self, request: &ModifyRequest) -> Result<(), FileError> {
+        match (request.source.is_empty(), request.destination.is_empty()) {
+            (true, true) => Err(FileError::INVALID_VALUE),
+            // Touch
+            (true, false) => {
+                let path: PathBuf = self.resolve(&request.destination)?;
+                let file: std::fs::File = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .map_err(|e| FileError::from(&e))?;
+                file.set_modified(std::time::SystemTime::now()).map_err(|e| FileError::from(&e))
+            },
+            // Remove
+            (false, true) => {
+                if request.preserve_source {
+                    return Err(FileError::INVALID_VALUE);
+                }
+                let path: PathBuf = self.resolve(&request.source)?;
+                if path == self.root {
+                    return Err(FileError::ACCESS_DENIED);
+                }
+                Self::remove(&path)
+            },
+            // Copy or move
+            (false, false) => {
+                let source: PathBuf = self.resolve(&request.source)?;
+                let destination: PathBuf = self.resolve(&request.destination)?;
+                if source == self.root || destination == self.root {
+                    return Err(FileError::ACCESS_DENIED);
+                }
+                if !source.exists() {
+                    return Err(FileError::NOT_FOUND);
+                }
+                if destination.exists() {
+                    if !request.overwrite_destination {
+                        return Err(FileError::INVALID_VALUE);
+                    }
+                    Self::remove(&destination)?;
+                }
+                if request.preserve_source {
+                    Self::copy(&source, &destination)
+                } else if std::fs::rename(&source, &destination).is_err() {
+                    Self::copy(&source, &destination)?;
+                    Self::remove(&source)
+                } else {
+                    Ok(())
+                }
+            },
+        }
+    }
+
+    fn remove(path: &Path) -> Result<(), FileError> {
+        let ret: std::io::Result<()> = if path.is_dir() {
+            std::fs::remove_dir_all(path)
+        } else {
+            std::fs::remove_file(path)
+        };
+        ret.map_err(|e| FileError::from(&e))
+    }
+
+    fn copy(source: &Path, destination: &Path) -> Result<(), FileError> {
+        if !source.is_dir() {
+            return std::fs::copy(source, destination).map(|_| ()).map_err(|e| FileError::from(&e));
+        }
+        std::fs::create_dir(destination).map_err(|e| FileError::from(&e))?;
+        for entry in std::fs::read_dir(source).map_err(|e| FileError::from(&e))? {
+            let entry: std::fs::DirEntry = entry.map_err(|e| FileError::from(&e))?;
+            Self::copy(&entry.path(), &destination.join(entry.file_name()))?;
+        }
+        Ok(())
+    }
+
+    /// Maps a remote path onto the local file system, refusing anything that would leave the root directory.
+    /// Every existing component is checked without following it: a symbolic link is only accepted if its target
+    /// resolves inside the root, so dangling links, which `create` would follow to anywhere, are refused.
+    fn resolve(&self, path: &[u8]) -> Result<PathBuf, FileError> {
+        let path: &str = std::str::from_utf8(path).map_err(|_| FileError::INVALID_VALUE)?;
+        let mut ret: PathBuf = self.root.clone();
+        let mut exists: bool = true;
+        for component in path.split(PATH_SEPARATOR as char) {
+            match component {
+                "" | "." => continue,
+                ".." => return Err(FileError::ACCESS_DENIED),
+                x if x.contains(['\0', '\\', ':']) => return Err(FileError::INVALID_VALUE),
+                x => ret.push(x),
+            }
+            if !exists {
+                continue;
+            }
+            match std::fs::symlink_metadata(&ret) {
+                Ok(x) if x.file_type().is_symlink() => {
+                    let target: PathBuf = ret.canonicalize().map_err(|_| FileError::ACCESS_DENIED)?;
+                    if !target.starts_with(&self.root) {
+                        return Err(FileError::ACCESS_DENIED);
+                    }
+                },
+                Ok(_) => {},
+                Err(_) => exists = false,
+            }
+        }
+        Ok(ret)
+    }
+}
+
+/// Blocking client for the uavcan.file services of a remote node.
+/// While a request is pending, the client owns the driver and drops unrelated transfers.
+pub struct CyphalFileClient<'a, const MTU: usize, D: CanDriver<MTU>> {
+    middleware: &'a mut CyphalMiddleware<MTU>,
+    driver: &'a mut D,
+    server_node_id: CyphalNodeID,
+    service_client: CyphalServiceClient,
+    retries: usize,
+}
+
+impl <'a, const MTU: usize, D: CanDriver<MTU>> CyphalFileClient<'a, MTU, D> {
+    pub fn new(middleware: &'a mut CyphalMiddleware<MTU>, driver: &'a mut D, server_node_id: CyphalNodeID) -> Self {
+        Self {
+            middleware,
+            driver,
+            server_node_id,
+            service_client: CyphalServiceClient::new(),
+            retries: 3,
+        }
+    }
+
+    /// Time to wait for each response before the request is sent again.
+    pub fn set_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.service_client = self.service_client.set_timeout_usec(timeout.as_micros() as CyphalMicrosecond);
+        self
+    }
+
+    /// Number of times a request is repeated after a timeout before giving up.
+    pub fn set_retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn get_info(&mut self, path: &[u8]) -> Result<GetInfoResponse, Box<dyn std::error::Error>> {
+        let request: GetInfoRequest = GetInfoRequest { path: path.to_vec() };
+        let response: GetInfoResponse = GetInfoResponse::deserialize(&self.call(GetInfoRequest::FIXED_PORT_ID, &request.serialize()?)?)?;
+        Self::check(response.error)?;
+        Ok(response)
+    }
+
+    /// Reads one chunk of up to DATA_CAPACITY bytes; a shorter chunk means the end of the file.
+    pub fn read_chunk(&mut self, path: &[u8], offset: u64) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let request: ReadRequest = ReadRequest { offset, path: path.to_vec() };
+        let response: ReadResponse = ReadResponse::deserialize(&self.call(ReadRequest::FIXED_PORT_ID, &request.serialize()?)?)?;
+        Self::check(response.error)?;
+        Ok(response.data)
+    }
+
+    /// Writes one chunk of up to DATA_CAPACITY bytes; an empty chunk truncates the remote file at the offset.
+    pub fn write_chunk(&mut self, path: &[u8], offset: u64, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let request: WriteRequest = WriteRequest { offset, path: path.to_vec(), data: data.to_vec() };
+        let response: FileErrorResponse = FileErrorResponse::deserialize(&self.call(WriteRequest::FIXED_PORT_ID, &request.serialize()?)?)?;
+        Self::check(response.error)
+    }
+
+    /// Returns a `std::io::Read` adapter streaming the remote file from the start.
+    pub fn reader(self, path: &[u8]) -> CyphalFileReader<'a, MTU, D> {
+        CyphalFileReader {
+            client: self,
+            path: path.to_vec(),
+            offset: 0,
+            buffer: vec![],
+            eof: false,
+        }
+    }
+
+    /// Returns a `std::io::Write` adapter uploading to the remote file from the start.
+    pub fn writer(self, path: &[u8]) -> CyphalFileWriter<'a, MTU, D> {
+        CyphalFileWriter {
+            client: self,
+            path: path.to_vec(),
+            offset: 0,
+            buffer: vec![],
+        }
+    }
+
+    fn check(error: FileError) -> Result<(), Box<dyn std::error::Error>> {
+        if error != FileError::OK {
+            return Err(format!("REMOTE FILE ERROR {}", error.0).into());
+        }
+        Ok(())
+    }
+
+    /// Sends a request and waits for the matching response, repeating it on timeout.
+    fn call(&mut self, port_id: CyphalPortID, data: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut attempt: usize = 0;
+        loop {
+            match self.service_client.call(self.middleware, self.driver, self.server_node_id, port_id, data) {
+                Ok(frame) => return Ok(frame.payload),
+                Err(err) if attempt < self.retries && matches!(err.downcast_ref(), Some(CyphalServiceError::Timeout(_))) => attempt += 1,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// `std::io::Read` adapter issuing uavcan.file.Read requests as data is consumed.
+pub struct CyphalFileReader<'a, const MTU: usize, D: CanDriver<MTU>> {
+    client: CyphalFileClient<'a, MTU, D>,
+    path: Vec<u8>,
+    offset: u64,
+    buffer: Vec<u8>,
+    eof: bool,
+}
+
+impl <'a, const MTU: usize, D: CanDriver<MTU>> CyphalFileReader<'a, MTU, D> {
+    /// Continues reading at the given offset of the remote file.
+    pub fn seek_to(&mut self, offset: u64) {
+        self.offset = offset;
+        self.buffer.clear();
+        self.eof = false;
+    }
+
+    pub fn into_inner(self) -> CyphalFileClient<'a, MTU, D> {
+        self.client
+    }
+}
+
+impl <const MTU: usize, D: CanDriver<MTU>> std::io::Read for CyphalFileReader<'_, MTU, D> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.buffer.is_empty() && !self.eof {
+            let chunk: Vec<u8> = self.client.read_chunk(&self.path, self.offset).map_err(|e| std::io::Error::other(e.to_string()))?;
+            self.eof = chunk.len() < DATA_CAPACITY;
+            self.offset += chunk.len() as u64;
+            self.buffer = chunk;
+        }
+        let size: usize = std::cmp::min(buf.len(), self.buffer.len());
+        buf[..size].copy_from_slice(&self.buffer[..size]);
+        self.buffer.drain(..size);
+        Ok(size)
+    }
+}
+
+/// `std::io::Write` adapter issuing uavcan.file.Write requests of DATA_CAPACITY bytes.
+/// `finish` flushes the remainder and truncates the remote file to the uploaded size.
+pub struct CyphalFileWriter<'a, const MTU: usize, D: CanDriver<MTU>> {
+    client: CyphalFileClient<'a, MTU, D>,
+    path: Vec<u8>,
+    offset: u64,
+    buffer: Vec<u8>,
+}
+
+impl <'a, const MTU: usize, D: CanDriver<MTU>> CyphalFileWriter<'a, MTU, D> {
+    pub fn finish(mut self) -> Result<CyphalFileClient<'a, MTU, D>, Box<dyn std::error::Error>> {
+        self.write_buffer()?;
+        self.client.write_chunk(&self.path, self.offset, &[])?;
+        Ok(self.client)
+    }
+
+    fn write_buffer(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.buffer.is_empty() {
+            let chunk: Vec<u8> = std::mem::take(&mut self.buffer);
+            self.client.write_chunk(&self.path, self.offset, &chunk)?;
+            self.offset += chunk.len() as u64;
+        }
+        Ok(())
+    }
+}
+
+impl <const MTU: usize, D: CanDriver<MTU>> std::io::Write for CyphalFileWriter<'_, MTU, D> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let size: usize = std::cmp::min(buf.len(), DATA_CAPACITY - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..size]);
+        if self.buffer.len() == DATA_CAPACITY {
+            self.write_buffer().map_err(|e| std::io::Error::other(e.to_string()))?;
+        }
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write_buffer().map_err(|e| std::io::Error::other(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory below the system temporary directory, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path: PathBuf = std::env::temp_dir().join(format!("cands_transport_file_{}_{}", std::process::id(), name));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(path.join("root")).unwrap();
+            Self(path)
+        }
+
+        fn root(&self) -> PathBuf {
+            self.0.join("root")
+        }
+
+        /// A sibling of the root, which the server must not reach.
+        fn outside(&self) -> PathBuf {
+            self.0.join("outside")
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn write_request(path: &str, data: &[u8]) -> WriteRequest {
+        WriteRequest { offset: 0, path: path.as_bytes().to_vec(), data: data.to_vec() }
+    }
+
+    #[test]
+    fn parent_components_are_refused() {
+        let dir: TempDir = TempDir::new("parent");
+        std::fs::create_dir(dir.root().join("sub")).unwrap();
+        let server: CyphalFileServer = CyphalFileServer::new(dir.root()).unwrap();
+        for path in ["../outside", "sub/../../outside", "sub/.."] {
+            assert_eq!(server.write(&write_request(path, b"x")).error, FileError::ACCESS_DENIED);
+            assert_eq!(server.read(&ReadRequest { offset: 0, path: path.as_bytes().to_vec() }).error, FileError::ACCESS_DENIED);
+        }
+        assert!(!dir.outside().exists());
+        assert_eq!(server.write(&write_request("./sub//file", b"x")).error, FileError::OK);
+        assert_eq!(std::fs::read(dir.root().join("sub/file")).unwrap(), b"x");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn escaping_symlink_is_refused() {
+        let dir: TempDir = TempDir::new("escaping");
+        std::fs::create_dir(dir.outside()).unwrap();
+        std::fs::write(dir.outside().join("secret"), b"secret").unwrap();
+        std::os::unix::fs::symlink(dir.outside(), dir.root().join("link")).unwrap();
+        std::fs::create_dir(dir.root().join("inside")).unwrap();
+        std::os::unix::fs::symlink(dir.root().join("inside"), dir.root().join("inner_link")).unwrap();
+        let server: CyphalFileServer = CyphalFileServer::new(dir.root()).unwrap();
+
+        assert_eq!(server.read(&ReadRequest { offset: 0, path: b"link/secret".to_vec() }).error, FileError::ACCESS_DENIED);
+        assert_eq!(server.write(&write_request("link/new", b"x")).error, FileError::ACCESS_DENIED);
+        assert!(!dir.outside().join("new").exists());
+
+        // Links that stay inside the root are followed.
+        assert_eq!(server.write(&write_request("inner_link/new", b"x")).error, FileError::OK);
+        assert!(dir.root().join("inside/new").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dangling_symlink_is_refused() {
+        let dir: TempDir = TempDir::new("dangling");
+        std::os::unix::fs::symlink(dir.outside().join("created"), dir.root().join("dangling")).unwrap();
+        let server: CyphalFileServer = CyphalFileServer::new(dir.root()).unwrap();
+
+        assert_eq!(server.write(&write_request("dangling", b"x")).error, FileError::ACCESS_DENIED);
+        let touch: ModifyRequest = ModifyRequest {
+            preserve_source: false,
+            overwrite_destination: false,
+            source: vec![],
+            destination: b"dangling".to_vec(),
+        };
+        assert_eq!(server.modify(&touch).error, FileError::ACCESS_DENIED);
+        assert!(!dir.outside().join("created").exists());
+    }
+
+    #[test]
+    fn file_is_read_in_chunks_at_offsets() {
+        let dir: TempDir = TempDir::new("chunks");
+        let content: Vec<u8> = (0..600).map(|x| x as u8).collect();
+        std::fs::write(dir.root().join("image.bin"), &content).unwrap();
+        let server: CyphalFileServer = CyphalFileServer::new(dir.root()).unwrap();
+
+        let mut data: Vec<u8> = vec![];
+        loop {
+            let request: ReadRequest = ReadRequest { offset: data.len() as u64, path: b"image.bin".to_vec() };
+            let response: ReadResponse = ReadResponse::deserialize(&server.handle(ReadRequest::FIXED_PORT_ID, &request.serialize().unwrap()).unwrap()).unwrap();
+            assert_eq!(response.error, FileError::OK);
+            assert!(response.data.len() <= DATA_CAPACITY);
+            if response.data.is_empty() {
+                break;
+            }
+            data.extend_from_slice(&response.data);
+        }
+        assert_eq!(data, content);
+
+        let response: ReadResponse = server.read(&ReadRequest { offset: 590, path: b"image.bin".to_vec() });
+        assert_eq!(response.data, content[590..]);
+        let response: ReadResponse = server.read(&ReadRequest { offset: 10_000, path: b"image.bin".to_vec() });
+        assert_eq!((response.error, response.data.len()), (FileError::OK, 0));
+        assert_eq!(server.read(&ReadRequest { offset: 0, path: b"missing".to_vec() }).error, FileError::NOT_FOUND);
+    }
+}
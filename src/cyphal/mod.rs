@@ -1,22 +1,51 @@
 pub mod defines;
 pub use defines::*;
 
+pub mod uavcan;
+pub mod pnp;
+pub mod time;
+pub mod file;
+pub mod port;
+pub mod node;
+pub mod service;
+pub mod pubsub;
+pub mod tracker;
+pub mod collision;
+
 mod tx;
 mod rx;
 mod crc;
 
 pub use crc::*;
 
+use std::collections::HashMap;
+
 pub struct CyphalMiddleware <const MTU: usize> {
     can_instance: CyphalInstance<MTU>,
     pub transfer_id: u8,
+    pub transfer_id_timeout_usec: CyphalMicrosecond,
+    monitor_mode: bool,
+    rx_sessions: HashMap<(CyphalTransferKind, CyphalPortID, CyphalNodeID), CyphalRxSession>,
+    tx_transfer_ids: HashMap<(CyphalTransferKind, CyphalPortID, CyphalNodeID), CyphalTransferID>,
+    default_extent: usize,
+    extents: HashMap<(CyphalTransferKind, CyphalPortID), usize>,
+    ports: CyphalPorts,
+    tx_marker: u8,
 }
 
 impl <const MTU: usize> CyphalMiddleware<MTU> {
     pub fn new(node_id: CyphalNodeID) -> Self {
         Self {
             can_instance: CyphalInstance::new(node_id),
-            transfer_id: 0
+            transfer_id: 0,
+            transfer_id_timeout_usec: CYPHAL_DEFAULT_TRANSFER_ID_TIMEOUT_USEC as CyphalMicrosecond,
+            monitor_mode: false,
+            rx_sessions: HashMap::new(),
+            tx_transfer_ids: HashMap::new(),
+            default_extent: CYPHAL_DEFAULT_EXTENT_BYTES,
+            extents: HashMap::new(),
+            ports: CyphalPorts::default(),
+            tx_marker: 0,
         }
     }
 
@@ -24,4 +53,89 @@ impl <const MTU: usize> CyphalMiddleware<MTU> {
         self.can_instance.node_id = node_id;
         self
     }
+
+    pub fn node_id(&self) -> CyphalNodeID {
+        self.can_instance.node_id
+    }
+
+    /// In monitor mode, service transfers addressed to other nodes are received as well.
+    pub fn set_monitor_mode(mut self, monitor_mode: bool) -> Self {
+        self.monitor_mode = monitor_mode;
+        self
+    }
+
+    pub fn monitor_mode(&self) -> bool {
+        self.monitor_mode
+    }
+
+    /// Extent of the ports subscribed without one.
+    pub fn set_default_extent(mut self, extent: usize) -> Self {
+        self.default_extent = extent;
+        self
+    }
+
+    /// Longest transfer payload accepted on a port; longer multi-frame transfers are dropped while being reassembled.
+    pub fn extent(&self, transfer_kind: CyphalTransferKind, port_id: CyphalPortID) -> usize {
+        *self.extents.get(&(transfer_kind, port_id)).unwrap_or(&self.default_extent)
+    }
+
+    /// Returns true if a received transfer is meant for this node: messages always are, service transfers only
+    /// when addressed to the local node-ID or in monitor mode.
+    pub fn is_addressed_to_us(&self, props: &CyphalRxProps) -> bool {
+        props.transfer_kind == CyphalTransferKind::Message || self.monitor_mode || props.destination_node_id == self.can_instance.node_id
+    }
+
+    /// Returns true while the local node has no node-ID and may only publish anonymous single-frame messages.
+    pub fn is_anonymous(&self) -> bool {
+        self.can_instance.node_id > CYPHAL_NODE_ID_MAX
+    }
+
+    /// Registers interest in a port: messages of a subject, requests of a served service or responses of a called service.
+    pub fn subscribe(&mut self, transfer_kind: CyphalTransferKind, port_id: CyphalPortID) -> Result<(), Box<dyn std::error::Error>> {
+        let port_id_max: CyphalPortID = if transfer_kind == CyphalTransferKind::Message { CYPHAL_SUBJECT_ID_MAX } else { CYPHAL_SERVICE_ID_MAX };
+        if port_id > port_id_max {
+            return Err("INVALID PORT ID".into());
+        }
+        match transfer_kind {
+            CyphalTransferKind::Message => self.ports.subscribers.insert(port_id),
+            CyphalTransferKind::Request => self.ports.servers.insert(port_id),
+            CyphalTransferKind::Response => self.ports.clients.insert(port_id),
+        };
+        Ok(())
+    }
+
+    /// Like `subscribe`, with the extent of the port: the size of the largest payload its data type can have.
+    pub fn subscribe_with_extent(&mut self, transfer_kind: CyphalTransferKind, port_id: CyphalPortID, extent: usize) -> Result<(), Box<dyn std::error::Error>> {
+        self.subscribe(transfer_kind, port_id)?;
+        self.extents.insert((transfer_kind, port_id), extent);
+        Ok(())
+    }
+
+    pub fn unsubscribe(&mut self, transfer_kind: CyphalTransferKind, port_id: CyphalPortID) {
+        match transfer_kind {
+            CyphalTransferKind::Message => self.ports.subscribers.remove(&port_id),
+            CyphalTransferKind::Request => self.ports.servers.remove(&port_id),
+            CyphalTransferKind::Response => self.ports.clients.remove(&port_id),
+        };
+        self.extents.remove(&(transfer_kind, port_id));
+        self.rx_sessions.retain(|k, _| k.0 != transfer_kind || k.1 != port_id);
+    }
+
+    pub fn ports(&self) -> &CyphalPorts {
+        &self.ports
+    }
+
+    /// Transfer-ID the next outgoing transfer of the session will carry. Messages use CYPHAL_NODE_ID_UNSET as remote node-ID.
+    pub fn transfer_id_for(&self, transfer_kind: CyphalTransferKind, port_id: CyphalPortID, remote_node_id: CyphalNodeID) -> CyphalTransferID {
+        *self.tx_transfer_ids.get(&(transfer_kind, port_id, remote_node_id)).unwrap_or(&0)
+    }
+
+    /// Takes the transfer-ID for the next outgoing transfer of the session.
+    /// Every sender of the node draws from these counters, so that transfers on one port never reuse a transfer-ID
+    /// regardless of the handle they were sent through.
+    pub fn next_transfer_id(&mut self, transfer_kind: CyphalTransferKind, port_id: CyphalPortID, remote_node_id: CyphalNodeID) -> CyphalTransferID {
+        let transfer_id: CyphalTransferID = self.transfer_id_for(transfer_kind, port_id, remote_node_id);
+        self.tx_transfer_ids.insert((transfer_kind, port_id, remote_node_id), (transfer_id + 1) & CYPHAL_TRANSFER_ID_MAX);
+        transfer_id
+    }
 }
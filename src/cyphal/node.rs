@@ -0,0 +1,555 @@
+//! Node runtime built on top of the middleware.
+
+use super::CyphalMiddleware;
+use crate::driver::*;
+use super::collision::*;
+use super::defines::*;
+use super::port::CyphalPortListPublisher;
+use super::pubsub::CyphalPublisher;
+use super::service::*;
+use super::tracker::*;
+use super::uavcan::CyphalDataType;
+use super::uavcan::node::*;
+
+use std::collections::HashMap;
+
+pub type CyphalTransferHandler = Box<dyn FnMut(&CyphalRxFrame)>;
+
+/// A Cyphal node owning the middleware and the housekeeping every application needs:
+/// the 1 Hz heartbeat with real uptime, GetInfo responses, port.List publication,
+/// matching of responses to pending requests and the dispatch of received transfers to handlers.
+/// Transfer-IDs are taken from the middleware's per-session counters, shared with publisher handles and service clients.
+/// Outgoing packets are queued and returned by `poll`; `spin_once` drives the whole loop over a CAN driver.
+/// All times passed to the node are in one time base: the `monotonic_usec` base in which drivers timestamp
+/// received packets and evaluate transmit deadlines.
+/// Bus events from the driver are kept for the application, and the published health is raised while the
+/// controller is error-warning (Advisory), error-passive (Caution) or bus-off (Warning).
+pub struct CyphalNode<const MTU: usize> {
+    middleware: CyphalMiddleware<MTU>,
+    info: GetInfoResponse,
+    heartbeat: Heartbeat,
+    started_usec: Option<CyphalMicrosecond>,
+    next_heartbeat_usec: CyphalMicrosecond,
+    port_list: Option<CyphalPortListPublisher>,
+    service_client: CyphalServiceClient,
+    service_server: CyphalServiceServer,
+    message_handlers: HashMap<CyphalPortID, CyphalTransferHandler>,
+    fallback_handler: Option<CyphalTransferHandler>,
+    collision_detector: Option<CyphalCollisionDetector>,
+    node_tracker: Option<CyphalNodeTracker>,
+    bus_state: CanBusState,
+    bus_events: Vec<CanBusEvent>,
+    tx_confirmations: Vec<CanTxConfirmation>,
+    tx_queue: Vec<CyphalTxPacket<MTU>>,
+}
+
+impl <const MTU: usize> CyphalNode<MTU> {
+    pub fn new(middleware: CyphalMiddleware<MTU>, info: GetInfoResponse) -> Self {
+        let mut middleware: CyphalMiddleware<MTU> = middleware;
+        // Registering a valid fixed port-ID cannot fail.
+        let _ = middleware.subscribe(CyphalTransferKind::Request, GetInfoRequest::FIXED_PORT_ID);
+        Self {
+            middleware,
+            info: GetInfoResponse {
+                protocol_version: Version { major: CYPHAL_SPECIFICATION_VERSION_MAJOR, minor: CYPHAL_SPECIFICATION_VERSION_MINOR },
+                ..info
+            },
+            heartbeat: Heartbeat {
+                uptime: 0,
+                health: Health::Nominal,
+                mode: Mode::Operational,
+                vendor_specific_status_code: 0,
+            },
+            started_usec: None,
+            next_heartbeat_usec: 0,
+            port_list: Some(CyphalPortListPublisher::new()),
+            service_client: CyphalServiceClient::new(),
+            service_server: CyphalServiceServer::new(),
+            message_handlers: HashMap::new(),
+            fallback_handler: None,
+            collision_detector: None,
+            node_tracker: None,
+            bus_state: CanBusState::ErrorActive,
+            bus_events: vec![],
+            tx_confirmations: vec![],
+            tx_queue: vec![],
+        }
+    }
+
+    /// uavcan.node.port.List is optional; it is published unless disabled here.
+    pub fn set_port_list_enabled(mut self, enabled: bool) -> Self {
+        self.port_list = if enabled { Some(CyphalPortListPublisher::new()) } else { None };
+        self
+    }
+
+    /// Replaces the client used by `request`, e.g. to change the request timeout or priority.
+    pub fn set_service_client(mut self, service_client: CyphalServiceClient) -> Self {
+        self.service_client = service_client;
+        self
+    }
+
+    /// Enables node-ID collision detection. A detector set to silence on conflict stops all transmissions of this node
+    /// and discards what is queued until it is reset.
+    pub fn set_collision_detector(mut self, collision_detector: CyphalCollisionDetector) -> Self {
+        self.collision_detector = Some(collision_detector);
+        self
+    }
+
+    pub fn collision_detector_mut(&mut self) -> Option<&mut CyphalCollisionDetector> {
+        self.collision_detector.as_mut()
+    }
+
+    pub fn take_collision_events(&mut self) -> Vec<CyphalCollisionEvent> {
+        self.collision_detector.as_mut().map(|x| x.take_events()).unwrap_or_default()
+    }
+
+    /// Tracks the nodes on the bus from their heartbeats. GetInfo requests of the tracker are sent through
+    /// the node's service client, alongside the requests of the application.
+    pub fn set_node_tracker(mut self, node_tracker: CyphalNodeTracker) -> Self {
+        // Registering a valid fixed port-ID cannot fail.
+        let _ = self.middleware.subscribe(CyphalTransferKind::Message, Heartbeat::FIXED_PORT_ID);
+        self.node_tracker = Some(node_tracker);
+        self
+    }
+
+    pub fn node_tracker(&self) -> Option<&CyphalNodeTracker> {
+        self.node_tracker.as_ref()
+    }
+
+    pub fn node_tracker_mut(&mut self) -> Option<&mut CyphalNodeTracker> {
+        self.node_tracker.as_mut()
+    }
+
+    pub fn middleware(&self) -> &CyphalMiddleware<MTU> {
+        &self.middleware
+    }
+
+    pub fn middleware_mut(&mut self) -> &mut CyphalMiddleware<MTU> {
+        &mut self.middleware
+    }
+
+    pub fn into_middleware(self) -> CyphalMiddleware<MTU> {
+        self.middleware
+    }
+
+    pub fn node_id(&self) -> CyphalNodeID {
+        self.middleware.node_id()
+    }
+
+    pub fn info(&self) -> &GetInfoResponse {
+        &self.info
+    }
+
+    /// Sets the health of the application; the published health also reflects the bus state.
+    pub fn set_health(&mut self, health: Health) {
+        self.heartbeat.health = health;
+    }
+
+    /// Health as published: the worse of the application health and the bus state.
+    pub fn health(&self) -> Health {
+        let bus_health: Health = match self.bus_state {
+            CanBusState::ErrorActive => Health::Nominal,
+            CanBusState::ErrorWarning => Health::Advisory,
+            CanBusState::ErrorPassive => Health::Caution,
+            CanBusState::BusOff => Health::Warning,
+        };
+        if bus_health as u8 > self.heartbeat.health as u8 { bus_health } else { self.heartbeat.health }
+    }
+
+    pub fn bus_state(&self) -> CanBusState {
+        self.bus_state
+    }
+
+    /// Records a bus event reported by the driver; `spin_once` does this for every event.
+    pub fn accept_bus_event(&mut self, event: CanBusEvent) {
+        if let CanBusEvent::BusStateChanged { to, .. } = event {
+            self.bus_state = to;
+        }
+        self.bus_events.push(event);
+    }
+
+    pub fn take_bus_events(&mut self) -> Vec<CanBusEvent> {
+        std::mem::take(&mut self.bus_events)
+    }
+
+    /// Transfers confirmed as transmitted by the driver during `spin_once`, e.g. for
+    /// `CyphalTimeSyncMaster::accept_tx_confirmation`.
+    pub fn take_tx_confirmations(&mut self) -> Vec<CanTxConfirmation> {
+        std::mem::take(&mut self.tx_confirmations)
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.heartbeat.mode = mode;
+    }
+
+    pub fn set_vendor_specific_status_code(&mut self, vendor_specific_status_code: u8) {
+        self.heartbeat.vendor_specific_status_code = vendor_specific_status_code;
+    }
+
+    /// The heartbeat as it will be published next, without the uptime update; its health is the one set by
+    /// the application, see `health` for the published one.
+    pub fn heartbeat(&self) -> &Heartbeat {
+        &self.heartbeat
+    }
+
+    /// Seconds elapsed since the first call to `poll` or `receive`.
+    pub fn uptime(&self, now_usec: CyphalMicrosecond) -> u32 {
+        match self.started_usec {
+            Some(x) => (now_usec.saturating_sub(x) / 1_000_000) as u32,
+            None => 0,
+        }
+    }
+
+    /// Monotonic time in the time base shared with the drivers, as used by `spin_once`.
+    pub fn now_usec(&self) -> CyphalMicrosecond {
+        monotonic_usec()
+    }
+
+    /// Subscribes to a subject and dispatches its transfers to the handler.
+    pub fn subscribe<F: FnMut(&CyphalRxFrame) + 'static>(&mut self, subject_id: CyphalPortID, handler: F) -> Result<(), Box<dyn std::error::Error>> {
+        self.middleware.subscribe(CyphalTransferKind::Message, subject_id)?;
+        self.message_handlers.insert(subject_id, Box::new(handler));
+        Ok(())
+    }
+
+    pub fn unsubscribe(&mut self, subject_id: CyphalPortID) {
+        self.middleware.unsubscribe(CyphalTransferKind::Message, subject_id);
+        self.message_handlers.remove(&subject_id);
+    }
+
+    /// Serves requests of the service; the handler's return value is sent back as the response.
+    pub fn serve<F>(&mut self, service_id: CyphalPortID, handler: F) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: FnMut(&CyphalRxProps, &[u8]) -> Option<Vec<u8>> + 'static
+    {
+        self.service_server.register(&mut self.middleware, service_id, handler)
+    }
+
+    pub fn serve_typed<Req, Resp, F>(&mut self, service_id: CyphalPortID, handler: F) -> Result<(), Box<dyn std::error::Error>>
+    where
+        Req: CyphalDataType,
+        Resp: CyphalDataType,
+        F: FnMut(&CyphalRxProps, Req) -> Option<Resp> + 'static
+    {
+        self.service_server.register_typed(&mut self.middleware, service_id, handler)
+    }
+
+    pub fn unserve(&mut self, service_id: CyphalPortID) {
+        self.service_server.unregister(&mut self.middleware, service_id);
+    }
+
+    /// Receives every transfer that is not consumed by a subscription or by the node itself.
+    pub fn set_fallback_handler<F: FnMut(&CyphalRxFrame) + 'static>(&mut self, handler: F) {
+        self.fallback_handler = Some(Box::new(handler));
+    }
+
+    /// Queues a message on the subject, using the subject's transfer-ID counter in the middleware.
+    pub fn publish(&mut self, subject_id: CyphalPortID, priority: CyphalPriority, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.send(CyphalTransferKind::Message, subject_id, CYPHAL_NODE_ID_UNSET, priority, data)?;
+        Ok(())
+    }
+
+    /// Queues a message through a typed publisher handle; it shares the subject's transfer-ID counter with `publish`.
+    pub fn publish_with<T: CyphalDataType>(
+        &mut self,
+        publisher: &mut CyphalPublisher<T>,
+        message: &T,
+        now_usec: CyphalMicrosecond
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let packets: Vec<CyphalTxPacket<MTU>> = publisher.publish(&mut self.middleware, message, now_usec)?;
+        self.tx_queue.extend(packets);
+        Ok(())
+    }
+
+    /// Queues a request to the server. The outcome is collected with `take_response` once the
+    /// response has arrived or the request has timed out. The deadline counts from `now_usec`,
+    /// in the time base passed to `poll`.
+    pub fn request(
+        &mut self,
+        server_node_id: CyphalNodeID,
+        service_id: CyphalPortID,
+        data: &[u8],
+        now_usec: CyphalMicrosecond
+    ) -> Result<CyphalRequestKey, Box<dyn std::error::Error>> {
+        let (key, packets) = self.service_client.request(&mut self.middleware, server_node_id, service_id, data, now_usec)?;
+        self.tx_queue.extend(packets);
+        Ok(key)
+    }
+
+    pub fn is_request_pending(&self, key: &CyphalRequestKey) -> bool {
+        self.service_client.is_pending(key)
+    }
+
+    pub fn take_response(&mut self, key: &CyphalRequestKey) -> Option<Result<CyphalRxFrame, CyphalServiceError>> {
+        self.service_client.take_result(key)
+    }
+
+    /// Feeds one received packet; complete transfers are answered or dispatched immediately.
+    pub fn receive(&mut self, packet: &CyphalRxPacket<MTU>, now_usec: CyphalMicrosecond) -> Result<(), Box<dyn std::error::Error>> {
+        self.started_usec.get_or_insert(now_usec);
+        let frame: CyphalRxFrame = match self.middleware.accept(packet, now_usec)? {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        if let Some(detector) = self.collision_detector.as_mut() {
+            if !self.middleware.is_anonymous() {
+                detector.accept(self.middleware.node_id(), &frame, now_usec);
+            }
+        }
+        if let Some(tracker) = self.node_tracker.as_mut() {
+            tracker.accept(&frame, now_usec)?;
+        }
+        self.dispatch(&frame)
+    }
+
+    /// Returns the packets to transmit: queued transfers followed by the heartbeat and port list when due.
+    /// While the collision detector has silenced the node nothing is returned and the queued transfers
+    /// are discarded, so that they are not sent late under a node-ID that may have changed meanwhile.
+    pub fn poll(&mut self, now_usec: CyphalMicrosecond) -> Result<Vec<CyphalTxPacket<MTU>>, Box<dyn std::error::Error>> {
+        let started_usec: CyphalMicrosecond = *self.started_usec.get_or_insert(now_usec);
+        if !self.middleware.is_anonymous() {
+            if now_usec >= self.next_heartbeat_usec {
+                self.next_heartbeat_usec = std::cmp::max(self.next_heartbeat_usec, started_usec) + Heartbeat::MAX_PUBLICATION_PERIOD_USEC;
+                if self.next_heartbeat_usec <= now_usec {
+                    // Resynchronize after a stall instead of publishing a burst of heartbeats.
+                    self.next_heartbeat_usec = now_usec + Heartbeat::MAX_PUBLICATION_PERIOD_USEC;
+                }
+                self.heartbeat.uptime = self.uptime(now_usec);
+                let heartbeat: Heartbeat = Heartbeat { health: self.health(), ..self.heartbeat };
+                let data: Vec<u8> = heartbeat.serialize()?;
+                self.send(CyphalTransferKind::Message, Heartbeat::FIXED_PORT_ID, CYPHAL_NODE_ID_UNSET, CyphalPriority::Nominal, &data)?;
+            }
+            if let Some(port_list) = self.port_list.as_mut() {
+                let packets: Vec<CyphalTxPacket<MTU>> = port_list.poll(&mut self.middleware, now_usec)?;
+                self.tx_queue.extend(packets);
+            }
+        }
+        self.service_client.poll_timeouts(now_usec);
+        if let Some(tracker) = self.node_tracker.as_mut() {
+            let packets: Vec<CyphalTxPacket<MTU>> = tracker.poll(&mut self.service_client, &mut self.middleware, now_usec)?;
+            self.tx_queue.extend(packets);
+        }
+        self.middleware.cleanup_rx_sessions(now_usec);
+        let packets: Vec<CyphalTxPacket<MTU>> = std::mem::take(&mut self.tx_queue);
+        match self.collision_detector.as_ref() {
+            Some(detector) if detector.is_silenced() => Ok(vec![]),
+            _ => Ok(packets),
+        }
+    }
+
+    /// Receives from the driver for at most `timeout`, handles the packets and transmits everything that is due.
+    /// Packets are received at their driver timestamp and `poll` runs at `now_usec`, both in the `monotonic_usec`
+    /// time base; packets the driver did not timestamp are taken as received when they were read.
+    pub fn spin_once<D: CanDriver<MTU>>(&mut self, driver: &mut D, timeout: std::time::Duration) -> Result<(), Box<dyn std::error::Error>> {
+        let packets: Vec<CyphalRxPacket<MTU>> = driver.receive(timeout)?;
+        let received_usec: CyphalMicrosecond = self.now_usec();
+        for packet in packets {
+            let timestamp_usec: CyphalMicrosecond = if packet.timestamp_usec > 0 { packet.timestamp_usec } else { received_usec };
+            self.receive(&packet, timestamp_usec)?;
+        }
+        for event in driver.take_events() {
+            self.accept_bus_event(event);
+        }
+        self.tx_confirmations.extend(driver.take_tx_confirmations());
+        let packets: Vec<CyphalTxPacket<MTU>> = self.poll(self.now_usec())?;
+        if !packets.is_empty() {
+            driver.transmit(&packets)?;
+        }
+        Ok(())
+    }
+
+    fn dispatch(&mut self, frame: &CyphalRxFrame) -> Result<(), Box<dyn std::error::Error>> {
+        if frame.props.transfer_kind == CyphalTransferKind::Request &&
+            frame.props.port_id == GetInfoRequest::FIXED_PORT_ID &&
+            frame.props.destination_node_id == self.middleware.node_id()
+        {
+            let data: Vec<u8> = self.info.serialize()?;
+            let packets: Vec<CyphalTxPacket<MTU>> = self.middleware.create_response_for(&frame.props, &data)?;
+            self.tx_queue.extend(packets);
+            return Ok(());
+        }
+        if self.service_client.accept(&self.middleware, frame).is_some() {
+            return Ok(());
+        }
+        if let Some(packets) = self.service_server.respond(&mut self.middleware, frame)? {
+            self.tx_queue.extend(packets);
+            return Ok(());
+        }
+
+        let handler: Option<&mut CyphalTransferHandler> = match frame.props.transfer_kind {
+            CyphalTransferKind::Message => self.message_handlers.get_mut(&frame.props.port_id),
+            _ => None,
+        };
+        match (handler, self.fallback_handler.as_mut()) {
+            (Some(f), _) => f(frame),
+            (None, Some(f)) => f(frame),
+            (None, None) => {},
+        }
+        Ok(())
+    }
+
+    fn send(
+        &mut self,
+        transfer_kind: CyphalTransferKind,
+        port_id: CyphalPortID,
+        remote_node_id: CyphalNodeID,
+        priority: CyphalPriority,
+        data: &[u8]
+    ) -> Result<CyphalTransferID, Box<dyn std::error::Error>> {
+        let transfer_id: CyphalTransferID = self.middleware.transfer_id_for(transfer_kind, port_id, remote_node_id);
+        let props: CyphalTxProps = CyphalTxProps {
+            priority,
+            transfer_kind,
+            transfer_id,
+            port_id,
+            remote_node_id,
+        };
+        let packets: Vec<CyphalTxPacket<MTU>> = self.middleware.create_transfer_data(props, data)?;
+        self.middleware.next_transfer_id(transfer_kind, port_id, remote_node_id);
+        self.tx_queue.extend(packets);
+        Ok(transfer_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NODE_ID: CyphalNodeID = 42;
+
+    fn node() -> CyphalNode<64> {
+        let info: GetInfoResponse = GetInfoResponse { name: "test.node".to_string(), ..Default::default() };
+        CyphalNode::new(CyphalMiddleware::<64>::new(NODE_ID), info).set_port_list_enabled(false)
+    }
+
+    fn deliver(receiver: &mut CyphalMiddleware<64>, packets: &[CyphalTxPacket<64>], now_usec: CyphalMicrosecond) -> Vec<CyphalRxFrame> {
+        packets.iter()
+            .map(|x| CyphalRxPacket::<64>::from_frame(x.xid, &x.payload[..x.payload_size]).unwrap())
+            .filter_map(|x| receiver.accept(&x, now_usec).unwrap())
+            .collect()
+    }
+
+    /// Heartbeats in the packets returned by one `poll`.
+    fn heartbeats(packets: &[CyphalTxPacket<64>], now_usec: CyphalMicrosecond) -> Vec<CyphalRxData<Heartbeat>> {
+        let mut receiver: CyphalMiddleware<64> = CyphalMiddleware::new(CYPHAL_NODE_ID_UNSET);
+        receiver.subscribe(CyphalTransferKind::Message, Heartbeat::FIXED_PORT_ID).unwrap();
+        deliver(&mut receiver, packets, now_usec).iter()
+            .filter(|x| x.props.port_id == Heartbeat::FIXED_PORT_ID)
+            .map(|x| CyphalRxData { data: Heartbeat::deserialize(&x.payload).unwrap(), props: x.props })
+            .collect()
+    }
+
+    #[test]
+    fn heartbeat_is_published_once_per_second() {
+        let mut node: CyphalNode<64> = node();
+        let first: Vec<CyphalRxData<Heartbeat>> = heartbeats(&node.poll(1_000_000).unwrap(), 1_000_000);
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].props.source_node_id, NODE_ID);
+        assert!(heartbeats(&node.poll(1_999_999).unwrap(), 1_999_999).is_empty());
+        let second: Vec<CyphalRxData<Heartbeat>> = heartbeats(&node.poll(2_000_000).unwrap(), 2_000_000);
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].props.transfer_id, first[0].props.transfer_id + 1);
+    }
+
+    #[test]
+    fn heartbeat_is_resynchronized_after_a_stall() {
+        let mut node: CyphalNode<64> = node();
+        assert_eq!(node.poll(0).unwrap().len(), 1);
+        assert_eq!(node.poll(5_300_000).unwrap().len(), 1);
+        assert!(node.poll(5_900_000).unwrap().is_empty());
+        assert!(node.poll(6_200_000).unwrap().is_empty());
+        assert_eq!(node.poll(6_300_000).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn uptime_counts_from_the_first_poll() {
+        let mut node: CyphalNode<64> = node();
+        assert_eq!(node.uptime(10_000_000), 0);
+        assert_eq!(heartbeats(&node.poll(10_000_000).unwrap(), 10_000_000)[0].data.uptime, 0);
+        assert_eq!(heartbeats(&node.poll(11_000_000).unwrap(), 11_000_000)[0].data.uptime, 1);
+        assert_eq!(heartbeats(&node.poll(13_500_000).unwrap(), 13_500_000)[0].data.uptime, 3);
+        assert_eq!(node.uptime(20_999_999), 10);
+    }
+
+    #[test]
+    fn get_info_request_is_answered_with_the_transfer_id_of_the_request() {
+        let mut node: CyphalNode<64> = node();
+        let mut client: CyphalMiddleware<64> = CyphalMiddleware::new(10);
+        client.subscribe(CyphalTransferKind::Response, GetInfoRequest::FIXED_PORT_ID).unwrap();
+        let props: CyphalTxProps = CyphalTxProps {
+            priority: CyphalPriority::Nominal,
+            transfer_kind: CyphalTransferKind::Request,
+            transfer_id: 7,
+            port_id: GetInfoRequest::FIXED_PORT_ID,
+            remote_node_id: NODE_ID,
+        };
+        for packet in client.create_transfer_data(props, &[]).unwrap() {
+            node.receive(&CyphalRxPacket::<64>::from_frame(packet.xid, &packet.payload[..packet.payload_size]).unwrap(), 0).unwrap();
+        }
+
+        let responses: Vec<CyphalRxFrame> = deliver(&mut client, &node.poll(0).unwrap(), 0).into_iter()
+            .filter(|x| x.props.transfer_kind == CyphalTransferKind::Response)
+            .collect();
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].props.port_id, GetInfoRequest::FIXED_PORT_ID);
+        assert_eq!(responses[0].props.source_node_id, NODE_ID);
+        assert_eq!(responses[0].props.transfer_id, 7);
+        let info: GetInfoResponse = GetInfoResponse::deserialize(&responses[0].payload).unwrap();
+        assert_eq!(info.name, "test.node");
+        assert_eq!(info.protocol_version.major, CYPHAL_SPECIFICATION_VERSION_MAJOR);
+    }
+
+    #[test]
+    fn get_info_request_to_another_node_is_not_answered() {
+        let mut node: CyphalNode<64> = CyphalNode::new(CyphalMiddleware::<64>::new(NODE_ID).set_monitor_mode(true), GetInfoResponse::default())
+            .set_port_list_enabled(false);
+        let mut client: CyphalMiddleware<64> = CyphalMiddleware::new(10);
+        let props: CyphalTxProps = CyphalTxProps {
+            priority: CyphalPriority::Nominal,
+            transfer_kind: CyphalTransferKind::Request,
+            transfer_id: 0,
+            port_id: GetInfoRequest::FIXED_PORT_ID,
+            remote_node_id: NODE_ID + 1,
+        };
+        for packet in client.create_transfer_data(props, &[]).unwrap() {
+            node.receive(&CyphalRxPacket::<64>::from_frame(packet.xid, &packet.payload[..packet.payload_size]).unwrap(), 0).unwrap();
+        }
+        // Only the heartbeat.
+        assert_eq!(node.poll(0).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn publish_and_publisher_handles_share_the_transfer_id_of_the_subject() {
+        let mut node: CyphalNode<64> = node();
+        let mut publisher: CyphalPublisher<Heartbeat> = CyphalPublisher::new(100).unwrap();
+        let message: Heartbeat = *node.heartbeat();
+        node.publish(100, CyphalPriority::Nominal, &message.serialize().unwrap()).unwrap();
+        node.publish_with(&mut publisher, &message, 0).unwrap();
+        node.publish(100, CyphalPriority::Nominal, &message.serialize().unwrap()).unwrap();
+
+        let mut receiver: CyphalMiddleware<64> = CyphalMiddleware::new(CYPHAL_NODE_ID_UNSET);
+        receiver.subscribe(CyphalTransferKind::Message, 100).unwrap();
+        let frames: Vec<CyphalRxFrame> = deliver(&mut receiver, &node.poll(0).unwrap(), 0);
+        let transfer_ids: Vec<CyphalTransferID> = frames.iter().filter(|x| x.props.port_id == 100).map(|x| x.props.transfer_id).collect();
+        assert_eq!(transfer_ids, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn node_tracker_requests_get_info_through_the_node() {
+        let mut node: CyphalNode<64> = node().set_node_tracker(CyphalNodeTracker::new().set_fetch_info(true));
+        let mut remote: CyphalNode<64> = CyphalNode::new(CyphalMiddleware::<64>::new(5), GetInfoResponse::default()).set_port_list_enabled(false);
+        for packet in remote.poll(0).unwrap() {
+            node.receive(&CyphalRxPacket::<64>::from_frame(packet.xid, &packet.payload[..packet.payload_size]).unwrap(), 0).unwrap();
+        }
+        assert_eq!(node.node_tracker_mut().unwrap().take_events(), vec![CyphalNodeEvent::Online(5)]);
+
+        for packet in node.poll(0).unwrap() {
+            remote.receive(&CyphalRxPacket::<64>::from_frame(packet.xid, &packet.payload[..packet.payload_size]).unwrap(), 0).unwrap();
+        }
+        for packet in remote.poll(1).unwrap() {
+            node.receive(&CyphalRxPacket::<64>::from_frame(packet.xid, &packet.payload[..packet.payload_size]).unwrap(), 1).unwrap();
+        }
+        node.poll(1).unwrap();
+        assert!(node.node_tracker().unwrap().get(5).unwrap().info.is_some());
+    }
+}
@@ -0,0 +1,481 @@
+//! Plug-and-play node-ID allocation (uavcan.pnp.NodeIDAllocationData).
+
+use super::CyphalMiddleware;
+use super::defines::*;
+use super::uavcan::CyphalDataType;
+use super::uavcan::node::Heartbeat;
+use super::uavcan::pnp::*;
+
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Upper bound of the randomized interval between two allocation requests.
+pub const PNP_REQUEST_PERIOD_USEC: CyphalMicrosecond = 1_000_000;
+
+/// The two highest node-IDs are reserved for diagnostic and maintenance tools and are never allocated.
+pub const PNP_NODE_ID_MAX: CyphalNodeID = CYPHAL_NODE_ID_MAX - 2;
+
+/// Allocatee side of the plug-and-play protocol.
+/// Classic CAN instances use NodeIDAllocationData.1.0, CAN FD instances use NodeIDAllocationData.2.0.
+/// The middleware passed to `poll` must be anonymous; once a response is accepted, `apply` assigns the granted node-ID.
+pub struct CyphalPnpAllocatee {
+    unique_id: UniqueID,
+    preferred_node_id: Option<CyphalNodeID>,
+    allocated_node_id: Option<CyphalNodeID>,
+    next_request_usec: Option<CyphalMicrosecond>,
+    prng_state: u64,
+}
+
+impl CyphalPnpAllocatee {
+    pub fn new(unique_id: UniqueID) -> Self {
+        Self {
+            unique_id,
+            preferred_node_id: None,
+            allocated_node_id: None,
+            next_request_usec: None,
+            prng_state: crate::cyphal::crc64we(&unique_id) | 1,
+        }
+    }
+
+    /// Node-ID requested from the allocator. Only NodeIDAllocationData.2.0 requests carry the preference.
+    pub fn set_preferred_node_id(mut self, node_id: CyphalNodeID) -> Self {
+        self.preferred_node_id = if node_id <= CYPHAL_NODE_ID_MAX { Some(node_id) } else { None };
+        self
+    }
+
+    pub fn unique_id(&self) -> &UniqueID {
+        &self.unique_id
+    }
+
+    pub fn allocated_node_id(&self) -> Option<CyphalNodeID> {
+        self.allocated_node_id
+    }
+
+    /// Publishes an allocation request when one is due. Requests are spaced by a random delay
+    /// within PNP_REQUEST_PERIOD_USEC so that several allocatees started together do not collide.
+    pub fn poll<const MTU: usize>(
+        &mut self,
+        middleware: &mut CyphalMiddleware<MTU>,
+        now_usec: CyphalMicrosecond
+    ) -> Result<Vec<CyphalTxPacket<MTU>>, Box<dyn std::error::Error>> {
+        if self.allocated_node_id.is_some() {
+            return Ok(vec![]);
+        }
+        if !middleware.is_anonymous() {
+            return Err("NODE ID ALREADY ASSIGNED".into());
+        }
+
+        let next_request_usec: CyphalMicrosecond = match self.next_request_usec {
+            Some(x) => x,
+            None => {
+                let x: CyphalMicrosecond = now_usec + self.random_delay_usec();
+                self.next_request_usec = Some(x);
+                x
+            }
+        };
+        if now_usec < next_request_usec {
+            return Ok(vec![]);
+        }
+        self.next_request_usec = Some(now_usec + self.random_delay_usec());
+
+        let (port_id, data): (CyphalPortID, Vec<u8>) = if MTU > CYPHAL_MTU_CAN_CLASSIC as usize {
+            let request: NodeIDAllocationDataV2 = NodeIDAllocationDataV2 {
+                node_id: self.preferred_node_id.unwrap_or(CYPHAL_NODE_ID_MAX),
+                unique_id: self.unique_id,
+            };
+            (NodeIDAllocationDataV2::FIXED_PORT_ID, request.serialize()?)
+        } else {
+            // Requests leave allocated_node_id empty: with a node-ID the payload no longer fits the single
+            // frame an anonymous transfer is limited to on classic CAN.
+            let request: NodeIDAllocationDataV1 = NodeIDAllocationDataV1 {
+                unique_id_hash: NodeIDAllocationDataV1::unique_id_hash(&self.unique_id),
+                allocated_node_id: None,
+            };
+            (NodeIDAllocationDataV1::FIXED_PORT_ID, request.serialize()?)
+        };
+        middleware.create_message_data(port_id, &data, data.len())
+    }
+
+    /// Consumes a reassembled transfer and returns the granted node-ID if it is the allocator's response to us.
+    pub fn accept(&mut self, frame: &CyphalRxFrame) -> Option<CyphalNodeID> {
+        // Responses are published by the allocator under its own node-ID; anonymous transfers are requests of other allocatees.
+        if frame.props.transfer_kind != CyphalTransferKind::Message || frame.props.source_node_id > CYPHAL_NODE_ID_MAX {
+            return None;
+        }
+
+        let granted: Option<CyphalNodeID> = match frame.props.port_id {
+            NodeIDAllocationDataV1::FIXED_PORT_ID => match NodeIDAllocationDataV1::deserialize(&frame.payload) {
+                Ok(x) if x.unique_id_hash == NodeIDAllocationDataV1::unique_id_hash(&self.unique_id) => x.allocated_node_id,
+                _ => None,
+            },
+            NodeIDAllocationDataV2::FIXED_PORT_ID => match NodeIDAllocationDataV2::deserialize(&frame.payload) {
+                Ok(x) if x.unique_id == self.unique_id => Some(x.node_id),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        if self.allocated_node_id.is_none() {
+            self.allocated_node_id = granted;
+        }
+        granted
+    }
+
+    /// Assigns the granted node-ID to the middleware. The middleware is returned unchanged while allocation is pending.
+    pub fn apply<const MTU: usize>(&self, middleware: CyphalMiddleware<MTU>) -> CyphalMiddleware<MTU> {
+        match self.allocated_node_id {
+            Some(node_id) => middleware.set_node_id(node_id),
+            None => middleware,
+        }
+    }
+
+    fn random_delay_usec(&mut self) -> CyphalMicrosecond {
+        // xorshift64; only used to spread the requests in time.
+        self.prng_state ^= self.prng_state << 13;
+        self.prng_state ^= self.prng_state >> 7;
+        self.prng_state ^= self.prng_state << 17;
+        self.prng_state % PNP_REQUEST_PERIOD_USEC
+    }
+}
+
+/// One entry of the allocation table. Entries created from NodeIDAllocationData.1.0 requests only know the unique-ID hash.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct CyphalPnpAllocation {
+    pub unique_id_hash: u64,
+    pub unique_id: Option<UniqueID>,
+    pub node_id: CyphalNodeID,
+}
+
+/// Allocator side of the plug-and-play protocol, serving both NodeIDAllocationData.1.0 and 2.0.
+/// The allocation table is kept in memory and, if a storage path is configured, written back to disk after every change.
+pub struct CyphalPnpAllocator {
+    allocations: Vec<CyphalPnpAllocation>,
+    occupied_node_ids: BTreeSet<CyphalNodeID>,
+    storage_path: Option<PathBuf>,
+}
+
+impl Default for CyphalPnpAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CyphalPnpAllocator {
+    pub fn new() -> Self {
+        Self {
+            allocations: vec![],
+            occupied_node_ids: BTreeSet::new(),
+            storage_path: None,
+        }
+    }
+
+    /// Loads the allocation table from `path` if it exists and persists every change to it.
+    pub fn with_storage<P: AsRef<Path>>(mut self, path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let path: PathBuf = path.as_ref().to_path_buf();
+        if path.exists() {
+            let file: std::fs::File = std::fs::File::open(&path)?;
+            self.read_table(std::io::BufReader::new(file))?;
+        }
+        self.storage_path = Some(path);
+        Ok(self)
+    }
+
+    pub fn allocations(&self) -> &[CyphalPnpAllocation] {
+        &self.allocations
+    }
+
+    /// Node-IDs observed on the bus, which are never handed out unless already allocated to the same unique-ID.
+    pub fn occupied_node_ids(&self) -> &BTreeSet<CyphalNodeID> {
+        &self.occupied_node_ids
+    }
+
+    /// Consumes a reassembled transfer. Non-anonymous heartbeats mark their source node-ID as occupied;
+    /// anonymous allocation requests are answered with the response packets to transmit.
+    pub fn accept<const MTU: usize>(
+        &mut self,
+        middleware: &mut CyphalMiddleware<MTU>,
+        frame: &CyphalRxFrame
+    ) -> Result<Vec<CyphalTxPacket<MTU>>, Box<dyn std::error::Error>> {
+        if frame.props.transfer_kind != CyphalTransferKind::Message {
+            return Ok(vec![]);
+        }
+        if frame.props.source_node_id <= CYPHAL_NODE_ID_MAX {
+            if frame.props.port_id == Heartbeat::FIXED_PORT_ID {
+                self.occupied_node_ids.insert(frame.props.source_node_id);
+            }
+            return Ok(vec![]);
+        }
+        if middleware.is_anonymous() {
+            return Err("ALLOCATOR REQUIRES A NODE ID".into());
+        }
+
+        match frame.props.port_id {
+            NodeIDAllocationDataV1::FIXED_PORT_ID => {
+                let request: NodeIDAllocationDataV1 = NodeIDAllocationDataV1::deserialize(&frame.payload)?;
+                let node_id: CyphalNodeID = match self.find_by_hash(request.unique_id_hash) {
+                    Some(x) => x.node_id,
+                    None => self.allocate(middleware.node_id(), request.unique_id_hash, None, request.allocated_node_id)?,
+                };
+                let response: NodeIDAllocationDataV1 = NodeIDAllocationDataV1 {
+                    unique_id_hash: request.unique_id_hash,
+                    allocated_node_id: Some(node_id),
+                };
+                let data: Vec<u8> = response.serialize()?;
+                middleware.create_message_data(NodeIDAllocationDataV1::FIXED_PORT_ID, &data, data.len())
+            },
+            NodeIDAllocationDataV2::FIXED_PORT_ID => {
+                let request: NodeIDAllocationDataV2 = NodeIDAllocationDataV2::deserialize(&frame.payload)?;
+                let unique_id_hash: u64 = NodeIDAllocationDataV1::unique_id_hash(&request.unique_id);
+                let node_id: CyphalNodeID = match self.find_by_unique_id(&request.unique_id)? {
+                    Some(x) => x,
+                    None => self.allocate(middleware.node_id(), unique_id_hash, Some(request.unique_id), Some(request.node_id))?,
+                };
+                let response: NodeIDAllocationDataV2 = NodeIDAllocationDataV2 {
+                    node_id,
+                    unique_id: request.unique_id,
+                };
+                let data: Vec<u8> = response.serialize()?;
+                middleware.create_message_data(NodeIDAllocationDataV2::FIXED_PORT_ID, &data, data.len())
+            },
+            _ => Ok(vec![]),
+        }
+    }
+
+    /// Reads an allocation table written by `write_table`.
+    pub fn read_table<R: BufRead>(&mut self, reader: R) -> Result<(), Box<dyn std::error::Error>> {
+        for line in reader.lines() {
+            let line: String = line?;
+            let line: &str = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 3 {
+                return Err("INVALID ALLOCATION TABLE".into());
+            }
+            let unique_id_hash: u64 = u64::from_str_radix(fields[0], 16)?;
+            let unique_id: Option<UniqueID> = match fields[1] {
+                "-" => None,
+                x => Some(Self::parse_unique_id(x)?),
+            };
+            let node_id: CyphalNodeID = fields[2].parse()?;
+            if node_id > CYPHAL_NODE_ID_MAX {
+                return Err("INVALID NODE ID".into());
+            }
+            self.allocations.retain(|a| a.node_id != node_id);
+            self.allocations.push(CyphalPnpAllocation { unique_id_hash, unique_id, node_id });
+        }
+        Ok(())
+    }
+
+    /// Writes the allocation table as one `<hash> <unique-ID or -> <node-ID>` line per entry.
+    pub fn write_table<W: Write>(&self, mut writer: W) -> Result<(), Box<dyn std::error::Error>> {
+        writeln!(writer, "# unique_id_hash unique_id node_id")?;
+        for a in &self.allocations {
+            let unique_id: String = match a.unique_id {
+                Some(x) => x.iter().map(|b| format!("{:02x}", b)).collect(),
+                None => String::from("-"),
+            };
+            writeln!(writer, "{:012x} {} {}", a.unique_id_hash, unique_id, a.node_id)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn find_by_hash(&self, unique_id_hash: u64) -> Option<&CyphalPnpAllocation> {
+        self.allocations.iter().find(|a| a.unique_id_hash == unique_id_hash)
+    }
+
+    /// Returns the node-ID allocated to the unique-ID.
+    fn find_by_unique_id(&mut self, unique_id: &UniqueID) -> Result<Option<CyphalNodeID>, Box<dyn std::error::Error>> {
+        let unique_id_hash: u64 = NodeIDAllocationDataV1::unique_id_hash(unique_id);
+        let allocation: &mut CyphalPnpAllocation = match self.allocations.iter_mut()
+            .find(|a| a.unique_id == Some(*unique_id) || (a.unique_id.is_none() && a.unique_id_hash == unique_id_hash)) {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        let node_id: CyphalNodeID = allocation.node_id;
+        // An entry created by a v1 request learns the full unique-ID once the node asks again over v2.
+        if allocation.unique_id.is_none() {
+            allocation.unique_id = Some(*unique_id);
+            self.store()?;
+        }
+        Ok(Some(node_id))
+    }
+
+    /// Picks the preferred node-ID if free, otherwise the closest free one searching upwards first, then downwards.
+    fn allocate(
+        &mut self,
+        local_node_id: CyphalNodeID,
+        unique_id_hash: u64,
+        unique_id: Option<UniqueID>,
+        preferred_node_id: Option<CyphalNodeID>
+    ) -> Result<CyphalNodeID, Box<dyn std::error::Error>> {
+        let is_free = |x: CyphalNodeID| -> bool {
+            x != local_node_id &&
+            !self.occupied_node_ids.contains(&x) &&
+            !self.allocations.iter().any(|a| a.node_id == x)
+        };
+        let preferred: CyphalNodeID = std::cmp::min(preferred_node_id.unwrap_or(PNP_NODE_ID_MAX), PNP_NODE_ID_MAX);
+        let node_id: CyphalNodeID = match (preferred..=PNP_NODE_ID_MAX).chain((0..preferred).rev()).find(|x| is_free(*x)) {
+            Some(x) => x,
+            None => return Err("NO FREE NODE ID".into()),
+        };
+
+        self.allocations.push(CyphalPnpAllocation { unique_id_hash, unique_id, node_id });
+        self.store()?;
+        Ok(node_id)
+    }
+
+    /// Writes the allocation table to the storage path, if one is configured.
+    fn store(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(path) = &self.storage_path {
+            let file: std::fs::File = std::fs::File::create(path)?;
+            self.write_table(std::io::BufWriter::new(file))?;
+        }
+        Ok(())
+    }
+
+    fn parse_unique_id(x: &str) -> Result<UniqueID, Box<dyn std::error::Error>> {
+        if x.len() != 32 || !x.is_ascii() {
+            return Err("INVALID UNIQUE ID".into());
+        }
+        let mut ret: UniqueID = [0; 16];
+        for (i, v) in ret.iter_mut().enumerate() {
+            *v = u8::from_str_radix(&x[2 * i..2 * i + 2], 16)?;
+        }
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNIQUE_ID: UniqueID = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF, 0x00];
+    const ALLOCATOR_NODE_ID: CyphalNodeID = 1;
+
+    fn deliver<const MTU: usize>(receiver: &mut CyphalMiddleware<MTU>, packets: &[CyphalTxPacket<MTU>]) -> Vec<CyphalRxFrame> {
+        packets.iter()
+            .map(|x| CyphalRxPacket::<MTU>::from_frame(x.xid, &x.payload[..x.payload_size]).unwrap())
+            .filter_map(|x| receiver.accept(&x, 0).unwrap())
+            .collect()
+    }
+
+    /// The first request of an allocatee, which is sent within one request period.
+    fn request<const MTU: usize>(allocatee: &mut CyphalPnpAllocatee, middleware: &mut CyphalMiddleware<MTU>) -> Vec<CyphalTxPacket<MTU>> {
+        assert!(allocatee.poll(middleware, 0).unwrap().is_empty());
+        let packets: Vec<CyphalTxPacket<MTU>> = allocatee.poll(middleware, PNP_REQUEST_PERIOD_USEC).unwrap();
+        assert!(!packets.is_empty());
+        packets
+    }
+
+    /// Runs one request and response between an allocatee and an allocator and returns the middleware of the allocatee.
+    fn allocate<const MTU: usize>(allocatee: &mut CyphalPnpAllocatee, allocator: &mut CyphalPnpAllocator) -> CyphalMiddleware<MTU> {
+        let mut node: CyphalMiddleware<MTU> = CyphalMiddleware::new(CYPHAL_NODE_ID_UNSET);
+        let mut server: CyphalMiddleware<MTU> = CyphalMiddleware::new(ALLOCATOR_NODE_ID);
+        let requests: Vec<CyphalRxFrame> = deliver(&mut server, &request(allocatee, &mut node));
+        assert_eq!(requests.len(), 1);
+        let responses: Vec<CyphalTxPacket<MTU>> = allocator.accept(&mut server, &requests[0]).unwrap();
+        for frame in deliver(&mut node, &responses) {
+            allocatee.accept(&frame);
+        }
+        allocatee.apply(node)
+    }
+
+    #[test]
+    fn classic_allocatee_is_served_over_v1() {
+        let mut allocatee: CyphalPnpAllocatee = CyphalPnpAllocatee::new(UNIQUE_ID).set_preferred_node_id(42);
+        let mut node: CyphalMiddleware<8> = CyphalMiddleware::new(CYPHAL_NODE_ID_UNSET);
+        let packets: Vec<CyphalTxPacket<8>> = request(&mut allocatee, &mut node);
+        let frames: Vec<CyphalRxFrame> = deliver(&mut CyphalMiddleware::<8>::new(ALLOCATOR_NODE_ID), &packets);
+        assert_eq!(frames[0].props.port_id, NodeIDAllocationDataV1::FIXED_PORT_ID);
+        assert_eq!(frames[0].props.source_node_id, CYPHAL_NODE_ID_UNSET);
+        let request: NodeIDAllocationDataV1 = NodeIDAllocationDataV1::deserialize(&frames[0].payload).unwrap();
+        assert_eq!(request.unique_id_hash, NodeIDAllocationDataV1::unique_id_hash(&UNIQUE_ID));
+        assert_eq!(request.allocated_node_id, None);
+
+        // v1 requests cannot carry the preference, so the allocator starts from the top.
+        let mut allocator: CyphalPnpAllocator = CyphalPnpAllocator::new();
+        let mut allocatee: CyphalPnpAllocatee = CyphalPnpAllocatee::new(UNIQUE_ID).set_preferred_node_id(42);
+        let node: CyphalMiddleware<8> = allocate(&mut allocatee, &mut allocator);
+        assert_eq!(node.node_id(), PNP_NODE_ID_MAX);
+        assert_eq!(allocatee.allocated_node_id(), Some(PNP_NODE_ID_MAX));
+        assert_eq!(allocator.allocations()[0].unique_id, None);
+    }
+
+    #[test]
+    fn fd_allocatee_gets_its_preferred_node_id_over_v2() {
+        let mut allocator: CyphalPnpAllocator = CyphalPnpAllocator::new();
+        let mut allocatee: CyphalPnpAllocatee = CyphalPnpAllocatee::new(UNIQUE_ID).set_preferred_node_id(42);
+        let node: CyphalMiddleware<64> = allocate(&mut allocatee, &mut allocator);
+        assert_eq!(node.node_id(), 42);
+        assert_eq!(allocator.allocations(), &[CyphalPnpAllocation {
+            unique_id_hash: NodeIDAllocationDataV1::unique_id_hash(&UNIQUE_ID),
+            unique_id: Some(UNIQUE_ID),
+            node_id: 42,
+        }]);
+
+        // The same node asking again keeps its node-ID; another one gets the next free one.
+        let mut allocatee: CyphalPnpAllocatee = CyphalPnpAllocatee::new(UNIQUE_ID).set_preferred_node_id(10);
+        assert_eq!(allocate::<64>(&mut allocatee, &mut allocator).node_id(), 42);
+        let mut allocatee: CyphalPnpAllocatee = CyphalPnpAllocatee::new([7; 16]).set_preferred_node_id(42);
+        assert_eq!(allocate::<64>(&mut allocatee, &mut allocator).node_id(), 43);
+    }
+
+    #[test]
+    fn occupied_node_ids_are_skipped() {
+        let mut allocator: CyphalPnpAllocator = CyphalPnpAllocator::new();
+        let mut server: CyphalMiddleware<64> = CyphalMiddleware::new(ALLOCATOR_NODE_ID);
+        let mut other: CyphalMiddleware<64> = CyphalMiddleware::new(42);
+        for frame in deliver(&mut server, &other.create_heartbeat_tx_data().unwrap()) {
+            assert!(allocator.accept(&mut server, &frame).unwrap().is_empty());
+        }
+        assert!(allocator.occupied_node_ids().contains(&42));
+
+        let mut allocatee: CyphalPnpAllocatee = CyphalPnpAllocatee::new(UNIQUE_ID).set_preferred_node_id(42);
+        assert_eq!(allocate::<64>(&mut allocatee, &mut allocator).node_id(), 43);
+    }
+
+    #[test]
+    fn response_for_another_unique_id_is_ignored() {
+        let mut allocatee: CyphalPnpAllocatee = CyphalPnpAllocatee::new(UNIQUE_ID);
+        let mut server: CyphalMiddleware<64> = CyphalMiddleware::new(ALLOCATOR_NODE_ID);
+        let mut node: CyphalMiddleware<64> = CyphalMiddleware::new(CYPHAL_NODE_ID_UNSET);
+        for (port_id, data) in [
+            (NodeIDAllocationDataV2::FIXED_PORT_ID, NodeIDAllocationDataV2 { node_id: 42, unique_id: [7; 16] }.serialize().unwrap()),
+            (NodeIDAllocationDataV1::FIXED_PORT_ID, NodeIDAllocationDataV1 {
+                unique_id_hash: NodeIDAllocationDataV1::unique_id_hash(&[7; 16]),
+                allocated_node_id: Some(42),
+            }.serialize().unwrap()),
+        ] {
+            for frame in deliver(&mut node, &server.create_message_data(port_id, &data, data.len()).unwrap()) {
+                assert_eq!(allocatee.accept(&frame), None);
+            }
+        }
+        assert_eq!(allocatee.allocated_node_id(), None);
+        assert_eq!(allocatee.apply(node).node_id(), CYPHAL_NODE_ID_UNSET);
+    }
+
+    #[test]
+    fn v1_entry_is_upgraded_and_stored() {
+        let path: PathBuf = std::env::temp_dir().join(format!("cands_transport_pnp_{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut allocator: CyphalPnpAllocator = CyphalPnpAllocator::new().with_storage(&path).unwrap();
+        let mut allocatee: CyphalPnpAllocatee = CyphalPnpAllocatee::new(UNIQUE_ID);
+        let node_id: CyphalNodeID = allocate::<8>(&mut allocatee, &mut allocator).node_id();
+
+        // The node moves to CAN FD and asks over v2 with the full unique-ID.
+        let mut allocatee: CyphalPnpAllocatee = CyphalPnpAllocatee::new(UNIQUE_ID).set_preferred_node_id(10);
+        assert_eq!(allocate::<64>(&mut allocatee, &mut allocator).node_id(), node_id);
+
+        let restored: CyphalPnpAllocator = CyphalPnpAllocator::new().with_storage(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(restored.allocations(), &[CyphalPnpAllocation {
+            unique_id_hash: NodeIDAllocationDataV1::unique_id_hash(&UNIQUE_ID),
+            unique_id: Some(UNIQUE_ID),
+            node_id,
+        }]);
+    }
+}
@@ -0,0 +1,128 @@
+//! uavcan.node.port.List introspection.
+
+use super::CyphalMiddleware;
+use super::defines::*;
+use super::uavcan::CyphalDataType;
+use super::uavcan::node::PortList;
+
+/// Publishes the ports recorded by the middleware as uavcan.node.port.List.
+/// The list is sent every MAX_PUBLICATION_PERIOD_USEC and as soon as possible after the port set changes,
+/// but never more often than `min_period_usec`.
+pub struct CyphalPortListPublisher {
+    min_period_usec: CyphalMicrosecond,
+    last_publication_usec: Option<CyphalMicrosecond>,
+    last_ports: Option<CyphalPorts>,
+}
+
+impl Default for CyphalPortListPublisher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CyphalPortListPublisher {
+    pub fn new() -> Self {
+        Self {
+            min_period_usec: 1_000_000,
+            last_publication_usec: None,
+            last_ports: None,
+        }
+    }
+
+    pub fn set_min_period_usec(mut self, min_period_usec: CyphalMicrosecond) -> Self {
+        self.min_period_usec = std::cmp::min(min_period_usec, PortList::MAX_PUBLICATION_PERIOD_USEC);
+        self
+    }
+
+    pub fn poll<const MTU: usize>(
+        &mut self,
+        middleware: &mut CyphalMiddleware<MTU>,
+        now_usec: CyphalMicrosecond
+    ) -> Result<Vec<CyphalTxPacket<MTU>>, Box<dyn std::error::Error>> {
+        if middleware.is_anonymous() {
+            return Ok(vec![]);
+        }
+        // The list itself is published on port.List; count it before comparing with the last publication.
+        let mut ports: CyphalPorts = middleware.ports().clone();
+        ports.record(CyphalTransferKind::Message, PortList::FIXED_PORT_ID);
+
+        let elapsed_usec: Option<CyphalMicrosecond> = self.last_publication_usec.map(|x| now_usec.saturating_sub(x));
+        let changed: bool = self.last_ports.as_ref() != Some(&ports);
+        let due: bool = match elapsed_usec {
+            None => true,
+            Some(x) => x >= PortList::MAX_PUBLICATION_PERIOD_USEC || (changed && x >= self.min_period_usec),
+        };
+        if !due {
+            return Ok(vec![]);
+        }
+
+        let message: PortList = PortList::from(&ports);
+        let props: CyphalTxProps = CyphalTxProps {
+            priority: CyphalPriority::Optional,
+            transfer_kind: CyphalTransferKind::Message,
+            transfer_id: middleware.transfer_id_for(CyphalTransferKind::Message, PortList::FIXED_PORT_ID, CYPHAL_NODE_ID_UNSET),
+            port_id: PortList::FIXED_PORT_ID,
+            remote_node_id: CYPHAL_NODE_ID_UNSET,
+        };
+        let packets: Vec<CyphalTxPacket<MTU>> = middleware.create_transfer_data(props, &message.serialize()?)?;
+        middleware.next_transfer_id(CyphalTransferKind::Message, PortList::FIXED_PORT_ID, CYPHAL_NODE_ID_UNSET);
+        self.last_publication_usec = Some(now_usec);
+        self.last_ports = Some(ports);
+        Ok(packets)
+    }
+
+    /// Decodes the port list published by another node.
+    pub fn decode(frame: &CyphalRxFrame) -> Option<Result<PortList, Box<dyn std::error::Error>>> {
+        if frame.props.transfer_kind != CyphalTransferKind::Message || frame.props.port_id != PortList::FIXED_PORT_ID {
+            return None;
+        }
+        Some(PortList::deserialize(&frame.payload))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::uavcan::node::SubjectIDList;
+
+    const MTU: usize = CYPHAL_MTU_CAN_FD as usize;
+
+    fn decode(middleware: &mut CyphalMiddleware<MTU>, packets: &[CyphalTxPacket<MTU>]) -> PortList {
+        let frames: Vec<CyphalRxFrame> = packets.iter()
+            .map(|x| CyphalRxPacket::<MTU>::from_frame(x.xid, &x.payload[..x.payload_size]).unwrap())
+            .filter_map(|x| middleware.accept(&x, 0).unwrap())
+            .collect();
+        CyphalPortListPublisher::decode(&frames[0]).unwrap().unwrap()
+    }
+
+    #[test]
+    fn anonymous_node_does_not_record_the_list() {
+        let mut publisher: CyphalPortListPublisher = CyphalPortListPublisher::new();
+        let mut middleware: CyphalMiddleware<MTU> = CyphalMiddleware::new(CYPHAL_NODE_ID_UNSET);
+        assert!(publisher.poll(&mut middleware, 0).unwrap().is_empty());
+        assert!(middleware.ports().publishers.is_empty());
+
+        let mut middleware: CyphalMiddleware<MTU> = middleware.set_node_id(5);
+        let packets: Vec<CyphalTxPacket<MTU>> = publisher.poll(&mut middleware, 0).unwrap();
+        assert!(!packets.is_empty());
+        assert!(middleware.ports().publishers.contains(&PortList::FIXED_PORT_ID));
+        let list: PortList = decode(&mut CyphalMiddleware::new(6), &packets);
+        assert_eq!(list.publishers, SubjectIDList::Set([PortList::FIXED_PORT_ID].into_iter().collect()));
+    }
+
+    #[test]
+    fn change_is_published_after_the_minimum_period() {
+        let mut publisher: CyphalPortListPublisher = CyphalPortListPublisher::new().set_min_period_usec(100_000);
+        let mut middleware: CyphalMiddleware<MTU> = CyphalMiddleware::new(5);
+        assert!(!publisher.poll(&mut middleware, 0).unwrap().is_empty());
+        assert!(publisher.poll(&mut middleware, 200_000).unwrap().is_empty());
+
+        middleware.subscribe(CyphalTransferKind::Message, 1000).unwrap();
+        assert!(!publisher.poll(&mut middleware, 250_000).unwrap().is_empty());
+        middleware.subscribe(CyphalTransferKind::Message, 1001).unwrap();
+        assert!(publisher.poll(&mut middleware, 300_000).unwrap().is_empty());
+        assert!(!publisher.poll(&mut middleware, 350_000).unwrap().is_empty());
+        assert!(publisher.poll(&mut middleware, 400_000).unwrap().is_empty());
+        assert!(!publisher.poll(&mut middleware, 350_000 + PortList::MAX_PUBLICATION_PERIOD_USEC).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,180 @@
+//! Typed publisher and subscriber handles.
+
+use super::CyphalMiddleware;
+use super::defines::*;
+use super::uavcan::CyphalDataType;
+
+use std::marker::PhantomData;
+
+/// Publishes messages of type `T` on one subject.
+/// The handle owns the subject-ID, priority and transmission timeout, which are validated on construction;
+/// transfer-IDs come from the middleware's counter of the subject.
+pub struct CyphalPublisher<T: CyphalDataType> {
+    subject_id: CyphalPortID,
+    priority: CyphalPriority,
+    tx_timeout_usec: CyphalMicrosecond,
+    _data_type: PhantomData<fn(&T)>,
+}
+
+impl <T: CyphalDataType> CyphalPublisher<T> {
+    pub fn new(subject_id: CyphalPortID) -> Result<Self, Box<dyn std::error::Error>> {
+        if subject_id > CYPHAL_SUBJECT_ID_MAX {
+            return Err("INVALID SUBJECT ID".into());
+        }
+        Ok(Self {
+            subject_id,
+            priority: CyphalPriority::Nominal,
+            tx_timeout_usec: 100_000,
+            _data_type: PhantomData,
+        })
+    }
+
+    pub fn set_priority(mut self, priority: CyphalPriority) -> Result<Self, Box<dyn std::error::Error>> {
+        if priority == CyphalPriority::Undefined {
+            return Err("INVALID PRIORITY".into());
+        }
+        self.priority = priority;
+        Ok(self)
+    }
+
+    /// Packets not transmitted within this time after `publish` are to be dropped.
+    pub fn set_tx_timeout_usec(mut self, tx_timeout_usec: CyphalMicrosecond) -> Self {
+        self.tx_timeout_usec = tx_timeout_usec;
+        self
+    }
+
+    pub fn subject_id(&self) -> CyphalPortID {
+        self.subject_id
+    }
+
+    pub fn priority(&self) -> CyphalPriority {
+        self.priority
+    }
+
+    /// Serializes the message and returns its packets with the transmission deadline set.
+    pub fn publish<const MTU: usize>(
+        &mut self,
+        middleware: &mut CyphalMiddleware<MTU>,
+        message: &T,
+        now_usec: CyphalMicrosecond
+    ) -> Result<Vec<CyphalTxPacket<MTU>>, Box<dyn std::error::Error>> {
+        let props: CyphalTxProps = CyphalTxProps {
+            priority: self.priority,
+            transfer_kind: CyphalTransferKind::Message,
+            transfer_id: middleware.transfer_id_for(CyphalTransferKind::Message, self.subject_id, CYPHAL_NODE_ID_UNSET),
+            port_id: self.subject_id,
+            remote_node_id: CYPHAL_NODE_ID_UNSET,
+        };
+        let mut packets: Vec<CyphalTxPacket<MTU>> = middleware.create_transfer_data(props, &message.serialize()?)?;
+        for packet in packets.iter_mut() {
+            packet.deadline_usec = Some(now_usec.saturating_add(self.tx_timeout_usec));
+        }
+        middleware.next_transfer_id(CyphalTransferKind::Message, self.subject_id, CYPHAL_NODE_ID_UNSET);
+        Ok(packets)
+    }
+}
+
+/// Receives messages of type `T` from one subject.
+pub struct CyphalSubscriber<T: CyphalDataType> {
+    subject_id: CyphalPortID,
+    _data_type: PhantomData<fn() -> T>,
+}
+
+impl <T: CyphalDataType> CyphalSubscriber<T> {
+    /// Creates the handle and registers the subscription with the middleware.
+    pub fn new<const MTU: usize>(middleware: &mut CyphalMiddleware<MTU>, subject_id: CyphalPortID) -> Result<Self, Box<dyn std::error::Error>> {
+        middleware.subscribe(CyphalTransferKind::Message, subject_id)?;
+        Ok(Self { subject_id, _data_type: PhantomData })
+    }
+
+    pub fn subject_id(&self) -> CyphalPortID {
+        self.subject_id
+    }
+
+    /// Decodes the transfer if it is a message on this subject; other transfers yield None.
+    pub fn accept(&self, frame: &CyphalRxFrame) -> Option<Result<CyphalRxData<T>, Box<dyn std::error::Error>>> {
+        if frame.props.transfer_kind != CyphalTransferKind::Message || frame.props.port_id != self.subject_id {
+            return None;
+        }
+        Some(T::deserialize(&frame.payload).map(|data| CyphalRxData { data, props: frame.props }))
+    }
+
+    /// Removes the subscription from the middleware.
+    pub fn close<const MTU: usize>(self, middleware: &mut CyphalMiddleware<MTU>) {
+        middleware.unsubscribe(CyphalTransferKind::Message, self.subject_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::uavcan::node::{Heartbeat, PortList};
+
+    fn deliver<const MTU: usize>(receiver: &mut CyphalMiddleware<MTU>, packets: &[CyphalTxPacket<MTU>]) -> Vec<CyphalRxFrame> {
+        packets.iter()
+            .map(|x| CyphalRxPacket::<MTU>::from_frame(x.xid, &x.payload[..x.payload_size]).unwrap())
+            .filter_map(|x| receiver.accept(&x, 0).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn subscriber_ignores_service_transfers_seen_in_monitor_mode() {
+        let mut sender: CyphalMiddleware<8> = CyphalMiddleware::new(1);
+        let mut monitor: CyphalMiddleware<8> = CyphalMiddleware::new(2).set_monitor_mode(true);
+        let subscriber: CyphalSubscriber<Heartbeat> = CyphalSubscriber::new(&mut monitor, 100).unwrap();
+
+        // A request to another node on a service-ID equal to the subject-ID.
+        let frames: Vec<CyphalRxFrame> = deliver(&mut monitor, &sender.create_request_data(3, 100, &[0; 7], 7).unwrap());
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].props.destination_node_id, 3);
+        assert!(subscriber.accept(&frames[0]).is_none());
+
+        let mut receiver: CyphalMiddleware<8> = CyphalMiddleware::new(2);
+        assert!(deliver(&mut receiver, &sender.create_request_data(3, 100, &[0; 7], 7).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn typed_message_round_trips_through_publisher_and_subscriber() {
+        let mut sender: CyphalMiddleware<8> = CyphalMiddleware::new(1);
+        let mut receiver: CyphalMiddleware<8> = CyphalMiddleware::new(2);
+        let mut publisher: CyphalPublisher<PortList> = CyphalPublisher::new(100).unwrap()
+            .set_priority(CyphalPriority::Fast).unwrap()
+            .set_tx_timeout_usec(500);
+        let subscriber: CyphalSubscriber<PortList> = CyphalSubscriber::new(&mut receiver, 100).unwrap();
+
+        let mut ports: CyphalPorts = CyphalPorts::default();
+        ports.publishers.extend([7509, 100, 8000]);
+        ports.subscribers.extend([1, 2, 3]);
+        ports.servers.insert(430);
+        let message: PortList = PortList::from(&ports);
+
+        for transfer_id in 0..2 {
+            let packets: Vec<CyphalTxPacket<8>> = publisher.publish(&mut sender, &message, 1_000).unwrap();
+            assert!(packets.len() > 1);
+            assert!(packets.iter().all(|x| x.deadline_usec == Some(1_500)));
+
+            let frames: Vec<CyphalRxFrame> = deliver(&mut receiver, &packets);
+            assert_eq!(frames.len(), 1);
+            let received: CyphalRxData<PortList> = subscriber.accept(&frames[0]).unwrap().unwrap();
+            assert_eq!(received.data, message);
+            assert_eq!(received.props.priority, CyphalPriority::Fast);
+            assert_eq!(received.props.source_node_id, 1);
+            assert_eq!(received.props.transfer_id, transfer_id);
+        }
+    }
+
+    #[test]
+    fn publisher_rejects_invalid_subject_and_priority() {
+        assert!(CyphalPublisher::<Heartbeat>::new(CYPHAL_SUBJECT_ID_MAX + 1).is_err());
+        assert!(CyphalPublisher::<Heartbeat>::new(CYPHAL_SUBJECT_ID_MAX).unwrap().set_priority(CyphalPriority::Undefined).is_err());
+    }
+
+    #[test]
+    fn tx_packet_new_has_no_deadline_and_marker_zero() {
+        let packet: CyphalTxPacket<8> = CyphalTxPacket::new(0x1234, [1, 2, 3, 0, 0, 0, 0, 0], 3);
+        assert_eq!(packet.xid, 0x1234);
+        assert_eq!(packet.payload[..packet.payload_size], [1, 2, 3]);
+        assert_eq!(packet.deadline_usec, None);
+        assert_eq!(packet.marker, 0);
+    }
+}
@@ -1,20 +1,27 @@
 use super::CyphalMiddleware;
 use super::defines::*;
+use super::crc::crc_add;
 
 const CAN_FRAME_XID_AND_HEADER_LENGTH: usize = 8;
 
 impl <const MTU: usize> CyphalMiddleware<MTU> {
 
     pub fn try_read(&self, data: &[u8]) -> Result<Vec<CyphalRxPacket<MTU>>, Box<dyn std::error::Error>> {
+        self.try_read_timestamped(data, |_| 0)
+    }
+
+    /// Like `try_read`, stamping each packet with `timestamp` applied to the 16-bit timestamp of its RX element,
+    /// e.g. the host time from a `CanTimestampTracker`.
+    pub fn try_read_timestamped<F: Fn(u16) -> CyphalMicrosecond>(&self, data: &[u8], timestamp: F) -> Result<Vec<CyphalRxPacket<MTU>>, Box<dyn std::error::Error>> {
 
         let data_len: usize = data.len();
-        if data_len % (MTU + CAN_FRAME_XID_AND_HEADER_LENGTH) != 0 {
+        if !data_len.is_multiple_of(MTU + CAN_FRAME_XID_AND_HEADER_LENGTH) {
             return Err(String::from("INVALID DATA RECEIVED: UNACCEPTABLE FRAME LENGTH").into());
         };
 
         let vecs: Vec<Vec<u8>> = data
             .chunks(MTU + CAN_FRAME_XID_AND_HEADER_LENGTH)
-            .map(|x| Vec::from(x))
+            .map(Vec::from)
             .collect();
 
         let mut ret: Vec<CyphalRxPacket<MTU>> = vec![];
@@ -22,7 +29,7 @@ impl <const MTU: usize> CyphalMiddleware<MTU> {
         for v in vecs {
 
             // CAN ID Field
-            let xid: u32 = u32::from_le_bytes([v[0], v[1], v[2], v[3]]) & CAN_EXT_ID_MASK;
+            let xid: u32 = u32::from_le_bytes([v[0], v[1], v[2], v[3]]);
 
             //CAN Header Field
             let header: u32 = u32::from_le_bytes([v[4], v[5], v[6], v[7]]);
@@ -32,71 +39,19 @@ impl <const MTU: usize> CyphalMiddleware<MTU> {
             // let brs: u8 = ((header >> 20) & 0x01) as u8;
 
             let dlen: usize = CAN_DLC_TO_DLEN[dlc as usize] as usize;
-            let tail: u8 = v[CAN_FRAME_XID_AND_HEADER_LENGTH + dlen - 1];
-            let transfer_id: u8 = tail & CYPHAL_TRANSFER_ID_MAX;
-            let start_of_transfer: bool = (tail & TAIL_START_OF_TRANSFER) != 0;
-            let end_of_transfer: bool = (tail & TAIL_END_OF_TRANSFER) != 0;
-            let toggle: bool = (tail & TAIL_TOGGLE) != 0;
-            let frame_type = match (start_of_transfer, end_of_transfer) {
-                (true, true) => CyphalRxPacketType::SignleFrame,
-                (true, false) => CyphalRxPacketType::MultiFrameStart,
-                (false, true) => CyphalRxPacketType::MultiFrameEnd,
-                (false, false) => CyphalRxPacketType::MultiFrameInProcess
-            };
-
-            //CAN Data Field
-            let dlen: usize = dlen - TAIL_SIZE_BYTES as usize;
-            let mut payload: [u8; MTU] = [0; MTU];
-            for i in 0..dlen {
-                payload[i] = v[i + CAN_FRAME_XID_AND_HEADER_LENGTH];
+            if dlen > MTU {
+                return Err(String::from("INVALID DATA RECEIVED: DLC EXCEEDS MTU").into());
             }
-            let payload_size: usize = dlen;
-            
-            //Construct Cyphal Rx Packet (with status and property) 
-            let transfer_kind: CyphalTransferKind = match ((xid & FLAG_SERVICE_NOT_MESSAGE) >> 25) == 1 {
-                true => {
-                    match ((xid & FLAG_REQUEST_NOT_RESPONSE) >> 24) == 1 {
-                        true => CyphalTransferKind::Request,
-                        false => CyphalTransferKind::Response,
-                    }
-                }
-                false => CyphalTransferKind::Message,
-            };
-
-            let (priority, port_id, source_node_id, destination_node_id) = match transfer_kind {
-                CyphalTransferKind::Message => {
-                    let priority: CyphalPriority = CyphalPriority::from(((xid >> OFFSET_PRIORITY) & CYPHAL_PRIORITY_MAX as u32) as u8);
-                    let port_id: CyphalPortID = ((xid >> OFFSET_SUBJECT_ID as u32) & CYPHAL_SUBJECT_ID_MAX as u32) as u16;
-                    let source_node_id: CyphalNodeID = if (xid & FLAG_ANONYMOUS_MESSAGE) != 0 { CYPHAL_NODE_ID_UNSET } else { (xid & CYPHAL_NODE_ID_MAX as u32) as u8 };
-                    let destination_node_id: CyphalNodeID = CYPHAL_NODE_ID_UNSET;
-                    (priority, port_id, source_node_id, destination_node_id)
-                },
-                _ => {
-                    let priority: CyphalPriority = CyphalPriority::from(((xid >> OFFSET_PRIORITY) & CYPHAL_PRIORITY_MAX as u32) as u8);
-                    let port_id: CyphalPortID = ((xid >> OFFSET_SERVICE_ID as u32) & CYPHAL_SERVICE_ID_MAX as u32) as u16; 
-                    let source_node_id: CyphalNodeID = (xid & CYPHAL_NODE_ID_MAX as u32) as u8;
-                    let destination_node_id: CyphalNodeID = ((xid >> OFFSET_DST_NODE_ID as u32) as u8) & CYPHAL_NODE_ID_MAX;
-                    (priority, port_id, source_node_id, destination_node_id)
-                },
-            };
-
-            ret.push(CyphalRxPacket {
-                xid,
-                payload,
-                payload_size,
-                status: CyphalRxPacketStatus {
-                    frame_type,
-                    toggle,
-                },
-                props: CyphalRxProps{
-                    priority,
-                    transfer_id,
-                    transfer_kind,
-                    port_id,
-                    source_node_id,
-                    destination_node_id
-                },
-            });
+            // Frames without a tail byte are not Cyphal frames.
+            if dlen == 0 {
+                continue;
+            }
+            let mut packet: CyphalRxPacket<MTU> = CyphalRxPacket::from_frame(xid, &v[CAN_FRAME_XID_AND_HEADER_LENGTH..(CAN_FRAME_XID_AND_HEADER_LENGTH + dlen)])?;
+            if !self.is_addressed_to_us(&packet.props) {
+                continue;
+            }
+            packet.timestamp_usec = timestamp(header as u16);
+            ret.push(packet);
         }
 
         Ok(ret)
@@ -104,3 +59,318 @@ impl <const MTU: usize> CyphalMiddleware<MTU> {
 
 
 }
+
+impl <const MTU: usize> CyphalRxPacket<MTU> {
+
+    /// Decodes one received CAN frame given its extended ID and data field, tail byte included.
+    /// No destination filtering is applied and the timestamp is left to the caller.
+    pub fn from_frame(xid: u32, data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        if data.is_empty() || data.len() > MTU {
+            return Err(String::from("INVALID DATA RECEIVED: UNACCEPTABLE DATA LENGTH").into());
+        }
+        let xid: u32 = xid & CAN_EXT_ID_MASK;
+
+        let tail: u8 = data[data.len() - 1];
+        let transfer_id: u8 = tail & CYPHAL_TRANSFER_ID_MAX;
+        let start_of_transfer: bool = (tail & TAIL_START_OF_TRANSFER) != 0;
+        let end_of_transfer: bool = (tail & TAIL_END_OF_TRANSFER) != 0;
+        let toggle: bool = (tail & TAIL_TOGGLE) != 0;
+        let frame_type = match (start_of_transfer, end_of_transfer) {
+            (true, true) => CyphalRxPacketType::SignleFrame,
+            (true, false) => CyphalRxPacketType::MultiFrameStart,
+            (false, true) => CyphalRxPacketType::MultiFrameEnd,
+            (false, false) => CyphalRxPacketType::MultiFrameInProcess
+        };
+
+        //CAN Data Field
+        let dlen: usize = data.len() - TAIL_SIZE_BYTES as usize;
+        let mut payload: [u8; MTU] = [0; MTU];
+        payload[..dlen].copy_from_slice(&data[..dlen]);
+        let payload_size: usize = dlen;
+
+        //Construct Cyphal Rx Packet (with status and property) 
+        let transfer_kind: CyphalTransferKind = match ((xid & FLAG_SERVICE_NOT_MESSAGE) >> 25) == 1 {
+            true => {
+                match ((xid & FLAG_REQUEST_NOT_RESPONSE) >> 24) == 1 {
+                    true => CyphalTransferKind::Request,
+                    false => CyphalTransferKind::Response,
+                }
+            }
+            false => CyphalTransferKind::Message,
+        };
+
+        let (priority, port_id, source_node_id, destination_node_id) = match transfer_kind {
+            CyphalTransferKind::Message => {
+                let priority: CyphalPriority = CyphalPriority::from(((xid >> OFFSET_PRIORITY) & CYPHAL_PRIORITY_MAX as u32) as u8);
+                let port_id: CyphalPortID = ((xid >> OFFSET_SUBJECT_ID as u32) & CYPHAL_SUBJECT_ID_MAX as u32) as u16;
+                let source_node_id: CyphalNodeID = if (xid & FLAG_ANONYMOUS_MESSAGE) != 0 { CYPHAL_NODE_ID_UNSET } else { (xid & CYPHAL_NODE_ID_MAX as u32) as u8 };
+                let destination_node_id: CyphalNodeID = CYPHAL_NODE_ID_UNSET;
+                (priority, port_id, source_node_id, destination_node_id)
+            },
+            _ => {
+                let priority: CyphalPriority = CyphalPriority::from(((xid >> OFFSET_PRIORITY) & CYPHAL_PRIORITY_MAX as u32) as u8);
+                let port_id: CyphalPortID = ((xid >> OFFSET_SERVICE_ID as u32) & CYPHAL_SERVICE_ID_MAX as u32) as u16; 
+                let source_node_id: CyphalNodeID = (xid & CYPHAL_NODE_ID_MAX as u32) as u8;
+                let destination_node_id: CyphalNodeID = ((xid >> OFFSET_DST_NODE_ID as u32) as u8) & CYPHAL_NODE_ID_MAX;
+                (priority, port_id, source_node_id, destination_node_id)
+            },
+        };
+
+        let props: CyphalRxProps = CyphalRxProps{
+            priority,
+            transfer_id,
+            transfer_kind,
+            port_id,
+            source_node_id,
+            destination_node_id
+        };
+
+        Ok(Self {
+            xid,
+            payload,
+            payload_size,
+            status: CyphalRxPacketStatus {
+                frame_type,
+                toggle,
+            },
+            props,
+            timestamp_usec: 0,
+        })
+    }
+}
+
+// Transfer reassembly
+impl <const MTU: usize> CyphalMiddleware<MTU> {
+
+    /// Feeds one received packet into the reassembly state machine.
+    /// Returns the complete transfer once its last frame has been accepted; the payload of a multi-frame
+    /// transfer is returned without the transfer CRC. Duplicates, frames out of order and transfers
+    /// with a CRC mismatch are dropped silently, as required by the Cyphal/CAN specification.
+    /// The transfer carries the timestamp of its first frame. A transfer that outgrows the extent of its port
+    /// by more than the one frame taken by the transfer CRC and the padding is dropped along with its session,
+    /// so that a sender that never ends its transfer cannot exhaust the memory.
+    pub fn accept(&mut self, packet: &CyphalRxPacket<MTU>, timestamp_usec: CyphalMicrosecond) -> Result<Option<CyphalRxFrame>, Box<dyn std::error::Error>> {
+        let start_of_transfer: bool = matches!(packet.status.frame_type, CyphalRxPacketType::SignleFrame | CyphalRxPacketType::MultiFrameStart);
+        let end_of_transfer: bool = matches!(packet.status.frame_type, CyphalRxPacketType::SignleFrame | CyphalRxPacketType::MultiFrameEnd);
+
+        if packet.payload_size > MTU {
+            return Err("INVALID PAYLOAD LENGTH".into());
+        }
+        if !self.is_addressed_to_us(&packet.props) {
+            return Ok(None);
+        }
+        if start_of_transfer && packet.status.toggle != INITIAL_TOGGLE_STATE {
+            return Ok(None);
+        }
+        if !end_of_transfer && packet.payload_size < MFT_NON_LAST_FRAME_PAYLOAD_MIN as usize {
+            return Ok(None);
+        }
+
+        // Anonymous transfers are stateless and can only be single-frame.
+        if packet.props.source_node_id > CYPHAL_NODE_ID_MAX {
+            if packet.status.frame_type != CyphalRxPacketType::SignleFrame {
+                return Ok(None);
+            }
+            return Ok(Some(Self::rx_make_frame(packet.xid, packet.payload[..packet.payload_size].to_vec(), packet.props, timestamp_usec)));
+        }
+
+        let key = (packet.props.transfer_kind, packet.props.port_id, packet.props.source_node_id);
+        let payload_max: usize = self.extent(packet.props.transfer_kind, packet.props.port_id).saturating_add(MTU);
+        let transfer_id_timeout_usec: CyphalMicrosecond = self.transfer_id_timeout_usec;
+        let session: &mut CyphalRxSession = match self.rx_sessions.get_mut(&key) {
+            Some(x) => x,
+            None if start_of_transfer => self.rx_sessions.entry(key).or_insert(CyphalRxSession::new(timestamp_usec, packet.props.transfer_id)),
+            None => return Ok(None),
+        };
+
+        let tid_timed_out: bool = timestamp_usec > session.transfer_timestamp_usec &&
+            (timestamp_usec - session.transfer_timestamp_usec) > transfer_id_timeout_usec;
+        let not_previous_tid: bool = Self::rx_transfer_id_difference(session.transfer_id, packet.props.transfer_id) > 1;
+        let need_restart: bool = tid_timed_out || (start_of_transfer && not_previous_tid);
+
+        if need_restart {
+            session.restart(packet.props.transfer_id);
+            if !start_of_transfer {
+                return Ok(None);
+            }
+        }
+
+        if packet.props.transfer_id != session.transfer_id || packet.status.toggle != session.toggle {
+            return Ok(None);
+        }
+
+        if start_of_transfer {
+            session.transfer_timestamp_usec = timestamp_usec;
+        }
+
+        if session.payload.len() + packet.payload_size > payload_max {
+            self.rx_sessions.remove(&key);
+            return Ok(None);
+        }
+
+        let frame_payload: &[u8] = &packet.payload[..packet.payload_size];
+        session.payload.extend_from_slice(frame_payload);
+        if !(start_of_transfer && end_of_transfer) {
+            session.crc = crc_add(session.crc, frame_payload.len(), frame_payload)?;
+        }
+
+        if !end_of_transfer {
+            session.toggle = !session.toggle;
+            return Ok(None);
+        }
+
+        let next_transfer_id: CyphalTransferID = session.transfer_id.wrapping_add(1);
+        let mut payload: Vec<u8> = std::mem::take(&mut session.payload);
+        let crc: TransferCRC = session.crc;
+        let transfer_timestamp_usec: CyphalMicrosecond = session.transfer_timestamp_usec;
+        session.restart(next_transfer_id);
+
+        if !start_of_transfer {
+            if crc != CRC_RESIDUE || payload.len() < CRC_SIZE_BYTES as usize {
+                return Ok(None);
+            }
+            payload.truncate(payload.len() - CRC_SIZE_BYTES as usize);
+        }
+
+        Ok(Some(Self::rx_make_frame(packet.xid, payload, packet.props, transfer_timestamp_usec)))
+    }
+
+    /// Drops the reassembly sessions that have not seen a transfer within the transfer-ID timeout.
+    pub fn cleanup_rx_sessions(&mut self, now_usec: CyphalMicrosecond) {
+        let transfer_id_timeout_usec: CyphalMicrosecond = self.transfer_id_timeout_usec;
+        self.rx_sessions.retain(|_, s| now_usec.saturating_sub(s.transfer_timestamp_usec) <= transfer_id_timeout_usec);
+    }
+
+    fn rx_make_frame(xid: u32, payload: Vec<u8>, props: CyphalRxProps, timestamp_usec: CyphalMicrosecond) -> CyphalRxFrame {
+        CyphalRxFrame {
+            xid,
+            payload_size: payload.len(),
+            payload,
+            props,
+            timestamp_usec,
+        }
+    }
+
+    fn rx_transfer_id_difference(a: CyphalTransferID, b: CyphalTransferID) -> u8 {
+        a.wrapping_sub(b) & CYPHAL_TRANSFER_ID_MAX
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MTU: usize = CYPHAL_MTU_CAN_CLASSIC as usize;
+    const SUBJECT_ID: CyphalPortID = 100;
+    const SERVICE_ID: CyphalPortID = 200;
+
+    fn deliver(receiver: &mut CyphalMiddleware<MTU>, packets: &[CyphalTxPacket<MTU>]) -> Vec<CyphalRxFrame> {
+        packets.iter()
+            .map(|x| CyphalRxPacket::<MTU>::from_frame(x.xid, &x.payload[..x.payload_size]).unwrap())
+            .filter_map(|x| receiver.accept(&x, 0).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn transfer_within_the_extent_is_accepted() {
+        let mut sender: CyphalMiddleware<MTU> = CyphalMiddleware::new(1);
+        let mut receiver: CyphalMiddleware<MTU> = CyphalMiddleware::new(2);
+        receiver.subscribe_with_extent(CyphalTransferKind::Message, SUBJECT_ID, 20).unwrap();
+        assert_eq!(receiver.extent(CyphalTransferKind::Message, SUBJECT_ID), 20);
+        assert_eq!(receiver.extent(CyphalTransferKind::Message, SUBJECT_ID + 1), CYPHAL_DEFAULT_EXTENT_BYTES);
+
+        let data: Vec<u8> = (0..20).collect();
+        let frames: Vec<CyphalRxFrame> = deliver(&mut receiver, &sender.create_message_data(SUBJECT_ID, &data, data.len()).unwrap());
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].payload[..data.len()], data[..]);
+    }
+
+    #[test]
+    fn transfer_beyond_the_extent_drops_its_session() {
+        let mut sender: CyphalMiddleware<MTU> = CyphalMiddleware::new(1);
+        let mut receiver: CyphalMiddleware<MTU> = CyphalMiddleware::new(2);
+        receiver.subscribe_with_extent(CyphalTransferKind::Message, SUBJECT_ID, 20).unwrap();
+
+        let data: Vec<u8> = (0..40).collect();
+        let packets: Vec<CyphalTxPacket<MTU>> = sender.create_message_data(SUBJECT_ID, &data, data.len()).unwrap();
+        assert!(deliver(&mut receiver, &packets).is_empty());
+        assert!(receiver.rx_sessions.is_empty());
+    }
+
+    #[test]
+    fn endless_transfer_does_not_grow_without_limit() {
+        let mut receiver: CyphalMiddleware<MTU> = CyphalMiddleware::new(2);
+        receiver.subscribe_with_extent(CyphalTransferKind::Message, SUBJECT_ID, 20).unwrap();
+        let xid: u32 = (SUBJECT_ID as u32) << OFFSET_SUBJECT_ID | 1;
+
+        // A sender that keeps toggling and never sets the end of transfer.
+        let mut toggle: bool = INITIAL_TOGGLE_STATE;
+        for i in 0..100 {
+            let tail: u8 = if i == 0 { TAIL_START_OF_TRANSFER } else { 0 } | if toggle { TAIL_TOGGLE } else { 0 };
+            let packet: CyphalRxPacket<MTU> = CyphalRxPacket::from_frame(xid, &[0, 0, 0, 0, 0, 0, 0, tail]).unwrap();
+            assert!(receiver.accept(&packet, 0).unwrap().is_none());
+            assert!(receiver.rx_sessions.values().all(|x| x.payload.len() <= 20 + MTU));
+            toggle = !toggle;
+        }
+        assert!(receiver.rx_sessions.is_empty());
+    }
+
+    /// RX buffer elements as read from the controller: extended ID, header with DLC and 16-bit timestamp, padded data.
+    fn rx_elements(packets: &[CyphalTxPacket<MTU>], timestamp: u16) -> Vec<u8> {
+        let mut data: Vec<u8> = vec![];
+        for packet in packets {
+            let header: u32 = (CAN_DLEN_TO_DLC[packet.payload_size] as u32) << 16 | timestamp as u32;
+            data.extend(packet.xid.to_le_bytes());
+            data.extend(header.to_le_bytes());
+            data.extend(&packet.payload[..packet.payload_size]);
+            data.resize(data.len() + MTU - packet.payload_size, 0);
+        }
+        data
+    }
+
+    /// A message, a request to node 2 and a request to node 3.
+    fn mixed_traffic() -> Vec<CyphalTxPacket<MTU>> {
+        let mut sender: CyphalMiddleware<MTU> = CyphalMiddleware::new(1);
+        let mut packets: Vec<CyphalTxPacket<MTU>> = sender.create_message_data(SUBJECT_ID, &[1], 1).unwrap();
+        packets.extend(sender.create_request_data(2, SERVICE_ID, &[2], 1).unwrap());
+        packets.extend(sender.create_request_data(3, SERVICE_ID, &[3], 1).unwrap());
+        packets
+    }
+
+    #[test]
+    fn try_read_timestamped_drops_service_frames_for_other_nodes() {
+        let receiver: CyphalMiddleware<MTU> = CyphalMiddleware::new(2);
+        let packets: Vec<CyphalRxPacket<MTU>> = receiver.try_read_timestamped(&rx_elements(&mixed_traffic(), 0x1234), |x| x as CyphalMicrosecond * 10).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].props.transfer_kind, CyphalTransferKind::Message);
+        assert_eq!(packets[1].props.transfer_kind, CyphalTransferKind::Request);
+        assert_eq!(packets[1].props.destination_node_id, 2);
+        assert!(packets.iter().all(|x| x.timestamp_usec == 0x1234 * 10));
+    }
+
+    #[test]
+    fn try_read_timestamped_keeps_service_frames_for_other_nodes_in_monitor_mode() {
+        let receiver: CyphalMiddleware<MTU> = CyphalMiddleware::new(2).set_monitor_mode(true);
+        let packets: Vec<CyphalRxPacket<MTU>> = receiver.try_read_timestamped(&rx_elements(&mixed_traffic(), 7), |x| x as CyphalMicrosecond).unwrap();
+        let destinations: Vec<CyphalNodeID> = packets.iter()
+            .filter(|x| x.props.transfer_kind == CyphalTransferKind::Request)
+            .map(|x| x.props.destination_node_id)
+            .collect();
+        assert_eq!(destinations, vec![2, 3]);
+        assert!(packets.iter().all(|x| x.timestamp_usec == 7));
+    }
+
+    #[test]
+    fn accept_filters_by_destination_unless_in_monitor_mode() {
+        let mut receiver: CyphalMiddleware<MTU> = CyphalMiddleware::new(2);
+        let frames: Vec<CyphalRxFrame> = deliver(&mut receiver, &mixed_traffic());
+        assert_eq!(frames.len(), 2);
+        assert!(frames.iter().all(|x| receiver.is_addressed_to_us(&x.props)));
+
+        let mut monitor: CyphalMiddleware<MTU> = CyphalMiddleware::new(2).set_monitor_mode(true);
+        let frames: Vec<CyphalRxFrame> = deliver(&mut monitor, &mixed_traffic());
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[2].props.destination_node_id, 3);
+        assert_eq!(frames[2].payload[0], 3);
+    }
+}
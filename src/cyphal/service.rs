@@ -0,0 +1,387 @@
+//! Service client with request/response matching and service server dispatch.
+
+use super::CyphalMiddleware;
+use crate::driver::*;
+use super::defines::*;
+use super::uavcan::CyphalDataType;
+
+use std::collections::HashMap;
+
+/// Identifies one in-flight request; the response carries the same server node-ID, service-ID and transfer-ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+pub struct CyphalRequestKey {
+    pub server_node_id: CyphalNodeID,
+    pub service_id: CyphalPortID,
+    pub transfer_id: CyphalTransferID,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum CyphalServiceError {
+    /// No response arrived before the deadline.
+    Timeout(CyphalRequestKey),
+    /// All transfer-IDs of the (server, service) session are taken by pending requests.
+    TooManyPendingRequests,
+}
+
+impl std::fmt::Display for CyphalServiceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CyphalServiceError::Timeout(key) => write!(
+                f, "SERVICE REQUEST TIMEOUT (NODE {}, SERVICE {}, TRANSFER {})",
+                key.server_node_id, key.service_id, key.transfer_id
+            ),
+            CyphalServiceError::TooManyPendingRequests => write!(f, "TOO MANY PENDING REQUESTS"),
+        }
+    }
+}
+
+impl std::error::Error for CyphalServiceError {}
+
+/// Keeps the table of pending requests and matches responses against it.
+/// Several requests may be in flight at once, to the same or to different servers.
+/// Completed requests, successful or timed out, are kept until collected with `take_result`.
+pub struct CyphalServiceClient {
+    priority: CyphalPriority,
+    timeout_usec: CyphalMicrosecond,
+    pending: HashMap<CyphalRequestKey, CyphalMicrosecond>,
+    completed: HashMap<CyphalRequestKey, Result<CyphalRxFrame, CyphalServiceError>>,
+}
+
+impl Default for CyphalServiceClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CyphalServiceClient {
+    pub fn new() -> Self {
+        Self {
+            priority: CyphalPriority::Nominal,
+            timeout_usec: 1_000_000,
+            pending: HashMap::new(),
+            completed: HashMap::new(),
+        }
+    }
+
+    pub fn set_priority(mut self, priority: CyphalPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Time after which a request without response fails with `CyphalServiceError::Timeout`.
+    pub fn set_timeout_usec(mut self, timeout_usec: CyphalMicrosecond) -> Self {
+        self.timeout_usec = timeout_usec;
+        self
+    }
+
+    /// Encodes a request and registers it as pending until the timeout expires.
+    pub fn request<const MTU: usize>(
+        &mut self,
+        middleware: &mut CyphalMiddleware<MTU>,
+        server_node_id: CyphalNodeID,
+        service_id: CyphalPortID,
+        data: &[u8],
+        now_usec: CyphalMicrosecond
+    ) -> Result<(CyphalRequestKey, Vec<CyphalTxPacket<MTU>>), Box<dyn std::error::Error>> {
+        let transfer_id: CyphalTransferID = middleware.transfer_id_for(CyphalTransferKind::Request, service_id, server_node_id);
+        let key: CyphalRequestKey = CyphalRequestKey { server_node_id, service_id, transfer_id };
+        if self.pending.contains_key(&key) {
+            return Err(CyphalServiceError::TooManyPendingRequests.into());
+        }
+
+        middleware.subscribe(CyphalTransferKind::Response, service_id)?;
+        let props: CyphalTxProps = CyphalTxProps {
+            priority: self.priority,
+            transfer_kind: CyphalTransferKind::Request,
+            transfer_id,
+            port_id: service_id,
+            remote_node_id: server_node_id,
+        };
+        let packets: Vec<CyphalTxPacket<MTU>> = middleware.create_transfer_data(props, data)?;
+
+        middleware.next_transfer_id(CyphalTransferKind::Request, service_id, server_node_id);
+        self.completed.remove(&key);
+        self.pending.insert(key, now_usec.saturating_add(self.timeout_usec));
+        Ok((key, packets))
+    }
+
+    /// Completes the pending request matching the response. Returns its key if the transfer was consumed.
+    pub fn accept<const MTU: usize>(&mut self, middleware: &CyphalMiddleware<MTU>, frame: &CyphalRxFrame) -> Option<CyphalRequestKey> {
+        if frame.props.transfer_kind != CyphalTransferKind::Response || frame.props.destination_node_id != middleware.node_id() {
+            return None;
+        }
+        let key: CyphalRequestKey = CyphalRequestKey {
+            server_node_id: frame.props.source_node_id,
+            service_id: frame.props.port_id,
+            transfer_id: frame.props.transfer_id,
+        };
+        self.pending.remove(&key)?;
+        self.completed.insert(key, Ok(frame.clone()));
+        Some(key)
+    }
+
+    /// Fails the requests whose deadline has passed and returns their keys.
+    pub fn poll_timeouts(&mut self, now_usec: CyphalMicrosecond) -> Vec<CyphalRequestKey> {
+        let expired: Vec<CyphalRequestKey> = self.pending.iter()
+            .filter(|(_, deadline)| now_usec >= **deadline)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in &expired {
+            self.pending.remove(key);
+            self.completed.insert(*key, Err(CyphalServiceError::Timeout(*key)));
+        }
+        expired
+    }
+
+    pub fn is_pending(&self, key: &CyphalRequestKey) -> bool {
+        self.pending.contains_key(key)
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns the outcome of a completed request; None while it is still pending or if it is unknown.
+    pub fn take_result(&mut self, key: &CyphalRequestKey) -> Option<Result<CyphalRxFrame, CyphalServiceError>> {
+        self.completed.remove(key)
+    }
+
+    /// Forgets a request; a late response to it will not be matched.
+    pub fn cancel(&mut self, key: &CyphalRequestKey) {
+        self.pending.remove(key);
+        self.completed.remove(key);
+    }
+
+    /// Sends a request over the driver and blocks until its response arrives or the timeout expires.
+    /// Transfers other than the awaited response are dropped.
+    pub fn call<const MTU: usize, D: CanDriver<MTU>>(
+        &mut self,
+        middleware: &mut CyphalMiddleware<MTU>,
+        driver: &mut D,
+        server_node_id: CyphalNodeID,
+        service_id: CyphalPortID,
+        data: &[u8]
+    ) -> Result<CyphalRxFrame, Box<dyn std::error::Error>> {
+        let started_usec: CyphalMicrosecond = monotonic_usec();
+        let (key, packets) = self.request(middleware, server_node_id, service_id, data, started_usec)?;
+        driver.transmit(&packets)?;
+
+        loop {
+            let elapsed_usec: CyphalMicrosecond = monotonic_usec().saturating_sub(started_usec);
+            let remaining: std::time::Duration = std::time::Duration::from_micros(self.timeout_usec.saturating_sub(elapsed_usec));
+            for packet in driver.receive(remaining)? {
+                let timestamp_usec: CyphalMicrosecond = if packet.timestamp_usec > 0 { packet.timestamp_usec } else { monotonic_usec() };
+                if let Some(frame) = middleware.accept(&packet, timestamp_usec)? {
+                    self.accept(middleware, &frame);
+                }
+            }
+            self.poll_timeouts(monotonic_usec());
+            if let Some(result) = self.take_result(&key) {
+                return Ok(result?);
+            }
+        }
+    }
+}
+
+pub type CyphalServiceHandler = Box<dyn FnMut(&CyphalRxProps, &[u8]) -> Option<Vec<u8>>>;
+
+/// Dispatch table of service handlers keyed by service-ID.
+/// Requests addressed to the local node are passed to the handler of their service, and the returned
+/// payload is sent back to the requester with the request's transfer-ID. A handler returning None sends no response.
+/// Requests to other nodes, which are only received in monitor mode, are never answered.
+pub struct CyphalServiceServer {
+    handlers: HashMap<CyphalPortID, CyphalServiceHandler>,
+}
+
+impl Default for CyphalServiceServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CyphalServiceServer {
+    pub fn new() -> Self {
+        Self { handlers: HashMap::new() }
+    }
+
+    pub fn register<const MTU: usize, F>(
+        &mut self,
+        middleware: &mut CyphalMiddleware<MTU>,
+        service_id: CyphalPortID,
+        handler: F
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: FnMut(&CyphalRxProps, &[u8]) -> Option<Vec<u8>> + 'static
+    {
+        middleware.subscribe(CyphalTransferKind::Request, service_id)?;
+        self.handlers.insert(service_id, Box::new(handler));
+        Ok(())
+    }
+
+    /// Registers a handler working on deserialized requests and responses.
+    /// Requests that fail to deserialize and responses that fail to serialize are dropped.
+    pub fn register_typed<const MTU: usize, Req, Resp, F>(
+        &mut self,
+        middleware: &mut CyphalMiddleware<MTU>,
+        service_id: CyphalPortID,
+        mut handler: F
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        Req: CyphalDataType,
+        Resp: CyphalDataType,
+        F: FnMut(&CyphalRxProps, Req) -> Option<Resp> + 'static
+    {
+        self.register(middleware, service_id, move |props: &CyphalRxProps, data: &[u8]| {
+            let request: Req = Req::deserialize(data).ok()?;
+            handler(props, request)?.serialize().ok()
+        })
+    }
+
+    pub fn unregister<const MTU: usize>(&mut self, middleware: &mut CyphalMiddleware<MTU>, service_id: CyphalPortID) {
+        middleware.unsubscribe(CyphalTransferKind::Request, service_id);
+        self.handlers.remove(&service_id);
+    }
+
+    pub fn is_registered(&self, service_id: CyphalPortID) -> bool {
+        self.handlers.contains_key(&service_id)
+    }
+
+    /// Dispatches a reassembled request. Returns None if no handler took the transfer,
+    /// otherwise the response packets, which are empty if the handler chose not to respond.
+    pub fn respond<const MTU: usize>(
+        &mut self,
+        middleware: &mut CyphalMiddleware<MTU>,
+        frame: &CyphalRxFrame
+    ) -> Result<Option<Vec<CyphalTxPacket<MTU>>>, Box<dyn std::error::Error>> {
+        if frame.props.transfer_kind != CyphalTransferKind::Request || frame.props.destination_node_id != middleware.node_id() {
+            return Ok(None);
+        }
+        let handler: &mut CyphalServiceHandler = match self.handlers.get_mut(&frame.props.port_id) {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        match handler(&frame.props, &frame.payload) {
+            Some(data) => Ok(Some(middleware.create_response_for(&frame.props, &data)?)),
+            None => Ok(Some(vec![])),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT: CyphalNodeID = 10;
+    const SERVER: CyphalNodeID = 20;
+    const SERVICE: CyphalPortID = 123;
+
+    fn deliver(receiver: &mut CyphalMiddleware<8>, packets: &[CyphalTxPacket<8>]) -> Vec<CyphalRxFrame> {
+        packets.iter()
+            .map(|x| CyphalRxPacket::<8>::from_frame(x.xid, &x.payload[..x.payload_size]).unwrap())
+            .filter_map(|x| receiver.accept(&x, 0).unwrap())
+            .collect()
+    }
+
+    /// Response of the node `server_node_id` to the request, with its transfer-ID replaced by `transfer_id`.
+    fn response(request: &CyphalRxFrame, server_node_id: CyphalNodeID, transfer_id: CyphalTransferID, data: &[u8]) -> Vec<CyphalTxPacket<8>> {
+        let mut server: CyphalMiddleware<8> = CyphalMiddleware::new(server_node_id);
+        let props: CyphalTxProps = CyphalTxProps {
+            priority: request.props.priority,
+            transfer_kind: CyphalTransferKind::Response,
+            transfer_id,
+            port_id: request.props.port_id,
+            remote_node_id: request.props.source_node_id,
+        };
+        server.create_transfer_data(props, data).unwrap()
+    }
+
+    fn server() -> CyphalMiddleware<8> {
+        let mut server: CyphalMiddleware<8> = CyphalMiddleware::new(SERVER);
+        server.subscribe(CyphalTransferKind::Request, SERVICE).unwrap();
+        server
+    }
+
+    #[test]
+    fn several_requests_to_one_server_are_matched_by_transfer_id() {
+        let mut middleware: CyphalMiddleware<8> = CyphalMiddleware::new(CLIENT);
+        let mut client: CyphalServiceClient = CyphalServiceClient::new();
+        let mut server: CyphalMiddleware<8> = server();
+        let mut requests: Vec<(CyphalRequestKey, CyphalRxFrame)> = vec![];
+        for i in 0..3u8 {
+            let (key, packets) = client.request(&mut middleware, SERVER, SERVICE, &[i], 0).unwrap();
+            assert_eq!(key.transfer_id, i);
+            requests.push((key, deliver(&mut server, &packets).remove(0)));
+        }
+        assert_eq!(client.pending_count(), 3);
+
+        // The server answers out of order.
+        for (key, request) in requests.iter().rev() {
+            let frames: Vec<CyphalRxFrame> = deliver(&mut middleware, &response(request, SERVER, request.props.transfer_id, &[request.payload[0] + 100]));
+            assert_eq!(client.accept(&middleware, &frames[0]), Some(*key));
+        }
+        assert_eq!(client.pending_count(), 0);
+        for (i, (key, _)) in requests.iter().enumerate() {
+            assert_eq!(client.take_result(key).unwrap().unwrap().payload[0], i as u8 + 100);
+        }
+    }
+
+    #[test]
+    fn request_without_response_times_out() {
+        let mut middleware: CyphalMiddleware<8> = CyphalMiddleware::new(CLIENT);
+        let mut client: CyphalServiceClient = CyphalServiceClient::new().set_timeout_usec(1_000);
+        let (key, _) = client.request(&mut middleware, SERVER, SERVICE, &[], 5_000).unwrap();
+        assert!(client.poll_timeouts(5_999).is_empty());
+        assert!(client.is_pending(&key));
+        assert!(client.take_result(&key).is_none());
+
+        assert_eq!(client.poll_timeouts(6_000), vec![key]);
+        assert!(!client.is_pending(&key));
+        assert_eq!(client.take_result(&key).unwrap().err(), Some(CyphalServiceError::Timeout(key)));
+        assert!(client.take_result(&key).is_none());
+    }
+
+    #[test]
+    fn late_response_after_the_timeout_is_not_matched() {
+        let mut middleware: CyphalMiddleware<8> = CyphalMiddleware::new(CLIENT);
+        let mut client: CyphalServiceClient = CyphalServiceClient::new().set_timeout_usec(1_000);
+        let mut server: CyphalMiddleware<8> = server();
+        let (key, packets) = client.request(&mut middleware, SERVER, SERVICE, &[], 0).unwrap();
+        let request: CyphalRxFrame = deliver(&mut server, &packets).remove(0);
+        client.poll_timeouts(1_000);
+
+        let frames: Vec<CyphalRxFrame> = deliver(&mut middleware, &response(&request, SERVER, key.transfer_id, &[]));
+        assert_eq!(client.accept(&middleware, &frames[0]), None);
+        assert_eq!(client.take_result(&key).unwrap().err(), Some(CyphalServiceError::Timeout(key)));
+    }
+
+    #[test]
+    fn response_from_another_server_or_with_another_transfer_id_is_ignored() {
+        let mut middleware: CyphalMiddleware<8> = CyphalMiddleware::new(CLIENT);
+        let mut client: CyphalServiceClient = CyphalServiceClient::new();
+        let mut server: CyphalMiddleware<8> = server();
+        let (key, packets) = client.request(&mut middleware, SERVER, SERVICE, &[], 0).unwrap();
+        let request: CyphalRxFrame = deliver(&mut server, &packets).remove(0);
+
+        let frames: Vec<CyphalRxFrame> = deliver(&mut middleware, &response(&request, SERVER + 1, key.transfer_id, &[]));
+        assert_eq!(client.accept(&middleware, &frames[0]), None);
+        let frames: Vec<CyphalRxFrame> = deliver(&mut middleware, &response(&request, SERVER, key.transfer_id + 1, &[]));
+        assert_eq!(client.accept(&middleware, &frames[0]), None);
+        assert!(client.is_pending(&key));
+
+        let frames: Vec<CyphalRxFrame> = deliver(&mut middleware, &response(&request, SERVER, key.transfer_id, &[]));
+        assert_eq!(client.accept(&middleware, &frames[0]), Some(key));
+    }
+
+    #[test]
+    fn all_transfer_ids_pending_is_an_error() {
+        let mut middleware: CyphalMiddleware<8> = CyphalMiddleware::new(CLIENT);
+        let mut client: CyphalServiceClient = CyphalServiceClient::new();
+        for _ in 0..=CYPHAL_TRANSFER_ID_MAX {
+            client.request(&mut middleware, SERVER, SERVICE, &[], 0).unwrap();
+        }
+        let error: Box<dyn std::error::Error> = client.request(&mut middleware, SERVER, SERVICE, &[], 0).unwrap_err();
+        assert_eq!(error.to_string(), CyphalServiceError::TooManyPendingRequests.to_string());
+        // Requests to another server use their own transfer-IDs.
+        assert!(client.request(&mut middleware, SERVER + 1, SERVICE, &[], 0).is_ok());
+    }
+}
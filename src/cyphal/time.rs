@@ -0,0 +1,342 @@
+//! Cyphal time synchronization (uavcan.time.Synchronization and GetSynchronizationMasterInfo).
+
+use super::CyphalMiddleware;
+use super::defines::*;
+use super::uavcan::CyphalDataType;
+use super::uavcan::time::*;
+use crate::driver::CanTxConfirmation;
+
+/// Time synchronization master.
+/// Every message carries the transmission timestamp of the previous one, so the caller has to report
+/// when each Synchronization transfer actually left the controller, e.g. from the TCAN4550 TX event FIFO.
+pub struct CyphalTimeSyncMaster {
+    period_usec: CyphalMicrosecond,
+    priority: CyphalPriority,
+    last_transfer_id: CyphalTransferID,
+    next_publication_usec: Option<CyphalMicrosecond>,
+    previous_transmission_timestamp_usec: Option<CyphalMicrosecond>,
+    info: GetSynchronizationMasterInfoResponse,
+}
+
+impl Default for CyphalTimeSyncMaster {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CyphalTimeSyncMaster {
+    pub fn new() -> Self {
+        Self {
+            period_usec: Synchronization::MAX_PUBLICATION_PERIOD_USEC,
+            priority: CyphalPriority::Fast,
+            last_transfer_id: CYPHAL_TRANSFER_ID_MAX,
+            next_publication_usec: None,
+            previous_transmission_timestamp_usec: None,
+            info: GetSynchronizationMasterInfoResponse {
+                error_variance: 0.0,
+                time_system: TimeSystem::MonotonicSinceBoot,
+                difference_tai_minus_utc: GetSynchronizationMasterInfoResponse::DIFFERENCE_TAI_MINUS_UTC_AS_OF_2020,
+            },
+        }
+    }
+
+    /// The period is clamped to the maximum publication period allowed by the specification.
+    pub fn set_period_usec(mut self, period_usec: CyphalMicrosecond) -> Self {
+        self.period_usec = std::cmp::min(period_usec, Synchronization::MAX_PUBLICATION_PERIOD_USEC);
+        self
+    }
+
+    pub fn set_priority(mut self, priority: CyphalPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn set_master_info(mut self, info: GetSynchronizationMasterInfoResponse) -> Self {
+        self.info = info;
+        self
+    }
+
+    /// Reports the time at which the last Synchronization transfer was transmitted.
+    pub fn record_transmission_timestamp(&mut self, tx_timestamp_usec: CyphalMicrosecond) {
+        self.previous_transmission_timestamp_usec = Some(tx_timestamp_usec);
+    }
+
+    /// Takes the transmission time from the confirmation of the last Synchronization transfer;
+    /// returns false for confirmations of other transfers.
+    pub fn accept_tx_confirmation(&mut self, confirmation: &CanTxConfirmation) -> bool {
+        let props: &CyphalRxProps = &confirmation.props;
+        if props.transfer_kind != CyphalTransferKind::Message ||
+            props.port_id != Synchronization::FIXED_PORT_ID ||
+            props.transfer_id != self.last_transfer_id()
+        {
+            return false;
+        }
+        self.record_transmission_timestamp(confirmation.first_frame_usec);
+        true
+    }
+
+    /// Transfer-ID of the most recently published Synchronization message, for matching TX confirmations.
+    pub fn last_transfer_id(&self) -> CyphalTransferID {
+        self.last_transfer_id
+    }
+
+    /// Publishes the next Synchronization message when due.
+    /// The previous timestamp is sent as zero if its transmission was never confirmed.
+    pub fn poll<const MTU: usize>(
+        &mut self,
+        middleware: &mut CyphalMiddleware<MTU>,
+        now_usec: CyphalMicrosecond
+    ) -> Result<Vec<CyphalTxPacket<MTU>>, Box<dyn std::error::Error>> {
+        if let Some(x) = self.next_publication_usec {
+            if now_usec < x {
+                return Ok(vec![]);
+            }
+        }
+        self.next_publication_usec = Some(now_usec + self.period_usec);
+
+        let message: Synchronization = Synchronization {
+            previous_transmission_timestamp_microsecond: self.previous_transmission_timestamp_usec.take().unwrap_or(0),
+        };
+        let props: CyphalTxProps = CyphalTxProps {
+            priority: self.priority,
+            transfer_kind: CyphalTransferKind::Message,
+            transfer_id: middleware.transfer_id_for(CyphalTransferKind::Message, Synchronization::FIXED_PORT_ID, CYPHAL_NODE_ID_UNSET),
+            port_id: Synchronization::FIXED_PORT_ID,
+            remote_node_id: CYPHAL_NODE_ID_UNSET,
+        };
+        let packets: Vec<CyphalTxPacket<MTU>> = middleware.create_transfer_data(props, &message.serialize()?)?;
+        self.last_transfer_id = middleware.next_transfer_id(CyphalTransferKind::Message, Synchronization::FIXED_PORT_ID, CYPHAL_NODE_ID_UNSET);
+        Ok(packets)
+    }
+
+    /// Answers a GetSynchronizationMasterInfo request addressed to this node.
+    pub fn respond<const MTU: usize>(
+        &self,
+        middleware: &mut CyphalMiddleware<MTU>,
+        frame: &CyphalRxFrame
+    ) -> Result<Vec<CyphalTxPacket<MTU>>, Box<dyn std::error::Error>> {
+        if frame.props.transfer_kind != CyphalTransferKind::Request ||
+            frame.props.port_id != GetSynchronizationMasterInfoRequest::FIXED_PORT_ID ||
+            frame.props.destination_node_id != middleware.node_id()
+        {
+            return Ok(vec![]);
+        }
+        middleware.create_response_for(&frame.props, &self.info.serialize()?)
+    }
+}
+
+/// Time synchronization slave.
+/// The offset is measured from pairs of consecutive Synchronization messages: the master time carried by
+/// message N belongs to message N-1, whose local reception timestamp the slave has kept.
+/// When several masters are present, the one with the lowest node-ID is followed.
+pub struct CyphalTimeSyncSlave {
+    master_node_id: Option<CyphalNodeID>,
+    previous_transfer_id: CyphalTransferID,
+    previous_rx_timestamp_usec: CyphalMicrosecond,
+    /// Local time of the reference point and master-minus-local offset measured at it.
+    reference_local_usec: Option<CyphalMicrosecond>,
+    offset_usec: i64,
+    /// Master clock rate relative to the local clock, minus one.
+    drift: f64,
+    drift_gain: f64,
+}
+
+impl Default for CyphalTimeSyncSlave {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CyphalTimeSyncSlave {
+    pub fn new() -> Self {
+        Self {
+            master_node_id: None,
+            previous_transfer_id: 0,
+            previous_rx_timestamp_usec: 0,
+            reference_local_usec: None,
+            offset_usec: 0,
+            drift: 0.0,
+            drift_gain: 0.1,
+        }
+    }
+
+    /// Weight of a new drift measurement in the exponential moving average, between 0 and 1.
+    pub fn set_drift_gain(mut self, drift_gain: f64) -> Self {
+        self.drift_gain = drift_gain.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn master_node_id(&self) -> Option<CyphalNodeID> {
+        self.master_node_id
+    }
+
+    /// Estimated master-minus-local offset at the last measurement, in microseconds.
+    pub fn offset_usec(&self) -> Option<i64> {
+        self.reference_local_usec.map(|_| self.offset_usec)
+    }
+
+    /// Estimated relative drift of the master clock, in parts per million.
+    pub fn drift_ppm(&self) -> f64 {
+        self.drift * 1e6
+    }
+
+    /// Consumes a reassembled transfer together with the local timestamp of its first frame.
+    /// Returns true if the transfer updated the offset estimate.
+    pub fn accept(&mut self, frame: &CyphalRxFrame, rx_timestamp_usec: CyphalMicrosecond) -> Result<bool, Box<dyn std::error::Error>> {
+        if frame.props.transfer_kind != CyphalTransferKind::Message ||
+            frame.props.port_id != Synchronization::FIXED_PORT_ID ||
+            frame.props.source_node_id > CYPHAL_NODE_ID_MAX
+        {
+            return Ok(false);
+        }
+        let message: Synchronization = Synchronization::deserialize(&frame.payload)?;
+        let source: CyphalNodeID = frame.props.source_node_id;
+
+        let timeout_usec: CyphalMicrosecond = Synchronization::MAX_PUBLICATION_PERIOD_USEC * Synchronization::PUBLISHER_TIMEOUT_PERIOD_MULTIPLIER;
+        let master_timed_out: bool = rx_timestamp_usec.saturating_sub(self.previous_rx_timestamp_usec) > timeout_usec;
+        match self.master_node_id {
+            Some(x) if x == source => {},
+            Some(x) if x < source && !master_timed_out => return Ok(false),
+            _ => {
+                // Switch to the new master; its first message only provides the reference for the next one.
+                self.master_node_id = Some(source);
+                self.reference_local_usec = None;
+                self.drift = 0.0;
+                self.previous_transfer_id = frame.props.transfer_id;
+                self.previous_rx_timestamp_usec = rx_timestamp_usec;
+                return Ok(false);
+            },
+        }
+
+        let consecutive: bool = frame.props.transfer_id == ((self.previous_transfer_id + 1) & CYPHAL_TRANSFER_ID_MAX);
+        let previous_rx_timestamp_usec: CyphalMicrosecond = self.previous_rx_timestamp_usec;
+        self.previous_transfer_id = frame.props.transfer_id;
+        self.previous_rx_timestamp_usec = rx_timestamp_usec;
+
+        if !consecutive || master_timed_out || message.previous_transmission_timestamp_microsecond == 0 {
+            return Ok(false);
+        }
+
+        let offset_usec: i64 = message.previous_transmission_timestamp_microsecond as i64 - previous_rx_timestamp_usec as i64;
+        if let Some(reference_local_usec) = self.reference_local_usec {
+            let elapsed_usec: i64 = previous_rx_timestamp_usec as i64 - reference_local_usec as i64;
+            if elapsed_usec > 0 {
+                let measured_drift: f64 = (offset_usec - self.offset_usec) as f64 / elapsed_usec as f64;
+                self.drift += self.drift_gain * (measured_drift - self.drift);
+            }
+        }
+        self.reference_local_usec = Some(previous_rx_timestamp_usec);
+        self.offset_usec = offset_usec;
+        Ok(true)
+    }
+
+    /// Converts a local timestamp into master time, extrapolating with the estimated drift.
+    pub fn to_master_time(&self, local_usec: CyphalMicrosecond) -> Option<CyphalMicrosecond> {
+        let reference_local_usec: CyphalMicrosecond = self.reference_local_usec?;
+        let elapsed_usec: f64 = local_usec as f64 - reference_local_usec as f64;
+        let master_usec: i64 = local_usec as i64 + self.offset_usec + (self.drift * elapsed_usec) as i64;
+        Some(std::cmp::max(master_usec, 0) as CyphalMicrosecond)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::tx_tracker::CanTxTracker;
+
+    const MTU: usize = CYPHAL_MTU_CAN_FD as usize;
+    const PERIOD_USEC: CyphalMicrosecond = 1_000_000;
+
+    fn deliver(receiver: &mut CyphalMiddleware<MTU>, packets: &[CyphalTxPacket<MTU>], now_usec: CyphalMicrosecond) -> Vec<CyphalRxFrame> {
+        packets.iter()
+            .map(|x| CyphalRxPacket::<MTU>::from_frame(x.xid, &x.payload[..x.payload_size]).unwrap())
+            .filter_map(|x| receiver.accept(&x, now_usec).unwrap())
+            .collect()
+    }
+
+    /// Master clock running 100 ppm fast and 5 ms ahead of the local clock.
+    fn master_usec(local_usec: CyphalMicrosecond) -> CyphalMicrosecond {
+        local_usec + 5_000 + local_usec / 10_000
+    }
+
+    /// Publishes `count` Synchronization messages, received without latency, and returns whether each updated the slave.
+    fn synchronize(slave: &mut CyphalTimeSyncSlave, count: u64) -> Vec<bool> {
+        let mut master: CyphalTimeSyncMaster = CyphalTimeSyncMaster::new();
+        let mut middleware: CyphalMiddleware<MTU> = CyphalMiddleware::new(1);
+        let mut receiver: CyphalMiddleware<MTU> = CyphalMiddleware::new(2);
+        let mut ret: Vec<bool> = vec![];
+        for k in 1..=count {
+            let local_usec: CyphalMicrosecond = k * PERIOD_USEC;
+            let packets: Vec<CyphalTxPacket<MTU>> = master.poll(&mut middleware, local_usec).unwrap();
+            master.record_transmission_timestamp(master_usec(local_usec));
+            for frame in deliver(&mut receiver, &packets, local_usec) {
+                ret.push(slave.accept(&frame, local_usec).unwrap());
+            }
+        }
+        ret
+    }
+
+    #[test]
+    fn slave_estimates_offset_and_drift() {
+        let mut slave: CyphalTimeSyncSlave = CyphalTimeSyncSlave::new().set_drift_gain(1.0);
+        // The first message only selects the master and the second one carries no timestamp yet.
+        assert_eq!(synchronize(&mut slave, 4), vec![false, true, true, true]);
+        assert_eq!(slave.master_node_id(), Some(1));
+        assert_eq!(slave.offset_usec(), Some((master_usec(3 * PERIOD_USEC) - 3 * PERIOD_USEC) as i64));
+        assert!((slave.drift_ppm() - 100.0).abs() < 1e-6);
+        let local_usec: CyphalMicrosecond = 10 * PERIOD_USEC;
+        assert_eq!(slave.to_master_time(local_usec), Some(master_usec(local_usec)));
+    }
+
+    #[test]
+    fn slave_follows_the_master_with_the_lowest_node_id() {
+        let mut slave: CyphalTimeSyncSlave = CyphalTimeSyncSlave::new();
+        let mut receiver: CyphalMiddleware<MTU> = CyphalMiddleware::new(3);
+        for (node_id, now_usec) in [(10, 0), (5, 1), (10, 2)] {
+            let mut middleware: CyphalMiddleware<MTU> = CyphalMiddleware::new(node_id);
+            let packets: Vec<CyphalTxPacket<MTU>> = CyphalTimeSyncMaster::new().poll(&mut middleware, 0).unwrap();
+            for frame in deliver(&mut receiver, &packets, now_usec) {
+                slave.accept(&frame, now_usec).unwrap();
+            }
+        }
+        assert_eq!(slave.master_node_id(), Some(5));
+    }
+
+    #[test]
+    fn master_takes_the_timestamp_of_its_own_confirmed_transfer() {
+        let mut master: CyphalTimeSyncMaster = CyphalTimeSyncMaster::new();
+        let mut middleware: CyphalMiddleware<MTU> = CyphalMiddleware::new(1);
+        let mut tracker: CanTxTracker = CanTxTracker::new();
+
+        let sync: Vec<CyphalTxPacket<MTU>> = master.poll(&mut middleware, 0).unwrap();
+        let other: Vec<CyphalTxPacket<MTU>> = middleware.create_message_data(Synchronization::FIXED_PORT_ID + 1, &[0; 7], 7).unwrap();
+        tracker.record(&sync);
+        tracker.record(&other);
+        tracker.confirm(other[0].marker, 1_000);
+        tracker.confirm(sync[0].marker, 2_000);
+
+        let accepted: Vec<bool> = tracker.take_confirmations().iter().map(|x| master.accept_tx_confirmation(x)).collect();
+        assert_eq!(accepted, vec![false, true]);
+
+        // The next message carries the confirmed time; a stale confirmation of the first one no longer matches.
+        let mut receiver: CyphalMiddleware<MTU> = CyphalMiddleware::new(2);
+        let frames: Vec<CyphalRxFrame> = deliver(&mut receiver, &master.poll(&mut middleware, PERIOD_USEC).unwrap(), 0);
+        assert_eq!(Synchronization::deserialize(&frames[0].payload).unwrap().previous_transmission_timestamp_microsecond, 2_000);
+        tracker.record(&sync);
+        tracker.confirm(sync[0].marker, 3_000);
+        assert!(!master.accept_tx_confirmation(&tracker.take_confirmations()[0]));
+    }
+
+    #[test]
+    fn unknown_time_system_serializes_back_unchanged() {
+        let info: GetSynchronizationMasterInfoResponse = GetSynchronizationMasterInfoResponse {
+            error_variance: 0.5,
+            time_system: TimeSystem::Reserved(7),
+            difference_tai_minus_utc: 37,
+        };
+        let data: Vec<u8> = info.serialize().unwrap();
+        assert_eq!(data[4], 7);
+        assert_eq!(GetSynchronizationMasterInfoResponse::deserialize(&data).unwrap(), info);
+        assert_eq!(TimeSystem::from(15), TimeSystem::ApplicationSpecific);
+    }
+}
@@ -0,0 +1,300 @@
+//! Table of online nodes built from received heartbeats.
+
+use super::CyphalMiddleware;
+use super::defines::*;
+use super::service::{CyphalRequestKey, CyphalServiceClient};
+use super::uavcan::CyphalDataType;
+use super::uavcan::node::*;
+
+use std::collections::{BTreeMap, HashMap};
+
+/// Number of GetInfo requests sent to a node before giving up.
+pub const NODE_TRACKER_GET_INFO_ATTEMPTS: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct CyphalNodeEntry {
+    pub node_id: CyphalNodeID,
+    pub heartbeat: Heartbeat,
+    pub first_seen_usec: CyphalMicrosecond,
+    pub last_seen_usec: CyphalMicrosecond,
+    pub info: Option<GetInfoResponse>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum CyphalNodeEvent {
+    Online(CyphalNodeID),
+    /// No heartbeat for longer than the offline timeout; the node has been removed from the table.
+    Offline(CyphalNodeID),
+    /// The reported uptime went down.
+    Restarted(CyphalNodeID),
+    InfoReceived(CyphalNodeID),
+}
+
+/// Tracks the nodes on the bus from their heartbeats. Events are queued and collected with `take_events`.
+/// If enabled, GetInfo is requested from every node that comes online or restarts. The requests go through
+/// the service client passed to `poll`, the one of the node, so that its pending requests and transfer-IDs
+/// are shared with the application's; `CyphalNode::set_node_tracker` does this wiring.
+pub struct CyphalNodeTracker {
+    nodes: BTreeMap<CyphalNodeID, CyphalNodeEntry>,
+    events: Vec<CyphalNodeEvent>,
+    offline_timeout_usec: CyphalMicrosecond,
+    fetch_info: bool,
+    info_requests: HashMap<CyphalRequestKey, CyphalNodeID>,
+    info_attempts: HashMap<CyphalNodeID, usize>,
+}
+
+impl Default for CyphalNodeTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CyphalNodeTracker {
+    pub fn new() -> Self {
+        Self {
+            nodes: BTreeMap::new(),
+            events: vec![],
+            offline_timeout_usec: Heartbeat::OFFLINE_TIMEOUT_USEC,
+            fetch_info: false,
+            info_requests: HashMap::new(),
+            info_attempts: HashMap::new(),
+        }
+    }
+
+    pub fn set_offline_timeout_usec(mut self, offline_timeout_usec: CyphalMicrosecond) -> Self {
+        self.offline_timeout_usec = offline_timeout_usec;
+        self
+    }
+
+    /// Requests uavcan.node.GetInfo from newly seen nodes; requires a node-ID of our own.
+    pub fn set_fetch_info(mut self, fetch_info: bool) -> Self {
+        self.fetch_info = fetch_info;
+        self
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &CyphalNodeEntry> {
+        self.nodes.values()
+    }
+
+    pub fn get(&self, node_id: CyphalNodeID) -> Option<&CyphalNodeEntry> {
+        self.nodes.get(&node_id)
+    }
+
+    pub fn take_events(&mut self) -> Vec<CyphalNodeEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Consumes a decoded heartbeat.
+    pub fn accept_heartbeat(&mut self, heartbeat: &CyphalRxData<Heartbeat>, now_usec: CyphalMicrosecond) {
+        let node_id: CyphalNodeID = heartbeat.props.source_node_id;
+        if node_id > CYPHAL_NODE_ID_MAX {
+            return;
+        }
+        match self.nodes.get_mut(&node_id) {
+            Some(entry) => {
+                if heartbeat.data.uptime < entry.heartbeat.uptime {
+                    entry.info = None;
+                    self.info_attempts.remove(&node_id);
+                    self.events.push(CyphalNodeEvent::Restarted(node_id));
+                }
+                entry.heartbeat = heartbeat.data;
+                entry.last_seen_usec = now_usec;
+            },
+            None => {
+                self.nodes.insert(node_id, CyphalNodeEntry {
+                    node_id,
+                    heartbeat: heartbeat.data,
+                    first_seen_usec: now_usec,
+                    last_seen_usec: now_usec,
+                    info: None,
+                });
+                self.info_attempts.remove(&node_id);
+                self.events.push(CyphalNodeEvent::Online(node_id));
+            },
+        }
+    }
+
+    /// Consumes a reassembled transfer; heartbeats update the table and anything else is ignored.
+    pub fn accept(&mut self, frame: &CyphalRxFrame, now_usec: CyphalMicrosecond) -> Result<(), Box<dyn std::error::Error>> {
+        if frame.props.transfer_kind == CyphalTransferKind::Message && frame.props.port_id == Heartbeat::FIXED_PORT_ID {
+            let heartbeat: CyphalRxData<Heartbeat> = CyphalRxData { data: Heartbeat::deserialize(&frame.payload)?, props: frame.props };
+            self.accept_heartbeat(&heartbeat, now_usec);
+        }
+        Ok(())
+    }
+
+    /// Removes silent nodes, collects the GetInfo responses completed in the client and returns the GetInfo requests to transmit.
+    /// Responses have to be fed to `service_client` before, and its timeouts polled.
+    pub fn poll<const MTU: usize>(
+        &mut self,
+        service_client: &mut CyphalServiceClient,
+        middleware: &mut CyphalMiddleware<MTU>,
+        now_usec: CyphalMicrosecond
+    ) -> Result<Vec<CyphalTxPacket<MTU>>, Box<dyn std::error::Error>> {
+        let offline: Vec<CyphalNodeID> = self.nodes.values()
+            .filter(|x| now_usec.saturating_sub(x.last_seen_usec) > self.offline_timeout_usec)
+            .map(|x| x.node_id)
+            .collect();
+        for node_id in offline {
+            self.nodes.remove(&node_id);
+            self.events.push(CyphalNodeEvent::Offline(node_id));
+        }
+
+        let keys: Vec<CyphalRequestKey> = self.info_requests.keys().copied().collect();
+        for key in keys {
+            if service_client.is_pending(&key) {
+                continue;
+            }
+            let node_id: CyphalNodeID = self.info_requests.remove(&key).unwrap_or(CYPHAL_NODE_ID_UNSET);
+            // Timed out or cancelled requests are retried until the attempts run out.
+            let frame: CyphalRxFrame = match service_client.take_result(&key) {
+                Some(Ok(x)) => x,
+                _ => continue,
+            };
+            if let Some(entry) = self.nodes.get_mut(&node_id) {
+                entry.info = Some(GetInfoResponse::deserialize(&frame.payload)?);
+                self.events.push(CyphalNodeEvent::InfoReceived(node_id));
+            }
+        }
+
+        let mut packets: Vec<CyphalTxPacket<MTU>> = vec![];
+        if !self.fetch_info || middleware.is_anonymous() {
+            return Ok(packets);
+        }
+        let wanted: Vec<CyphalNodeID> = self.nodes.values()
+            .filter(|x| x.info.is_none())
+            .filter(|x| !self.info_requests.values().any(|n| *n == x.node_id))
+            .filter(|x| *self.info_attempts.get(&x.node_id).unwrap_or(&0) < NODE_TRACKER_GET_INFO_ATTEMPTS)
+            .map(|x| x.node_id)
+            .collect();
+        for node_id in wanted {
+            let (key, request) = service_client.request(middleware, node_id, GetInfoRequest::FIXED_PORT_ID, &[], now_usec)?;
+            *self.info_attempts.entry(node_id).or_insert(0) += 1;
+            self.info_requests.insert(key, node_id);
+            packets.extend(request);
+        }
+        Ok(packets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heartbeat(node_id: CyphalNodeID, uptime: u32) -> CyphalRxData<Heartbeat> {
+        CyphalRxData {
+            data: Heartbeat { uptime, health: Health::Nominal, mode: Mode::Operational, vendor_specific_status_code: 0 },
+            props: CyphalRxProps {
+                priority: CyphalPriority::Nominal,
+                transfer_kind: CyphalTransferKind::Message,
+                transfer_id: 0,
+                port_id: Heartbeat::FIXED_PORT_ID,
+                source_node_id: node_id,
+                destination_node_id: CYPHAL_NODE_ID_UNSET,
+            },
+        }
+    }
+
+    fn deliver(receiver: &mut CyphalMiddleware<8>, packets: &[CyphalTxPacket<8>]) -> Vec<CyphalRxFrame> {
+        packets.iter()
+            .map(|x| CyphalRxPacket::<8>::from_frame(x.xid, &x.payload[..x.payload_size]).unwrap())
+            .filter_map(|x| receiver.accept(&x, 0).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn first_heartbeat_brings_a_node_online() {
+        let mut tracker: CyphalNodeTracker = CyphalNodeTracker::new();
+        tracker.accept_heartbeat(&heartbeat(5, 10), 1_000);
+        tracker.accept_heartbeat(&heartbeat(5, 11), 2_000);
+        assert_eq!(tracker.take_events(), vec![CyphalNodeEvent::Online(5)]);
+        let entry: &CyphalNodeEntry = tracker.get(5).unwrap();
+        assert_eq!(entry.first_seen_usec, 1_000);
+        assert_eq!(entry.last_seen_usec, 2_000);
+        assert_eq!(entry.heartbeat.uptime, 11);
+    }
+
+    #[test]
+    fn anonymous_heartbeats_are_ignored() {
+        let mut tracker: CyphalNodeTracker = CyphalNodeTracker::new();
+        tracker.accept_heartbeat(&heartbeat(CYPHAL_NODE_ID_UNSET, 10), 0);
+        assert!(tracker.take_events().is_empty());
+        assert_eq!(tracker.nodes().count(), 0);
+    }
+
+    #[test]
+    fn silent_node_goes_offline_after_the_timeout() {
+        let mut middleware: CyphalMiddleware<8> = CyphalMiddleware::new(1);
+        let mut client: CyphalServiceClient = CyphalServiceClient::new();
+        let mut tracker: CyphalNodeTracker = CyphalNodeTracker::new().set_offline_timeout_usec(1_000);
+        tracker.accept_heartbeat(&heartbeat(5, 0), 0);
+        tracker.take_events();
+
+        tracker.poll(&mut client, &mut middleware, 1_000).unwrap();
+        assert!(tracker.take_events().is_empty());
+        tracker.poll(&mut client, &mut middleware, 1_001).unwrap();
+        assert_eq!(tracker.take_events(), vec![CyphalNodeEvent::Offline(5)]);
+        assert!(tracker.get(5).is_none());
+
+        tracker.accept_heartbeat(&heartbeat(5, 2), 2_000);
+        assert_eq!(tracker.take_events(), vec![CyphalNodeEvent::Online(5)]);
+    }
+
+    #[test]
+    fn uptime_decrease_is_a_restart() {
+        let mut tracker: CyphalNodeTracker = CyphalNodeTracker::new();
+        tracker.accept_heartbeat(&heartbeat(5, 100), 0);
+        tracker.accept_heartbeat(&heartbeat(5, 100), 1_000);
+        tracker.accept_heartbeat(&heartbeat(5, 1), 2_000);
+        assert_eq!(tracker.take_events(), vec![CyphalNodeEvent::Online(5), CyphalNodeEvent::Restarted(5)]);
+        assert_eq!(tracker.get(5).unwrap().heartbeat.uptime, 1);
+        assert_eq!(tracker.get(5).unwrap().first_seen_usec, 0);
+    }
+
+    #[test]
+    fn get_info_goes_through_the_given_client() {
+        let mut middleware: CyphalMiddleware<8> = CyphalMiddleware::new(1);
+        let mut client: CyphalServiceClient = CyphalServiceClient::new();
+        let mut tracker: CyphalNodeTracker = CyphalNodeTracker::new().set_fetch_info(true);
+        let mut server: CyphalMiddleware<8> = CyphalMiddleware::new(5);
+        server.subscribe(CyphalTransferKind::Request, GetInfoRequest::FIXED_PORT_ID).unwrap();
+
+        // A request of the application takes the first transfer-ID of the session.
+        let (application_key, _) = client.request(&mut middleware, 5, GetInfoRequest::FIXED_PORT_ID, &[], 0).unwrap();
+        tracker.accept_heartbeat(&heartbeat(5, 0), 0);
+        let packets: Vec<CyphalTxPacket<8>> = tracker.poll(&mut client, &mut middleware, 0).unwrap();
+        let requests: Vec<CyphalRxFrame> = deliver(&mut server, &packets);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].props.transfer_id, application_key.transfer_id + 1);
+        assert_eq!(client.pending_count(), 2);
+        // No second request while the first one is pending.
+        assert!(tracker.poll(&mut client, &mut middleware, 0).unwrap().is_empty());
+
+        let info: GetInfoResponse = GetInfoResponse { name: "remote".to_string(), ..Default::default() };
+        let response: Vec<CyphalTxPacket<8>> = server.create_response_for(&requests[0].props, &info.serialize().unwrap()).unwrap();
+        for frame in deliver(&mut middleware, &response) {
+            client.accept(&middleware, &frame);
+        }
+        tracker.take_events();
+        assert!(tracker.poll(&mut client, &mut middleware, 0).unwrap().is_empty());
+        assert_eq!(tracker.take_events(), vec![CyphalNodeEvent::InfoReceived(5)]);
+        assert_eq!(tracker.get(5).unwrap().info.as_ref().unwrap().name, "remote");
+        assert!(client.is_pending(&application_key));
+    }
+
+    #[test]
+    fn get_info_is_retried_after_a_timeout_until_the_attempts_run_out() {
+        let mut middleware: CyphalMiddleware<8> = CyphalMiddleware::new(1);
+        let mut client: CyphalServiceClient = CyphalServiceClient::new().set_timeout_usec(100);
+        let mut tracker: CyphalNodeTracker = CyphalNodeTracker::new().set_fetch_info(true);
+        tracker.accept_heartbeat(&heartbeat(5, 0), 0);
+
+        for attempt in 0..NODE_TRACKER_GET_INFO_ATTEMPTS as CyphalMicrosecond {
+            client.poll_timeouts(attempt * 100);
+            assert!(!tracker.poll(&mut client, &mut middleware, attempt * 100).unwrap().is_empty());
+        }
+        client.poll_timeouts(NODE_TRACKER_GET_INFO_ATTEMPTS as CyphalMicrosecond * 100);
+        assert!(tracker.poll(&mut client, &mut middleware, NODE_TRACKER_GET_INFO_ATTEMPTS as CyphalMicrosecond * 100).unwrap().is_empty());
+        assert_eq!(client.pending_count(), 0);
+    }
+}
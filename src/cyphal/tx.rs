@@ -87,17 +87,58 @@ impl <const MTU: usize> CyphalMiddleware<MTU> {
         self.transfer_id = if self.transfer_id == 255 { 0 } else { self.transfer_id + 1 };
         self.create_packet(transfer_data)
     }
+
+    /// Encodes a transfer with caller-supplied properties. The shared `transfer_id` counter is left untouched,
+    /// so callers can keep a transfer-ID per session or reuse the transfer-ID of a request in its response.
+    pub fn create_transfer_data(
+        &mut self,
+        props: CyphalTxProps,
+        data: &[u8]
+    ) -> Result<Vec<CyphalTxPacket<MTU>>, Box<dyn std::error::Error>> {
+        let transfer_data: CyphalTxPacketFrame = CyphalTxPacketFrame {
+            props,
+            payload_size: data.len(),
+            payload: Vec::from(data),
+        };
+        self.create_packet(transfer_data)
+    }
+
+    /// Encodes the response to a received request, addressed to the requester and carrying the request's transfer-ID and priority.
+    pub fn create_response_for(
+        &mut self,
+        request: &CyphalRxProps,
+        data: &[u8]
+    ) -> Result<Vec<CyphalTxPacket<MTU>>, Box<dyn std::error::Error>> {
+        if request.transfer_kind != CyphalTransferKind::Request {
+            return Err("INVALID TRANSFER KIND".into());
+        }
+        let props: CyphalTxProps = CyphalTxProps {
+            priority: request.priority,
+            transfer_kind: CyphalTransferKind::Response,
+            transfer_id: request.transfer_id,
+            port_id: request.port_id,
+            remote_node_id: request.source_node_id,
+        };
+        self.create_transfer_data(props, data)
+    }
 }
 
 // Private functions
 impl <const MTU: usize> CyphalMiddleware<MTU> {
-    fn create_packet<T: Borrow<CyphalTxPacketFrame>>(&self, transfer_data: T) -> Result<Vec<CyphalTxPacket<MTU>>, Box<dyn std::error::Error>> {
+    fn create_packet<T: Borrow<CyphalTxPacketFrame>>(&mut self, transfer_data: T) -> Result<Vec<CyphalTxPacket<MTU>>, Box<dyn std::error::Error>> {
         let transfer_data: &CyphalTxPacketFrame = transfer_data.borrow();
+        let mut packets: Vec<CyphalTxPacket<MTU>> = self.create_packet_impl(transfer_data)?;
+        self.ports.record(transfer_data.props.transfer_kind, transfer_data.props.port_id);
+        for packet in packets.iter_mut() {
+            packet.marker = self.tx_marker;
+        }
+        self.tx_marker = self.tx_marker.wrapping_add(1);
+        Ok(packets)
+    }
+
+    fn create_packet_impl(&self, transfer_data: &CyphalTxPacketFrame) -> Result<Vec<CyphalTxPacket<MTU>>, Box<dyn std::error::Error>> {
         let pl_mtu: u8 = self.tx_get_presentation_layer_mtu();
-        let can_id: u32 = match self.tx_make_can_id(transfer_data, self.can_instance.node_id) {
-            Ok(x) => x,
-            Err(err) => return Err(err)
-        };
+        let can_id: u32 = self.tx_make_can_id(transfer_data, self.can_instance.node_id)?;
         if can_id > 0 {
             if transfer_data.payload_size <= pl_mtu as usize {
                 match self.handle_single_frame(can_id, transfer_data) {
@@ -122,19 +163,14 @@ impl <const MTU: usize> CyphalMiddleware<MTU> {
             return Err("INVALID PAYLOAD LENGTH".into());
         };
 
-        let frame_payload_size: usize = match Self::tx_round_frame_payload_sizeup(transfer_data.payload_size + 1) {
-            Ok(x) => x,
-            Err(err) => return Err(err)
-        };
+        let frame_payload_size: usize = Self::tx_round_frame_payload_sizeup(transfer_data.payload_size + 1)?;
 
         if frame_payload_size > self.can_instance.mtu_bytes {
             return Err("INVALID FRAME LENGTH".into());
         };
 
         let mut payload: [u8; MTU] = [0; MTU];
-        for i in 0..transfer_data.payload_size {
-            payload[i] = transfer_data.payload[i];
-        }
+        payload[..transfer_data.payload_size].copy_from_slice(&transfer_data.payload[..transfer_data.payload_size]);
 
         match Self::tx_make_tail_byte(true, true, true, transfer_data.props.transfer_id) {
             Ok(tail_byte) => payload[frame_payload_size - 1] = tail_byte,
@@ -143,7 +179,7 @@ impl <const MTU: usize> CyphalMiddleware<MTU> {
 
         let payload_size: usize = frame_payload_size;
         
-        Ok(CyphalTxPacket { xid, payload, payload_size })
+        Ok(CyphalTxPacket::new(xid, payload, payload_size))
     }
 
     fn handle_multi_frame(&self, xid: u32, transfer_data: &CyphalTxPacketFrame) -> Result<Vec<CyphalTxPacket<MTU>>, Box<dyn std::error::Error>> {
@@ -176,8 +212,8 @@ impl <const MTU: usize> CyphalMiddleware<MTU> {
                 false => 0
             };
 
-            for i in 0..payload_size_in_frame {
-                payload[i] = transfer_data.payload[i + offset];
+            if payload_size_in_frame > 0 {
+                payload[..payload_size_in_frame].copy_from_slice(&transfer_data.payload[offset..(offset + payload_size_in_frame)]);
             }
 
             frame_offset += payload_size_in_frame;
@@ -214,11 +250,7 @@ impl <const MTU: usize> CyphalMiddleware<MTU> {
             start_of_transfer = false;
             toggle = !toggle;
     
-            packets.push(CyphalTxPacket {
-                xid,
-                payload,
-                payload_size: frame_payload_size_with_tail
-            });
+            packets.push(CyphalTxPacket::new(xid, payload, frame_payload_size_with_tail));
         }
 
         Ok(packets)
@@ -229,7 +261,7 @@ impl <const MTU: usize> CyphalMiddleware<MTU> {
         let mtu: u8 = if self.can_instance.mtu_bytes < (CYPHAL_MTU_CAN_CLASSIC as usize) {
             CYPHAL_MTU_CAN_CLASSIC
         } else if self.can_instance.mtu_bytes < (MAX_INDEX as usize) {
-            CAN_DLC_TO_DLEN[CAN_DLEN_TO_DLC[self.can_instance.mtu_bytes as usize] as usize]
+            CAN_DLC_TO_DLEN[CAN_DLEN_TO_DLC[self.can_instance.mtu_bytes] as usize]
         } else {
             CAN_DLC_TO_DLEN[CAN_DLEN_TO_DLC[MAX_INDEX as usize] as usize]
         };
@@ -286,10 +318,7 @@ impl <const MTU: usize> CyphalMiddleware<MTU> {
             transfer_data.props.port_id <= CYPHAL_SUBJECT_ID_MAX
         {
             out = if local_node_id <= CYPHAL_NODE_ID_MAX {
-                match Self::tx_make_message_session_specifier(transfer_data.props.port_id, local_node_id) {
-                    Ok(x) => x,
-                    Err(err) => return Err(err)
-                }
+                Self::tx_make_message_session_specifier(transfer_data.props.port_id, local_node_id)?
             }
             else if transfer_data.payload_size <= pl_mtu as usize {
                 let c: u8 = match crc_add(CRC_INITIAL, transfer_data.payload_size, &transfer_data.payload) {
@@ -310,15 +339,12 @@ impl <const MTU: usize> CyphalMiddleware<MTU> {
             (transfer_data.props.port_id <= CYPHAL_SERVICE_ID_MAX)
         {
             out = if local_node_id <= CYPHAL_NODE_ID_MAX {
-                match Self::tx_make_service_session_specifier(
+                Self::tx_make_service_session_specifier(
                     transfer_data.props.port_id,
                     transfer_data.props.transfer_kind == CyphalTransferKind::Request,
                     local_node_id,
                     transfer_data.props.remote_node_id
-                ) {
-                    Ok(x) => x,
-                    Err(err) => return Err(err)
-                }
+                )?
             }
             else {
                 return Err("INVALID_ARGUMENT".into())  // Anonymous service transfers are not allowed.
@@ -0,0 +1,335 @@
+use super::*;
+
+/// Maximum length of uavcan.file.Path.2.0.
+pub const PATH_MAX_LENGTH: usize = 255;
+pub const PATH_SEPARATOR: u8 = b'/';
+
+/// Capacity of uavcan.primitive.Unstructured.1.0 as used by Read and Write.
+pub const DATA_CAPACITY: usize = 256;
+
+/// uavcan.file.Error.1.0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct FileError(pub u16);
+
+impl FileError {
+    pub const OK: FileError = FileError(0);
+    pub const UNKNOWN_ERROR: FileError = FileError(65535);
+    pub const NOT_FOUND: FileError = FileError(2);
+    pub const IO_ERROR: FileError = FileError(5);
+    pub const ACCESS_DENIED: FileError = FileError(13);
+    pub const IS_DIRECTORY: FileError = FileError(21);
+    pub const INVALID_VALUE: FileError = FileError(22);
+    pub const FILE_TOO_LARGE: FileError = FileError(27);
+    pub const OUT_OF_SPACE: FileError = FileError(28);
+    pub const NOT_SUPPORTED: FileError = FileError(38);
+}
+
+impl From<&std::io::Error> for FileError {
+    fn from(err: &std::io::Error) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::NotFound => FileError::NOT_FOUND,
+            std::io::ErrorKind::PermissionDenied => FileError::ACCESS_DENIED,
+            std::io::ErrorKind::IsADirectory => FileError::IS_DIRECTORY,
+            std::io::ErrorKind::InvalidInput => FileError::INVALID_VALUE,
+            std::io::ErrorKind::FileTooLarge => FileError::FILE_TOO_LARGE,
+            std::io::ErrorKind::StorageFull => FileError::OUT_OF_SPACE,
+            std::io::ErrorKind::Unsupported => FileError::NOT_SUPPORTED,
+            _ => FileError::IO_ERROR,
+        }
+    }
+}
+
+fn write_path(out: &mut Vec<u8>, path: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    if path.len() > PATH_MAX_LENGTH {
+        return Err("INVALID PATH LENGTH".into());
+    }
+    out.push(path.len() as u8);
+    out.extend_from_slice(path);
+    Ok(())
+}
+
+fn read_path(reader: &mut DsdlReader) -> Vec<u8> {
+    let size: usize = reader.read_u8() as usize;
+    reader.read_bytes(size)
+}
+
+fn write_data(out: &mut Vec<u8>, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    if data.len() > DATA_CAPACITY {
+        return Err("INVALID DATA LENGTH".into());
+    }
+    write_uint(out, data.len() as u64, 2);
+    out.extend_from_slice(data);
+    Ok(())
+}
+
+fn read_data(reader: &mut DsdlReader) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let size: usize = reader.read_uint(2) as usize;
+    if size > DATA_CAPACITY {
+        return Err("INVALID DATA LENGTH".into());
+    }
+    Ok(reader.read_bytes(size))
+}
+
+/// uavcan.file.GetInfo.0.2 request.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct GetInfoRequest {
+    pub path: Vec<u8>,
+}
+
+impl GetInfoRequest {
+    pub const FIXED_PORT_ID: CyphalPortID = 405;
+}
+
+impl CyphalDataType for GetInfoRequest {
+    fn serialize(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut ret: Vec<u8> = vec![];
+        write_path(&mut ret, &self.path)?;
+        Ok(ret)
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader: DsdlReader = DsdlReader::new(data);
+        Ok(Self { path: read_path(&mut reader) })
+    }
+}
+
+/// uavcan.file.GetInfo.0.2 response.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct GetInfoResponse {
+    pub error: FileError,
+    pub size: u64,
+    pub unix_timestamp_of_last_modification: u64,
+    pub is_file_not_directory: bool,
+    pub is_link: bool,
+    pub is_readable: bool,
+    pub is_writeable: bool,
+}
+
+impl GetInfoResponse {
+    pub fn from_error(error: FileError) -> Self {
+        Self {
+            error,
+            size: 0,
+            unix_timestamp_of_last_modification: 0,
+            is_file_not_directory: false,
+            is_link: false,
+            is_readable: false,
+            is_writeable: false,
+        }
+    }
+}
+
+impl CyphalDataType for GetInfoResponse {
+    fn serialize(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        const UINT40_MAX: u64 = (1 << 40) - 1;
+        let mut ret: Vec<u8> = vec![];
+        write_uint(&mut ret, self.error.0 as u64, 2);
+        write_uint(&mut ret, std::cmp::min(self.size, UINT40_MAX), 5);
+        write_uint(&mut ret, std::cmp::min(self.unix_timestamp_of_last_modification, UINT40_MAX), 5);
+        ret.push(
+            (self.is_file_not_directory as u8) |
+            ((self.is_link as u8) << 1) |
+            ((self.is_readable as u8) << 2) |
+            ((self.is_writeable as u8) << 3)
+        );
+        Ok(ret)
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader: DsdlReader = DsdlReader::new(data);
+        let error: FileError = FileError(reader.read_uint(2) as u16);
+        let size: u64 = reader.read_uint(5);
+        let unix_timestamp_of_last_modification: u64 = reader.read_uint(5);
+        let flags: u8 = reader.read_u8();
+        Ok(Self {
+            error,
+            size,
+            unix_timestamp_of_last_modification,
+            is_file_not_directory: (flags & 0x01) != 0,
+            is_link: (flags & 0x02) != 0,
+            is_readable: (flags & 0x04) != 0,
+            is_writeable: (flags & 0x08) != 0,
+        })
+    }
+}
+
+/// uavcan.file.List.0.2 request.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ListRequest {
+    pub entry_index: u32,
+    pub directory_path: Vec<u8>,
+}
+
+impl ListRequest {
+    pub const FIXED_PORT_ID: CyphalPortID = 406;
+}
+
+impl CyphalDataType for ListRequest {
+    fn serialize(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut ret: Vec<u8> = vec![];
+        write_uint(&mut ret, self.entry_index as u64, 4);
+        write_uint(&mut ret, 0, 4);
+        write_path(&mut ret, &self.directory_path)?;
+        Ok(ret)
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader: DsdlReader = DsdlReader::new(data);
+        let entry_index: u32 = reader.read_uint(4) as u32;
+        reader.read_uint(4);
+        Ok(Self { entry_index, directory_path: read_path(&mut reader) })
+    }
+}
+
+/// uavcan.file.List.0.2 response. An empty name marks the end of the directory listing.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ListResponse {
+    pub entry_base_name: Vec<u8>,
+}
+
+impl CyphalDataType for ListResponse {
+    fn serialize(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut ret: Vec<u8> = vec![];
+        write_uint(&mut ret, 0, 4);
+        write_path(&mut ret, &self.entry_base_name)?;
+        Ok(ret)
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader: DsdlReader = DsdlReader::new(data);
+        reader.read_uint(4);
+        Ok(Self { entry_base_name: read_path(&mut reader) })
+    }
+}
+
+/// uavcan.file.Modify.1.1 request.
+/// Copy: source and destination with preserve_source. Move: source and destination.
+/// Touch: empty source. Remove: empty destination.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ModifyRequest {
+    pub preserve_source: bool,
+    pub overwrite_destination: bool,
+    pub source: Vec<u8>,
+    pub destination: Vec<u8>,
+}
+
+impl ModifyRequest {
+    pub const FIXED_PORT_ID: CyphalPortID = 407;
+}
+
+impl CyphalDataType for ModifyRequest {
+    fn serialize(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut ret: Vec<u8> = vec![];
+        let flags: u32 = (self.preserve_source as u32) | ((self.overwrite_destination as u32) << 1);
+        write_uint(&mut ret, flags as u64, 4);
+        write_path(&mut ret, &self.source)?;
+        write_path(&mut ret, &self.destination)?;
+        Ok(ret)
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader: DsdlReader = DsdlReader::new(data);
+        let flags: u64 = reader.read_uint(4);
+        Ok(Self {
+            preserve_source: (flags & 0x01) != 0,
+            overwrite_destination: (flags & 0x02) != 0,
+            source: read_path(&mut reader),
+            destination: read_path(&mut reader),
+        })
+    }
+}
+
+/// Response of uavcan.file.Modify.1.1 and uavcan.file.Write.1.1, which only carry an error code.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct FileErrorResponse {
+    pub error: FileError,
+}
+
+impl CyphalDataType for FileErrorResponse {
+    fn serialize(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut ret: Vec<u8> = vec![];
+        write_uint(&mut ret, self.error.0 as u64, 2);
+        Ok(ret)
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader: DsdlReader = DsdlReader::new(data);
+        Ok(Self { error: FileError(reader.read_uint(2) as u16) })
+    }
+}
+
+/// uavcan.file.Read.1.1 request.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ReadRequest {
+    pub offset: u64,
+    pub path: Vec<u8>,
+}
+
+impl ReadRequest {
+    pub const FIXED_PORT_ID: CyphalPortID = 408;
+}
+
+impl CyphalDataType for ReadRequest {
+    fn serialize(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut ret: Vec<u8> = vec![];
+        write_uint(&mut ret, self.offset, 5);
+        write_path(&mut ret, &self.path)?;
+        Ok(ret)
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader: DsdlReader = DsdlReader::new(data);
+        Ok(Self { offset: reader.read_uint(5), path: read_path(&mut reader) })
+    }
+}
+
+/// uavcan.file.Read.1.1 response. Less than DATA_CAPACITY bytes of data means the end of the file was reached.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ReadResponse {
+    pub error: FileError,
+    pub data: Vec<u8>,
+}
+
+impl CyphalDataType for ReadResponse {
+    fn serialize(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut ret: Vec<u8> = vec![];
+        write_uint(&mut ret, self.error.0 as u64, 2);
+        write_data(&mut ret, &self.data)?;
+        Ok(ret)
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader: DsdlReader = DsdlReader::new(data);
+        let error: FileError = FileError(reader.read_uint(2) as u16);
+        Ok(Self { error, data: read_data(&mut reader)? })
+    }
+}
+
+/// uavcan.file.Write.1.1 request.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct WriteRequest {
+    pub offset: u64,
+    pub path: Vec<u8>,
+    pub data: Vec<u8>,
+}
+
+impl WriteRequest {
+    pub const FIXED_PORT_ID: CyphalPortID = 409;
+}
+
+impl CyphalDataType for WriteRequest {
+    fn serialize(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut ret: Vec<u8> = vec![];
+        write_uint(&mut ret, self.offset, 5);
+        write_path(&mut ret, &self.path)?;
+        write_data(&mut ret, &self.data)?;
+        Ok(ret)
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader: DsdlReader = DsdlReader::new(data);
+        let offset: u64 = reader.read_uint(5);
+        let path: Vec<u8> = read_path(&mut reader);
+        Ok(Self { offset, path, data: read_data(&mut reader)? })
+    }
+}
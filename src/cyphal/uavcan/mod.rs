@@ -0,0 +1,88 @@
+//! Serialization of the standard `uavcan.*` DSDL data types used by this crate.
+//! Only the subset of the standard namespace needed by the higher-level services is provided.
+
+pub mod file;
+pub mod node;
+pub mod pnp;
+pub mod time;
+
+use crate::cyphal::defines::*;
+
+/// A DSDL data type that can be converted to and from its serialized representation.
+/// Deserialization applies the implicit zero extension rule: missing trailing bytes read as zero.
+pub trait CyphalDataType: Sized {
+    fn serialize(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>>;
+    fn deserialize(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>>;
+}
+
+/// Byte-aligned little-endian reader with implicit zero extension.
+pub(crate) struct DsdlReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl <'a> DsdlReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    pub(crate) fn read_u8(&mut self) -> u8 {
+        let ret: u8 = self.data.get(self.offset).copied().unwrap_or(0);
+        self.offset += 1;
+        ret
+    }
+
+    /// Reads an unsigned integer of `size` bytes (at most 8).
+    pub(crate) fn read_uint(&mut self, size: usize) -> u64 {
+        let mut ret: u64 = 0;
+        for i in 0..size {
+            ret |= (self.read_u8() as u64) << (8 * i);
+        }
+        ret
+    }
+
+    pub(crate) fn read_bytes(&mut self, size: usize) -> Vec<u8> {
+        (0..size).map(|_| self.read_u8()).collect()
+    }
+
+    /// Reads the delimiter header of a nested extensible type and returns a reader limited to its body.
+    pub(crate) fn read_delimited(&mut self) -> DsdlReader<'a> {
+        let size: usize = self.read_uint(4) as usize;
+        let start: usize = std::cmp::min(self.offset, self.data.len());
+        let end: usize = std::cmp::min(self.offset.saturating_add(size), self.data.len());
+        self.offset = self.offset.saturating_add(size);
+        DsdlReader::new(&self.data[start..end])
+    }
+}
+
+pub(crate) fn write_uint(out: &mut Vec<u8>, value: u64, size: usize) {
+    out.extend_from_slice(&value.to_le_bytes()[..size]);
+}
+
+/// Writes a nested extensible type prefixed with its delimiter header.
+pub(crate) fn write_delimited(out: &mut Vec<u8>, body: &[u8]) {
+    write_uint(out, body.len() as u64, 4);
+    out.extend_from_slice(body);
+}
+
+/// Packs a bit mask of `capacity` bits, least significant bit first.
+pub(crate) fn write_bit_mask<I: IntoIterator<Item = usize>>(out: &mut Vec<u8>, bits: I, capacity: usize) {
+    let mut mask: Vec<u8> = vec![0; capacity.div_ceil(8)];
+    for i in bits.into_iter().filter(|x| *x < capacity) {
+        mask[i / 8] |= 1 << (i % 8);
+    }
+    out.extend_from_slice(&mask);
+}
+
+pub(crate) fn read_bit_mask(reader: &mut DsdlReader, capacity: usize) -> Vec<usize> {
+    let mask: Vec<u8> = reader.read_bytes(capacity.div_ceil(8));
+    (0..capacity).filter(|i| (mask[i / 8] & (1 << (i % 8))) != 0).collect()
+}
+
+/// uavcan.node.ID.1.0 is 16 bits wide but Cyphal/CAN only allows node-IDs up to CYPHAL_NODE_ID_MAX.
+pub(crate) fn node_id_from_u16(x: u16) -> Result<CyphalNodeID, Box<dyn std::error::Error>> {
+    if x > CYPHAL_NODE_ID_MAX as u16 {
+        return Err("INVALID NODE ID".into());
+    }
+    Ok(x as CyphalNodeID)
+}
@@ -0,0 +1,296 @@
+use super::*;
+use std::collections::BTreeSet;
+
+/// uavcan.node.Health.1.0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum Health {
+    Nominal,
+    Advisory,
+    Caution,
+    Warning,
+}
+
+impl From<u8> for Health {
+    fn from(x: u8) -> Self {
+        match x & 0x03 {
+            0 => Health::Nominal,
+            1 => Health::Advisory,
+            2 => Health::Caution,
+            _ => Health::Warning,
+        }
+    }
+}
+
+/// uavcan.node.Mode.1.0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum Mode {
+    Operational,
+    Initialization,
+    Maintenance,
+    SoftwareUpdate,
+    Undefined,
+}
+
+impl From<u8> for Mode {
+    fn from(x: u8) -> Self {
+        match x & 0x07 {
+            0 => Mode::Operational,
+            1 => Mode::Initialization,
+            2 => Mode::Maintenance,
+            3 => Mode::SoftwareUpdate,
+            _ => Mode::Undefined,
+        }
+    }
+}
+
+/// uavcan.node.Heartbeat.1.0
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct Heartbeat {
+    pub uptime: u32,
+    pub health: Health,
+    pub mode: Mode,
+    pub vendor_specific_status_code: u8,
+}
+
+impl Heartbeat {
+    pub const FIXED_PORT_ID: CyphalPortID = 7509;
+    pub const MAX_PUBLICATION_PERIOD_USEC: CyphalMicrosecond = 1_000_000;
+    pub const OFFLINE_TIMEOUT_USEC: CyphalMicrosecond = 3_000_000;
+}
+
+impl CyphalDataType for Heartbeat {
+    fn serialize(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if self.mode == Mode::Undefined {
+            return Err("INVALID MODE".into());
+        }
+        let mut ret: Vec<u8> = vec![];
+        write_uint(&mut ret, self.uptime as u64, 4);
+        ret.push(self.health as u8);
+        ret.push(self.mode as u8);
+        ret.push(self.vendor_specific_status_code);
+        Ok(ret)
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader: DsdlReader = DsdlReader::new(data);
+        Ok(Self {
+            uptime: reader.read_uint(4) as u32,
+            health: Health::from(reader.read_u8()),
+            mode: Mode::from(reader.read_u8()),
+            vendor_specific_status_code: reader.read_u8(),
+        })
+    }
+}
+
+/// uavcan.node.port.SubjectIDList.0.1
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub enum SubjectIDList {
+    /// Serialized as a bit mask or a sparse list, whichever is smaller.
+    Set(BTreeSet<CyphalPortID>),
+    /// The node uses all subjects, e.g. a bus monitor.
+    Total,
+}
+
+impl SubjectIDList {
+    const SPARSE_LIST_CAPACITY: usize = 255;
+
+    fn serialize_body(&self) -> Vec<u8> {
+        let mut ret: Vec<u8> = vec![];
+        match self {
+            SubjectIDList::Set(x) if x.len() <= Self::SPARSE_LIST_CAPACITY && 1 + 2 * x.len() < (CYPHAL_SUBJECT_ID_MAX as usize + 1) / 8 => {
+                ret.push(1);
+                ret.push(x.len() as u8);
+                for v in x {
+                    write_uint(&mut ret, *v as u64, 2);
+                }
+            },
+            SubjectIDList::Set(x) => {
+                ret.push(0);
+                write_bit_mask(&mut ret, x.iter().map(|v| *v as usize), CYPHAL_SUBJECT_ID_MAX as usize + 1);
+            },
+            SubjectIDList::Total => ret.push(2),
+        }
+        ret
+    }
+
+    fn deserialize_body(reader: &mut DsdlReader) -> Result<Self, Box<dyn std::error::Error>> {
+        match reader.read_u8() {
+            0 => {
+                let bits: Vec<usize> = read_bit_mask(reader, CYPHAL_SUBJECT_ID_MAX as usize + 1);
+                Ok(SubjectIDList::Set(bits.into_iter().map(|x| x as CyphalPortID).collect()))
+            },
+            1 => {
+                let size: usize = reader.read_u8() as usize;
+                let mut ret: BTreeSet<CyphalPortID> = BTreeSet::new();
+                for _ in 0..size {
+                    ret.insert((reader.read_uint(2) as CyphalPortID) & CYPHAL_SUBJECT_ID_MAX);
+                }
+                Ok(SubjectIDList::Set(ret))
+            },
+            2 => Ok(SubjectIDList::Total),
+            _ => Err("INVALID UNION TAG".into()),
+        }
+    }
+}
+
+/// uavcan.node.port.List.0.1. Service lists are carried as uavcan.node.port.ServiceIDList.0.1 bit masks.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct PortList {
+    pub publishers: SubjectIDList,
+    pub subscribers: SubjectIDList,
+    pub clients: BTreeSet<CyphalPortID>,
+    pub servers: BTreeSet<CyphalPortID>,
+}
+
+impl PortList {
+    pub const FIXED_PORT_ID: CyphalPortID = 7510;
+    pub const MAX_PUBLICATION_PERIOD_USEC: CyphalMicrosecond = 10_000_000;
+}
+
+impl From<&CyphalPorts> for PortList {
+    fn from(ports: &CyphalPorts) -> Self {
+        Self {
+            publishers: SubjectIDList::Set(ports.publishers.clone()),
+            subscribers: SubjectIDList::Set(ports.subscribers.clone()),
+            clients: ports.clients.clone(),
+            servers: ports.servers.clone(),
+        }
+    }
+}
+
+impl CyphalDataType for PortList {
+    fn serialize(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut ret: Vec<u8> = vec![];
+        write_delimited(&mut ret, &self.publishers.serialize_body());
+        write_delimited(&mut ret, &self.subscribers.serialize_body());
+        for services in [&self.clients, &self.servers] {
+            let mut body: Vec<u8> = vec![];
+            write_bit_mask(&mut body, services.iter().map(|v| *v as usize), CYPHAL_SERVICE_ID_MAX as usize + 1);
+            write_delimited(&mut ret, &body);
+        }
+        Ok(ret)
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader: DsdlReader = DsdlReader::new(data);
+        let publishers: SubjectIDList = SubjectIDList::deserialize_body(&mut reader.read_delimited())?;
+        let subscribers: SubjectIDList = SubjectIDList::deserialize_body(&mut reader.read_delimited())?;
+        let clients: Vec<usize> = read_bit_mask(&mut reader.read_delimited(), CYPHAL_SERVICE_ID_MAX as usize + 1);
+        let servers: Vec<usize> = read_bit_mask(&mut reader.read_delimited(), CYPHAL_SERVICE_ID_MAX as usize + 1);
+        Ok(Self {
+            publishers,
+            subscribers,
+            clients: clients.into_iter().map(|x| x as CyphalPortID).collect(),
+            servers: servers.into_iter().map(|x| x as CyphalPortID).collect(),
+        })
+    }
+}
+
+/// uavcan.node.Version.1.0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize)]
+pub struct Version {
+    pub major: u8,
+    pub minor: u8,
+}
+
+/// uavcan.node.GetInfo.1.0 request. The request has no fields.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct GetInfoRequest;
+
+impl GetInfoRequest {
+    pub const FIXED_PORT_ID: CyphalPortID = 430;
+}
+
+impl CyphalDataType for GetInfoRequest {
+    fn serialize(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(vec![])
+    }
+
+    fn deserialize(_data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self)
+    }
+}
+
+/// uavcan.node.GetInfo.1.0 response.
+#[derive(Debug, Clone, PartialEq, Default, serde::Serialize)]
+pub struct GetInfoResponse {
+    pub protocol_version: Version,
+    pub hardware_version: Version,
+    pub software_version: Version,
+    pub software_vcs_revision_id: u64,
+    pub unique_id: [u8; 16],
+    pub name: String,
+    pub software_image_crc: Option<u64>,
+    pub certificate_of_authenticity: Vec<u8>,
+}
+
+impl GetInfoResponse {
+    pub const NAME_CAPACITY: usize = 50;
+    pub const CERTIFICATE_OF_AUTHENTICITY_CAPACITY: usize = 222;
+}
+
+impl CyphalDataType for GetInfoResponse {
+    fn serialize(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if self.name.len() > Self::NAME_CAPACITY {
+            return Err("INVALID NAME LENGTH".into());
+        }
+        if self.certificate_of_authenticity.len() > Self::CERTIFICATE_OF_AUTHENTICITY_CAPACITY {
+            return Err("INVALID CERTIFICATE LENGTH".into());
+        }
+        let mut ret: Vec<u8> = vec![];
+        for v in [self.protocol_version, self.hardware_version, self.software_version] {
+            ret.push(v.major);
+            ret.push(v.minor);
+        }
+        write_uint(&mut ret, self.software_vcs_revision_id, 8);
+        ret.extend_from_slice(&self.unique_id);
+        ret.push(self.name.len() as u8);
+        ret.extend_from_slice(self.name.as_bytes());
+        match self.software_image_crc {
+            Some(x) => {
+                ret.push(1);
+                write_uint(&mut ret, x, 8);
+            },
+            None => ret.push(0),
+        }
+        ret.push(self.certificate_of_authenticity.len() as u8);
+        ret.extend_from_slice(&self.certificate_of_authenticity);
+        Ok(ret)
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader: DsdlReader = DsdlReader::new(data);
+        let mut versions: [Version; 3] = [Version::default(); 3];
+        for v in versions.iter_mut() {
+            v.major = reader.read_u8();
+            v.minor = reader.read_u8();
+        }
+        let software_vcs_revision_id: u64 = reader.read_uint(8);
+        let mut unique_id: [u8; 16] = [0; 16];
+        unique_id.copy_from_slice(&reader.read_bytes(16));
+        let name_size: usize = reader.read_u8() as usize;
+        if name_size > Self::NAME_CAPACITY {
+            return Err("INVALID NAME LENGTH".into());
+        }
+        let name: String = String::from_utf8(reader.read_bytes(name_size))?;
+        let software_image_crc: Option<u64> = match reader.read_u8() {
+            0 => None,
+            1 => Some(reader.read_uint(8)),
+            _ => return Err("INVALID ARRAY LENGTH".into()),
+        };
+        let certificate_size: usize = reader.read_u8() as usize;
+        if certificate_size > Self::CERTIFICATE_OF_AUTHENTICITY_CAPACITY {
+            return Err("INVALID CERTIFICATE LENGTH".into());
+        }
+        Ok(Self {
+            protocol_version: versions[0],
+            hardware_version: versions[1],
+            software_version: versions[2],
+            software_vcs_revision_id,
+            unique_id,
+            name,
+            software_image_crc,
+            certificate_of_authenticity: reader.read_bytes(certificate_size),
+        })
+    }
+}
@@ -0,0 +1,75 @@
+use super::*;
+use crate::cyphal::defines::*;
+
+pub type UniqueID = [u8; 16];
+
+/// uavcan.pnp.NodeIDAllocationData.1.0, used on Classic CAN.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NodeIDAllocationDataV1 {
+    /// The lower 48 bits of the CRC-64WE hash of the unique-ID.
+    pub unique_id_hash: u64,
+    pub allocated_node_id: Option<CyphalNodeID>,
+}
+
+impl NodeIDAllocationDataV1 {
+    pub const FIXED_PORT_ID: CyphalPortID = 8166;
+    pub const UNIQUE_ID_HASH_MASK: u64 = (1 << 48) - 1;
+
+    pub fn unique_id_hash(unique_id: &UniqueID) -> u64 {
+        crate::cyphal::crc64we(unique_id) & Self::UNIQUE_ID_HASH_MASK
+    }
+}
+
+impl CyphalDataType for NodeIDAllocationDataV1 {
+    fn serialize(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut ret: Vec<u8> = vec![];
+        write_uint(&mut ret, self.unique_id_hash & Self::UNIQUE_ID_HASH_MASK, 6);
+        match self.allocated_node_id {
+            Some(node_id) => {
+                ret.push(1);
+                write_uint(&mut ret, node_id as u64, 2);
+            },
+            None => ret.push(0),
+        }
+        Ok(ret)
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader: DsdlReader = DsdlReader::new(data);
+        let unique_id_hash: u64 = reader.read_uint(6);
+        let allocated_node_id: Option<CyphalNodeID> = match reader.read_u8() {
+            0 => None,
+            1 => Some(node_id_from_u16(reader.read_uint(2) as u16)?),
+            _ => return Err("INVALID ARRAY LENGTH".into()),
+        };
+        Ok(Self { unique_id_hash, allocated_node_id })
+    }
+}
+
+/// uavcan.pnp.NodeIDAllocationData.2.0, used on CAN FD.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NodeIDAllocationDataV2 {
+    pub node_id: CyphalNodeID,
+    pub unique_id: UniqueID,
+}
+
+impl NodeIDAllocationDataV2 {
+    pub const FIXED_PORT_ID: CyphalPortID = 8165;
+}
+
+impl CyphalDataType for NodeIDAllocationDataV2 {
+    fn serialize(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut ret: Vec<u8> = vec![];
+        write_uint(&mut ret, self.node_id as u64, 2);
+        ret.extend_from_slice(&self.unique_id);
+        Ok(ret)
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader: DsdlReader = DsdlReader::new(data);
+        let node_id: CyphalNodeID = node_id_from_u16(reader.read_uint(2) as u16)?;
+        let mut unique_id: UniqueID = [0; 16];
+        unique_id.copy_from_slice(&reader.read_bytes(16));
+        Ok(Self { node_id, unique_id })
+    }
+}
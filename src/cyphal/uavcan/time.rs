@@ -0,0 +1,116 @@
+use super::*;
+
+/// uavcan.time.Synchronization.1.0
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct Synchronization {
+    /// Master time of the previous Synchronization transmission; zero if unknown.
+    pub previous_transmission_timestamp_microsecond: u64,
+}
+
+impl Synchronization {
+    pub const FIXED_PORT_ID: CyphalPortID = 7168;
+    pub const MAX_PUBLICATION_PERIOD_USEC: CyphalMicrosecond = 1_000_000;
+    pub const PUBLISHER_TIMEOUT_PERIOD_MULTIPLIER: u64 = 3;
+    pub const TIMESTAMP_MASK: u64 = (1 << 56) - 1;
+}
+
+impl CyphalDataType for Synchronization {
+    fn serialize(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut ret: Vec<u8> = vec![];
+        write_uint(&mut ret, self.previous_transmission_timestamp_microsecond & Self::TIMESTAMP_MASK, 7);
+        Ok(ret)
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader: DsdlReader = DsdlReader::new(data);
+        Ok(Self { previous_transmission_timestamp_microsecond: reader.read_uint(7) })
+    }
+}
+
+/// uavcan.time.TimeSystem.0.1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum TimeSystem {
+    MonotonicSinceBoot,
+    Tai,
+    ApplicationSpecific,
+    /// A code this version of the data type does not define, kept so that it serializes back unchanged.
+    Reserved(u8),
+}
+
+impl From<u8> for TimeSystem {
+    fn from(x: u8) -> Self {
+        match x & 0x0f {
+            0 => TimeSystem::MonotonicSinceBoot,
+            1 => TimeSystem::Tai,
+            15 => TimeSystem::ApplicationSpecific,
+            x => TimeSystem::Reserved(x),
+        }
+    }
+}
+
+impl TimeSystem {
+    fn to_u8(self) -> u8 {
+        match self {
+            TimeSystem::MonotonicSinceBoot => 0,
+            TimeSystem::Tai => 1,
+            TimeSystem::ApplicationSpecific => 15,
+            TimeSystem::Reserved(x) => x & 0x0f,
+        }
+    }
+}
+
+/// uavcan.time.GetSynchronizationMasterInfo.0.1 request. The request has no fields.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct GetSynchronizationMasterInfoRequest;
+
+impl GetSynchronizationMasterInfoRequest {
+    pub const FIXED_PORT_ID: CyphalPortID = 510;
+}
+
+impl CyphalDataType for GetSynchronizationMasterInfoRequest {
+    fn serialize(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(vec![])
+    }
+
+    fn deserialize(_data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self)
+    }
+}
+
+/// uavcan.time.GetSynchronizationMasterInfo.0.1 response.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct GetSynchronizationMasterInfoResponse {
+    /// [second^2]
+    pub error_variance: f32,
+    pub time_system: TimeSystem,
+    /// uavcan.time.TAIInfo.0.1
+    pub difference_tai_minus_utc: u16,
+}
+
+impl GetSynchronizationMasterInfoResponse {
+    pub const DIFFERENCE_TAI_MINUS_GPS: u16 = 19;
+    pub const DIFFERENCE_TAI_MINUS_UTC_AS_OF_2020: u16 = 37;
+    pub const DIFFERENCE_TAI_MINUS_UTC_MAX: u16 = (1 << 10) - 1;
+}
+
+impl CyphalDataType for GetSynchronizationMasterInfoResponse {
+    fn serialize(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        if self.difference_tai_minus_utc > Self::DIFFERENCE_TAI_MINUS_UTC_MAX {
+            return Err("INVALID TAI DIFFERENCE".into());
+        }
+        let mut ret: Vec<u8> = vec![];
+        ret.extend_from_slice(&self.error_variance.to_le_bytes());
+        ret.push(self.time_system.to_u8());
+        write_uint(&mut ret, self.difference_tai_minus_utc as u64, 2);
+        Ok(ret)
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader: DsdlReader = DsdlReader::new(data);
+        Ok(Self {
+            error_variance: f32::from_bits(reader.read_uint(4) as u32),
+            time_system: TimeSystem::from(reader.read_u8()),
+            difference_tai_minus_utc: (reader.read_uint(2) as u16) & Self::DIFFERENCE_TAI_MINUS_UTC_MAX,
+        })
+    }
+}
@@ -0,0 +1,258 @@
+use super::*;
+use crate::driver::spi::SpiBus;
+use crate::driver::tcan4550::Tcan4550Frame;
+use crate::driver::tcan4550::registers::*;
+
+use std::io::{Read, Write};
+
+/// Host side of the link over any byte stream, such as the FT232H in FIFO mode or a `BoardLoopback`.
+/// Each request waits for the response with its sequence number; responses to earlier requests that
+/// arrive late, e.g. after a timeout, are dropped. As an `SpiBus` it lets `Tcan4550Driver` run over the
+/// board, with each SPI transaction carried by one register read or write command.
+pub struct BoardClient<T: Read + Write> {
+    link: T,
+    decoder: BoardFrameDecoder,
+    sequence: u8,
+    timeout: std::time::Duration,
+    poll_interval: std::time::Duration,
+    stale_responses: u64,
+}
+
+impl <T: Read + Write> BoardClient<T> {
+    pub fn new(link: T) -> Self {
+        Self {
+            link,
+            decoder: BoardFrameDecoder::new(),
+            sequence: 0,
+            timeout: std::time::Duration::from_millis(100),
+            poll_interval: std::time::Duration::from_micros(100),
+            stale_responses: 0,
+        }
+    }
+
+    /// Time to wait for each response.
+    pub fn set_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Pause between reads of the link while no bytes arrive.
+    pub fn set_poll_interval(mut self, poll_interval: std::time::Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn link(&self) -> &T {
+        &self.link
+    }
+
+    pub fn link_mut(&mut self) -> &mut T {
+        &mut self.link
+    }
+
+    pub fn into_inner(self) -> T {
+        self.link
+    }
+
+    /// Decoder of the response stream, with its resynchronization counters.
+    pub fn decoder(&self) -> &BoardFrameDecoder {
+        &self.decoder
+    }
+
+    /// Responses dropped because they belonged to an earlier request.
+    pub fn stale_responses(&self) -> u64 {
+        self.stale_responses
+    }
+
+    /// Sends a command and waits for its response. Rejections by the board are returned as `BoardLinkError::Board`.
+    pub fn request(&mut self, command: &BoardCommand) -> Result<BoardResponse, Box<dyn std::error::Error>> {
+        self.sequence = self.sequence.wrapping_add(1);
+        let sequence: u8 = self.sequence;
+        self.link.write_all(&command.to_frame(sequence).encode()?)?;
+        self.link.flush()?;
+
+        let started: std::time::Instant = std::time::Instant::now();
+        let mut buffer: [u8; 512] = [0; 512];
+        loop {
+            while let Some(frame) = self.decoder.next_frame() {
+                if frame.sequence != sequence {
+                    self.stale_responses += 1;
+                    continue;
+                }
+                return match BoardResponse::from_frame(&frame)? {
+                    BoardResponse::Error(x) => Err(BoardLinkError::Board(x).into()),
+                    x => Ok(x),
+                };
+            }
+            if started.elapsed() >= self.timeout {
+                return Err(BoardLinkError::Timeout(sequence).into());
+            }
+            match self.link.read(&mut buffer) {
+                Ok(0) => std::thread::sleep(self.poll_interval.min(self.timeout.saturating_sub(started.elapsed()))),
+                Ok(x) => self.decoder.push(&buffer[..x]),
+                Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut | std::io::ErrorKind::Interrupted) => {},
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Queues a frame in the TX FIFO and returns the buffer it was written to.
+    pub fn transmit_frame(&mut self, frame: &Tcan4550Frame, marker: Option<u8>) -> Result<usize, Box<dyn std::error::Error>> {
+        let (t0, t1) = frame.tx_header(marker);
+        match self.request(&BoardCommand::Transmit { t0, t1, data: frame.data.clone() })? {
+            BoardResponse::Transmitted { buffer } => Ok(buffer as usize),
+            x => Err(BoardLinkError::UnexpectedResponse(x.code(BOARD_CMD_TRANSMIT)).into()),
+        }
+    }
+
+    /// Reads and acknowledges every element in an RX FIFO; returns the element size and the elements.
+    pub fn read_rx_fifo(&mut self, fifo: u8) -> Result<(usize, Vec<u8>), Box<dyn std::error::Error>> {
+        match self.request(&BoardCommand::ReadRxFifo { fifo })? {
+            BoardResponse::RxElements { element_size, data } => Ok((element_size as usize, data)),
+            x => Err(BoardLinkError::UnexpectedResponse(x.code(BOARD_CMD_READ_RX_FIFO)).into()),
+        }
+    }
+
+    pub fn read_registers(&mut self, address: u16, count: u16) -> Result<Vec<u32>, Box<dyn std::error::Error>> {
+        match self.request(&BoardCommand::ReadRegisters { address, count })? {
+            BoardResponse::Registers(x) if x.len() == count as usize => Ok(x),
+            x => Err(BoardLinkError::UnexpectedResponse(x.code(BOARD_CMD_READ_REGISTERS)).into()),
+        }
+    }
+
+    pub fn write_registers(&mut self, address: u16, values: &[u32]) -> Result<(), Box<dyn std::error::Error>> {
+        match self.request(&BoardCommand::WriteRegisters { address, values: values.to_vec() })? {
+            BoardResponse::Written => Ok(()),
+            x => Err(BoardLinkError::UnexpectedResponse(x.code(BOARD_CMD_WRITE_REGISTERS)).into()),
+        }
+    }
+
+    /// Reading the status resets the error codes in PSR, as any read of PSR does.
+    pub fn status(&mut self) -> Result<BoardStatus, Box<dyn std::error::Error>> {
+        match self.request(&BoardCommand::Status)? {
+            BoardResponse::Status(x) => Ok(x),
+            x => Err(BoardLinkError::UnexpectedResponse(x.code(BOARD_CMD_STATUS)).into()),
+        }
+    }
+}
+
+impl <T: Read + Write> SpiBus for BoardClient<T> {
+    fn transfer(&mut self, data: &mut [u8]) -> Result<(), Box<dyn std::error::Error>> {
+        if data.len() < SPI_HEADER_SIZE || !(data.len() - SPI_HEADER_SIZE).is_multiple_of(SPI_WORD_SIZE) {
+            return Err(BoardLinkError::Malformed(data.first().copied().unwrap_or(0)).into());
+        }
+        let address: u16 = u16::from_be_bytes([data[1], data[2]]);
+        let count: usize = (data.len() - SPI_HEADER_SIZE) / SPI_WORD_SIZE;
+        let (header, words) = data.split_at_mut(SPI_HEADER_SIZE);
+        match header[0] {
+            SPI_OPCODE_READ => {
+                let values: Vec<u32> = self.read_registers(address, count as u16)?;
+                for (word, value) in words.chunks_mut(SPI_WORD_SIZE).zip(values) {
+                    word.copy_from_slice(&value.to_be_bytes());
+                }
+            },
+            SPI_OPCODE_WRITE => {
+                let values: Vec<u32> = words.chunks(SPI_WORD_SIZE).map(|x| u32::from_be_bytes([x[0], x[1], x[2], x[3]])).collect();
+                self.write_registers(address, &values)?;
+                words.fill(0);
+            },
+            x => return Err(BoardLinkError::Malformed(x).into()),
+        }
+        header.fill(0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cyphal::*;
+    use crate::driver::*;
+    use crate::driver::board::loopback::BoardLoopback;
+    use crate::driver::tcan4550::Tcan4550Mode;
+    use crate::driver::tcan4550::device::*;
+    use crate::driver::tcan4550::mram::Tcan4550MramLayout;
+    use crate::driver::tcan4550::simulator::Tcan4550Simulator;
+
+    use std::collections::VecDeque;
+
+    const MTU: usize = CYPHAL_MTU_CAN_FD as usize;
+
+    /// Plays back a recorded response stream and keeps what the client writes.
+    struct Recorded {
+        input: VecDeque<u8>,
+        written: Vec<u8>,
+    }
+
+    impl Read for Recorded {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let count: usize = buf.len().min(self.input.len());
+            for (x, y) in buf.iter_mut().zip(self.input.drain(..count)) {
+                *x = y;
+            }
+            Ok(count)
+        }
+    }
+
+    impl Write for Recorded {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn stale_response_is_dropped() {
+        let stale: Vec<u8> = BoardResponse::Written.to_frame(0xFF, BOARD_CMD_WRITE_REGISTERS).encode().unwrap();
+        let answer: Vec<u8> = BoardResponse::Registers(vec![0x4E41_4354, 0x3035_3534]).to_frame(1, BOARD_CMD_READ_REGISTERS).encode().unwrap();
+        let link: Recorded = Recorded { input: [stale, answer].concat().into(), written: vec![] };
+        let mut client: BoardClient<Recorded> = BoardClient::new(link);
+
+        assert_eq!(client.read_registers(REG_DEVICE_ID1, 2).unwrap(), vec![0x4E41_4354, 0x3035_3534]);
+        assert_eq!(client.stale_responses(), 1);
+        assert_eq!(client.link().written, BoardCommand::ReadRegisters { address: REG_DEVICE_ID1, count: 2 }.to_frame(1).encode().unwrap());
+
+        // Nothing answers the next request.
+        let mut client: BoardClient<Recorded> = client.set_timeout(std::time::Duration::from_millis(1));
+        let error: Box<dyn std::error::Error> = client.status().unwrap_err();
+        assert_eq!(error.downcast_ref::<BoardLinkError>(), Some(&BoardLinkError::Timeout(2)));
+    }
+
+    #[test]
+    fn driver_runs_over_the_board_link() {
+        let client: BoardClient<BoardLoopback<Tcan4550Simulator>> = BoardClient::new(BoardLoopback::new(Tcan4550Simulator::new()));
+        let mut driver: Tcan4550Driver<BoardClient<BoardLoopback<Tcan4550Simulator>>> = Tcan4550Driver::new(client);
+        driver.check_device_id().unwrap();
+        driver.enable_configuration().unwrap();
+        driver.set_cccr(&Tcan4550CccrConfig::default()).unwrap();
+        driver.configure_mram(&Tcan4550MramLayout::default().plan::<MTU>().unwrap()).unwrap();
+        driver.disable_configuration().unwrap();
+        driver.set_mode(Tcan4550Mode::Normal).unwrap();
+
+        let mut middleware: CyphalMiddleware<MTU> = CyphalMiddleware::new(10);
+        let data: Vec<u8> = (0..150).collect();
+        let packets: Vec<CyphalTxPacket<MTU>> = middleware.create_message_data(1000, &data, data.len()).unwrap();
+        CanDriver::<MTU>::transmit(&mut driver, &packets).unwrap();
+
+        // Loop the frames the chip sent back onto its own bus.
+        let simulator: &mut Tcan4550Simulator = driver.spi_mut().link_mut().device_mut().spi_mut();
+        let sent: Vec<Tcan4550Frame> = simulator.take_transmitted();
+        assert_eq!(sent.len(), packets.len());
+        for frame in &sent {
+            assert!(simulator.receive_frame(frame));
+        }
+
+        let received: Vec<CyphalRxPacket<MTU>> = CanDriver::<MTU>::receive(&mut driver, std::time::Duration::ZERO).unwrap();
+        assert_eq!(received.iter().map(|x| x.xid).collect::<Vec<u32>>(), packets.iter().map(|x| x.xid).collect::<Vec<u32>>());
+        assert_eq!(CanDriver::<MTU>::take_tx_confirmations(&mut driver).len(), 1);
+        assert_eq!(CanDriver::<MTU>::status(&mut driver).unwrap().bus_state, CanBusState::ErrorActive);
+
+        let client: &BoardClient<BoardLoopback<Tcan4550Simulator>> = driver.spi();
+        assert_eq!((client.stale_responses(), client.decoder().crc_errors()), (0, 0));
+        assert_eq!(client.link().decoder().crc_errors(), 0);
+    }
+}
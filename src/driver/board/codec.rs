@@ -0,0 +1,379 @@
+use super::*;
+use crate::cyphal::{crc_add_byte, CRC_INITIAL, CRC_RESIDUE};
+
+/// A frame that passed the CRC check, before its payload is interpreted.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct BoardFrame {
+    pub sequence: u8,
+    pub code: u8,
+    pub payload: Vec<u8>,
+}
+
+impl BoardFrame {
+    pub fn encode(&self) -> Result<Vec<u8>, BoardLinkError> {
+        if self.payload.len() > BOARD_MAX_PAYLOAD {
+            return Err(BoardLinkError::PayloadTooLong(self.payload.len()));
+        }
+        let mut ret: Vec<u8> = Vec::with_capacity(BOARD_SYNC.len() + BOARD_HEADER_SIZE + self.payload.len() + BOARD_CRC_SIZE);
+        ret.extend_from_slice(&BOARD_SYNC);
+        ret.extend_from_slice(&[self.sequence, self.code]);
+        ret.extend_from_slice(&(self.payload.len() as u16).to_le_bytes());
+        ret.extend_from_slice(&frame_crc(&ret[BOARD_SYNC.len()..]).to_be_bytes());
+        ret.extend_from_slice(&self.payload);
+        ret.extend_from_slice(&frame_crc(&ret[BOARD_SYNC.len()..]).to_be_bytes());
+        Ok(ret)
+    }
+}
+
+fn frame_crc(data: &[u8]) -> u16 {
+    data.iter().fold(CRC_INITIAL, |crc, x| crc_add_byte(crc, *x))
+}
+
+impl BoardCommand {
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Transmit { .. } => BOARD_CMD_TRANSMIT,
+            Self::ReadRxFifo { .. } => BOARD_CMD_READ_RX_FIFO,
+            Self::ReadRegisters { .. } => BOARD_CMD_READ_REGISTERS,
+            Self::WriteRegisters { .. } => BOARD_CMD_WRITE_REGISTERS,
+            Self::Status => BOARD_CMD_STATUS,
+        }
+    }
+
+    pub fn to_frame(&self, sequence: u8) -> BoardFrame {
+        let mut payload: Vec<u8> = vec![];
+        match self {
+            Self::Transmit { t0, t1, data } => {
+                payload.extend_from_slice(&t0.to_le_bytes());
+                payload.extend_from_slice(&t1.to_le_bytes());
+                payload.extend_from_slice(data);
+            },
+            Self::ReadRxFifo { fifo } => payload.push(*fifo),
+            Self::ReadRegisters { address, count } => {
+                payload.extend_from_slice(&address.to_le_bytes());
+                payload.extend_from_slice(&count.to_le_bytes());
+            },
+            Self::WriteRegisters { address, values } => {
+                payload.extend_from_slice(&address.to_le_bytes());
+                payload.extend(values.iter().flat_map(|x| x.to_le_bytes()));
+            },
+            Self::Status => {},
+        }
+        BoardFrame { sequence, code: self.code(), payload }
+    }
+
+    pub fn from_frame(frame: &BoardFrame) -> Result<Self, BoardLinkError> {
+        let mut reader: PayloadReader = PayloadReader::new(frame);
+        let ret: Self = match frame.code {
+            BOARD_CMD_TRANSMIT => Self::Transmit { t0: reader.u32()?, t1: reader.u32()?, data: reader.rest().to_vec() },
+            BOARD_CMD_READ_RX_FIFO => Self::ReadRxFifo { fifo: reader.u8()? },
+            BOARD_CMD_READ_REGISTERS => Self::ReadRegisters { address: reader.u16()?, count: reader.u16()? },
+            BOARD_CMD_WRITE_REGISTERS => Self::WriteRegisters { address: reader.u16()?, values: reader.words()? },
+            BOARD_CMD_STATUS => Self::Status,
+            x => return Err(BoardLinkError::UnknownCode(x)),
+        };
+        reader.finish()?;
+        Ok(ret)
+    }
+}
+
+impl BoardResponse {
+    pub fn code(&self, command_code: u8) -> u8 {
+        match self {
+            Self::Error(_) => BOARD_RSP_ERROR,
+            _ => command_code | BOARD_RSP_FLAG,
+        }
+    }
+
+    /// Frames the response to a command with the given sequence number and code.
+    pub fn to_frame(&self, sequence: u8, command_code: u8) -> BoardFrame {
+        let mut payload: Vec<u8> = vec![];
+        match self {
+            Self::Transmitted { buffer } => payload.push(*buffer),
+            Self::RxElements { element_size, data } => {
+                payload.extend_from_slice(&element_size.to_le_bytes());
+                payload.extend_from_slice(data);
+            },
+            Self::Registers(values) => payload.extend(values.iter().flat_map(|x| x.to_le_bytes())),
+            Self::Written => {},
+            Self::Status(status) => {
+                for x in [
+                    status.interrupts,
+                    status.mcan_interrupts,
+                    status.protocol_status,
+                    status.error_counters,
+                    status.rx_fifo0_status,
+                    status.rx_fifo1_status,
+                    status.tx_fifo_status,
+                ] {
+                    payload.extend_from_slice(&x.to_le_bytes());
+                }
+            },
+            Self::Error(code) => payload.push(u8::from(*code)),
+        }
+        BoardFrame { sequence, code: self.code(command_code), payload }
+    }
+
+    pub fn from_frame(frame: &BoardFrame) -> Result<Self, BoardLinkError> {
+        let mut reader: PayloadReader = PayloadReader::new(frame);
+        let ret: Self = match frame.code {
+            BOARD_RSP_ERROR => Self::Error(BoardErrorCode::from(reader.u8()?)),
+            x if x == BOARD_CMD_TRANSMIT | BOARD_RSP_FLAG => Self::Transmitted { buffer: reader.u8()? },
+            x if x == BOARD_CMD_READ_RX_FIFO | BOARD_RSP_FLAG => {
+                let element_size: u16 = reader.u16()?;
+                let data: Vec<u8> = reader.rest().to_vec();
+                if (element_size == 0 && !data.is_empty()) || (element_size > 0 && !data.len().is_multiple_of(element_size as usize)) {
+                    return Err(BoardLinkError::Malformed(frame.code));
+                }
+                Self::RxElements { element_size, data }
+            },
+            x if x == BOARD_CMD_READ_REGISTERS | BOARD_RSP_FLAG => Self::Registers(reader.words()?),
+            x if x == BOARD_CMD_WRITE_REGISTERS | BOARD_RSP_FLAG => Self::Written,
+            x if x == BOARD_CMD_STATUS | BOARD_RSP_FLAG => Self::Status(BoardStatus {
+                interrupts: reader.u32()?,
+                mcan_interrupts: reader.u32()?,
+                protocol_status: reader.u32()?,
+                error_counters: reader.u32()?,
+                rx_fifo0_status: reader.u32()?,
+                rx_fifo1_status: reader.u32()?,
+                tx_fifo_status: reader.u32()?,
+            }),
+            x => return Err(BoardLinkError::UnknownCode(x)),
+        };
+        reader.finish()?;
+        Ok(ret)
+    }
+}
+
+/// Reassembles frames from the byte stream of the link. Bytes before `BOARD_SYNC` are skipped. When a
+/// header or frame fails its CRC or the length is impossible, only the sync of the candidate is dropped and
+/// the search continues from the next byte, so a frame that follows a damaged one is still found.
+/// A frame that lost payload bytes is only rejected once bytes of the following frames have filled it up.
+#[derive(Debug, Default)]
+pub struct BoardFrameDecoder {
+    buffer: Vec<u8>,
+    discarded_bytes: u64,
+    crc_errors: u64,
+}
+
+impl BoardFrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Returns the next complete frame, or None until more bytes arrive.
+    pub fn next_frame(&mut self) -> Option<BoardFrame> {
+        loop {
+            match self.buffer.windows(BOARD_SYNC.len()).position(|x| x == BOARD_SYNC) {
+                Some(x) => self.discard(x),
+                None => {
+                    // Keep a trailing first sync byte, which may be followed by the second one.
+                    let keep: usize = if self.buffer.last() == Some(&BOARD_SYNC[0]) { 1 } else { 0 };
+                    self.discard(self.buffer.len() - keep);
+                    return None;
+                },
+            }
+            let body: &[u8] = &self.buffer[BOARD_SYNC.len()..];
+            if body.len() < BOARD_HEADER_SIZE {
+                return None;
+            }
+            let length: usize = u16::from_le_bytes([body[2], body[3]]) as usize;
+            if frame_crc(&body[..BOARD_HEADER_SIZE]) != CRC_RESIDUE || length > BOARD_MAX_PAYLOAD {
+                self.crc_errors += 1;
+                self.discard(1);
+                continue;
+            }
+            let size: usize = BOARD_HEADER_SIZE + length + BOARD_CRC_SIZE;
+            if body.len() < size {
+                return None;
+            }
+            if frame_crc(&body[..size]) != CRC_RESIDUE {
+                self.crc_errors += 1;
+                self.discard(1);
+                continue;
+            }
+            let frame: BoardFrame = BoardFrame {
+                sequence: body[0],
+                code: body[1],
+                payload: body[BOARD_HEADER_SIZE..BOARD_HEADER_SIZE + length].to_vec(),
+            };
+            self.buffer.drain(..BOARD_SYNC.len() + size);
+            return Some(frame);
+        }
+    }
+
+    /// Bytes skipped while searching for frames.
+    pub fn discarded_bytes(&self) -> u64 {
+        self.discarded_bytes
+    }
+
+    /// Frame candidates rejected by the header or frame CRC check.
+    pub fn crc_errors(&self) -> u64 {
+        self.crc_errors
+    }
+
+    /// Bytes received but not yet returned as a frame.
+    pub fn pending_bytes(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.discarded_bytes += self.buffer.len() as u64;
+        self.buffer.clear();
+    }
+
+    fn discard(&mut self, count: usize) {
+        self.buffer.drain(..count);
+        self.discarded_bytes += count as u64;
+    }
+}
+
+struct PayloadReader<'a> {
+    code: u8,
+    data: &'a [u8],
+}
+
+impl <'a> PayloadReader<'a> {
+    fn new(frame: &'a BoardFrame) -> Self {
+        Self { code: frame.code, data: &frame.payload }
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], BoardLinkError> {
+        if self.data.len() < count {
+            return Err(BoardLinkError::Malformed(self.code));
+        }
+        let (ret, rest) = self.data.split_at(count);
+        self.data = rest;
+        Ok(ret)
+    }
+
+    fn u8(&mut self) -> Result<u8, BoardLinkError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, BoardLinkError> {
+        let x: &[u8] = self.take(2)?;
+        Ok(u16::from_le_bytes([x[0], x[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, BoardLinkError> {
+        let x: &[u8] = self.take(4)?;
+        Ok(u32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+    }
+
+    fn words(&mut self) -> Result<Vec<u32>, BoardLinkError> {
+        if !self.data.len().is_multiple_of(4) {
+            return Err(BoardLinkError::Malformed(self.code));
+        }
+        let mut ret: Vec<u32> = Vec::with_capacity(self.data.len() / 4);
+        while !self.data.is_empty() {
+            ret.push(self.u32()?);
+        }
+        Ok(ret)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.data)
+    }
+
+    /// Fails if bytes are left over.
+    fn finish(&self) -> Result<(), BoardLinkError> {
+        if !self.data.is_empty() {
+            return Err(BoardLinkError::Malformed(self.code));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `Status` with sequence number 1, as sent by the host.
+    const STATUS: [u8; 10] = [0xA5, 0x5A, 0x01, 0x05, 0x00, 0x00, 0x19, 0x84, 0x00, 0x00];
+    /// `ReadRegisters { address: 0x1000, count: 2 }` with sequence number 2.
+    const READ_REGISTERS: [u8; 14] = [0xA5, 0x5A, 0x02, 0x03, 0x04, 0x00, 0xFC, 0x3C, 0x00, 0x10, 0x02, 0x00, 0x25, 0x01];
+    /// `WriteRegisters { address: 0x8000, values: vec![0x0403_0201] }` with sequence number 3.
+    const WRITE_REGISTERS: [u8; 16] = [0xA5, 0x5A, 0x03, 0x04, 0x06, 0x00, 0x69, 0x7A, 0x00, 0x80, 0x01, 0x02, 0x03, 0x04, 0x2F, 0xD3];
+    /// The answer to `READ_REGISTERS`: the two device ID words.
+    const REGISTERS: [u8; 18] = [0xA5, 0x5A, 0x02, 0x83, 0x08, 0x00, 0x82, 0x0B, 0x54, 0x43, 0x41, 0x4E, 0x34, 0x35, 0x35, 0x30, 0x34, 0x69];
+
+    fn decode(stream: &[u8]) -> (Vec<BoardFrame>, BoardFrameDecoder) {
+        let mut decoder: BoardFrameDecoder = BoardFrameDecoder::new();
+        let mut ret: Vec<BoardFrame> = vec![];
+        // Byte by byte, as a slow link would deliver them.
+        for x in stream {
+            decoder.push(&[*x]);
+            while let Some(frame) = decoder.next_frame() {
+                ret.push(frame);
+            }
+        }
+        (ret, decoder)
+    }
+
+    fn sequences(frames: &[BoardFrame]) -> Vec<u8> {
+        frames.iter().map(|x| x.sequence).collect()
+    }
+
+    #[test]
+    fn recorded_stream_round_trips() {
+        let commands: Vec<BoardCommand> = vec![
+            BoardCommand::Status,
+            BoardCommand::ReadRegisters { address: 0x1000, count: 2 },
+            BoardCommand::WriteRegisters { address: 0x8000, values: vec![0x0403_0201] },
+        ];
+        let stream: Vec<u8> = [&STATUS[..], &READ_REGISTERS, &WRITE_REGISTERS].concat();
+        let encoded: Vec<u8> = commands.iter().zip(1..).flat_map(|(x, sequence)| x.to_frame(sequence).encode().unwrap()).collect();
+        assert_eq!(encoded, stream);
+
+        let (frames, decoder) = decode(&stream);
+        assert_eq!(frames.iter().map(|x| BoardCommand::from_frame(x).unwrap()).collect::<Vec<BoardCommand>>(), commands);
+        assert_eq!((decoder.discarded_bytes(), decoder.crc_errors(), decoder.pending_bytes()), (0, 0, 0));
+
+        let (frames, _) = decode(&REGISTERS);
+        assert_eq!(BoardResponse::from_frame(&frames[0]).unwrap(), BoardResponse::Registers(vec![0x4E41_4354, 0x3035_3534]));
+    }
+
+    #[test]
+    fn garbage_before_sync_is_skipped() {
+        // Includes lone sync bytes, which must not hide the real sync.
+        let stream: Vec<u8> = [&[0x00, 0xA5, 0x13, 0x5A, 0xA5][..], &STATUS, &[0xFF, 0xA5], &READ_REGISTERS].concat();
+        let (frames, decoder) = decode(&stream);
+        assert_eq!(sequences(&frames), vec![1, 2]);
+        assert_eq!(decoder.discarded_bytes(), 7);
+        assert_eq!(decoder.crc_errors(), 0);
+    }
+
+    #[test]
+    fn corrupted_header_is_rejected_without_waiting_for_its_length() {
+        let mut damaged: Vec<u8> = WRITE_REGISTERS.to_vec();
+        // Length 0x1F06 would make the decoder wait for almost 8 KB, were it not for the header CRC.
+        damaged[5] = 0x1F;
+        let (frames, decoder) = decode(&[&damaged[..], &STATUS].concat());
+        assert_eq!(sequences(&frames), vec![1]);
+        assert_eq!(decoder.crc_errors(), 1);
+        assert_eq!(decoder.pending_bytes(), 0);
+
+        let mut damaged: Vec<u8> = READ_REGISTERS.to_vec();
+        damaged[7] ^= 0x01;
+        let (frames, decoder) = decode(&[&damaged[..], &STATUS].concat());
+        assert_eq!(sequences(&frames), vec![1]);
+        assert_eq!(decoder.crc_errors(), 1);
+    }
+
+    #[test]
+    fn dropped_payload_byte_resyncs_on_the_next_frame() {
+        let mut damaged: Vec<u8> = WRITE_REGISTERS.to_vec();
+        damaged.remove(11);
+        let (frames, decoder) = decode(&[&damaged[..], &STATUS, &READ_REGISTERS].concat());
+        // The damaged frame borrows a byte of the next one, fails its CRC, and the search goes on from there.
+        assert_eq!(sequences(&frames), vec![1, 2]);
+        assert_eq!(decoder.crc_errors(), 1);
+        assert_eq!(decoder.discarded_bytes(), damaged.len() as u64);
+        assert_eq!(decoder.pending_bytes(), 0);
+    }
+}
@@ -0,0 +1,125 @@
+use super::*;
+use crate::driver::CanDriverError;
+use crate::driver::spi::SpiBus;
+use crate::driver::tcan4550::device::*;
+use crate::driver::tcan4550::registers::*;
+
+use std::collections::VecDeque;
+
+/// Board side of the link, for running host code without the board: the bytes written to it are decoded
+/// as commands and executed on a TCAN4550, e.g. a `Tcan4550Simulator`, and the encoded responses are
+/// queued to be read back. Register writes that touch the MRAM section registers reload the MRAM layout,
+/// as the firmware does.
+pub struct BoardLoopback<S: SpiBus> {
+    device: Tcan4550Driver<S>,
+    decoder: BoardFrameDecoder,
+    output: VecDeque<u8>,
+}
+
+impl <S: SpiBus> BoardLoopback<S> {
+    pub fn new(spi: S) -> Self {
+        Self {
+            device: Tcan4550Driver::new(spi),
+            decoder: BoardFrameDecoder::new(),
+            output: VecDeque::new(),
+        }
+    }
+
+    pub fn device(&self) -> &Tcan4550Driver<S> {
+        &self.device
+    }
+
+    pub fn device_mut(&mut self) -> &mut Tcan4550Driver<S> {
+        &mut self.device
+    }
+
+    /// Decoder of the command stream, with its resynchronization counters.
+    pub fn decoder(&self) -> &BoardFrameDecoder {
+        &self.decoder
+    }
+
+    /// Encoded responses not yet read.
+    pub fn pending_output(&self) -> usize {
+        self.output.len()
+    }
+
+    fn handle(&mut self, frame: &BoardFrame) -> BoardResponse {
+        let command: BoardCommand = match BoardCommand::from_frame(frame) {
+            Ok(x) => x,
+            Err(BoardLinkError::UnknownCode(_)) => return BoardResponse::Error(BoardErrorCode::UnknownCommand),
+            Err(_) => return BoardResponse::Error(BoardErrorCode::Malformed),
+        };
+        match self.execute(&command) {
+            Ok(x) => x,
+            Err(e) if matches!(e.downcast_ref::<CanDriverError>(), Some(CanDriverError::TxQueueFull)) => BoardResponse::Error(BoardErrorCode::TxFifoFull),
+            Err(e) if e.downcast_ref::<Tcan4550Error>().is_some() => BoardResponse::Error(BoardErrorCode::NotConfigured),
+            Err(_) => BoardResponse::Error(BoardErrorCode::Spi),
+        }
+    }
+
+    fn execute(&mut self, command: &BoardCommand) -> Result<BoardResponse, Box<dyn std::error::Error>> {
+        Ok(match command {
+            BoardCommand::Transmit { t0, t1, data } => {
+                let buffer: usize = self.device.transmit_element(*t0, *t1, data)?;
+                BoardResponse::Transmitted { buffer: buffer as u8 }
+            },
+            BoardCommand::ReadRxFifo { fifo } if *fifo > 1 => BoardResponse::Error(BoardErrorCode::Malformed),
+            BoardCommand::ReadRxFifo { fifo } => {
+                let fifo: usize = *fifo as usize;
+                let data: Vec<u8> = self.device.read_rx_fifo(fifo)?;
+                BoardResponse::RxElements { element_size: self.device.mram_config().rx_fifo(fifo).2 as u16, data }
+            },
+            BoardCommand::ReadRegisters { count, .. } if *count == 0 || *count as usize * SPI_WORD_SIZE > BOARD_MAX_PAYLOAD => {
+                BoardResponse::Error(BoardErrorCode::Malformed)
+            },
+            BoardCommand::ReadRegisters { address, count } => BoardResponse::Registers(self.device.read_words(*address, *count as usize)?),
+            BoardCommand::WriteRegisters { address, values } => {
+                self.device.write_words(*address, values)?;
+                let end: u16 = address.wrapping_add((values.len() * SPI_WORD_SIZE) as u16);
+                if *address <= REG_MCAN_TXEFC && end > REG_MCAN_SIDFC {
+                    self.device.load_mram_config()?;
+                }
+                BoardResponse::Written
+            },
+            BoardCommand::Status => BoardResponse::Status(BoardStatus {
+                interrupts: self.device.read_register(REG_INTERRUPTS)?,
+                mcan_interrupts: self.device.read_register(REG_MCAN_IR)?,
+                protocol_status: self.device.read_register(REG_MCAN_PSR)?,
+                error_counters: self.device.read_register(REG_MCAN_ECR)?,
+                rx_fifo0_status: self.device.read_register(REG_MCAN_RXF0S)?,
+                rx_fifo1_status: self.device.read_register(REG_MCAN_RXF1S)?,
+                tx_fifo_status: self.device.read_register(REG_MCAN_TXFQS)?,
+            }),
+        })
+    }
+}
+
+impl <S: SpiBus> std::io::Write for BoardLoopback<S> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.decoder.push(buf);
+        while let Some(frame) = self.decoder.next_frame() {
+            let response: BoardResponse = self.handle(&frame);
+            let encoded: Vec<u8> = match response.to_frame(frame.sequence, frame.code).encode() {
+                Ok(x) => x,
+                Err(_) => BoardResponse::Error(BoardErrorCode::Malformed).to_frame(frame.sequence, frame.code).encode().unwrap_or_default(),
+            };
+            self.output.extend(encoded);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl <S: SpiBus> std::io::Read for BoardLoopback<S> {
+    /// Returns zero bytes when no response is pending, like a serial port read that timed out.
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count: usize = buf.len().min(self.output.len());
+        for (x, y) in buf.iter_mut().zip(self.output.drain(..count)) {
+            *x = y;
+        }
+        Ok(count)
+    }
+}
@@ -0,0 +1,140 @@
+//! Framed command and response protocol between the host and the DigitalServo USB board over the FT232H link.
+//!
+//! Every frame is `SYNC`, a sequence number, a code, the payload length (u16), a CRC-16/CCITT of these
+//! header fields, the payload and a CRC-16/CCITT over everything after `SYNC`. The CRCs are appended most
+//! significant byte first; the header CRC lets a receiver reject a corrupted length without waiting for
+//! that many bytes. Multi-byte values in payloads are little-endian, like the MRAM elements they carry.
+//! The board answers each command with one response carrying the sequence number of the command.
+
+pub mod codec;
+pub mod loopback;
+pub mod client;
+
+pub use codec::*;
+
+/// Marks the start of a frame.
+pub const BOARD_SYNC: [u8; 2] = [0xA5, 0x5A];
+/// Sequence number, code, payload length and header CRC.
+pub const BOARD_HEADER_SIZE: usize = 6;
+pub const BOARD_CRC_SIZE: usize = 2;
+/// Longest payload accepted; longer length fields are taken as corruption.
+pub const BOARD_MAX_PAYLOAD: usize = 0x2000;
+
+pub const BOARD_CMD_TRANSMIT: u8 = 0x01;
+pub const BOARD_CMD_READ_RX_FIFO: u8 = 0x02;
+pub const BOARD_CMD_READ_REGISTERS: u8 = 0x03;
+pub const BOARD_CMD_WRITE_REGISTERS: u8 = 0x04;
+pub const BOARD_CMD_STATUS: u8 = 0x05;
+/// Set in the code of a response to the command with the remaining bits.
+pub const BOARD_RSP_FLAG: u8 = 0x80;
+pub const BOARD_RSP_ERROR: u8 = 0xFF;
+
+/// Request from the host to the board.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub enum BoardCommand {
+    /// Queues a TX element, given as its T0 and T1 words and data, in the TX FIFO of the TCAN4550.
+    Transmit { t0: u32, t1: u32, data: Vec<u8> },
+    /// Reads and acknowledges every element in an RX FIFO.
+    ReadRxFifo { fifo: u8 },
+    /// Reads `count` consecutive registers or MRAM words.
+    ReadRegisters { address: u16, count: u16 },
+    WriteRegisters { address: u16, values: Vec<u32> },
+    Status,
+}
+
+/// Reply of the board to one command.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub enum BoardResponse {
+    /// The element was written to TX buffer `buffer`.
+    Transmitted { buffer: u8 },
+    /// RX elements in MRAM byte order, each `element_size` bytes long, as parsed by `CyphalMiddleware::try_read`.
+    RxElements { element_size: u16, data: Vec<u8> },
+    Registers(Vec<u32>),
+    Written,
+    Status(BoardStatus),
+    Error(BoardErrorCode),
+}
+
+/// Register snapshot returned for `BoardCommand::Status`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct BoardStatus {
+    /// Device interrupt flags (INTERRUPTS).
+    pub interrupts: u32,
+    /// MCAN interrupt flags (IR).
+    pub mcan_interrupts: u32,
+    pub protocol_status: u32,
+    pub error_counters: u32,
+    pub rx_fifo0_status: u32,
+    pub rx_fifo1_status: u32,
+    pub tx_fifo_status: u32,
+}
+
+/// Reason the board rejected a command.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize)]
+pub enum BoardErrorCode {
+    UnknownCommand,
+    /// The payload does not match the command.
+    Malformed,
+    TxFifoFull,
+    /// The MRAM section needed by the command is not configured.
+    NotConfigured,
+    /// The SPI transaction with the TCAN4550 failed.
+    Spi,
+    Other(u8),
+}
+
+impl From<u8> for BoardErrorCode {
+    fn from(x: u8) -> Self {
+        match x {
+            1 => Self::UnknownCommand,
+            2 => Self::Malformed,
+            3 => Self::TxFifoFull,
+            4 => Self::NotConfigured,
+            5 => Self::Spi,
+            _ => Self::Other(x),
+        }
+    }
+}
+
+impl From<BoardErrorCode> for u8 {
+    fn from(x: BoardErrorCode) -> Self {
+        match x {
+            BoardErrorCode::UnknownCommand => 1,
+            BoardErrorCode::Malformed => 2,
+            BoardErrorCode::TxFifoFull => 3,
+            BoardErrorCode::NotConfigured => 4,
+            BoardErrorCode::Spi => 5,
+            BoardErrorCode::Other(x) => x,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BoardLinkError {
+    /// A frame passed the CRC but its code is not known.
+    UnknownCode(u8),
+    /// A frame passed the CRC but its payload does not match its code.
+    Malformed(u8),
+    /// The payload exceeds `BOARD_MAX_PAYLOAD`.
+    PayloadTooLong(usize),
+    /// No response with the sequence number of the command arrived in time.
+    Timeout(u8),
+    /// The response does not belong to the command, e.g. registers in reply to a transmit.
+    UnexpectedResponse(u8),
+    Board(BoardErrorCode),
+}
+
+impl std::fmt::Display for BoardLinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownCode(x) => write!(f, "UNKNOWN BOARD FRAME CODE: {:02X}", x),
+            Self::Malformed(x) => write!(f, "MALFORMED BOARD FRAME PAYLOAD FOR CODE {:02X}", x),
+            Self::PayloadTooLong(x) => write!(f, "BOARD FRAME PAYLOAD TOO LONG: {} BYTES", x),
+            Self::Timeout(x) => write!(f, "NO BOARD RESPONSE TO COMMAND {}", x),
+            Self::UnexpectedResponse(x) => write!(f, "UNEXPECTED BOARD RESPONSE CODE: {:02X}", x),
+            Self::Board(x) => write!(f, "BOARD REJECTED COMMAND: {:?}", x),
+        }
+    }
+}
+
+impl std::error::Error for BoardLinkError {}
@@ -0,0 +1,349 @@
+use super::*;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct CanFaultStats {
+    pub dropped: u64,
+    pub duplicated: u64,
+    pub reordered: u64,
+    pub delayed: u64,
+    pub corrupted: u64,
+}
+
+/// Wraps a driver and injects faults into the frames transmitted through it: drops, duplicates,
+/// reordering, delays and single bit flips in the data field, each with its own probability.
+/// The randomness comes from a seeded generator, so a test run can be reproduced exactly.
+/// Received frames are passed through unchanged; wrap the sending side of each link to be disturbed.
+/// Probabilities are clamped to 0..=1; NaN disables the fault.
+pub struct CanFaultInjector<const MTU: usize, D: CanDriver<MTU>> {
+    inner: D,
+    prng_state: u64,
+    drop_probability: f64,
+    duplicate_probability: f64,
+    reorder_probability: f64,
+    delay_probability: f64,
+    bit_flip_probability: f64,
+    delay_usec: CyphalMicrosecond,
+    /// Packet held back to be sent after the next one.
+    reordered: Option<CyphalTxPacket<MTU>>,
+    /// Delayed packets with their release time.
+    delayed: Vec<(CyphalMicrosecond, CyphalTxPacket<MTU>)>,
+    stats: CanFaultStats,
+}
+
+impl <const MTU: usize, D: CanDriver<MTU>> CanFaultInjector<MTU, D> {
+    pub fn new(inner: D, seed: u64) -> Self {
+        Self {
+            inner,
+            // xorshift64 must not start from zero.
+            prng_state: seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1,
+            drop_probability: 0.0,
+            duplicate_probability: 0.0,
+            reorder_probability: 0.0,
+            delay_probability: 0.0,
+            bit_flip_probability: 0.0,
+            delay_usec: 10_000,
+            reordered: None,
+            delayed: vec![],
+            stats: CanFaultStats::default(),
+        }
+    }
+
+    pub fn set_drop_probability(mut self, probability: f64) -> Self {
+        self.drop_probability = clamp_probability(probability);
+        self
+    }
+
+    pub fn set_duplicate_probability(mut self, probability: f64) -> Self {
+        self.duplicate_probability = clamp_probability(probability);
+        self
+    }
+
+    /// Probability that a frame is swapped with the frame transmitted after it.
+    pub fn set_reorder_probability(mut self, probability: f64) -> Self {
+        self.reorder_probability = clamp_probability(probability);
+        self
+    }
+
+    /// Probability that a frame is held back for `delay`, letting later frames overtake it.
+    pub fn set_delay(mut self, probability: f64, delay: std::time::Duration) -> Self {
+        self.delay_probability = clamp_probability(probability);
+        self.delay_usec = delay.as_micros() as CyphalMicrosecond;
+        self
+    }
+
+    /// Probability that one random bit of the data field, tail byte included, is inverted.
+    pub fn set_bit_flip_probability(mut self, probability: f64) -> Self {
+        self.bit_flip_probability = clamp_probability(probability);
+        self
+    }
+
+    pub fn stats(&self) -> CanFaultStats {
+        self.stats
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut D {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// Sends delayed and held-back packets that are due, e.g. when no further traffic is expected.
+    pub fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let now_usec: CyphalMicrosecond = monotonic_usec();
+        let mut packets: Vec<CyphalTxPacket<MTU>> = vec![];
+        let (due, delayed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.delayed).into_iter().partition(|x| x.0 <= now_usec);
+        self.delayed = delayed;
+        packets.extend(due.into_iter().map(|x| x.1));
+        packets.extend(self.reordered.take());
+        if packets.is_empty() {
+            return Ok(());
+        }
+        self.inner.transmit(&packets)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        // xorshift64; only used to decide which faults to inject.
+        self.prng_state ^= self.prng_state << 13;
+        self.prng_state ^= self.prng_state >> 7;
+        self.prng_state ^= self.prng_state << 17;
+        self.prng_state
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        // Draw even for zero probabilities so that enabling one fault does not shift the sequence of the others.
+        let x: f64 = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        x < probability
+    }
+}
+
+fn clamp_probability(probability: f64) -> f64 {
+    if probability.is_nan() { 0.0 } else { probability.clamp(0.0, 1.0) }
+}
+
+impl <const MTU: usize, D: CanDriver<MTU>> CanDriver<MTU> for CanFaultInjector<MTU, D> {
+    fn transmit(&mut self, packets: &[CyphalTxPacket<MTU>]) -> Result<(), Box<dyn std::error::Error>> {
+        let now_usec: CyphalMicrosecond = monotonic_usec();
+        let mut out: Vec<CyphalTxPacket<MTU>> = vec![];
+        let (due, delayed): (Vec<_>, Vec<_>) = std::mem::take(&mut self.delayed).into_iter().partition(|x| x.0 <= now_usec);
+        self.delayed = delayed;
+        out.extend(due.into_iter().map(|x| x.1));
+
+        for packet in packets {
+            let drop: bool = self.chance(self.drop_probability);
+            let flip: bool = self.chance(self.bit_flip_probability);
+            let delay: bool = self.chance(self.delay_probability);
+            let reorder: bool = self.chance(self.reorder_probability);
+            let duplicate: bool = self.chance(self.duplicate_probability);
+            let bit: u64 = self.next_u64();
+
+            if drop {
+                self.stats.dropped += 1;
+                continue;
+            }
+            let mut packet: CyphalTxPacket<MTU> = packet.clone();
+            if flip && packet.payload_size > 0 {
+                let bit: usize = (bit % (packet.payload_size as u64 * 8)) as usize;
+                packet.payload[bit / 8] ^= 1 << (bit % 8);
+                self.stats.corrupted += 1;
+            }
+            if delay {
+                self.stats.delayed += 1;
+                self.delayed.push((now_usec + self.delay_usec, packet));
+                continue;
+            }
+            if duplicate {
+                self.stats.duplicated += 1;
+                out.push(packet.clone());
+            }
+            if reorder && self.reordered.is_none() {
+                self.stats.reordered += 1;
+                self.reordered = Some(packet);
+                continue;
+            }
+            out.push(packet);
+            out.extend(self.reordered.take());
+        }
+
+        if out.is_empty() {
+            return Ok(());
+        }
+        self.inner.transmit(&out)
+    }
+
+    fn receive(&mut self, timeout: std::time::Duration) -> Result<Vec<CyphalRxPacket<MTU>>, Box<dyn std::error::Error>> {
+        self.flush()?;
+        // Wake up in time to release the next delayed packet.
+        let now_usec: CyphalMicrosecond = monotonic_usec();
+        let timeout: std::time::Duration = match self.delayed.iter().map(|x| x.0).min() {
+            Some(x) => timeout.min(std::time::Duration::from_micros(x.saturating_sub(now_usec))),
+            None => timeout,
+        };
+        let ret: Vec<CyphalRxPacket<MTU>> = self.inner.receive(timeout)?;
+        self.flush()?;
+        Ok(ret)
+    }
+
+    fn status(&mut self) -> Result<CanDriverStatus, Box<dyn std::error::Error>> {
+        self.inner.status()
+    }
+
+    fn take_events(&mut self) -> Vec<CanBusEvent> {
+        self.inner.take_events()
+    }
+
+    fn take_tx_confirmations(&mut self) -> Vec<CanTxConfirmation> {
+        self.inner.take_tx_confirmations()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MTU: usize = CYPHAL_MTU_CAN_CLASSIC as usize;
+
+    /// Keeps what the injector passes on instead of sending it.
+    #[derive(Default)]
+    struct Recorder {
+        sent: Vec<CyphalTxPacket<MTU>>,
+    }
+
+    impl CanDriver<MTU> for Recorder {
+        fn transmit(&mut self, packets: &[CyphalTxPacket<MTU>]) -> Result<(), Box<dyn std::error::Error>> {
+            self.sent.extend_from_slice(packets);
+            Ok(())
+        }
+
+        fn receive(&mut self, _timeout: std::time::Duration) -> Result<Vec<CyphalRxPacket<MTU>>, Box<dyn std::error::Error>> {
+            Ok(vec![])
+        }
+
+        fn status(&mut self) -> Result<CanDriverStatus, Box<dyn std::error::Error>> {
+            Ok(CanDriverStatus::default())
+        }
+    }
+
+    /// A transfer of several classic CAN frames, with data counting up from `first`.
+    fn transfer(middleware: &mut CyphalMiddleware<MTU>, first: u8) -> (Vec<u8>, Vec<CyphalTxPacket<MTU>>) {
+        let data: Vec<u8> = (first..first + 20).collect();
+        let packets: Vec<CyphalTxPacket<MTU>> = middleware.create_message_data(1000, &data, data.len()).unwrap();
+        assert!(packets.len() > 2);
+        (data, packets)
+    }
+
+    fn deliver(receiver: &mut CyphalMiddleware<MTU>, packets: &[CyphalTxPacket<MTU>], now_usec: CyphalMicrosecond) -> Vec<CyphalRxFrame> {
+        packets.iter()
+            .filter_map(|x| CyphalRxPacket::<MTU>::from_frame(x.xid, &x.payload[..x.payload_size]).ok())
+            .filter_map(|x| receiver.accept(&x, now_usec).unwrap())
+            .collect()
+    }
+
+    fn run(seed: u64) -> (Vec<(u32, Vec<u8>)>, CanFaultStats) {
+        let mut injector: CanFaultInjector<MTU, Recorder> = CanFaultInjector::new(Recorder::default(), seed)
+            .set_drop_probability(0.2)
+            .set_duplicate_probability(0.2)
+            .set_reorder_probability(0.2)
+            .set_bit_flip_probability(0.2);
+        let mut middleware: CyphalMiddleware<MTU> = CyphalMiddleware::new(1);
+        for _ in 0..20 {
+            injector.transmit(&transfer(&mut middleware, 0).1).unwrap();
+        }
+        injector.flush().unwrap();
+        let stats: CanFaultStats = injector.stats();
+        let sent: Vec<(u32, Vec<u8>)> = injector.into_inner().sent.iter().map(|x| (x.xid, x.payload[..x.payload_size].to_vec())).collect();
+        (sent, stats)
+    }
+
+    #[test]
+    fn seeded_injection_is_reproducible() {
+        let (sent, stats) = run(42);
+        assert_eq!(run(42), (sent.clone(), stats));
+        assert!(stats.dropped > 0 && stats.duplicated > 0 && stats.reordered > 0 && stats.corrupted > 0);
+        assert_ne!(run(43).0, sent);
+    }
+
+    #[test]
+    fn probabilities_are_clamped() {
+        let mut middleware: CyphalMiddleware<MTU> = CyphalMiddleware::new(1);
+        let (_, packets) = transfer(&mut middleware, 0);
+
+        let mut injector: CanFaultInjector<MTU, Recorder> = CanFaultInjector::new(Recorder::default(), 1).set_drop_probability(2.0);
+        injector.transmit(&packets).unwrap();
+        assert_eq!(injector.stats().dropped, packets.len() as u64);
+
+        for probability in [-1.0, f64::NAN] {
+            let mut injector: CanFaultInjector<MTU, Recorder> = CanFaultInjector::new(Recorder::default(), 1).set_drop_probability(probability);
+            injector.transmit(&packets).unwrap();
+            assert_eq!(injector.into_inner().sent.len(), packets.len());
+        }
+    }
+
+    #[test]
+    fn corrupted_transfer_fails_the_crc_check() {
+        let mut middleware: CyphalMiddleware<MTU> = CyphalMiddleware::new(1);
+        let (_, packets) = transfer(&mut middleware, 0);
+        let corrupt = |seed: u64| -> Vec<CyphalTxPacket<MTU>> {
+            let mut injector: CanFaultInjector<MTU, Recorder> = CanFaultInjector::new(Recorder::default(), seed).set_bit_flip_probability(1.0);
+            injector.transmit(&packets).unwrap();
+            injector.into_inner().sent
+        };
+        // Take the first seed whose flips all miss the tail bytes, so that only the transfer CRC can catch them.
+        let tails_intact = |x: &Vec<CyphalTxPacket<MTU>>| packets.iter().zip(x).all(|(a, b)| a.payload[a.payload_size - 1] == b.payload[b.payload_size - 1]);
+        let corrupted: Vec<CyphalTxPacket<MTU>> = (0..).map(corrupt).find(tails_intact).unwrap();
+        assert!(packets.iter().zip(&corrupted).all(|(a, b)| a.payload != b.payload));
+
+        let mut receiver: CyphalMiddleware<MTU> = CyphalMiddleware::new(2);
+        assert!(deliver(&mut receiver, &corrupted, 0).is_empty());
+        // The session is ready for the next transfer.
+        let (data, packets) = transfer(&mut middleware, 0);
+        let frames: Vec<CyphalRxFrame> = deliver(&mut receiver, &packets, 1000);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].payload[..data.len()], data[..]);
+    }
+
+    #[test]
+    fn duplicated_frames_are_rejected_by_the_toggle_bit() {
+        let mut middleware: CyphalMiddleware<MTU> = CyphalMiddleware::new(1);
+        let (data, packets) = transfer(&mut middleware, 0);
+        let mut injector: CanFaultInjector<MTU, Recorder> = CanFaultInjector::new(Recorder::default(), 3).set_duplicate_probability(1.0);
+        injector.transmit(&packets).unwrap();
+        let sent: Vec<CyphalTxPacket<MTU>> = injector.into_inner().sent;
+        assert_eq!(sent.len(), 2 * packets.len());
+
+        let mut receiver: CyphalMiddleware<MTU> = CyphalMiddleware::new(2);
+        let frames: Vec<CyphalRxFrame> = deliver(&mut receiver, &sent, 0);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].payload[..data.len()], data[..]);
+    }
+
+    #[test]
+    fn stalled_session_recovers_after_the_transfer_id_timeout() {
+        let (_, packets) = transfer(&mut CyphalMiddleware::new(1), 0);
+
+        // Only the first frame gets through; the session waits for the rest of the transfer.
+        let mut injector: CanFaultInjector<MTU, Recorder> = CanFaultInjector::new(Recorder::default(), 5);
+        injector.transmit(&packets[..1]).unwrap();
+        let mut injector: CanFaultInjector<MTU, Recorder> = injector.set_drop_probability(1.0);
+        injector.transmit(&packets[1..]).unwrap();
+        let sent: Vec<CyphalTxPacket<MTU>> = injector.into_inner().sent;
+        assert_eq!(sent.len(), 1);
+
+        let mut receiver: CyphalMiddleware<MTU> = CyphalMiddleware::new(2);
+        assert!(deliver(&mut receiver, &sent, 0).is_empty());
+
+        // After a restart the sender repeats the transfer-ID with other data. Within the timeout its frames
+        // continue the stalled transfer and fail the CRC; afterwards they start a new one.
+        let (data, packets) = transfer(&mut CyphalMiddleware::new(1), 100);
+        let timeout_usec: CyphalMicrosecond = receiver.transfer_id_timeout_usec;
+        assert!(deliver(&mut receiver, &packets, timeout_usec / 2).is_empty());
+        let frames: Vec<CyphalRxFrame> = deliver(&mut receiver, &packets, 2 * timeout_usec);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].payload[..data.len()], data[..]);
+    }
+}
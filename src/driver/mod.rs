@@ -0,0 +1,178 @@
+use crate::cyphal::*;
+
+#[cfg(target_os = "linux")]
+pub mod socketcan;
+pub mod virtual_bus;
+pub mod fault;
+pub mod tx_tracker;
+pub mod timestamp;
+pub mod spi;
+pub mod tcan4550;
+pub mod board;
+
+pub use tx_tracker::*;
+pub use timestamp::*;
+
+use std::sync::OnceLock;
+
+/// Microseconds elapsed on the host's monotonic clock since its first use in this process.
+/// Drivers timestamp received packets and evaluate transmit deadlines in this time base,
+/// so the values can be compared with those used by the middleware and the node.
+pub fn monotonic_usec() -> CyphalMicrosecond {
+    static EPOCH: OnceLock<std::time::Instant> = OnceLock::new();
+    EPOCH.get_or_init(std::time::Instant::now).elapsed().as_micros() as CyphalMicrosecond
+}
+
+/// Fault confinement state of the CAN controller.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize)]
+pub enum CanBusState {
+    ErrorActive,
+    /// An error counter reached the warning limit of 96.
+    ErrorWarning,
+    /// An error counter exceeded 127; the controller may only send passive error flags.
+    ErrorPassive,
+    /// The transmit error counter exceeded 255; the controller no longer takes part in bus traffic.
+    BusOff,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct CanDriverStatus {
+    pub bus_state: CanBusState,
+    pub tx_error_count: u8,
+    pub rx_error_count: u8,
+    /// Packets discarded because their deadline passed before they reached the bus.
+    pub tx_deadline_expired: u64,
+    /// Received frames lost because the receive queue was full.
+    pub rx_overflows: u64,
+}
+
+impl Default for CanDriverStatus {
+    fn default() -> Self {
+        Self {
+            bus_state: CanBusState::ErrorActive,
+            tx_error_count: 0,
+            rx_error_count: 0,
+            tx_deadline_expired: 0,
+            rx_overflows: 0,
+        }
+    }
+}
+
+/// Change of the bus condition reported by a driver, to be handled alongside the received packets.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize)]
+pub enum CanBusEvent {
+    BusStateChanged { from: CanBusState, to: CanBusState },
+    /// The driver restarted the controller after bus-off; `attempt` counts the restarts since the bus was last stable.
+    RecoveryStarted { attempt: u32 },
+}
+
+/// Decides when to restart a controller that went bus-off: the first restart follows after `initial_backoff`,
+/// and each further one doubles the wait up to `max_backoff`, so a shorted bus is not flooded with error frames.
+/// The backoff starts over once the bus has stayed out of bus-off for `max_backoff`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct CanRecoveryPolicy {
+    initial_backoff_usec: CyphalMicrosecond,
+    max_backoff_usec: CyphalMicrosecond,
+    backoff_usec: CyphalMicrosecond,
+    restart_usec: Option<CyphalMicrosecond>,
+    stable_since_usec: Option<CyphalMicrosecond>,
+    attempts: u32,
+}
+
+impl Default for CanRecoveryPolicy {
+    fn default() -> Self {
+        Self::new(std::time::Duration::from_millis(100), std::time::Duration::from_secs(5))
+    }
+}
+
+impl CanRecoveryPolicy {
+    pub fn new(initial_backoff: std::time::Duration, max_backoff: std::time::Duration) -> Self {
+        let initial_backoff_usec: CyphalMicrosecond = initial_backoff.as_micros() as CyphalMicrosecond;
+        Self {
+            initial_backoff_usec,
+            max_backoff_usec: (max_backoff.as_micros() as CyphalMicrosecond).max(initial_backoff_usec),
+            backoff_usec: initial_backoff_usec,
+            restart_usec: None,
+            stable_since_usec: None,
+            attempts: 0,
+        }
+    }
+
+    /// Restarts since the bus was last stable.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Feeds the current bus state; returns true when the controller should be restarted now.
+    pub fn poll(&mut self, bus_state: CanBusState, now_usec: CyphalMicrosecond) -> bool {
+        if bus_state != CanBusState::BusOff {
+            self.restart_usec = None;
+            let stable_since_usec: CyphalMicrosecond = *self.stable_since_usec.get_or_insert(now_usec);
+            if self.attempts > 0 && now_usec.saturating_sub(stable_since_usec) >= self.max_backoff_usec {
+                self.attempts = 0;
+                self.backoff_usec = self.initial_backoff_usec;
+            }
+            return false;
+        }
+        self.stable_since_usec = None;
+        let restart_usec: CyphalMicrosecond = *self.restart_usec.get_or_insert(now_usec + self.backoff_usec);
+        if now_usec < restart_usec {
+            return false;
+        }
+        self.restart_usec = None;
+        self.attempts += 1;
+        self.backoff_usec = (self.backoff_usec * 2).min(self.max_backoff_usec);
+        true
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CanDriverError {
+    BusOff,
+    TxQueueFull,
+    /// The TX queue filled up at a transfer boundary after this many packets were taken; the rest were not queued.
+    TxQueueFullAfter(usize),
+    /// The device or socket is gone.
+    Disconnected,
+}
+
+impl std::fmt::Display for CanDriverError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BusOff => write!(f, "CAN CONTROLLER IS BUS-OFF"),
+            Self::TxQueueFull => write!(f, "CAN TX QUEUE IS FULL"),
+            Self::TxQueueFullAfter(x) => write!(f, "CAN TX QUEUE IS FULL AFTER {} PACKETS", x),
+            Self::Disconnected => write!(f, "CAN DEVICE DISCONNECTED"),
+        }
+    }
+}
+
+impl std::error::Error for CanDriverError {}
+
+/// Moves encoded packets between the middleware and a CAN bus.
+/// The USB board, simulators and other backends implement this trait, so the node,
+/// the service client and the file client run unchanged on any of them.
+pub trait CanDriver<const MTU: usize> {
+    /// Queues packets for transmission in the given order. A packet whose `deadline_usec`
+    /// (in the `monotonic_usec` time base) has passed before it reaches the bus is discarded
+    /// and counted in `CanDriverStatus::tx_deadline_expired`.
+    fn transmit(&mut self, packets: &[CyphalTxPacket<MTU>]) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Returns the packets received within `timeout`, stamped with their reception time in the
+    /// `monotonic_usec` time base; an empty vector means nothing arrived in time.
+    fn receive(&mut self, timeout: std::time::Duration) -> Result<Vec<CyphalRxPacket<MTU>>, Box<dyn std::error::Error>>;
+
+    /// Returns the bus state and error counters of the controller.
+    fn status(&mut self) -> Result<CanDriverStatus, Box<dyn std::error::Error>>;
+
+    /// Returns the bus events observed since the last call. Drivers without such reporting return none.
+    fn take_events(&mut self) -> Vec<CanBusEvent> {
+        vec![]
+    }
+
+    /// Returns the transfers fully transmitted since the last call, with their transmission times.
+    /// Drivers that cannot observe transmissions return none.
+    fn take_tx_confirmations(&mut self) -> Vec<CanTxConfirmation> {
+        vec![]
+    }
+}
@@ -0,0 +1,268 @@
+use super::*;
+
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+// Controller state bits in data[1] of a CAN_ERR_CRTL error frame.
+const CAN_ERR_CRTL_WARNING: u8 = (libc::CAN_ERR_CRTL_RX_WARNING | libc::CAN_ERR_CRTL_TX_WARNING) as u8;
+const CAN_ERR_CRTL_PASSIVE: u8 = (libc::CAN_ERR_CRTL_RX_PASSIVE | libc::CAN_ERR_CRTL_TX_PASSIVE) as u8;
+const CAN_ERR_CRTL_RX_OVERFLOW: u8 = libc::CAN_ERR_CRTL_RX_OVERFLOW as u8;
+
+/// How long the rest of a started transfer may wait for room in a full socket queue.
+const TX_RETRY_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(10);
+
+/// CAN driver on a Linux SocketCAN interface such as `can0` or `vcan0`.
+/// With an MTU above 8 the socket is opened with `CAN_RAW_FD_FRAMES` and frames are sent as CAN FD,
+/// with bit rate switching unless disabled. Only extended-ID data frames are passed to the middleware;
+/// error frames update the status returned by `status` and report state changes as events.
+/// Bus-off recovery is left to the kernel (`ip link set can0 type can restart-ms 100`).
+/// A full socket queue stops `transmit` before the first frame of a transfer; once a transfer has started,
+/// its remaining frames are retried for up to TX_RETRY_TIMEOUT so that no partial transfer is left behind.
+pub struct SocketCanDriver<const MTU: usize> {
+    socket: OwnedFd,
+    bit_rate_switch: bool,
+    status: CanDriverStatus,
+    events: Vec<CanBusEvent>,
+}
+
+impl <const MTU: usize> SocketCanDriver<MTU> {
+    pub fn open(interface: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        if MTU != libc::CAN_MAX_DLEN && !(MTU > libc::CAN_MAX_DLEN && MTU <= libc::CANFD_MAX_DLEN) {
+            return Err("INVALID MTU FOR SOCKETCAN".into());
+        }
+        let name: std::ffi::CString = std::ffi::CString::new(interface)?;
+        let ifindex: libc::c_uint = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if ifindex == 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        let fd: libc::c_int = unsafe { libc::socket(libc::PF_CAN, libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC, libc::CAN_RAW) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let socket: OwnedFd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        if MTU > libc::CAN_MAX_DLEN {
+            Self::set_option(&socket, libc::CAN_RAW_FD_FRAMES, 1)?;
+        }
+        let err_mask: libc::can_err_mask_t = libc::CAN_ERR_CRTL | libc::CAN_ERR_BUSOFF | libc::CAN_ERR_RESTARTED | libc::CAN_ERR_CNT;
+        Self::set_option(&socket, libc::CAN_RAW_ERR_FILTER, err_mask)?;
+
+        let mut addr: libc::sockaddr_can = unsafe { std::mem::zeroed() };
+        addr.can_family = libc::AF_CAN as libc::sa_family_t;
+        addr.can_ifindex = ifindex as libc::c_int;
+        let ret: libc::c_int = unsafe {
+            libc::bind(
+                socket.as_raw_fd(),
+                &addr as *const libc::sockaddr_can as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_can>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        Ok(Self {
+            socket,
+            bit_rate_switch: true,
+            status: CanDriverStatus::default(),
+            events: vec![],
+        })
+    }
+
+    /// Sends CAN FD frames with or without switching to the data bit rate. Ignored for classic CAN.
+    pub fn set_bit_rate_switch(mut self, bit_rate_switch: bool) -> Self {
+        self.bit_rate_switch = bit_rate_switch;
+        self
+    }
+
+    fn set_option<T>(socket: &OwnedFd, option: libc::c_int, value: T) -> Result<(), Box<dyn std::error::Error>> {
+        let ret: libc::c_int = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::SOL_CAN_RAW,
+                option,
+                &value as *const T as *const libc::c_void,
+                std::mem::size_of::<T>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    fn to_frame(&self, packet: &CyphalTxPacket<MTU>) -> libc::canfd_frame {
+        let mut frame: libc::canfd_frame = unsafe { std::mem::zeroed() };
+        frame.can_id = (packet.xid & libc::CAN_EFF_MASK) | libc::CAN_EFF_FLAG;
+        frame.len = packet.payload_size as u8;
+        frame.data[..packet.payload_size].copy_from_slice(&packet.payload[..packet.payload_size]);
+        if MTU > libc::CAN_MAX_DLEN {
+            frame.flags = libc::CANFD_FDF as u8;
+            if self.bit_rate_switch {
+                frame.flags |= libc::CANFD_BRS as u8;
+            }
+        }
+        frame
+    }
+
+    fn write_frame(&mut self, frame: &libc::canfd_frame) -> Result<(), Box<dyn std::error::Error>> {
+        let size: usize = if MTU > libc::CAN_MAX_DLEN { libc::CANFD_MTU } else { libc::CAN_MTU };
+        let ret: isize = unsafe { libc::write(self.socket.as_raw_fd(), frame as *const libc::canfd_frame as *const libc::c_void, size) };
+        if ret < 0 {
+            let error: std::io::Error = std::io::Error::last_os_error();
+            return Err(match error.raw_os_error() {
+                Some(libc::ENOBUFS) | Some(libc::EAGAIN) => CanDriverError::TxQueueFull.into(),
+                Some(libc::ENETDOWN) | Some(libc::ENODEV) => CanDriverError::Disconnected.into(),
+                _ => error.into(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Reads one frame without blocking; returns None when the socket queue is empty.
+    fn read_frame(&mut self) -> Result<Option<libc::canfd_frame>, Box<dyn std::error::Error>> {
+        let mut frame: libc::canfd_frame = unsafe { std::mem::zeroed() };
+        let ret: isize = unsafe {
+            libc::read(self.socket.as_raw_fd(), &mut frame as *mut libc::canfd_frame as *mut libc::c_void, libc::CANFD_MTU)
+        };
+        if ret < 0 {
+            let error: std::io::Error = std::io::Error::last_os_error();
+            return match error.raw_os_error() {
+                Some(libc::EAGAIN) => Ok(None),
+                Some(libc::ENETDOWN) | Some(libc::ENODEV) => Err(CanDriverError::Disconnected.into()),
+                _ => Err(error.into()),
+            };
+        }
+        if ret as usize != libc::CAN_MTU && ret as usize != libc::CANFD_MTU {
+            return Err("INVALID SOCKETCAN FRAME SIZE".into());
+        }
+        Ok(Some(frame))
+    }
+
+    /// Writes a frame, retrying while the socket queue is full until `until` has passed.
+    fn write_frame_until(&mut self, frame: &libc::canfd_frame, until: std::time::Instant) -> Result<(), Box<dyn std::error::Error>> {
+        loop {
+            match self.write_frame(frame) {
+                Err(e) if Self::is_tx_queue_full(&*e) && std::time::Instant::now() < until => {
+                    self.wait(libc::POLLOUT, std::time::Duration::from_millis(1))?;
+                },
+                x => return x,
+            }
+        }
+    }
+
+    fn is_tx_queue_full(error: &(dyn std::error::Error + 'static)) -> bool {
+        matches!(error.downcast_ref::<CanDriverError>(), Some(CanDriverError::TxQueueFull))
+    }
+
+    fn wait_readable(&self, timeout: std::time::Duration) -> Result<bool, Box<dyn std::error::Error>> {
+        self.wait(libc::POLLIN, timeout)
+    }
+
+    fn wait(&self, events: libc::c_short, timeout: std::time::Duration) -> Result<bool, Box<dyn std::error::Error>> {
+        let mut pollfd: libc::pollfd = libc::pollfd { fd: self.socket.as_raw_fd(), events, revents: 0 };
+        let timeout_ms: libc::c_int = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        let ret: libc::c_int = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+        if ret < 0 {
+            let error: std::io::Error = std::io::Error::last_os_error();
+            if error.kind() == std::io::ErrorKind::Interrupted {
+                return Ok(false);
+            }
+            return Err(error.into());
+        }
+        Ok(ret > 0)
+    }
+
+    fn accept_error_frame(&mut self, frame: &libc::canfd_frame) {
+        let previous: CanBusState = self.status.bus_state;
+        let class: u32 = frame.can_id & libc::CAN_ERR_MASK;
+        if class & libc::CAN_ERR_CNT != 0 {
+            self.status.tx_error_count = frame.data[6];
+            self.status.rx_error_count = frame.data[7];
+        }
+        if class & libc::CAN_ERR_CRTL != 0 {
+            let state: u8 = frame.data[1];
+            if state & CAN_ERR_CRTL_RX_OVERFLOW != 0 {
+                self.status.rx_overflows += 1;
+            }
+            if state & CAN_ERR_CRTL_PASSIVE != 0 {
+                self.status.bus_state = CanBusState::ErrorPassive;
+            } else if state & CAN_ERR_CRTL_WARNING != 0 {
+                self.status.bus_state = CanBusState::ErrorWarning;
+            } else if state & libc::CAN_ERR_CRTL_ACTIVE as u8 != 0 {
+                self.status.bus_state = CanBusState::ErrorActive;
+            }
+        }
+        if class & libc::CAN_ERR_BUSOFF != 0 {
+            self.status.bus_state = CanBusState::BusOff;
+        }
+        if class & libc::CAN_ERR_RESTARTED != 0 {
+            self.status.bus_state = CanBusState::ErrorActive;
+        }
+        if self.status.bus_state != previous {
+            self.events.push(CanBusEvent::BusStateChanged { from: previous, to: self.status.bus_state });
+        }
+    }
+}
+
+impl <const MTU: usize> CanDriver<MTU> for SocketCanDriver<MTU> {
+    fn transmit(&mut self, packets: &[CyphalTxPacket<MTU>]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut accepted: usize = 0;
+        for transfer in packets.split_inclusive(|x| x.payload_size == 0 || x.payload[x.payload_size - 1] & TAIL_END_OF_TRANSFER != 0) {
+            let now_usec: CyphalMicrosecond = monotonic_usec();
+            let (expired, due): (Vec<&CyphalTxPacket<MTU>>, Vec<&CyphalTxPacket<MTU>>) = transfer.iter()
+                .partition(|x| x.deadline_usec.is_some_and(|x| now_usec > x));
+            for (i, packet) in due.iter().enumerate() {
+                let frame: libc::canfd_frame = self.to_frame(packet);
+                if i > 0 {
+                    self.write_frame_until(&frame, std::time::Instant::now() + TX_RETRY_TIMEOUT)?;
+                    continue;
+                }
+                match self.write_frame(&frame) {
+                    Err(e) if Self::is_tx_queue_full(&*e) => return Err(match accepted {
+                        0 => CanDriverError::TxQueueFull,
+                        x => CanDriverError::TxQueueFullAfter(x),
+                    }.into()),
+                    x => x?,
+                }
+            }
+            self.status.tx_deadline_expired += expired.len() as u64;
+            accepted += transfer.len();
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, timeout: std::time::Duration) -> Result<Vec<CyphalRxPacket<MTU>>, Box<dyn std::error::Error>> {
+        let mut ret: Vec<CyphalRxPacket<MTU>> = vec![];
+        if !self.wait_readable(timeout)? {
+            return Ok(ret);
+        }
+        while let Some(frame) = self.read_frame()? {
+            let timestamp_usec: CyphalMicrosecond = monotonic_usec();
+            if frame.can_id & libc::CAN_ERR_FLAG != 0 {
+                self.accept_error_frame(&frame);
+                continue;
+            }
+            // Cyphal/CAN uses extended-ID data frames only.
+            if frame.can_id & libc::CAN_EFF_FLAG == 0 || frame.can_id & libc::CAN_RTR_FLAG != 0 {
+                continue;
+            }
+            let len: usize = frame.len as usize;
+            if len == 0 || len > MTU {
+                continue;
+            }
+            let mut packet: CyphalRxPacket<MTU> = CyphalRxPacket::from_frame(frame.can_id & libc::CAN_EFF_MASK, &frame.data[..len])?;
+            packet.timestamp_usec = timestamp_usec;
+            ret.push(packet);
+        }
+        Ok(ret)
+    }
+
+    fn status(&mut self) -> Result<CanDriverStatus, Box<dyn std::error::Error>> {
+        Ok(self.status)
+    }
+
+    fn take_events(&mut self) -> Vec<CanBusEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
@@ -0,0 +1,12 @@
+/// Full-duplex SPI bus with a single chip select, such as the FT232H MPSSE on the board.
+pub trait SpiBus {
+    /// Performs one transaction with chip select asserted for its whole duration;
+    /// the bytes in `data` are sent and replaced by the bytes received.
+    fn transfer(&mut self, data: &mut [u8]) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+impl <T: SpiBus + ?Sized> SpiBus for &mut T {
+    fn transfer(&mut self, data: &mut [u8]) -> Result<(), Box<dyn std::error::Error>> {
+        (**self).transfer(data)
+    }
+}
@@ -0,0 +1,244 @@
+use super::registers::*;
+
+/// Frequency of the crystal on the board, which clocks the MCAN directly.
+pub const TCAN4550_CLOCK_HZ: u32 = 40_000_000;
+
+/// Data bitrates above this need transceiver delay compensation, as the loop delay exceeds the sample point.
+const TDC_MIN_BITRATE: u32 = 1_000_000;
+
+/// ISO 11898-1 only allows transceiver delay compensation with data prescalers of one or two.
+const TDC_MAX_PRESCALER: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tcan4550BitTimingError {
+    /// No prescaler divides the clock into a whole number of time quanta per bit within the register limits.
+    InexactBitrate { clock_hz: u32, bitrate: u32 },
+    /// The sample point must lie strictly between 0 and 1.
+    InvalidSamplePoint(f64),
+    /// The transceiver delay compensation offset, in clock periods, exceeds TDCR.TDCO.
+    TdcOffsetOutOfRange(u32),
+}
+
+impl std::fmt::Display for Tcan4550BitTimingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InexactBitrate { clock_hz, bitrate } => write!(f, "NO EXACT BIT TIMING FOR {} BIT/S FROM {} HZ", bitrate, clock_hz),
+            Self::InvalidSamplePoint(x) => write!(f, "INVALID SAMPLE POINT: {}", x),
+            Self::TdcOffsetOutOfRange(x) => write!(f, "TRANSCEIVER DELAY COMPENSATION OFFSET OUT OF RANGE: {}", x),
+        }
+    }
+}
+
+impl std::error::Error for Tcan4550BitTimingError {}
+
+/// Bit timing of one phase in time quanta. A bit is the sync quantum followed by `tseg1` and `tseg2`.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize)]
+pub struct Tcan4550PhaseTiming {
+    pub prescaler: u32,
+    /// Propagation and phase 1 segments, up to the sample point.
+    pub tseg1: u32,
+    /// Phase 2 segment, after the sample point.
+    pub tseg2: u32,
+    pub sjw: u32,
+    /// Sample point actually achieved, as a fraction of the bit time.
+    pub sample_point: f64,
+}
+
+impl Tcan4550PhaseTiming {
+    pub fn quanta_per_bit(&self) -> u32 {
+        1 + self.tseg1 + self.tseg2
+    }
+
+    pub fn bitrate(&self, clock_hz: u32) -> f64 {
+        clock_hz as f64 / (self.prescaler * self.quanta_per_bit()) as f64
+    }
+}
+
+/// Register values for both phases of a CAN FD bit, ready for `Tcan4550Driver::set_bit_timing`.
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize)]
+pub struct Tcan4550BitTiming {
+    pub nominal: Tcan4550PhaseTiming,
+    pub data: Tcan4550PhaseTiming,
+    /// Transceiver delay compensation offset in clock periods, when enabled.
+    pub tdc_offset: Option<u32>,
+    pub nbtp: u32,
+    pub dbtp: u32,
+    pub tdcr: u32,
+}
+
+impl Tcan4550BitTiming {
+    /// Finds the bit timing for the given bitrates with the sample points closest to the targets (e.g. 0.8).
+    /// Among equally close solutions the one with the smallest prescaler, and so the finest resolution, wins.
+    /// Bitrates the clock cannot produce exactly are reported as errors instead of being approximated.
+    /// For classic CAN pass the nominal bitrate and sample point for the data phase too.
+    pub fn calculate(
+        clock_hz: u32,
+        nominal_bitrate: u32,
+        nominal_sample_point: f64,
+        data_bitrate: u32,
+        data_sample_point: f64,
+    ) -> Result<Self, Tcan4550BitTimingError> {
+        let nominal: Tcan4550PhaseTiming = calculate_phase(
+            clock_hz,
+            nominal_bitrate,
+            nominal_sample_point,
+            (NBTP_NBRP_MAX, NBTP_NTSEG1_MAX, NBTP_NTSEG2_MAX, NBTP_NSJW_MAX),
+            2,
+        )?;
+        let tdc: bool = data_bitrate > TDC_MIN_BITRATE;
+        let data: Tcan4550PhaseTiming = calculate_phase(
+            clock_hz,
+            data_bitrate,
+            data_sample_point,
+            (if tdc { TDC_MAX_PRESCALER } else { DBTP_DBRP_MAX }, DBTP_DTSEG1_MAX, DBTP_DTSEG2_MAX, DBTP_DSJW_MAX),
+            1,
+        )?;
+
+        // The secondary sample point sits at the sample point of the data phase, counted from the
+        // measured transmitter delay.
+        let tdc_offset: Option<u32> = if tdc { Some(data.prescaler * (1 + data.tseg1)) } else { None };
+        if let Some(x) = tdc_offset.filter(|x| *x > TDCR_MAX) {
+            return Err(Tcan4550BitTimingError::TdcOffsetOutOfRange(x));
+        }
+
+        Ok(Self {
+            nbtp: ((nominal.sjw - 1) << NBTP_NSJW_SHIFT)
+                | ((nominal.prescaler - 1) << NBTP_NBRP_SHIFT)
+                | ((nominal.tseg1 - 1) << NBTP_NTSEG1_SHIFT)
+                | ((nominal.tseg2 - 1) << NBTP_NTSEG2_SHIFT),
+            dbtp: if tdc { DBTP_TDC } else { 0 }
+                | ((data.prescaler - 1) << DBTP_DBRP_SHIFT)
+                | ((data.tseg1 - 1) << DBTP_DTSEG1_SHIFT)
+                | ((data.tseg2 - 1) << DBTP_DTSEG2_SHIFT)
+                | ((data.sjw - 1) << DBTP_DSJW_SHIFT),
+            // A TDCF of zero leaves the filter window disabled.
+            tdcr: tdc_offset.unwrap_or(0) << TDCR_TDCO_SHIFT,
+            nominal,
+            data,
+            tdc_offset,
+        })
+    }
+
+    /// Period of the internal timestamp counter, which counts nominal bit times divided by the TSCC prescaler.
+    pub fn timestamp_tick(&self, clock_hz: u32, prescaler: u32) -> std::time::Duration {
+        std::time::Duration::from_secs_f64(prescaler.max(1) as f64 / self.nominal.bitrate(clock_hz))
+    }
+}
+
+/// Limits are (prescaler, tseg1, tseg2, sjw), each the largest value the register field can express.
+fn calculate_phase(
+    clock_hz: u32,
+    bitrate: u32,
+    sample_point: f64,
+    limits: (u32, u32, u32, u32),
+    min_tseg1: u32,
+) -> Result<Tcan4550PhaseTiming, Tcan4550BitTimingError> {
+    if !(sample_point > 0.0 && sample_point < 1.0) {
+        return Err(Tcan4550BitTimingError::InvalidSamplePoint(sample_point));
+    }
+    let (max_prescaler, max_tseg1, max_tseg2, max_sjw) = limits;
+    let mut best: Option<Tcan4550PhaseTiming> = None;
+    for prescaler in 1..=max_prescaler {
+        let divisor: u64 = prescaler as u64 * bitrate as u64;
+        if divisor == 0 || !(clock_hz as u64).is_multiple_of(divisor) {
+            continue;
+        }
+        let quanta: u32 = (clock_hz as u64 / divisor) as u32;
+        if quanta < 1 + min_tseg1 + 1 || quanta > 1 + max_tseg1 + max_tseg2 {
+            continue;
+        }
+        let tseg1: u32 = ((quanta as f64 * sample_point).round() as u32)
+            .saturating_sub(1)
+            .clamp(min_tseg1.max((quanta - 1).saturating_sub(max_tseg2)), max_tseg1.min(quanta - 2));
+        let tseg2: u32 = quanta - 1 - tseg1;
+        let candidate: Tcan4550PhaseTiming = Tcan4550PhaseTiming {
+            prescaler,
+            tseg1,
+            tseg2,
+            sjw: tseg2.min(tseg1).min(max_sjw),
+            sample_point: (1 + tseg1) as f64 / quanta as f64,
+        };
+        let error = |x: &Tcan4550PhaseTiming| (x.sample_point - sample_point).abs();
+        if best.as_ref().is_none_or(|x| error(&candidate) < error(x) - f64::EPSILON) {
+            best = Some(candidate);
+        }
+    }
+    best.ok_or(Tcan4550BitTimingError::InexactBitrate { clock_hz, bitrate })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registers_for_500k_2m() {
+        let timing: Tcan4550BitTiming = Tcan4550BitTiming::calculate(TCAN4550_CLOCK_HZ, 500_000, 0.8, 2_000_000, 0.75).unwrap();
+        assert_eq!((timing.nominal.prescaler, timing.nominal.tseg1, timing.nominal.tseg2, timing.nominal.sjw), (1, 63, 16, 16));
+        assert_eq!((timing.data.prescaler, timing.data.tseg1, timing.data.tseg2, timing.data.sjw), (1, 14, 5, 5));
+        assert_eq!(timing.nbtp, 0x1E00_3E0F);
+        assert_eq!(timing.dbtp, 0x0080_0D44);
+        assert_eq!(timing.tdc_offset, Some(15));
+        assert_eq!(timing.tdcr, 0x0000_0F00);
+        assert_eq!(timing.nominal.bitrate(TCAN4550_CLOCK_HZ), 500_000.0);
+        assert_eq!(timing.data.bitrate(TCAN4550_CLOCK_HZ), 2_000_000.0);
+    }
+
+    #[test]
+    fn registers_for_1m_5m() {
+        let timing: Tcan4550BitTiming = Tcan4550BitTiming::calculate(TCAN4550_CLOCK_HZ, 1_000_000, 0.8, 5_000_000, 0.75).unwrap();
+        assert_eq!((timing.nominal.prescaler, timing.nominal.tseg1, timing.nominal.tseg2, timing.nominal.sjw), (1, 31, 8, 8));
+        assert_eq!((timing.data.prescaler, timing.data.tseg1, timing.data.tseg2, timing.data.sjw), (1, 5, 2, 2));
+        assert_eq!(timing.nbtp, 0x0E00_1E07);
+        assert_eq!(timing.dbtp, 0x0080_0411);
+        assert_eq!(timing.tdc_offset, Some(6));
+        assert_eq!(timing.tdcr, 0x0000_0600);
+        assert_eq!(timing.data.sample_point, 0.75);
+    }
+
+    #[test]
+    fn tdc_is_off_at_or_below_1m() {
+        for data_bitrate in [500_000, 1_000_000] {
+            let timing: Tcan4550BitTiming = Tcan4550BitTiming::calculate(TCAN4550_CLOCK_HZ, 500_000, 0.8, data_bitrate, 0.8).unwrap();
+            assert_eq!(timing.tdc_offset, None);
+            assert_eq!(timing.dbtp & DBTP_TDC, 0);
+            assert_eq!(timing.tdcr, 0);
+        }
+    }
+
+    #[test]
+    fn inexact_bitrate_is_an_error() {
+        assert_eq!(
+            Tcan4550BitTiming::calculate(TCAN4550_CLOCK_HZ, 500_000, 0.8, 3_000_000, 0.75),
+            Err(Tcan4550BitTimingError::InexactBitrate { clock_hz: TCAN4550_CLOCK_HZ, bitrate: 3_000_000 })
+        );
+        assert_eq!(
+            Tcan4550BitTiming::calculate(TCAN4550_CLOCK_HZ, 333_333, 0.8, 2_000_000, 0.75),
+            Err(Tcan4550BitTimingError::InexactBitrate { clock_hz: TCAN4550_CLOCK_HZ, bitrate: 333_333 })
+        );
+    }
+
+    #[test]
+    fn sample_point_outside_the_bit_is_an_error() {
+        for sample_point in [0.0, 1.0, -0.5, 1.5] {
+            assert_eq!(
+                Tcan4550BitTiming::calculate(TCAN4550_CLOCK_HZ, 500_000, sample_point, 2_000_000, 0.75),
+                Err(Tcan4550BitTimingError::InvalidSamplePoint(sample_point))
+            );
+            assert_eq!(
+                Tcan4550BitTiming::calculate(TCAN4550_CLOCK_HZ, 500_000, 0.8, 2_000_000, sample_point),
+                Err(Tcan4550BitTimingError::InvalidSamplePoint(sample_point))
+            );
+        }
+        assert!(matches!(
+            Tcan4550BitTiming::calculate(TCAN4550_CLOCK_HZ, 500_000, f64::NAN, 2_000_000, 0.75),
+            Err(Tcan4550BitTimingError::InvalidSamplePoint(x)) if x.is_nan()
+        ));
+    }
+
+    #[test]
+    fn timestamp_tick_counts_nominal_bits() {
+        let timing: Tcan4550BitTiming = Tcan4550BitTiming::calculate(TCAN4550_CLOCK_HZ, 500_000, 0.8, 2_000_000, 0.75).unwrap();
+        assert_eq!(timing.timestamp_tick(TCAN4550_CLOCK_HZ, 1), std::time::Duration::from_micros(2));
+        assert_eq!(timing.timestamp_tick(TCAN4550_CLOCK_HZ, 8), std::time::Duration::from_micros(16));
+    }
+}
@@ -0,0 +1,892 @@
+use super::*;
+use super::registers::*;
+use crate::cyphal::*;
+use crate::driver::*;
+use crate::driver::spi::SpiBus;
+use super::filter::Tcan4550FilterPlan;
+use super::mram::Tcan4550MramConfig;
+use super::status::*;
+
+/// Number of read-backs before a mode or CCCR change is considered failed.
+const CONFIRMATION_ATTEMPTS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tcan4550Error {
+    UnexpectedDeviceId { id1: u32, id2: u32 },
+    /// A register did not take the written value, e.g. CCCR.INIT while the MCAN clock is not running.
+    WriteNotConfirmed(u16),
+    /// The MRAM sections needed for the operation are not configured.
+    NotConfigured(&'static str),
+}
+
+impl std::fmt::Display for Tcan4550Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnexpectedDeviceId { id1, id2 } => write!(f, "UNEXPECTED TCAN4550 DEVICE ID: {:08X} {:08X}", id1, id2),
+            Self::WriteNotConfirmed(address) => write!(f, "TCAN4550 REGISTER {:04X} DID NOT TAKE THE WRITTEN VALUE", address),
+            Self::NotConfigured(section) => write!(f, "TCAN4550 {} IS NOT CONFIGURED", section),
+        }
+    }
+}
+
+impl std::error::Error for Tcan4550Error {}
+
+/// Operating options of the MCAN, written to CCCR.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Tcan4550CccrConfig {
+    pub fd_operation: bool,
+    pub bit_rate_switch: bool,
+    /// Retransmit frames that lost arbitration or were disturbed by errors.
+    pub auto_retransmission: bool,
+    /// Listen only, without acknowledging frames or sending error flags.
+    pub bus_monitoring: bool,
+}
+
+impl Default for Tcan4550CccrConfig {
+    fn default() -> Self {
+        Self {
+            fd_operation: true,
+            bit_rate_switch: true,
+            auto_retransmission: true,
+            bus_monitoring: false,
+        }
+    }
+}
+
+/// Fill state of an RX FIFO or the TX event FIFO.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Tcan4550FifoStatus {
+    pub fill_level: usize,
+    pub get_index: usize,
+    pub put_index: usize,
+    pub full: bool,
+    pub message_lost: bool,
+}
+
+/// Fill state of the TX FIFO/queue: the host writes the next frame to the buffer at `put_index`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Tcan4550TxFifoStatus {
+    pub free_level: usize,
+    pub get_index: usize,
+    pub put_index: usize,
+    pub full: bool,
+}
+
+/// A frame reported as transmitted in the TX event FIFO.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Tcan4550TxEvent {
+    pub id: u32,
+    pub extended: bool,
+    pub fd: bool,
+    pub brs: bool,
+    pub dlc: u8,
+    /// Message marker copied from the TX element.
+    pub marker: u8,
+    /// Value of the timestamp counter at the start of frame.
+    pub timestamp: u16,
+}
+
+/// Pending interrupt flags of the device (INTERRUPTS) and of the MCAN (IR).
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Tcan4550Interrupts {
+    pub device: u32,
+    pub mcan: u32,
+}
+
+/// Register-level driver of the TCAN4550 over any SPI bus.
+/// The usual bring-up is `check_device_id`, `enable_configuration`, `set_cccr`, `set_bit_timing`,
+/// `configure_mram` and the filters, `disable_configuration` and `set_mode(Normal)`.
+/// As a `CanDriver` it sends packets through the TX FIFO and polls both RX FIFOs. Each `receive` also checks
+/// the protocol status, reports bus state changes as events and, with a recovery policy, restarts the MCAN
+/// after bus-off, which it otherwise leaves in the initialization state. When the MRAM has a TX event FIFO,
+/// frames are sent with the marker of their packet and transfers are confirmed from the TX events.
+/// Received packets and TX events are stamped with the time they are read, or, with a timestamp tracker,
+/// with the host time the controller captured them, converted from the timestamp counter.
+pub struct Tcan4550Driver<S: SpiBus> {
+    spi: S,
+    mram: Tcan4550MramConfig,
+    poll_interval: std::time::Duration,
+    recovery: Option<CanRecoveryPolicy>,
+    bus_state: CanBusState,
+    events: Vec<CanBusEvent>,
+    tx_tracker: CanTxTracker,
+    timestamps: Option<CanTimestampTracker>,
+    tx_deadline_expired: u64,
+    rx_overflows: u64,
+}
+
+impl <S: SpiBus> Tcan4550Driver<S> {
+    pub fn new(spi: S) -> Self {
+        Self {
+            spi,
+            mram: Tcan4550MramConfig::default(),
+            poll_interval: std::time::Duration::from_millis(1),
+            recovery: None,
+            bus_state: CanBusState::ErrorActive,
+            events: vec![],
+            tx_tracker: CanTxTracker::new(),
+            timestamps: None,
+            tx_deadline_expired: 0,
+            rx_overflows: 0,
+        }
+    }
+
+    /// Interval between two RX FIFO polls while `receive` waits for frames.
+    pub fn set_poll_interval(mut self, poll_interval: std::time::Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Enables automatic restarts after bus-off.
+    pub fn set_recovery_policy(mut self, recovery: CanRecoveryPolicy) -> Self {
+        self.recovery = Some(recovery);
+        self
+    }
+
+    /// Converts the element timestamps to host time. Needs the internal timestamp counter, see
+    /// `enable_timestamp_counter`, and a tracker of 16 bits with the tick from `Tcan4550BitTiming::timestamp_tick`.
+    pub fn set_timestamp_tracker(mut self, tracker: CanTimestampTracker) -> Self {
+        self.timestamps = Some(tracker);
+        self
+    }
+
+    pub fn timestamp_tracker(&self) -> Option<&CanTimestampTracker> {
+        self.timestamps.as_ref()
+    }
+
+    pub fn spi(&self) -> &S {
+        &self.spi
+    }
+
+    pub fn spi_mut(&mut self) -> &mut S {
+        &mut self.spi
+    }
+
+    pub fn into_inner(self) -> S {
+        self.spi
+    }
+
+    pub fn mram_config(&self) -> &Tcan4550MramConfig {
+        &self.mram
+    }
+
+    pub fn read_words(&mut self, address: u16, count: usize) -> Result<Vec<u32>, Box<dyn std::error::Error>> {
+        let mut ret: Vec<u32> = Vec::with_capacity(count);
+        while ret.len() < count {
+            let words: usize = (count - ret.len()).min(SPI_MAX_WORDS);
+            let address: u16 = address.wrapping_add((ret.len() * SPI_WORD_SIZE) as u16);
+            let mut data: Vec<u8> = vec![0; SPI_HEADER_SIZE + words * SPI_WORD_SIZE];
+            data[..SPI_HEADER_SIZE].copy_from_slice(&[SPI_OPCODE_READ, (address >> 8) as u8, address as u8, words as u8]);
+            self.spi.transfer(&mut data)?;
+            ret.extend(data[SPI_HEADER_SIZE..].chunks(SPI_WORD_SIZE).map(|x| u32::from_be_bytes([x[0], x[1], x[2], x[3]])));
+        }
+        Ok(ret)
+    }
+
+    pub fn write_words(&mut self, address: u16, values: &[u32]) -> Result<(), Box<dyn std::error::Error>> {
+        for (i, chunk) in values.chunks(SPI_MAX_WORDS).enumerate() {
+            let address: u16 = address.wrapping_add((i * SPI_MAX_WORDS * SPI_WORD_SIZE) as u16);
+            let mut data: Vec<u8> = Vec::with_capacity(SPI_HEADER_SIZE + chunk.len() * SPI_WORD_SIZE);
+            // A length of zero stands for the maximum of 256 words.
+            data.extend_from_slice(&[SPI_OPCODE_WRITE, (address >> 8) as u8, address as u8, chunk.len() as u8]);
+            data.extend(chunk.iter().flat_map(|x| x.to_be_bytes()));
+            self.spi.transfer(&mut data)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_register(&mut self, address: u16) -> Result<u32, Box<dyn std::error::Error>> {
+        Ok(self.read_words(address, 1)?[0])
+    }
+
+    pub fn write_register(&mut self, address: u16, value: u32) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_words(address, &[value])
+    }
+
+    /// Checks that the chip answers with the "TCAN4550" device ID, i.e. that SPI works.
+    pub fn check_device_id(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let id: Vec<u32> = self.read_words(REG_DEVICE_ID1, 2)?;
+        if id[0] != DEVICE_ID1_VALUE || id[1] != DEVICE_ID2_VALUE {
+            return Err(Tcan4550Error::UnexpectedDeviceId { id1: id[0], id2: id[1] }.into());
+        }
+        Ok(())
+    }
+
+    pub fn mode(&mut self) -> Result<Tcan4550Mode, Box<dyn std::error::Error>> {
+        let value: u32 = self.read_register(REG_MODES_OF_OPERATION)?;
+        Ok(match (value & MODES_MODE_SEL_MASK) >> MODES_MODE_SEL_SHIFT {
+            MODES_MODE_SLEEP => Tcan4550Mode::Sleep,
+            MODES_MODE_NORMAL => Tcan4550Mode::Normal,
+            _ => Tcan4550Mode::Standby,
+        })
+    }
+
+    /// Switches the operating mode. The chip stops answering on SPI in sleep mode, so that change is not confirmed.
+    pub fn set_mode(&mut self, mode: Tcan4550Mode) -> Result<(), Box<dyn std::error::Error>> {
+        let selection: u32 = match mode {
+            Tcan4550Mode::Sleep => MODES_MODE_SLEEP,
+            Tcan4550Mode::Standby => MODES_MODE_STANDBY,
+            Tcan4550Mode::Normal => MODES_MODE_NORMAL,
+        };
+        let value: u32 = self.read_register(REG_MODES_OF_OPERATION)?;
+        self.write_register(REG_MODES_OF_OPERATION, (value & !MODES_MODE_SEL_MASK) | (selection << MODES_MODE_SEL_SHIFT))?;
+        if mode == Tcan4550Mode::Sleep {
+            return Ok(());
+        }
+        for _ in 0..CONFIRMATION_ATTEMPTS {
+            if self.mode()? == mode {
+                return Ok(());
+            }
+        }
+        Err(Tcan4550Error::WriteNotConfirmed(REG_MODES_OF_OPERATION).into())
+    }
+
+    /// Stops the MCAN and unlocks the protected configuration registers (CCCR.INIT and CCCR.CCE).
+    pub fn enable_configuration(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let cccr: u32 = self.read_register(REG_MCAN_CCCR)?;
+        self.write_register(REG_MCAN_CCCR, cccr | CCCR_INIT)?;
+        self.write_cccr_confirmed(cccr | CCCR_INIT | CCCR_CCE, CCCR_INIT | CCCR_CCE)
+    }
+
+    /// Locks the configuration registers and lets the MCAN join the bus once the chip is in normal mode.
+    pub fn disable_configuration(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let cccr: u32 = self.read_register(REG_MCAN_CCCR)?;
+        self.write_cccr_confirmed(cccr & !(CCCR_INIT | CCCR_CCE), CCCR_INIT | CCCR_CCE)
+    }
+
+    /// Requires `enable_configuration`.
+    pub fn set_cccr(&mut self, config: &Tcan4550CccrConfig) -> Result<(), Box<dyn std::error::Error>> {
+        let cccr: u32 = self.read_register(REG_MCAN_CCCR)?;
+        let options: u32 = CCCR_FDOE | CCCR_BRSE | CCCR_DAR | CCCR_MON;
+        let value: u32 = (cccr & !options)
+            | if config.fd_operation { CCCR_FDOE } else { 0 }
+            | if config.fd_operation && config.bit_rate_switch { CCCR_BRSE } else { 0 }
+            | if config.auto_retransmission { 0 } else { CCCR_DAR }
+            | if config.bus_monitoring { CCCR_MON } else { 0 };
+        self.write_cccr_confirmed(value, options)
+    }
+
+    /// Writes the nominal and data bit timing and the transceiver delay compensation, as calculated by
+    /// `Tcan4550BitTiming::calculate`. Requires `enable_configuration`.
+    pub fn set_bit_timing(&mut self, nbtp: u32, dbtp: u32, tdcr: u32) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_register(REG_MCAN_NBTP, nbtp)?;
+        self.write_register(REG_MCAN_DBTP, dbtp)?;
+        self.write_register(REG_MCAN_TDCR, tdcr)?;
+        if self.read_register(REG_MCAN_NBTP)? != nbtp {
+            return Err(Tcan4550Error::WriteNotConfirmed(REG_MCAN_NBTP).into());
+        }
+        Ok(())
+    }
+
+    /// Lets the internal timestamp counter advance once every `prescaler` (1 to 16) nominal bit times
+    /// and restarts it from zero. Requires `enable_configuration`.
+    pub fn enable_timestamp_counter(&mut self, prescaler: u32) -> Result<(), Box<dyn std::error::Error>> {
+        let value: u32 = ((prescaler.clamp(1, TSCC_TCP_MAX) - 1) << TSCC_TCP_SHIFT) | TSCC_TSS_INTERNAL;
+        self.write_register(REG_MCAN_TSCC, value)?;
+        if self.read_register(REG_MCAN_TSCC)? != value {
+            return Err(Tcan4550Error::WriteNotConfirmed(REG_MCAN_TSCC).into());
+        }
+        self.write_register(REG_MCAN_TSCV, 0)?;
+        if let Some(tracker) = self.timestamps.as_mut() {
+            tracker.reset();
+        }
+        Ok(())
+    }
+
+    /// Writes the MRAM section registers and clears the MRAM, as the element areas are not initialized
+    /// at power-up. Requires `enable_configuration`.
+    pub fn configure_mram(&mut self, config: &Tcan4550MramConfig) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_words(MRAM_BASE, &[0; MRAM_SIZE / SPI_WORD_SIZE])?;
+        for (address, value) in [
+            (REG_MCAN_SIDFC, config.sidfc),
+            (REG_MCAN_XIDFC, config.xidfc),
+            (REG_MCAN_RXF0C, config.rxf0c),
+            (REG_MCAN_RXF1C, config.rxf1c),
+            (REG_MCAN_RXESC, config.rxesc),
+            (REG_MCAN_TXBC, config.txbc),
+            (REG_MCAN_TXESC, config.txesc),
+            (REG_MCAN_TXEFC, config.txefc),
+        ] {
+            self.write_register(address, value)?;
+            if self.read_register(address)? != value {
+                return Err(Tcan4550Error::WriteNotConfirmed(address).into());
+            }
+        }
+        self.mram = *config;
+        Ok(())
+    }
+
+    /// Reads the MRAM section registers back and uses them from now on, e.g. after they were written
+    /// with `write_register` or by another SPI master.
+    pub fn load_mram_config(&mut self) -> Result<Tcan4550MramConfig, Box<dyn std::error::Error>> {
+        self.mram = Tcan4550MramConfig {
+            sidfc: self.read_register(REG_MCAN_SIDFC)?,
+            xidfc: self.read_register(REG_MCAN_XIDFC)?,
+            rxf0c: self.read_register(REG_MCAN_RXF0C)?,
+            rxf1c: self.read_register(REG_MCAN_RXF1C)?,
+            rxesc: self.read_register(REG_MCAN_RXESC)?,
+            txbc: self.read_register(REG_MCAN_TXBC)?,
+            txesc: self.read_register(REG_MCAN_TXESC)?,
+            txefc: self.read_register(REG_MCAN_TXEFC)?,
+        };
+        Ok(self.mram)
+    }
+
+    /// Sets the handling of frames that match no filter (GFC). Requires `enable_configuration`.
+    pub fn set_global_filter(&mut self, gfc: u32) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_register(REG_MCAN_GFC, gfc)
+    }
+
+    /// Sets the mask ANDed to extended IDs before they are compared with the filters. Requires `enable_configuration`.
+    pub fn set_extended_id_mask(&mut self, mask: u32) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_register(REG_MCAN_XIDAM, mask & ELEMENT_EXT_ID_MASK)
+    }
+
+    pub fn write_standard_filter(&mut self, index: usize, element: u32) -> Result<(), Box<dyn std::error::Error>> {
+        let count: usize = ((self.mram.sidfc & SIDFC_LSS_MASK) >> SIDFC_LSS_SHIFT) as usize;
+        if index >= count {
+            return Err(Tcan4550Error::NotConfigured("STANDARD FILTER ELEMENT").into());
+        }
+        let offset: usize = (self.mram.sidfc & START_ADDRESS_MASK) as usize + index * STD_FILTER_ELEMENT_SIZE;
+        self.write_register(MRAM_BASE + offset as u16, element)
+    }
+
+    pub fn write_extended_filter(&mut self, index: usize, element: [u32; 2]) -> Result<(), Box<dyn std::error::Error>> {
+        let count: usize = ((self.mram.xidfc & XIDFC_LSE_MASK) >> XIDFC_LSE_SHIFT) as usize;
+        if index >= count {
+            return Err(Tcan4550Error::NotConfigured("EXTENDED FILTER ELEMENT").into());
+        }
+        let offset: usize = (self.mram.xidfc & START_ADDRESS_MASK) as usize + index * EXT_FILTER_ELEMENT_SIZE;
+        self.write_words(MRAM_BASE + offset as u16, &element)
+    }
+
+    /// Number of extended filter elements the MRAM configuration has room for.
+    pub fn extended_filter_capacity(&self) -> usize {
+        ((self.mram.xidfc & XIDFC_LSE_MASK) >> XIDFC_LSE_SHIFT) as usize
+    }
+
+    /// Installs the filters of a plan into RX FIFO 0, disables the remaining elements and rejects
+    /// standard, remote and non-matching frames, which Cyphal has no use for. Requires `enable_configuration`.
+    pub fn set_filter_plan(&mut self, plan: &Tcan4550FilterPlan) -> Result<(), Box<dyn std::error::Error>> {
+        if plan.filter_count() > self.extended_filter_capacity() {
+            return Err(Tcan4550Error::NotConfigured("EXTENDED FILTER ELEMENT").into());
+        }
+        self.set_extended_id_mask(plan.id_mask)?;
+        for i in 0..self.extended_filter_capacity() {
+            let element: [u32; 2] = plan.filters.get(i).map_or([0, 0], |x| x.element(FILTER_CONFIG_FIFO0));
+            self.write_extended_filter(i, element)?;
+        }
+        self.set_global_filter((GFC_REJECT << GFC_ANFS_SHIFT) | (GFC_REJECT << GFC_ANFE_SHIFT) | GFC_RRFS | GFC_RRFE)
+    }
+
+    pub fn rx_fifo_status(&mut self, fifo: usize) -> Result<Tcan4550FifoStatus, Box<dyn std::error::Error>> {
+        let value: u32 = self.read_register(if fifo == 0 { REG_MCAN_RXF0S } else { REG_MCAN_RXF1S })?;
+        Ok(Tcan4550FifoStatus {
+            fill_level: (value & RXFS_FILL_MASK) as usize,
+            get_index: ((value & RXFS_GET_MASK) >> RXFS_GET_SHIFT) as usize,
+            put_index: ((value & RXFS_PUT_MASK) >> RXFS_PUT_SHIFT) as usize,
+            full: value & RXFS_FULL != 0,
+            message_lost: value & RXFS_LOST != 0,
+        })
+    }
+
+    /// Releases the elements of an RX FIFO up to and including `index`.
+    pub fn acknowledge_rx_fifo(&mut self, fifo: usize, index: usize) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_register(if fifo == 0 { REG_MCAN_RXF0A } else { REG_MCAN_RXF1A }, index as u32 & RXFA_INDEX_MASK)
+    }
+
+    /// Reads and acknowledges every element in an RX FIFO. The elements are returned in MRAM byte order,
+    /// each `rx_fifo(fifo).2` bytes long, which `CyphalMiddleware::try_read` parses when it equals MTU + 8.
+    pub fn read_rx_fifo(&mut self, fifo: usize) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let (start, size, element_size) = self.mram.rx_fifo(fifo);
+        if size == 0 {
+            return Err(Tcan4550Error::NotConfigured("RX FIFO").into());
+        }
+        let status: Tcan4550FifoStatus = self.rx_fifo_status(fifo)?;
+        if status.message_lost {
+            self.rx_overflows += 1;
+        }
+        if status.fill_level == 0 {
+            return Ok(vec![]);
+        }
+        let mut ret: Vec<u8> = Vec::with_capacity(status.fill_level * element_size);
+        // The elements may wrap around the end of the FIFO; read each contiguous run at once.
+        let first: usize = status.fill_level.min(size - status.get_index);
+        for (index, count) in [(status.get_index, first), (0, status.fill_level - first)] {
+            if count == 0 {
+                continue;
+            }
+            let address: u16 = MRAM_BASE + (start + index * element_size) as u16;
+            let words: Vec<u32> = self.read_words(address, count * element_size / SPI_WORD_SIZE)?;
+            ret.extend(words.iter().flat_map(|x| x.to_le_bytes()));
+        }
+        self.acknowledge_rx_fifo(fifo, (status.get_index + status.fill_level - 1) % size)?;
+        Ok(ret)
+    }
+
+    pub fn tx_fifo_status(&mut self) -> Result<Tcan4550TxFifoStatus, Box<dyn std::error::Error>> {
+        let value: u32 = self.read_register(REG_MCAN_TXFQS)?;
+        Ok(Tcan4550TxFifoStatus {
+            free_level: (value & TXFQS_FREE_MASK) as usize,
+            get_index: ((value & TXFQS_GET_MASK) >> TXFQS_GET_SHIFT) as usize,
+            put_index: ((value & TXFQS_PUT_MASK) >> TXFQS_PUT_SHIFT) as usize,
+            full: value & TXFQS_FULL != 0,
+        })
+    }
+
+    /// Writes a TX element to buffer `index`; `t1` carries the DLC, FDF, BRS, EFC and message marker fields.
+    pub fn write_tx_buffer(&mut self, index: usize, t0: u32, t1: u32, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let (start, count, element_size) = self.mram.tx_buffers();
+        if index >= count || data.len() > element_size - ELEMENT_HEADER_SIZE {
+            return Err(Tcan4550Error::NotConfigured("TX BUFFER").into());
+        }
+        let mut words: Vec<u32> = vec![t0, t1];
+        words.extend(data.chunks(SPI_WORD_SIZE).map(|x| {
+            let mut word: [u8; 4] = [0; 4];
+            word[..x.len()].copy_from_slice(x);
+            u32::from_le_bytes(word)
+        }));
+        self.write_words(MRAM_BASE + (start + index * element_size) as u16, &words)
+    }
+
+    /// Requests transmission of the TX buffers in `mask` (TXBAR).
+    pub fn request_transmission(&mut self, mask: u32) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_register(REG_MCAN_TXBAR, mask)
+    }
+
+    /// Queues a frame in the TX FIFO and returns the buffer it was written to.
+    /// With `marker`, a TX event carrying it is stored once the frame has been sent.
+    pub fn transmit_frame(&mut self, frame: &Tcan4550Frame, marker: Option<u8>) -> Result<usize, Box<dyn std::error::Error>> {
+        let (t0, t1) = frame.tx_header(marker);
+        self.transmit_element(t0, t1, &frame.data)
+    }
+
+    /// Queues a TX element given as its header words and data in the TX FIFO and returns the buffer it was written to.
+    pub fn transmit_element(&mut self, t0: u32, t1: u32, data: &[u8]) -> Result<usize, Box<dyn std::error::Error>> {
+        let status: Tcan4550TxFifoStatus = self.tx_fifo_status()?;
+        if status.full {
+            return Err(CanDriverError::TxQueueFull.into());
+        }
+        self.write_tx_buffer(status.put_index, t0, t1, data)?;
+        self.request_transmission(1 << status.put_index)?;
+        Ok(status.put_index)
+    }
+
+    pub fn tx_event_fifo_status(&mut self) -> Result<Tcan4550FifoStatus, Box<dyn std::error::Error>> {
+        let value: u32 = self.read_register(REG_MCAN_TXEFS)?;
+        Ok(Tcan4550FifoStatus {
+            fill_level: (value & TXEFS_FILL_MASK) as usize,
+            get_index: ((value & TXEFS_GET_MASK) >> TXEFS_GET_SHIFT) as usize,
+            put_index: ((value & TXEFS_PUT_MASK) >> TXEFS_PUT_SHIFT) as usize,
+            full: value & TXEFS_FULL != 0,
+            message_lost: value & TXEFS_LOST != 0,
+        })
+    }
+
+    /// Releases the TX event elements up to and including `index`.
+    pub fn acknowledge_tx_event(&mut self, index: usize) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_register(REG_MCAN_TXEFA, index as u32 & TXEFA_INDEX_MASK)
+    }
+
+    /// Reads and acknowledges every element in the TX event FIFO.
+    pub fn read_tx_events(&mut self) -> Result<Vec<Tcan4550TxEvent>, Box<dyn std::error::Error>> {
+        let (start, size) = self.mram.tx_event_fifo();
+        if size == 0 {
+            return Err(Tcan4550Error::NotConfigured("TX EVENT FIFO").into());
+        }
+        let status: Tcan4550FifoStatus = self.tx_event_fifo_status()?;
+        let mut ret: Vec<Tcan4550TxEvent> = Vec::with_capacity(status.fill_level);
+        for i in 0..status.fill_level {
+            let offset: usize = start + (status.get_index + i) % size * TX_EVENT_ELEMENT_SIZE;
+            let words: Vec<u32> = self.read_words(MRAM_BASE + offset as u16, TX_EVENT_ELEMENT_SIZE / SPI_WORD_SIZE)?;
+            let (e0, e1) = (words[0], words[1]);
+            let extended: bool = e0 & ELEMENT_XTD != 0;
+            ret.push(Tcan4550TxEvent {
+                id: if extended { e0 & ELEMENT_EXT_ID_MASK } else { (e0 >> ELEMENT_STD_ID_SHIFT) & ELEMENT_STD_ID_MASK },
+                extended,
+                fd: e1 & ELEMENT_FDF != 0,
+                brs: e1 & ELEMENT_BRS != 0,
+                dlc: ((e1 & ELEMENT_DLC_MASK) >> ELEMENT_DLC_SHIFT) as u8,
+                marker: (e1 >> ELEMENT_MM_SHIFT) as u8,
+                timestamp: (e1 & ELEMENT_TIMESTAMP_MASK) as u16,
+            });
+        }
+        if status.fill_level > 0 {
+            self.acknowledge_tx_event((status.get_index + status.fill_level - 1) % size)?;
+        }
+        Ok(ret)
+    }
+
+    pub fn read_error_counters(&mut self) -> Result<Tcan4550ErrorCounters, Box<dyn std::error::Error>> {
+        Ok(Tcan4550ErrorCounters::from_register(self.read_register(REG_MCAN_ECR)?))
+    }
+
+    /// Reading PSR resets its error codes, so each error is reported by one read only.
+    pub fn read_protocol_status(&mut self) -> Result<Tcan4550ProtocolStatus, Box<dyn std::error::Error>> {
+        Ok(Tcan4550ProtocolStatus::from_register(self.read_register(REG_MCAN_PSR)?))
+    }
+
+    /// Restarts the MCAN after bus-off by clearing CCCR.INIT. It rejoins the bus after monitoring
+    /// 129 sequences of 11 recessive bits.
+    pub fn restart(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let cccr: u32 = self.read_register(REG_MCAN_CCCR)?;
+        self.write_register(REG_MCAN_CCCR, cccr & !(CCCR_INIT | CCCR_CCE))
+    }
+
+    pub fn read_interrupts(&mut self) -> Result<Tcan4550Interrupts, Box<dyn std::error::Error>> {
+        Ok(Tcan4550Interrupts {
+            device: self.read_register(REG_INTERRUPTS)?,
+            mcan: self.read_register(REG_MCAN_IR)?,
+        })
+    }
+
+    /// Clears the given flags; both registers are write-one-to-clear.
+    pub fn clear_interrupts(&mut self, interrupts: &Tcan4550Interrupts) -> Result<(), Box<dyn std::error::Error>> {
+        if interrupts.mcan != 0 {
+            self.write_register(REG_MCAN_IR, interrupts.mcan)?;
+        }
+        if interrupts.device != 0 {
+            self.write_register(REG_INTERRUPTS, interrupts.device)?;
+        }
+        Ok(())
+    }
+
+    /// Routes the MCAN interrupts in `mask` (IR bits) to the nINT pin.
+    pub fn enable_interrupts(&mut self, mask: u32) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_register(REG_MCAN_IE, mask)?;
+        self.write_register(REG_MCAN_ILS, 0)?;
+        self.write_register(REG_MCAN_ILE, if mask != 0 { ILE_EINT0 } else { 0 })
+    }
+
+    fn write_cccr_confirmed(&mut self, value: u32, mask: u32) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_register(REG_MCAN_CCCR, value)?;
+        for _ in 0..CONFIRMATION_ATTEMPTS {
+            if self.read_register(REG_MCAN_CCCR)? & mask == value & mask {
+                return Ok(());
+            }
+        }
+        Err(Tcan4550Error::WriteNotConfirmed(REG_MCAN_CCCR).into())
+    }
+
+    /// Reads the protocol status, records a change of the bus state and applies the recovery policy.
+    fn update_bus_state(&mut self) -> Result<Tcan4550ProtocolStatus, Box<dyn std::error::Error>> {
+        let status: Tcan4550ProtocolStatus = self.read_protocol_status()?;
+        let bus_state: CanBusState = status.bus_state();
+        if bus_state != self.bus_state {
+            self.events.push(CanBusEvent::BusStateChanged { from: self.bus_state, to: bus_state });
+            self.bus_state = bus_state;
+        }
+        if let Some(recovery) = self.recovery.as_mut() {
+            if recovery.poll(bus_state, monotonic_usec()) {
+                let attempt: u32 = recovery.attempts();
+                self.restart()?;
+                self.events.push(CanBusEvent::RecoveryStarted { attempt });
+            }
+        }
+        Ok(status)
+    }
+
+    /// Reads the timestamp counter as an anchor for the tracker, if there is one.
+    /// Elements read before the anchor can then be converted.
+    fn anchor_timestamps(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.timestamps.is_none() {
+            return Ok(());
+        }
+        let before_usec: CyphalMicrosecond = monotonic_usec();
+        let counter: u32 = self.read_register(REG_MCAN_TSCV)?;
+        let after_usec: CyphalMicrosecond = monotonic_usec();
+        if let Some(tracker) = self.timestamps.as_mut() {
+            tracker.anchor((counter & ELEMENT_TIMESTAMP_MASK) as u64, before_usec, after_usec);
+        }
+        Ok(())
+    }
+
+    /// Host time of an element timestamp, or `read_usec` without a tracker.
+    fn element_usec(&self, timestamp: u16, read_usec: CyphalMicrosecond) -> CyphalMicrosecond {
+        self.timestamps.as_ref().and_then(|x| x.host_usec(timestamp as u64)).unwrap_or(read_usec)
+    }
+
+    /// Feeds the TX events to the tracker. The timestamp counter is anchored on every call, even without events,
+    /// so that the tracker sees each wrap-around.
+    fn process_tx_events(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let read_usec: CyphalMicrosecond = monotonic_usec();
+        let events: Vec<Tcan4550TxEvent> = if self.mram.tx_event_fifo().1 > 0 { self.read_tx_events()? } else { vec![] };
+        self.anchor_timestamps()?;
+        for event in events {
+            let timestamp_usec: CyphalMicrosecond = self.element_usec(event.timestamp, read_usec);
+            self.tx_tracker.confirm(event.marker, timestamp_usec);
+        }
+        Ok(())
+    }
+
+    /// Reads both RX FIFOs and converts the element timestamps once all elements have been read.
+    fn read_rx_packets<const MTU: usize>(&mut self) -> Result<Vec<CyphalRxPacket<MTU>>, Box<dyn std::error::Error>> {
+        let mut elements: Vec<(CyphalRxPacket<MTU>, u16)> = vec![];
+        self.read_rx_elements(0, &mut elements)?;
+        self.read_rx_elements(1, &mut elements)?;
+        if !elements.is_empty() {
+            self.anchor_timestamps()?;
+        }
+        Ok(elements.into_iter().map(|(mut packet, timestamp)| {
+            packet.timestamp_usec = self.element_usec(timestamp, packet.timestamp_usec);
+            packet
+        }).collect())
+    }
+
+    /// Decodes the RX elements of one FIFO into packets stamped with the read time, paired with their
+    /// element timestamps; frames that are not Cyphal frames are skipped.
+    fn read_rx_elements<const MTU: usize>(&mut self, fifo: usize, ret: &mut Vec<(CyphalRxPacket<MTU>, u16)>) -> Result<(), Box<dyn std::error::Error>> {
+        let (_, size, element_size) = self.mram.rx_fifo(fifo);
+        if size == 0 {
+            return Ok(());
+        }
+        let timestamp_usec: CyphalMicrosecond = monotonic_usec();
+        for element in self.read_rx_fifo(fifo)?.chunks(element_size) {
+            let r0: u32 = u32::from_le_bytes([element[0], element[1], element[2], element[3]]);
+            let r1: u32 = u32::from_le_bytes([element[4], element[5], element[6], element[7]]);
+            let length: usize = CAN_DLC_TO_DLEN[((r1 & ELEMENT_DLC_MASK) >> ELEMENT_DLC_SHIFT) as usize] as usize;
+            if r0 & ELEMENT_XTD == 0 || r0 & ELEMENT_RTR != 0 || length == 0 || length > MTU || length > element_size - ELEMENT_HEADER_SIZE {
+                continue;
+            }
+            let data: &[u8] = &element[ELEMENT_HEADER_SIZE..ELEMENT_HEADER_SIZE + length];
+            let mut packet: CyphalRxPacket<MTU> = CyphalRxPacket::from_frame(r0 & ELEMENT_EXT_ID_MASK, data)?;
+            packet.timestamp_usec = timestamp_usec;
+            ret.push((packet, (r1 & ELEMENT_TIMESTAMP_MASK) as u16));
+        }
+        Ok(())
+    }
+}
+
+impl <const MTU: usize, S: SpiBus> CanDriver<MTU> for Tcan4550Driver<S> {
+    /// A transfer is only queued if all of its frames fit in the TX FIFO, so the FIFO must hold at least
+    /// as many elements as the longest transfer has frames. When a transfer does not fit, nothing after
+    /// the previous transfer is queued and `CanDriverError::TxQueueFullAfter` reports how many packets were taken.
+    fn transmit(&mut self, packets: &[CyphalTxPacket<MTU>]) -> Result<(), Box<dyn std::error::Error>> {
+        let tracked: bool = self.mram.tx_event_fifo().1 > 0;
+        let mut accepted: usize = 0;
+        for transfer in packets.split_inclusive(|x| x.payload_size == 0 || x.payload[x.payload_size - 1] & TAIL_END_OF_TRANSFER != 0) {
+            let now_usec: CyphalMicrosecond = monotonic_usec();
+            let (expired, due): (Vec<&CyphalTxPacket<MTU>>, Vec<&CyphalTxPacket<MTU>>) = transfer.iter()
+                .partition(|x| x.deadline_usec.is_some_and(|x| now_usec > x));
+            if due.len() > self.tx_fifo_status()?.free_level {
+                return Err(match accepted {
+                    0 => CanDriverError::TxQueueFull,
+                    x => CanDriverError::TxQueueFullAfter(x),
+                }.into());
+            }
+            self.tx_deadline_expired += expired.len() as u64;
+            for packet in &due {
+                let frame: Tcan4550Frame = Tcan4550Frame {
+                    id: packet.xid,
+                    extended: true,
+                    remote: false,
+                    fd: MTU > CYPHAL_MTU_CAN_CLASSIC as usize,
+                    brs: true,
+                    data: packet.payload[..packet.payload_size].to_vec(),
+                };
+                self.transmit_frame(&frame, if tracked { Some(packet.marker) } else { None })?;
+            }
+            if tracked {
+                let due: Vec<CyphalTxPacket<MTU>> = due.into_iter().cloned().collect();
+                self.tx_tracker.record(&due);
+            }
+            accepted += transfer.len();
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, timeout: std::time::Duration) -> Result<Vec<CyphalRxPacket<MTU>>, Box<dyn std::error::Error>> {
+        let started: std::time::Instant = std::time::Instant::now();
+        self.update_bus_state()?;
+        self.process_tx_events()?;
+        loop {
+            let ret: Vec<CyphalRxPacket<MTU>> = self.read_rx_packets()?;
+            if !ret.is_empty() || started.elapsed() >= timeout {
+                return Ok(ret);
+            }
+            std::thread::sleep(self.poll_interval.min(timeout.saturating_sub(started.elapsed())));
+        }
+    }
+
+    fn status(&mut self) -> Result<CanDriverStatus, Box<dyn std::error::Error>> {
+        let counters: Tcan4550ErrorCounters = self.read_error_counters()?;
+        let status: Tcan4550ProtocolStatus = self.update_bus_state()?;
+        Ok(CanDriverStatus {
+            bus_state: status.bus_state(),
+            tx_error_count: counters.tx_error_count,
+            rx_error_count: counters.rx_error_count,
+            tx_deadline_expired: self.tx_deadline_expired,
+            rx_overflows: self.rx_overflows,
+        })
+    }
+
+    fn take_events(&mut self) -> Vec<CanBusEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn take_tx_confirmations(&mut self) -> Vec<CanTxConfirmation> {
+        self.tx_tracker.take_confirmations()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::bit_timing::*;
+    use super::super::mram::Tcan4550MramLayout;
+    use super::super::simulator::Tcan4550Simulator;
+
+    const MTU: usize = CYPHAL_MTU_CAN_FD as usize;
+
+    /// Brings the simulated chip onto the bus as an application would, with the default MRAM layout.
+    fn driver() -> Tcan4550Driver<Tcan4550Simulator> {
+        let mut driver: Tcan4550Driver<Tcan4550Simulator> = Tcan4550Driver::new(Tcan4550Simulator::new());
+        let timing: Tcan4550BitTiming = Tcan4550BitTiming::calculate(TCAN4550_CLOCK_HZ, 1_000_000, 0.8, 5_000_000, 0.8).unwrap();
+        driver.check_device_id().unwrap();
+        driver.enable_configuration().unwrap();
+        driver.set_cccr(&Tcan4550CccrConfig::default()).unwrap();
+        driver.set_bit_timing(timing.nbtp, timing.dbtp, timing.tdcr).unwrap();
+        driver.configure_mram(&Tcan4550MramLayout::default().plan::<MTU>().unwrap()).unwrap();
+        driver.disable_configuration().unwrap();
+        driver.set_mode(Tcan4550Mode::Normal).unwrap();
+        driver
+    }
+
+    /// A message transfer of `length` bytes counting up from zero.
+    fn transfer(middleware: &mut CyphalMiddleware<MTU>, length: usize) -> Vec<CyphalTxPacket<MTU>> {
+        let data: Vec<u8> = (0..length).map(|x| x as u8).collect();
+        middleware.create_message_data(1000, &data, data.len()).unwrap()
+    }
+
+    fn to_frame(packet: &CyphalTxPacket<MTU>) -> Tcan4550Frame {
+        Tcan4550Frame { id: packet.xid, extended: true, remote: false, fd: true, brs: true, data: packet.payload[..packet.payload_size].to_vec() }
+    }
+
+    fn queue_error(result: Result<(), Box<dyn std::error::Error>>) -> CanDriverError {
+        result.unwrap_err().downcast_ref::<CanDriverError>().unwrap().clone()
+    }
+
+    #[test]
+    fn bring_up_joins_the_bus() {
+        let mut driver: Tcan4550Driver<Tcan4550Simulator> = driver();
+        assert_eq!(driver.spi().mode(), Tcan4550Mode::Normal);
+        assert_eq!(driver.read_register(REG_MCAN_CCCR).unwrap() & (CCCR_INIT | CCCR_CCE), 0);
+        assert_eq!(driver.load_mram_config().unwrap(), Tcan4550MramLayout::default().plan::<MTU>().unwrap());
+        assert_eq!(driver.tx_fifo_status().unwrap().free_level, 8);
+        let status: CanDriverStatus = CanDriver::<MTU>::status(&mut driver).unwrap();
+        assert_eq!(status.bus_state, CanBusState::ErrorActive);
+        assert!(CanDriver::<MTU>::take_events(&mut driver).is_empty());
+    }
+
+    #[test]
+    fn sent_transfer_is_confirmed_from_the_tx_events() {
+        let mut driver: Tcan4550Driver<Tcan4550Simulator> = driver();
+        let mut middleware: CyphalMiddleware<MTU> = CyphalMiddleware::new(10);
+        let packets: Vec<CyphalTxPacket<MTU>> = transfer(&mut middleware, 150);
+        assert_eq!(packets.len(), 3);
+
+        driver.transmit(&packets).unwrap();
+        let sent: Vec<Tcan4550Frame> = driver.spi_mut().take_transmitted();
+        assert_eq!(sent, packets.iter().map(to_frame).collect::<Vec<Tcan4550Frame>>());
+        assert_eq!(driver.tx_tracker.pending_count(), 1);
+
+        assert!(CanDriver::<MTU>::receive(&mut driver, std::time::Duration::ZERO).unwrap().is_empty());
+        let confirmations: Vec<CanTxConfirmation> = CanDriver::<MTU>::take_tx_confirmations(&mut driver);
+        assert_eq!(confirmations.len(), 1);
+        assert_eq!(confirmations[0].marker, packets[0].marker);
+        assert_eq!(confirmations[0].frame_count, 3);
+        assert_eq!(confirmations[0].props.source_node_id, 10);
+        assert_eq!(driver.tx_event_fifo_status().unwrap().fill_level, 0);
+        assert_eq!(driver.tx_tracker.pending_count(), 0);
+    }
+
+    #[test]
+    fn rx_fifo_is_read_across_its_wrap_around() {
+        let mut driver: Tcan4550Driver<Tcan4550Simulator> = driver();
+        let mut middleware: CyphalMiddleware<MTU> = CyphalMiddleware::new(20);
+        // RX FIFO 0 holds 16 elements, so the second and third batches wrap around its end.
+        for batch in 1..=3 {
+            let packets: Vec<CyphalTxPacket<MTU>> = (0..10).flat_map(|_| transfer(&mut middleware, 8)).collect();
+            for packet in &packets {
+                assert!(driver.spi_mut().receive_frame(&to_frame(packet)));
+            }
+            assert_eq!(driver.rx_fifo_status(0).unwrap().fill_level, 10);
+
+            let received: Vec<CyphalRxPacket<MTU>> = CanDriver::<MTU>::receive(&mut driver, std::time::Duration::ZERO).unwrap();
+            assert_eq!(received.iter().map(|x| x.xid).collect::<Vec<u32>>(), packets.iter().map(|x| x.xid).collect::<Vec<u32>>());
+            assert_eq!(received.iter().map(|x| x.props.transfer_id).collect::<Vec<u8>>(), (0..10).map(|x| (x + (batch - 1) * 10) as u8).collect::<Vec<u8>>());
+
+            let status: Tcan4550FifoStatus = driver.rx_fifo_status(0).unwrap();
+            assert_eq!(status.fill_level, 0);
+            assert_eq!(status.get_index, batch * 10 % 16);
+            assert!(!status.message_lost);
+        }
+    }
+
+    #[test]
+    fn bus_off_is_reported_and_restarted() {
+        let mut driver: Tcan4550Driver<Tcan4550Simulator> = driver();
+        let mut middleware: CyphalMiddleware<MTU> = CyphalMiddleware::new(30);
+        driver.spi_mut().set_error_counters(256, 0);
+
+        let status: CanDriverStatus = CanDriver::<MTU>::status(&mut driver).unwrap();
+        assert_eq!(status.bus_state, CanBusState::BusOff);
+        assert_eq!(CanDriver::<MTU>::take_events(&mut driver), vec![
+            CanBusEvent::BusStateChanged { from: CanBusState::ErrorActive, to: CanBusState::BusOff },
+        ]);
+
+        // Without a recovery policy the controller stays off the bus and keeps the frames.
+        driver.transmit(&transfer(&mut middleware, 8)).unwrap();
+        assert!(driver.spi_mut().take_transmitted().is_empty());
+
+        driver.restart().unwrap();
+        assert_eq!(driver.spi_mut().take_transmitted().len(), 1);
+        let status: CanDriverStatus = CanDriver::<MTU>::status(&mut driver).unwrap();
+        assert_eq!(status.bus_state, CanBusState::ErrorActive);
+        assert_eq!(status.tx_error_count, 0);
+        assert_eq!(CanDriver::<MTU>::take_events(&mut driver), vec![
+            CanBusEvent::BusStateChanged { from: CanBusState::BusOff, to: CanBusState::ErrorActive },
+        ]);
+    }
+
+    #[test]
+    fn recovery_policy_restarts_after_bus_off() {
+        let policy: CanRecoveryPolicy = CanRecoveryPolicy::new(std::time::Duration::ZERO, std::time::Duration::from_secs(1));
+        let mut driver: Tcan4550Driver<Tcan4550Simulator> = driver().set_recovery_policy(policy);
+        driver.spi_mut().set_error_counters(256, 0);
+
+        assert_eq!(CanDriver::<MTU>::status(&mut driver).unwrap().bus_state, CanBusState::BusOff);
+        assert_eq!(CanDriver::<MTU>::take_events(&mut driver), vec![
+            CanBusEvent::BusStateChanged { from: CanBusState::ErrorActive, to: CanBusState::BusOff },
+            CanBusEvent::RecoveryStarted { attempt: 1 },
+        ]);
+        assert_eq!(CanDriver::<MTU>::status(&mut driver).unwrap().bus_state, CanBusState::ErrorActive);
+        assert_eq!(driver.read_register(REG_MCAN_CCCR).unwrap() & CCCR_INIT, 0);
+    }
+
+    #[test]
+    fn transfer_is_only_queued_when_it_fits_whole() {
+        let mut driver: Tcan4550Driver<Tcan4550Simulator> = driver();
+        let mut middleware: CyphalMiddleware<MTU> = CyphalMiddleware::new(40);
+        // Bus-off keeps the frames in the TX FIFO, which holds 8 elements.
+        driver.spi_mut().set_error_counters(256, 0);
+        let first: Vec<CyphalTxPacket<MTU>> = transfer(&mut middleware, 300);
+        assert_eq!(first.len(), 5);
+        driver.transmit(&first).unwrap();
+        let status: Tcan4550TxFifoStatus = driver.tx_fifo_status().unwrap();
+        assert_eq!((status.free_level, status.get_index, status.put_index, status.full), (3, 0, 5, false));
+
+        let second: Vec<CyphalTxPacket<MTU>> = transfer(&mut middleware, 8);
+        let third: Vec<CyphalTxPacket<MTU>> = transfer(&mut middleware, 300);
+        let packets: Vec<CyphalTxPacket<MTU>> = [second, third.clone()].concat();
+        assert_eq!(queue_error(driver.transmit(&packets)), CanDriverError::TxQueueFullAfter(1));
+        let status: Tcan4550TxFifoStatus = driver.tx_fifo_status().unwrap();
+        assert_eq!((status.free_level, status.get_index, status.put_index), (2, 0, 6));
+        assert_eq!(driver.tx_tracker.pending_count(), 2);
+        assert_eq!(queue_error(driver.transmit(&third)), CanDriverError::TxQueueFull);
+        assert_eq!(driver.tx_tracker.pending_count(), 2);
+
+        driver.restart().unwrap();
+        assert_eq!(driver.spi_mut().take_transmitted().len(), 6);
+    }
+}
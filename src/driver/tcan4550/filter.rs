@@ -0,0 +1,295 @@
+use super::registers::*;
+use crate::cyphal::*;
+
+/// Bits of the extended ID compared by the filters (XIDAM): the priority, the anonymous/request flag and the
+/// source node-ID are ignored, so one element covers a port regardless of who sends at which priority.
+/// Bits 21 and 22 are kept, as they carry the service-ID in service frames.
+pub const CYPHAL_FILTER_ID_MASK: u32 = CAN_EXT_ID_MASK & !(0b111 << OFFSET_PRIORITY) & !FLAG_ANONYMOUS_MESSAGE & !(CYPHAL_NODE_ID_MAX as u32);
+
+/// Reserved bits 21 and 22 of message IDs. Transmitters set them to one but receivers must ignore them,
+/// so message filters are classic elements leaving them out of their mask, as libcanard's do.
+const MESSAGE_RESERVED_BITS: u32 = 0b11 << 21;
+
+const MESSAGE_FILTER_MASK: u32 = CYPHAL_FILTER_ID_MASK & !MESSAGE_RESERVED_BITS;
+
+/// An extended ID filter element. IDs are compared after masking with `CYPHAL_FILTER_ID_MASK`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize)]
+pub enum Tcan4550ExtendedFilter {
+    /// Accepts either of two IDs.
+    Dual(u32, u32),
+    /// Accepts IDs from the first to the second, inclusive.
+    Range(u32, u32),
+    /// Accepts IDs equal to `id` in the bits set in `mask`.
+    Classic { id: u32, mask: u32 },
+}
+
+impl Tcan4550ExtendedFilter {
+    pub fn accepts(&self, masked_id: u32) -> bool {
+        match *self {
+            Self::Dual(a, b) => masked_id == a || masked_id == b,
+            Self::Range(lo, hi) => lo <= masked_id && masked_id <= hi,
+            Self::Classic { id, mask } => masked_id & mask == id & mask,
+        }
+    }
+
+    /// Encodes the filter as an MRAM element storing matches with the given configuration (FILTER_CONFIG_*).
+    pub fn element(&self, config: u32) -> [u32; 2] {
+        let (filter_type, id1, id2) = match *self {
+            Self::Dual(a, b) => (FILTER_TYPE_DUAL, a, b),
+            Self::Range(lo, hi) => (FILTER_TYPE_RANGE, lo, hi),
+            Self::Classic { id, mask } => (FILTER_TYPE_CLASSIC, id, mask),
+        };
+        [(config << XFE_EFEC_SHIFT) | (id1 & XFE_EFID_MASK), (filter_type << XFE_EFT_SHIFT) | (id2 & XFE_EFID_MASK)]
+    }
+
+    /// The same set of IDs, or a superset for ranges, as a classic filter.
+    fn to_classic(self) -> Vec<(u32, u32)> {
+        match self {
+            Self::Dual(a, b) if a == b => vec![(a, CYPHAL_FILTER_ID_MASK)],
+            Self::Dual(a, b) => vec![(a, CYPHAL_FILTER_ID_MASK), (b, CYPHAL_FILTER_ID_MASK)],
+            Self::Range(lo, hi) => {
+                let varying: u32 = (1u64 << (32 - (lo ^ hi).leading_zeros())) as u32 - 1;
+                vec![(lo & !varying, CYPHAL_FILTER_ID_MASK & !varying)]
+            },
+            Self::Classic { id, mask } => vec![(id, mask)],
+        }
+    }
+}
+
+/// Extended ID filters that let only the frames the middleware is interested in through to the host:
+/// messages of subscribed subjects and, unless the node is anonymous, service transfers of served and
+/// called services addressed to the local node-ID, or all service transfers in monitor mode.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Tcan4550FilterPlan {
+    /// Value for XIDAM.
+    pub id_mask: u32,
+    pub filters: Vec<Tcan4550ExtendedFilter>,
+    /// Elements needed to accept exactly the subscribed ports, before any merging.
+    pub required: usize,
+}
+
+impl Tcan4550FilterPlan {
+    /// Builds the filters for the current subscriptions of `middleware`. If they need more than `max_filters`
+    /// elements, filters are merged into classic mask filters that let some unrelated frames through as well,
+    /// to be dropped by `try_read`. Merging picks the pair that keeps the most ID bits, as libcanard does.
+    pub fn from_middleware<const MTU: usize>(middleware: &CyphalMiddleware<MTU>, max_filters: usize) -> Self {
+        let ports: &CyphalPorts = middleware.ports();
+        let mut filters: Vec<Tcan4550ExtendedFilter> = vec![];
+        let mut singles: Vec<u32> = vec![];
+
+        // Subscribers are kept sorted, so consecutive subjects are adjacent. Runs of them are split into aligned
+        // power-of-two blocks, each matched exactly by one element that ignores the low subject-ID bits.
+        let subjects: Vec<CyphalPortID> = ports.subscribers.iter().copied().collect();
+        let mut start: usize = 0;
+        while start < subjects.len() {
+            let mut size: usize = 1;
+            while (subjects[start] as usize).is_multiple_of(size * 2) &&
+                start + size * 2 <= subjects.len() &&
+                subjects[start + size * 2 - 1] as usize == subjects[start] as usize + size * 2 - 1
+            {
+                size *= 2;
+            }
+            let varying: u32 = ((size - 1) as u32) << OFFSET_SUBJECT_ID;
+            filters.push(Tcan4550ExtendedFilter::Classic { id: Self::message_id(subjects[start]), mask: MESSAGE_FILTER_MASK & !varying });
+            start += size;
+        }
+
+        if middleware.monitor_mode() {
+            filters.push(Tcan4550ExtendedFilter::Classic { id: FLAG_SERVICE_NOT_MESSAGE, mask: FLAG_SERVICE_NOT_MESSAGE });
+        } else if !middleware.is_anonymous() {
+            let services: std::collections::BTreeSet<CyphalPortID> = ports.servers.union(&ports.clients).copied().collect();
+            singles.extend(services.iter().map(|x| Self::service_id(*x, middleware.node_id())));
+        }
+
+        filters.extend(singles.chunks(2).map(|x| Tcan4550ExtendedFilter::Dual(x[0], *x.last().unwrap_or(&x[0]))));
+        let required: usize = filters.len();
+        if required > max_filters {
+            filters = Self::merge(&filters, max_filters.max(1));
+        }
+
+        Self {
+            id_mask: CYPHAL_FILTER_ID_MASK,
+            filters,
+            required,
+        }
+    }
+
+    pub fn filter_count(&self) -> usize {
+        self.filters.len()
+    }
+
+    /// True if the filters had to be merged to fit, so some unrelated frames pass as well.
+    pub fn is_merged(&self) -> bool {
+        self.filters.len() < self.required
+    }
+
+    /// Returns true if the controller would store a frame with this extended ID.
+    pub fn accepts(&self, xid: u32) -> bool {
+        self.filters.iter().any(|x| x.accepts(xid & self.id_mask))
+    }
+
+    fn message_id(subject_id: CyphalPortID) -> u32 {
+        (subject_id as u32) << OFFSET_SUBJECT_ID
+    }
+
+    fn service_id(service_id: CyphalPortID, destination: CyphalNodeID) -> u32 {
+        FLAG_SERVICE_NOT_MESSAGE | ((service_id as u32) << OFFSET_SERVICE_ID) | ((destination as u32) << OFFSET_DST_NODE_ID)
+    }
+
+    fn merge(filters: &[Tcan4550ExtendedFilter], max_filters: usize) -> Vec<Tcan4550ExtendedFilter> {
+        let mut classic: Vec<(u32, u32)> = filters.iter().flat_map(|x| x.to_classic()).collect();
+        let consolidate = |a: (u32, u32), b: (u32, u32)| {
+            let mask: u32 = a.1 & b.1 & !(a.0 ^ b.0);
+            (a.0 & mask, mask)
+        };
+        while classic.len() > max_filters {
+            let mut best: (usize, usize, u32) = (0, 1, 0);
+            for i in 0..classic.len() {
+                for j in i + 1..classic.len() {
+                    let bits: u32 = consolidate(classic[i], classic[j]).1.count_ones();
+                    if bits > best.2 {
+                        best = (i, j, bits);
+                    }
+                }
+            }
+            let merged: (u32, u32) = consolidate(classic[best.0], classic[best.1]);
+            classic.swap_remove(best.1);
+            classic[best.0] = merged;
+        }
+        classic.into_iter().map(|(id, mask)| Tcan4550ExtendedFilter::Classic { id, mask }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCAL: CyphalNodeID = 42;
+
+    fn message_xid(subject_id: CyphalPortID) -> u32 {
+        CyphalMiddleware::<8>::new(1).create_message_data(subject_id, &[0], 1).unwrap()[0].xid
+    }
+
+    fn request_xid(destination: CyphalNodeID, service_id: CyphalPortID) -> u32 {
+        CyphalMiddleware::<8>::new(1).create_request_data(destination, service_id, &[0], 1).unwrap()[0].xid
+    }
+
+    fn middleware(subjects: &[CyphalPortID], services: &[CyphalPortID]) -> CyphalMiddleware<8> {
+        let mut middleware: CyphalMiddleware<8> = CyphalMiddleware::new(LOCAL);
+        for x in subjects {
+            middleware.subscribe(CyphalTransferKind::Message, *x).unwrap();
+        }
+        for x in services {
+            middleware.subscribe(CyphalTransferKind::Request, *x).unwrap();
+        }
+        middleware
+    }
+
+    #[test]
+    fn elements_encode_type_configuration_and_ids() {
+        assert_eq!(
+            Tcan4550ExtendedFilter::Dual(0x100, 0x200).element(FILTER_CONFIG_FIFO0),
+            [(FILTER_CONFIG_FIFO0 << XFE_EFEC_SHIFT) | 0x100, (FILTER_TYPE_DUAL << XFE_EFT_SHIFT) | 0x200]
+        );
+        assert_eq!(
+            Tcan4550ExtendedFilter::Range(0x100, 0x1FF).element(FILTER_CONFIG_FIFO1),
+            [(FILTER_CONFIG_FIFO1 << XFE_EFEC_SHIFT) | 0x100, (FILTER_TYPE_RANGE << XFE_EFT_SHIFT) | 0x1FF]
+        );
+        assert_eq!(
+            Tcan4550ExtendedFilter::Classic { id: 0x1234, mask: 0x1FFF_FF00 }.element(FILTER_CONFIG_FIFO0),
+            [(FILTER_CONFIG_FIFO0 << XFE_EFEC_SHIFT) | 0x1234, (FILTER_TYPE_CLASSIC << XFE_EFT_SHIFT) | 0x1FFF_FF00]
+        );
+        // IDs wider than 29 bits do not spill into the type and configuration fields.
+        assert_eq!(Tcan4550ExtendedFilter::Dual(u32::MAX, 0).element(FILTER_CONFIG_DISABLE)[0], XFE_EFID_MASK);
+    }
+
+    #[test]
+    fn elements_accept_their_ids() {
+        let dual: Tcan4550ExtendedFilter = Tcan4550ExtendedFilter::Dual(10, 20);
+        assert!(dual.accepts(10) && dual.accepts(20) && !dual.accepts(15));
+        let range: Tcan4550ExtendedFilter = Tcan4550ExtendedFilter::Range(10, 20);
+        assert!(range.accepts(10) && range.accepts(15) && range.accepts(20) && !range.accepts(9) && !range.accepts(21));
+        let classic: Tcan4550ExtendedFilter = Tcan4550ExtendedFilter::Classic { id: 0x10, mask: 0xF0 };
+        assert!(classic.accepts(0x10) && classic.accepts(0x1F) && !classic.accepts(0x20));
+    }
+
+    #[test]
+    fn range_as_classic_is_a_superset() {
+        let range: Tcan4550ExtendedFilter = Tcan4550ExtendedFilter::Range(0x500, 0x6FF);
+        let classic: Vec<(u32, u32)> = range.to_classic();
+        assert_eq!(classic.len(), 1);
+        let (id, mask) = classic[0];
+        assert!((0x500..=0x6FF).all(|x| x & mask == id & mask));
+    }
+
+    #[test]
+    fn message_filters_ignore_priority_source_and_reserved_bits() {
+        let plan: Tcan4550FilterPlan = Tcan4550FilterPlan::from_middleware(&middleware(&[100], &[]), 8);
+        let xid: u32 = message_xid(100);
+        assert_eq!(xid & MESSAGE_RESERVED_BITS, MESSAGE_RESERVED_BITS);
+        assert!(plan.accepts(xid));
+        assert!(plan.accepts(xid & !MESSAGE_RESERVED_BITS));
+        assert!(plan.accepts(xid ^ (0b111 << OFFSET_PRIORITY)));
+        assert!(plan.accepts((xid & !(CYPHAL_NODE_ID_MAX as u32)) | 99));
+        assert!(!plan.accepts(message_xid(101)));
+        assert!(!plan.accepts(request_xid(LOCAL, 100)));
+    }
+
+    #[test]
+    fn consecutive_subjects_share_aligned_elements() {
+        let subjects: Vec<CyphalPortID> = (7..=16).collect();
+        let plan: Tcan4550FilterPlan = Tcan4550FilterPlan::from_middleware(&middleware(&subjects, &[]), 8);
+        // 7, 8..=15 and 16.
+        assert_eq!(plan.filter_count(), 3);
+        assert!(!plan.is_merged());
+        assert!(subjects.iter().all(|x| plan.accepts(message_xid(*x))));
+        assert!(!plan.accepts(message_xid(6)));
+        assert!(!plan.accepts(message_xid(17)));
+    }
+
+    #[test]
+    fn services_pass_only_when_addressed_to_us() {
+        let plan: Tcan4550FilterPlan = Tcan4550FilterPlan::from_middleware(&middleware(&[], &[430, 431, 432]), 8);
+        // Two dual elements for three services.
+        assert_eq!(plan.filters, vec![
+            Tcan4550ExtendedFilter::Dual(request_xid(LOCAL, 430) & CYPHAL_FILTER_ID_MASK, request_xid(LOCAL, 431) & CYPHAL_FILTER_ID_MASK),
+            Tcan4550ExtendedFilter::Dual(request_xid(LOCAL, 432) & CYPHAL_FILTER_ID_MASK, request_xid(LOCAL, 432) & CYPHAL_FILTER_ID_MASK),
+        ]);
+        assert!(plan.accepts(request_xid(LOCAL, 430)));
+        assert!(!plan.accepts(request_xid(LOCAL + 1, 430)));
+        assert!(!plan.accepts(request_xid(LOCAL, 433)));
+    }
+
+    #[test]
+    fn anonymous_node_gets_no_service_filters_and_monitor_gets_all() {
+        let anonymous: CyphalMiddleware<8> = middleware(&[], &[430]).set_node_id(CYPHAL_NODE_ID_UNSET);
+        assert_eq!(Tcan4550FilterPlan::from_middleware(&anonymous, 8).filter_count(), 0);
+
+        let monitor: CyphalMiddleware<8> = middleware(&[], &[430]).set_monitor_mode(true);
+        let plan: Tcan4550FilterPlan = Tcan4550FilterPlan::from_middleware(&monitor, 8);
+        assert!(plan.accepts(request_xid(LOCAL + 1, 1)));
+        assert!(!plan.accepts(message_xid(100)));
+    }
+
+    #[test]
+    fn filters_are_merged_when_slots_run_out() {
+        let subjects: [CyphalPortID; 3] = [100, 300, 5000];
+        let services: [CyphalPortID; 2] = [430, 7];
+        let wanted: Vec<u32> = subjects.iter().map(|x| message_xid(*x))
+            .chain(services.iter().map(|x| request_xid(LOCAL, *x)))
+            .collect();
+
+        let exact: Tcan4550FilterPlan = Tcan4550FilterPlan::from_middleware(&middleware(&subjects, &services), 4);
+        assert_eq!((exact.filter_count(), exact.required), (4, 4));
+        assert!(!exact.is_merged());
+
+        for max_filters in [0, 1, 2] {
+            let plan: Tcan4550FilterPlan = Tcan4550FilterPlan::from_middleware(&middleware(&subjects, &services), max_filters);
+            assert_eq!(plan.filter_count(), max_filters.max(1));
+            assert_eq!(plan.required, 4);
+            assert!(plan.is_merged());
+            assert!(plan.filters.iter().all(|x| matches!(x, Tcan4550ExtendedFilter::Classic { .. })));
+            assert!(wanted.iter().all(|x| plan.accepts(*x)));
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Support for the TI TCAN4550 CAN FD controller on the DigitalServo USB board.
+
+pub mod registers;
+pub mod simulator;
+pub mod device;
+pub mod bit_timing;
+pub mod filter;
+pub mod mram;
+pub mod status;
+
+use registers::*;
+use crate::cyphal::CAN_DLEN_TO_DLC;
+
+/// Operating mode selected in the MODES_OF_OPERATION register.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize)]
+pub enum Tcan4550Mode {
+    Sleep,
+    Standby,
+    Normal,
+}
+
+/// A frame as seen on the CAN bus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tcan4550Frame {
+    pub id: u32,
+    pub extended: bool,
+    pub remote: bool,
+    pub fd: bool,
+    pub brs: bool,
+    pub data: Vec<u8>,
+}
+
+impl Tcan4550Frame {
+    /// The T0 and T1 words of a TX element for this frame. With `marker`, the controller stores
+    /// a TX event carrying it once the frame has been sent.
+    pub fn tx_header(&self, marker: Option<u8>) -> (u32, u32) {
+        let t0: u32 = match self.extended {
+            true => (self.id & ELEMENT_EXT_ID_MASK) | ELEMENT_XTD,
+            false => (self.id & ELEMENT_STD_ID_MASK) << ELEMENT_STD_ID_SHIFT,
+        } | if self.remote { ELEMENT_RTR } else { 0 };
+        let t1: u32 = ((CAN_DLEN_TO_DLC[self.data.len().min(CAN_DLEN_TO_DLC.len() - 1)] as u32) << ELEMENT_DLC_SHIFT)
+            | if self.fd { ELEMENT_FDF } else { 0 }
+            | if self.fd && self.brs { ELEMENT_BRS } else { 0 }
+            | match marker {
+                Some(x) => ((x as u32) << ELEMENT_MM_SHIFT) | TX_ELEMENT_EFC,
+                None => 0,
+            };
+        (t0, t1)
+    }
+}
@@ -0,0 +1,239 @@
+use super::registers::*;
+
+const MAX_STD_FILTERS: usize = 128;
+const MAX_EXT_FILTERS: usize = 64;
+const MAX_RX_FIFO_ELEMENTS: usize = 64;
+const MAX_TX_BUFFERS: usize = 32;
+const MAX_TX_EVENTS: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tcan4550MramError {
+    /// No element data size holds the MTU; the MCAN supports 8 to 64 bytes.
+    UnsupportedPayloadSize(usize),
+    /// A section has more elements than its register field allows.
+    TooManyElements { section: &'static str, count: usize, max: usize },
+    /// The sections together need more than the MRAM provides.
+    DoesNotFit { required: usize, available: usize },
+}
+
+impl std::fmt::Display for Tcan4550MramError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnsupportedPayloadSize(x) => write!(f, "UNSUPPORTED PAYLOAD SIZE: {}", x),
+            Self::TooManyElements { section, count, max } => write!(f, "TOO MANY {}: {} (MAX {})", section, count, max),
+            Self::DoesNotFit { required, available } => write!(f, "MRAM LAYOUT DOES NOT FIT: {} BYTES REQUIRED, {} AVAILABLE", required, available),
+        }
+    }
+}
+
+impl std::error::Error for Tcan4550MramError {}
+
+/// Desired number of elements in each MRAM section. RX and TX elements are sized for the MTU of the middleware.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Tcan4550MramLayout {
+    pub std_filters: usize,
+    pub ext_filters: usize,
+    pub rx_fifo0: usize,
+    pub rx_fifo1: usize,
+    /// Dedicated TX buffers, placed before the TX FIFO.
+    pub tx_buffers: usize,
+    pub tx_fifo: usize,
+    pub tx_events: usize,
+}
+
+impl Default for Tcan4550MramLayout {
+    /// Fits 64-byte payloads: Cyphal only needs extended filters, and one RX FIFO is enough as
+    /// every accepted frame goes to the host anyway.
+    fn default() -> Self {
+        Self {
+            std_filters: 0,
+            ext_filters: 16,
+            rx_fifo0: 16,
+            rx_fifo1: 0,
+            tx_buffers: 0,
+            tx_fifo: 8,
+            tx_events: 8,
+        }
+    }
+}
+
+impl Tcan4550MramLayout {
+    /// Bytes of MRAM the layout needs for the given MTU.
+    pub fn size<const MTU: usize>(&self) -> Result<usize, Tcan4550MramError> {
+        let element_size: usize = ELEMENT_HEADER_SIZE + DATA_SIZE_CODE_TO_BYTES[Self::data_size_code::<MTU>()? as usize];
+        Ok(self.std_filters * STD_FILTER_ELEMENT_SIZE
+            + self.ext_filters * EXT_FILTER_ELEMENT_SIZE
+            + (self.rx_fifo0 + self.rx_fifo1 + self.tx_buffers + self.tx_fifo) * element_size
+            + self.tx_events * TX_EVENT_ELEMENT_SIZE)
+    }
+
+    /// Places the sections one after another from the start of the MRAM, in the order filters, RX FIFOs,
+    /// TX event FIFO and TX buffers, and returns the register values for `Tcan4550Driver::configure_mram`.
+    /// Empty sections get a zero start address, which the MCAN ignores.
+    pub fn plan<const MTU: usize>(&self) -> Result<Tcan4550MramConfig, Tcan4550MramError> {
+        for (section, count, max) in [
+            ("STANDARD FILTERS", self.std_filters, MAX_STD_FILTERS),
+            ("EXTENDED FILTERS", self.ext_filters, MAX_EXT_FILTERS),
+            ("RX FIFO 0 ELEMENTS", self.rx_fifo0, MAX_RX_FIFO_ELEMENTS),
+            ("RX FIFO 1 ELEMENTS", self.rx_fifo1, MAX_RX_FIFO_ELEMENTS),
+            ("TX BUFFERS", self.tx_buffers + self.tx_fifo, MAX_TX_BUFFERS),
+            ("TX EVENT ELEMENTS", self.tx_events, MAX_TX_EVENTS),
+        ] {
+            if count > max {
+                return Err(Tcan4550MramError::TooManyElements { section, count, max });
+            }
+        }
+        let required: usize = self.size::<MTU>()?;
+        if required > MRAM_SIZE {
+            return Err(Tcan4550MramError::DoesNotFit { required, available: MRAM_SIZE });
+        }
+
+        let code: u32 = Self::data_size_code::<MTU>()?;
+        let element_size: usize = ELEMENT_HEADER_SIZE + DATA_SIZE_CODE_TO_BYTES[code as usize];
+        let mut offset: usize = 0;
+        // The element count sits at bit 16 in SIDFC, XIDFC, RXFnC, TXEFC and, as NDTB, in TXBC.
+        let mut section = |count: usize, size: usize| {
+            let start: u32 = if count > 0 { offset as u32 } else { 0 };
+            offset += count * size;
+            start | ((count as u32) << 16)
+        };
+        Ok(Tcan4550MramConfig {
+            sidfc: section(self.std_filters, STD_FILTER_ELEMENT_SIZE),
+            xidfc: section(self.ext_filters, EXT_FILTER_ELEMENT_SIZE),
+            rxf0c: section(self.rx_fifo0, element_size),
+            rxf1c: section(self.rx_fifo1, element_size),
+            txefc: section(self.tx_events, TX_EVENT_ELEMENT_SIZE),
+            // TXBC splits the count into dedicated buffers (NDTB) and FIFO/queue size (TFQS).
+            txbc: (section(self.tx_buffers + self.tx_fifo, element_size) & START_ADDRESS_MASK)
+                | ((self.tx_buffers as u32) << TXBC_NDTB_SHIFT)
+                | ((self.tx_fifo as u32) << TXBC_TFQS_SHIFT),
+            rxesc: (code << RXESC_F0DS_SHIFT) | (code << RXESC_F1DS_SHIFT) | (code << RXESC_RBDS_SHIFT),
+            txesc: code << TXESC_TBDS_SHIFT,
+        })
+    }
+
+    fn data_size_code<const MTU: usize>() -> Result<u32, Tcan4550MramError> {
+        match MTU {
+            0 => Err(Tcan4550MramError::UnsupportedPayloadSize(MTU)),
+            _ => data_size_code(MTU).ok_or(Tcan4550MramError::UnsupportedPayloadSize(MTU)),
+        }
+    }
+}
+
+/// Register values locating the filter lists, RX FIFOs, TX buffers and TX event FIFO in MRAM.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Tcan4550MramConfig {
+    pub sidfc: u32,
+    pub xidfc: u32,
+    pub rxf0c: u32,
+    pub rxf1c: u32,
+    pub rxesc: u32,
+    pub txbc: u32,
+    pub txesc: u32,
+    pub txefc: u32,
+}
+
+impl Tcan4550MramConfig {
+    /// Returns (start offset, element count, element size) of RX FIFO 0 or 1.
+    pub fn rx_fifo(&self, fifo: usize) -> (usize, usize, usize) {
+        let (config, shift) = if fifo == 0 { (self.rxf0c, RXESC_F0DS_SHIFT) } else { (self.rxf1c, RXESC_F1DS_SHIFT) };
+        let data_size: usize = DATA_SIZE_CODE_TO_BYTES[((self.rxesc >> shift) & DATA_SIZE_CODE_MASK) as usize];
+        ((config & START_ADDRESS_MASK) as usize, ((config & RXFC_SIZE_MASK) >> RXFC_SIZE_SHIFT) as usize, ELEMENT_HEADER_SIZE + data_size)
+    }
+
+    /// Returns (start offset, buffer count, element size) of the TX buffers, dedicated and FIFO/queue together.
+    pub fn tx_buffers(&self) -> (usize, usize, usize) {
+        let data_size: usize = DATA_SIZE_CODE_TO_BYTES[((self.txesc >> TXESC_TBDS_SHIFT) & DATA_SIZE_CODE_MASK) as usize];
+        let count: usize = (((self.txbc & TXBC_NDTB_MASK) >> TXBC_NDTB_SHIFT) + ((self.txbc & TXBC_TFQS_MASK) >> TXBC_TFQS_SHIFT)) as usize;
+        ((self.txbc & START_ADDRESS_MASK) as usize, count, ELEMENT_HEADER_SIZE + data_size)
+    }
+
+    /// Returns (start offset, element count) of the TX event FIFO.
+    pub fn tx_event_fifo(&self) -> (usize, usize) {
+        ((self.txefc & START_ADDRESS_MASK) as usize, ((self.txefc & TXEFC_SIZE_MASK) >> TXEFC_SIZE_SHIFT) as usize)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_layout_places_sections_in_order() {
+        let config: Tcan4550MramConfig = Tcan4550MramLayout::default().plan::<64>().unwrap();
+        assert_eq!(config.sidfc, 0);
+        assert_eq!(config.xidfc, 0x0010_0000);
+        assert_eq!(config.rxf0c, 0x0010_0080);
+        assert_eq!(config.rxf1c, 0);
+        assert_eq!(config.txefc, 0x0008_0500);
+        assert_eq!(config.txbc, 0x0800_0540);
+        assert_eq!(config.rxesc, 0x777);
+        assert_eq!(config.txesc, 0x7);
+        assert_eq!(Tcan4550MramLayout::default().size::<64>(), Ok(0x780));
+
+        assert_eq!(config.rx_fifo(0), (0x80, 16, 72));
+        assert_eq!(config.tx_event_fifo(), (0x500, 8));
+        assert_eq!(config.tx_buffers(), (0x540, 8, 72));
+    }
+
+    #[test]
+    fn every_section_starts_where_the_previous_one_ends() {
+        let layout: Tcan4550MramLayout = Tcan4550MramLayout {
+            std_filters: 3,
+            ext_filters: 5,
+            rx_fifo0: 4,
+            rx_fifo1: 2,
+            tx_buffers: 2,
+            tx_fifo: 6,
+            tx_events: 7,
+        };
+        let config: Tcan4550MramConfig = layout.plan::<8>().unwrap();
+        let element_size: usize = ELEMENT_HEADER_SIZE + 8;
+        let std_end: usize = 3 * STD_FILTER_ELEMENT_SIZE;
+        let ext_end: usize = std_end + 5 * EXT_FILTER_ELEMENT_SIZE;
+        assert_eq!(config.sidfc, 3 << 16);
+        assert_eq!(config.xidfc, (5 << 16) | std_end as u32);
+        assert_eq!(config.rx_fifo(0), (ext_end, 4, element_size));
+        assert_eq!(config.rx_fifo(1), (ext_end + 4 * element_size, 2, element_size));
+        let tx_event_start: usize = ext_end + 6 * element_size;
+        assert_eq!(config.tx_event_fifo(), (tx_event_start, 7));
+        assert_eq!(config.tx_buffers(), (tx_event_start + 7 * TX_EVENT_ELEMENT_SIZE, 8, element_size));
+        assert_eq!(config.tx_buffers().0 + 8 * element_size, layout.size::<8>().unwrap());
+    }
+
+    #[test]
+    fn txbc_packs_dedicated_buffers_and_fifo_size_apart() {
+        let layout: Tcan4550MramLayout = Tcan4550MramLayout { tx_buffers: 3, tx_fifo: 29, ..Default::default() };
+        let txbc: u32 = layout.plan::<8>().unwrap().txbc;
+        assert_eq!((txbc & TXBC_NDTB_MASK) >> TXBC_NDTB_SHIFT, 3);
+        assert_eq!((txbc & TXBC_TFQS_MASK) >> TXBC_TFQS_SHIFT, 29);
+        assert_eq!(txbc & TXBC_TFQM, 0);
+        assert_eq!(txbc & !(START_ADDRESS_MASK | TXBC_NDTB_MASK | TXBC_TFQS_MASK), 0);
+    }
+
+    #[test]
+    fn too_many_elements_is_an_error() {
+        let layout: Tcan4550MramLayout = Tcan4550MramLayout { ext_filters: 65, ..Default::default() };
+        assert_eq!(layout.plan::<8>(), Err(Tcan4550MramError::TooManyElements { section: "EXTENDED FILTERS", count: 65, max: 64 }));
+        // Dedicated buffers and the FIFO share the 32 TX buffers.
+        let layout: Tcan4550MramLayout = Tcan4550MramLayout { tx_buffers: 20, tx_fifo: 13, ..Default::default() };
+        assert_eq!(layout.plan::<8>(), Err(Tcan4550MramError::TooManyElements { section: "TX BUFFERS", count: 33, max: 32 }));
+    }
+
+    #[test]
+    fn layout_larger_than_the_mram_is_an_error() {
+        let layout: Tcan4550MramLayout = Tcan4550MramLayout { rx_fifo0: 32, ..Default::default() };
+        assert!(layout.plan::<8>().is_ok());
+        let required: usize = layout.size::<64>().unwrap();
+        assert!(required > MRAM_SIZE);
+        assert_eq!(layout.plan::<64>(), Err(Tcan4550MramError::DoesNotFit { required, available: MRAM_SIZE }));
+    }
+
+    #[test]
+    fn payload_sizes_round_up_to_an_element_size() {
+        assert_eq!(Tcan4550MramLayout::default().plan::<10>().unwrap().rxesc & DATA_SIZE_CODE_MASK, 1);
+        assert_eq!(Tcan4550MramLayout::default().plan::<0>(), Err(Tcan4550MramError::UnsupportedPayloadSize(0)));
+        assert_eq!(Tcan4550MramLayout::default().plan::<65>(), Err(Tcan4550MramError::UnsupportedPayloadSize(65)));
+    }
+}
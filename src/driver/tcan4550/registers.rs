@@ -0,0 +1,312 @@
+//! TCAN4550 register map, bit fields and MRAM element layout.
+
+// SPI protocol
+pub const SPI_OPCODE_WRITE: u8 = 0x61;
+pub const SPI_OPCODE_READ: u8 = 0x41;
+pub const SPI_HEADER_SIZE: usize = 4;
+pub const SPI_WORD_SIZE: usize = 4;
+/// A length field of zero transfers this many words.
+pub const SPI_MAX_WORDS: usize = 256;
+
+// Device registers
+pub const REG_DEVICE_ID1: u16 = 0x0000;
+pub const REG_DEVICE_ID2: u16 = 0x0004;
+pub const REG_REVISION: u16 = 0x0008;
+pub const REG_SPI_STATUS: u16 = 0x000C;
+pub const REG_MODES_OF_OPERATION: u16 = 0x0800;
+pub const REG_TIMESTAMP_PRESCALER: u16 = 0x0804;
+pub const REG_TEST_SCRATCH: u16 = 0x0808;
+pub const REG_INTERRUPTS: u16 = 0x0820;
+pub const REG_MCAN_INTERRUPTS: u16 = 0x0824;
+pub const REG_INTERRUPT_ENABLE: u16 = 0x0830;
+
+/// "TCAN" and "4550" in ASCII, as read from DEVICE_ID1 and DEVICE_ID2.
+pub const DEVICE_ID1_VALUE: u32 = 0x4E41_4354;
+pub const DEVICE_ID2_VALUE: u32 = 0x3035_3534;
+
+pub const MODES_MODE_SEL_SHIFT: u32 = 6;
+pub const MODES_MODE_SEL_MASK: u32 = 0b11 << MODES_MODE_SEL_SHIFT;
+pub const MODES_MODE_SLEEP: u32 = 0b00;
+pub const MODES_MODE_STANDBY: u32 = 0b01;
+pub const MODES_MODE_NORMAL: u32 = 0b10;
+pub const MODES_RESET_VALUE: u32 = 0xC800_0468;
+
+pub const INTERRUPTS_M_CAN_INT: u32 = 1 << 1;
+pub const INTERRUPTS_SPIERR: u32 = 1 << 3;
+
+// MCAN registers
+pub const REG_MCAN_CREL: u16 = 0x1000;
+pub const REG_MCAN_ENDN: u16 = 0x1004;
+pub const REG_MCAN_DBTP: u16 = 0x100C;
+pub const REG_MCAN_TEST: u16 = 0x1010;
+pub const REG_MCAN_RWD: u16 = 0x1014;
+pub const REG_MCAN_CCCR: u16 = 0x1018;
+pub const REG_MCAN_NBTP: u16 = 0x101C;
+pub const REG_MCAN_TSCC: u16 = 0x1020;
+pub const REG_MCAN_TSCV: u16 = 0x1024;
+pub const REG_MCAN_TOCC: u16 = 0x1028;
+pub const REG_MCAN_TOCV: u16 = 0x102C;
+pub const REG_MCAN_ECR: u16 = 0x1040;
+pub const REG_MCAN_PSR: u16 = 0x1044;
+pub const REG_MCAN_TDCR: u16 = 0x1048;
+pub const REG_MCAN_IR: u16 = 0x1050;
+pub const REG_MCAN_IE: u16 = 0x1054;
+pub const REG_MCAN_ILS: u16 = 0x1058;
+pub const REG_MCAN_ILE: u16 = 0x105C;
+pub const REG_MCAN_GFC: u16 = 0x1080;
+pub const REG_MCAN_SIDFC: u16 = 0x1084;
+pub const REG_MCAN_XIDFC: u16 = 0x1088;
+pub const REG_MCAN_XIDAM: u16 = 0x1090;
+pub const REG_MCAN_HPMS: u16 = 0x1094;
+pub const REG_MCAN_RXF0C: u16 = 0x10A0;
+pub const REG_MCAN_RXF0S: u16 = 0x10A4;
+pub const REG_MCAN_RXF0A: u16 = 0x10A8;
+pub const REG_MCAN_RXBC: u16 = 0x10AC;
+pub const REG_MCAN_RXF1C: u16 = 0x10B0;
+pub const REG_MCAN_RXF1S: u16 = 0x10B4;
+pub const REG_MCAN_RXF1A: u16 = 0x10B8;
+pub const REG_MCAN_RXESC: u16 = 0x10BC;
+pub const REG_MCAN_TXBC: u16 = 0x10C0;
+pub const REG_MCAN_TXFQS: u16 = 0x10C4;
+pub const REG_MCAN_TXESC: u16 = 0x10C8;
+pub const REG_MCAN_TXBRP: u16 = 0x10CC;
+pub const REG_MCAN_TXBAR: u16 = 0x10D0;
+pub const REG_MCAN_TXBCR: u16 = 0x10D4;
+pub const REG_MCAN_TXBTO: u16 = 0x10D8;
+pub const REG_MCAN_TXBCF: u16 = 0x10DC;
+pub const REG_MCAN_TXBTIE: u16 = 0x10E0;
+pub const REG_MCAN_TXBCIE: u16 = 0x10E4;
+pub const REG_MCAN_TXEFC: u16 = 0x10F0;
+pub const REG_MCAN_TXEFS: u16 = 0x10F4;
+pub const REG_MCAN_TXEFA: u16 = 0x10F8;
+
+pub const MCAN_CREL_VALUE: u32 = 0x3215_0320;
+pub const MCAN_ENDN_VALUE: u32 = 0x8765_4321;
+
+pub const CCCR_INIT: u32 = 1 << 0;
+pub const CCCR_CCE: u32 = 1 << 1;
+pub const CCCR_ASM: u32 = 1 << 2;
+pub const CCCR_CSR: u32 = 1 << 4;
+pub const CCCR_MON: u32 = 1 << 5;
+pub const CCCR_DAR: u32 = 1 << 6;
+pub const CCCR_TEST: u32 = 1 << 7;
+pub const CCCR_FDOE: u32 = 1 << 8;
+pub const CCCR_BRSE: u32 = 1 << 9;
+pub const CCCR_PXHD: u32 = 1 << 12;
+pub const CCCR_EFBI: u32 = 1 << 13;
+pub const CCCR_TXP: u32 = 1 << 14;
+pub const CCCR_NISO: u32 = 1 << 15;
+
+// Bit timing fields hold their value minus one.
+pub const NBTP_NTSEG2_SHIFT: u32 = 0;
+pub const NBTP_NTSEG2_MAX: u32 = 0x80;
+pub const NBTP_NTSEG1_SHIFT: u32 = 8;
+pub const NBTP_NTSEG1_MAX: u32 = 0x100;
+pub const NBTP_NBRP_SHIFT: u32 = 16;
+pub const NBTP_NBRP_MAX: u32 = 0x200;
+pub const NBTP_NSJW_SHIFT: u32 = 25;
+pub const NBTP_NSJW_MAX: u32 = 0x80;
+pub const DBTP_DSJW_SHIFT: u32 = 0;
+pub const DBTP_DSJW_MAX: u32 = 0x10;
+pub const DBTP_DTSEG2_SHIFT: u32 = 4;
+pub const DBTP_DTSEG2_MAX: u32 = 0x10;
+pub const DBTP_DTSEG1_SHIFT: u32 = 8;
+pub const DBTP_DTSEG1_MAX: u32 = 0x20;
+pub const DBTP_DBRP_SHIFT: u32 = 16;
+pub const DBTP_DBRP_MAX: u32 = 0x20;
+pub const DBTP_TDC: u32 = 1 << 23;
+pub const TDCR_TDCF_SHIFT: u32 = 0;
+pub const TDCR_TDCO_SHIFT: u32 = 8;
+/// TDCO and TDCF are in minimum time quanta (MCAN clock periods), not offset by one.
+pub const TDCR_MAX: u32 = 0x7F;
+
+pub const TSCC_TSS_MASK: u32 = 0b11;
+pub const TSCC_TSS_INTERNAL: u32 = 0b01;
+pub const TSCC_TCP_SHIFT: u32 = 16;
+/// The counter advances once every TCP + 1 nominal bit times.
+pub const TSCC_TCP_MAX: u32 = 0x10;
+
+pub const ECR_TEC_MASK: u32 = 0xFF;
+pub const ECR_REC_SHIFT: u32 = 8;
+pub const ECR_REC_MASK: u32 = 0x7F << ECR_REC_SHIFT;
+pub const ECR_RP: u32 = 1 << 15;
+pub const ECR_CEL_SHIFT: u32 = 16;
+pub const ECR_CEL_MASK: u32 = 0xFF << ECR_CEL_SHIFT;
+
+pub const PSR_LEC_MASK: u32 = 0b111;
+pub const PSR_ACT_SHIFT: u32 = 3;
+pub const PSR_ACT_MASK: u32 = 0b11 << PSR_ACT_SHIFT;
+pub const PSR_EP: u32 = 1 << 5;
+pub const PSR_EW: u32 = 1 << 6;
+pub const PSR_BO: u32 = 1 << 7;
+pub const PSR_DLEC_SHIFT: u32 = 8;
+pub const PSR_DLEC_MASK: u32 = 0b111 << PSR_DLEC_SHIFT;
+pub const PSR_RESI: u32 = 1 << 11;
+pub const PSR_RBRS: u32 = 1 << 12;
+pub const PSR_RFDF: u32 = 1 << 13;
+pub const PSR_PXE: u32 = 1 << 14;
+pub const PSR_TDCV_SHIFT: u32 = 16;
+pub const PSR_TDCV_MASK: u32 = 0x7F << PSR_TDCV_SHIFT;
+/// Last error code "no change"; LEC and DLEC are reset to it when PSR is read.
+pub const PSR_LEC_NO_CHANGE: u32 = 0b111;
+
+pub const IR_RF0N: u32 = 1 << 0;
+pub const IR_RF0W: u32 = 1 << 1;
+pub const IR_RF0F: u32 = 1 << 2;
+pub const IR_RF0L: u32 = 1 << 3;
+pub const IR_RF1N: u32 = 1 << 4;
+pub const IR_RF1W: u32 = 1 << 5;
+pub const IR_RF1F: u32 = 1 << 6;
+pub const IR_RF1L: u32 = 1 << 7;
+pub const IR_HPM: u32 = 1 << 8;
+pub const IR_TC: u32 = 1 << 9;
+pub const IR_TCF: u32 = 1 << 10;
+pub const IR_TFE: u32 = 1 << 11;
+pub const IR_TEFN: u32 = 1 << 12;
+pub const IR_TEFW: u32 = 1 << 13;
+pub const IR_TEFF: u32 = 1 << 14;
+pub const IR_TEFL: u32 = 1 << 15;
+pub const IR_TSW: u32 = 1 << 16;
+pub const IR_MRAF: u32 = 1 << 17;
+pub const IR_TOO: u32 = 1 << 18;
+pub const IR_DRX: u32 = 1 << 19;
+pub const IR_BEC: u32 = 1 << 20;
+pub const IR_BEU: u32 = 1 << 21;
+pub const IR_ELO: u32 = 1 << 22;
+pub const IR_EP: u32 = 1 << 23;
+pub const IR_EW: u32 = 1 << 24;
+pub const IR_BO: u32 = 1 << 25;
+pub const IR_WDI: u32 = 1 << 26;
+pub const IR_PEA: u32 = 1 << 27;
+pub const IR_PED: u32 = 1 << 28;
+pub const IR_ARA: u32 = 1 << 29;
+
+pub const ILE_EINT0: u32 = 1 << 0;
+pub const ILE_EINT1: u32 = 1 << 1;
+
+pub const GFC_RRFE: u32 = 1 << 0;
+pub const GFC_RRFS: u32 = 1 << 1;
+pub const GFC_ANFE_SHIFT: u32 = 2;
+pub const GFC_ANFS_SHIFT: u32 = 4;
+/// Non-matching frame handling in GFC.ANFE and GFC.ANFS.
+pub const GFC_ACCEPT_FIFO0: u32 = 0b00;
+pub const GFC_ACCEPT_FIFO1: u32 = 0b01;
+pub const GFC_REJECT: u32 = 0b10;
+
+/// Start address fields (FLSSA, FLESA, FnSA, TBSA, EFSA) hold a word address in bits 15:2.
+pub const START_ADDRESS_MASK: u32 = 0xFFFC;
+pub const SIDFC_LSS_SHIFT: u32 = 16;
+pub const SIDFC_LSS_MASK: u32 = 0xFF << SIDFC_LSS_SHIFT;
+pub const XIDFC_LSE_SHIFT: u32 = 16;
+pub const XIDFC_LSE_MASK: u32 = 0x7F << XIDFC_LSE_SHIFT;
+
+pub const RXFC_SIZE_SHIFT: u32 = 16;
+pub const RXFC_SIZE_MASK: u32 = 0x7F << RXFC_SIZE_SHIFT;
+pub const RXFC_WM_SHIFT: u32 = 24;
+pub const RXFC_WM_MASK: u32 = 0x7F << RXFC_WM_SHIFT;
+pub const RXFC_OM: u32 = 1 << 31;
+pub const RXFS_FILL_MASK: u32 = 0x7F;
+pub const RXFS_GET_SHIFT: u32 = 8;
+pub const RXFS_GET_MASK: u32 = 0x3F << RXFS_GET_SHIFT;
+pub const RXFS_PUT_SHIFT: u32 = 16;
+pub const RXFS_PUT_MASK: u32 = 0x3F << RXFS_PUT_SHIFT;
+pub const RXFS_FULL: u32 = 1 << 24;
+pub const RXFS_LOST: u32 = 1 << 25;
+pub const RXFA_INDEX_MASK: u32 = 0x3F;
+
+pub const RXESC_F0DS_SHIFT: u32 = 0;
+pub const RXESC_F1DS_SHIFT: u32 = 4;
+pub const RXESC_RBDS_SHIFT: u32 = 8;
+pub const TXESC_TBDS_SHIFT: u32 = 0;
+pub const DATA_SIZE_CODE_MASK: u32 = 0b111;
+/// Data field size in bytes for each element size code of RXESC and TXESC.
+pub const DATA_SIZE_CODE_TO_BYTES: [usize; 8] = [8, 12, 16, 20, 24, 32, 48, 64];
+
+pub const TXBC_NDTB_SHIFT: u32 = 16;
+pub const TXBC_NDTB_MASK: u32 = 0x3F << TXBC_NDTB_SHIFT;
+pub const TXBC_TFQS_SHIFT: u32 = 24;
+pub const TXBC_TFQS_MASK: u32 = 0x3F << TXBC_TFQS_SHIFT;
+pub const TXBC_TFQM: u32 = 1 << 30;
+pub const TXFQS_FREE_MASK: u32 = 0x3F;
+pub const TXFQS_GET_SHIFT: u32 = 8;
+pub const TXFQS_GET_MASK: u32 = 0x1F << TXFQS_GET_SHIFT;
+pub const TXFQS_PUT_SHIFT: u32 = 16;
+pub const TXFQS_PUT_MASK: u32 = 0x1F << TXFQS_PUT_SHIFT;
+pub const TXFQS_FULL: u32 = 1 << 21;
+/// The MCAN supports at most 32 TX buffers, dedicated and FIFO/queue together.
+pub const TX_BUFFER_COUNT_MAX: usize = 32;
+
+pub const TXEFC_SIZE_SHIFT: u32 = 16;
+pub const TXEFC_SIZE_MASK: u32 = 0x3F << TXEFC_SIZE_SHIFT;
+pub const TXEFC_WM_SHIFT: u32 = 24;
+pub const TXEFC_WM_MASK: u32 = 0x3F << TXEFC_WM_SHIFT;
+pub const TXEFS_FILL_MASK: u32 = 0x3F;
+pub const TXEFS_GET_SHIFT: u32 = 8;
+pub const TXEFS_GET_MASK: u32 = 0x1F << TXEFS_GET_SHIFT;
+pub const TXEFS_PUT_SHIFT: u32 = 16;
+pub const TXEFS_PUT_MASK: u32 = 0x1F << TXEFS_PUT_SHIFT;
+pub const TXEFS_FULL: u32 = 1 << 24;
+pub const TXEFS_LOST: u32 = 1 << 25;
+pub const TXEFA_INDEX_MASK: u32 = 0x1F;
+
+// MRAM
+pub const MRAM_BASE: u16 = 0x8000;
+pub const MRAM_SIZE: usize = 2048;
+
+/// Filter, RX, TX and TX event elements all start with the same ID word.
+pub const ELEMENT_ESI: u32 = 1 << 31;
+pub const ELEMENT_XTD: u32 = 1 << 30;
+pub const ELEMENT_RTR: u32 = 1 << 29;
+pub const ELEMENT_EXT_ID_MASK: u32 = 0x1FFF_FFFF;
+pub const ELEMENT_STD_ID_SHIFT: u32 = 18;
+pub const ELEMENT_STD_ID_MASK: u32 = 0x7FF;
+/// The second word of RX, TX and TX event elements.
+pub const ELEMENT_FDF: u32 = 1 << 21;
+pub const ELEMENT_BRS: u32 = 1 << 20;
+pub const ELEMENT_DLC_SHIFT: u32 = 16;
+pub const ELEMENT_DLC_MASK: u32 = 0xF << ELEMENT_DLC_SHIFT;
+pub const ELEMENT_TIMESTAMP_MASK: u32 = 0xFFFF;
+pub const ELEMENT_MM_SHIFT: u32 = 24;
+pub const RX_ELEMENT_ANMF: u32 = 1 << 31;
+pub const RX_ELEMENT_FIDX_SHIFT: u32 = 24;
+pub const RX_ELEMENT_FIDX_MASK: u32 = 0x7F << RX_ELEMENT_FIDX_SHIFT;
+pub const TX_ELEMENT_EFC: u32 = 1 << 23;
+pub const TX_EVENT_ET_SHIFT: u32 = 22;
+pub const TX_EVENT_ET_TRANSMITTED: u32 = 0b01;
+/// RX, TX and TX event elements carry two header words before the data field.
+pub const ELEMENT_HEADER_SIZE: usize = 8;
+pub const TX_EVENT_ELEMENT_SIZE: usize = 8;
+pub const STD_FILTER_ELEMENT_SIZE: usize = 4;
+pub const EXT_FILTER_ELEMENT_SIZE: usize = 8;
+
+/// Standard filter element: type, configuration and the two IDs.
+pub const SFE_SFT_SHIFT: u32 = 30;
+pub const SFE_SFEC_SHIFT: u32 = 27;
+pub const SFE_SFID1_SHIFT: u32 = 16;
+pub const SFE_SFID_MASK: u32 = 0x7FF;
+/// Extended filter element: configuration in the first word, type in the second.
+pub const XFE_EFEC_SHIFT: u32 = 29;
+pub const XFE_EFT_SHIFT: u32 = 30;
+pub const XFE_EFID_MASK: u32 = 0x1FFF_FFFF;
+
+/// Filter types (SFT, EFT).
+pub const FILTER_TYPE_RANGE: u32 = 0b00;
+pub const FILTER_TYPE_DUAL: u32 = 0b01;
+pub const FILTER_TYPE_CLASSIC: u32 = 0b10;
+/// Range without the XIDAM mask for extended filters; disabled for standard filters.
+pub const FILTER_TYPE_RANGE_NO_MASK: u32 = 0b11;
+
+/// Filter element configurations (SFEC, EFEC).
+pub const FILTER_CONFIG_DISABLE: u32 = 0b000;
+pub const FILTER_CONFIG_FIFO0: u32 = 0b001;
+pub const FILTER_CONFIG_FIFO1: u32 = 0b010;
+pub const FILTER_CONFIG_REJECT: u32 = 0b011;
+pub const FILTER_CONFIG_PRIORITY: u32 = 0b100;
+pub const FILTER_CONFIG_PRIORITY_FIFO0: u32 = 0b101;
+pub const FILTER_CONFIG_PRIORITY_FIFO1: u32 = 0b110;
+pub const FILTER_CONFIG_RX_BUFFER: u32 = 0b111;
+
+/// Returns the element size code of RXESC/TXESC for a data field of at least `size` bytes.
+pub fn data_size_code(size: usize) -> Option<u32> {
+    DATA_SIZE_CODE_TO_BYTES.iter().position(|x| *x >= size).map(|x| x as u32)
+}
@@ -0,0 +1,691 @@
+use super::*;
+use super::registers::*;
+use crate::cyphal::{CAN_DLC_TO_DLEN, CAN_DLEN_TO_DLC};
+
+use std::collections::{HashMap, VecDeque};
+
+/// Registers that can only be written while CCCR.INIT and CCCR.CCE are set.
+const PROTECTED_REGISTERS: [u16; 17] = [
+    REG_MCAN_DBTP, REG_MCAN_TEST, REG_MCAN_NBTP, REG_MCAN_TSCC, REG_MCAN_TOCC, REG_MCAN_TDCR,
+    REG_MCAN_GFC, REG_MCAN_SIDFC, REG_MCAN_XIDFC, REG_MCAN_XIDAM, REG_MCAN_RXF0C, REG_MCAN_RXBC,
+    REG_MCAN_RXF1C, REG_MCAN_RXESC, REG_MCAN_TXBC, REG_MCAN_TXESC, REG_MCAN_TXEFC,
+];
+
+/// Registers whose value is maintained by the controller; writes are ignored.
+const READ_ONLY_REGISTERS: [u16; 15] = [
+    REG_DEVICE_ID1, REG_DEVICE_ID2, REG_REVISION, REG_MCAN_CREL, REG_MCAN_ENDN, REG_MCAN_TOCV,
+    REG_MCAN_ECR, REG_MCAN_PSR, REG_MCAN_HPMS, REG_MCAN_RXF0S, REG_MCAN_RXF1S, REG_MCAN_TXFQS,
+    REG_MCAN_TXBRP, REG_MCAN_TXBTO, REG_MCAN_TXBCF,
+];
+
+#[derive(Debug, Default, Clone, Copy)]
+struct FifoState {
+    fill: usize,
+    get: usize,
+    put: usize,
+    lost: bool,
+}
+
+/// Where the filter engine sends a received frame.
+struct FilterResult {
+    fifo: usize,
+    filter_index: u32,
+    non_matching: bool,
+    high_priority: bool,
+}
+
+/// Behavioural model of the TCAN4550 for tests without the board.
+/// It answers the same SPI transactions as the chip and models the device and MCAN registers, the 2 KB MRAM
+/// with standard and extended filters, RX FIFO 0 and 1, TX buffers with FIFO or queue, the TX event FIFO,
+/// the timestamp counter, error counters and interrupt flags. Frames are exchanged with the bus through
+/// `receive_frame` and `take_transmitted`. Dedicated RX buffers and bit-level bus behaviour are not modelled.
+pub struct Tcan4550Simulator {
+    registers: HashMap<u16, u32>,
+    mram: Vec<u8>,
+    rx_fifos: [FifoState; 2],
+    tx_event_fifo: FifoState,
+    tx_fifo_put: usize,
+    transmitted: VecDeque<Tcan4550Frame>,
+}
+
+impl Default for Tcan4550Simulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tcan4550Simulator {
+    /// Creates a chip in its power-on state: standby mode with the MCAN in initialization.
+    pub fn new() -> Self {
+        let registers: HashMap<u16, u32> = [
+            (REG_DEVICE_ID1, DEVICE_ID1_VALUE),
+            (REG_DEVICE_ID2, DEVICE_ID2_VALUE),
+            (REG_REVISION, 0x0011_0201),
+            (REG_MODES_OF_OPERATION, MODES_RESET_VALUE),
+            (REG_MCAN_CREL, MCAN_CREL_VALUE),
+            (REG_MCAN_ENDN, MCAN_ENDN_VALUE),
+            (REG_MCAN_DBTP, 0x0000_0A33),
+            (REG_MCAN_CCCR, CCCR_INIT),
+            (REG_MCAN_NBTP, 0x0600_0A03),
+            (REG_MCAN_TOCC, 0xFFFF_0000),
+            (REG_MCAN_PSR, PSR_LEC_NO_CHANGE | (PSR_LEC_NO_CHANGE << PSR_DLEC_SHIFT)),
+            (REG_MCAN_XIDAM, ELEMENT_EXT_ID_MASK),
+        ].into_iter().collect();
+        Self {
+            registers,
+            mram: vec![0; MRAM_SIZE],
+            rx_fifos: [FifoState::default(); 2],
+            tx_event_fifo: FifoState::default(),
+            tx_fifo_put: 0,
+            transmitted: VecDeque::new(),
+        }
+    }
+
+    /// Executes one SPI transaction, from chip select to deselect, in place: the command word and the
+    /// data words are replaced by the bytes the chip clocks out. Words are sent most significant byte first.
+    /// The bytes clocked out during the command word carry the low byte of the device interrupt register.
+    pub fn transfer(&mut self, data: &mut [u8]) -> Result<(), Box<dyn std::error::Error>> {
+        if data.len() < SPI_HEADER_SIZE {
+            return Err(self.spi_error("SPI TRANSACTION TOO SHORT"));
+        }
+        let opcode: u8 = data[0];
+        let address: u16 = u16::from_be_bytes([data[1], data[2]]);
+        let words: usize = if data[3] == 0 { SPI_MAX_WORDS } else { data[3] as usize };
+        if data.len() != SPI_HEADER_SIZE + words * SPI_WORD_SIZE {
+            return Err(self.spi_error("SPI TRANSACTION LENGTH DOES NOT MATCH WORD COUNT"));
+        }
+        let status: u8 = self.read_register(REG_INTERRUPTS) as u8;
+        data[..SPI_HEADER_SIZE].copy_from_slice(&[status, 0, 0, 0]);
+
+        for (i, word) in data[SPI_HEADER_SIZE..].chunks_mut(SPI_WORD_SIZE).enumerate() {
+            let address: u16 = address.wrapping_add((i * SPI_WORD_SIZE) as u16);
+            match opcode {
+                SPI_OPCODE_WRITE => {
+                    self.write_register(address, u32::from_be_bytes([word[0], word[1], word[2], word[3]]));
+                    word.fill(0);
+                },
+                SPI_OPCODE_READ => word.copy_from_slice(&self.read_register(address).to_be_bytes()),
+                _ => return Err(self.spi_error("UNKNOWN SPI OPCODE")),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_register(&mut self, address: u16) -> u32 {
+        if let Some(offset) = Self::mram_offset(address) {
+            return self.mram_word(offset);
+        }
+        match address {
+            REG_INTERRUPTS => self.reg(REG_INTERRUPTS) | if self.mcan_interrupt_pending() { INTERRUPTS_M_CAN_INT } else { 0 },
+            REG_MCAN_INTERRUPTS => self.reg(REG_MCAN_IR),
+            REG_MCAN_PSR => {
+                // Reading PSR resets the last error codes.
+                let value: u32 = self.reg(REG_MCAN_PSR);
+                self.registers.insert(REG_MCAN_PSR, value | PSR_LEC_NO_CHANGE | (PSR_LEC_NO_CHANGE << PSR_DLEC_SHIFT));
+                value
+            },
+            REG_MCAN_RXF0S => self.rx_fifo_status(0),
+            REG_MCAN_RXF1S => self.rx_fifo_status(1),
+            REG_MCAN_TXFQS => self.tx_fifo_status(),
+            REG_MCAN_TXEFS => Self::fifo_status(&self.tx_event_fifo, self.tx_event_fifo_size(), TXEFS_GET_SHIFT, TXEFS_PUT_SHIFT, TXEFS_FULL, TXEFS_LOST),
+            _ => self.reg(address),
+        }
+    }
+
+    pub fn write_register(&mut self, address: u16, value: u32) {
+        if let Some(offset) = Self::mram_offset(address) {
+            self.mram[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+            return;
+        }
+        if READ_ONLY_REGISTERS.contains(&address) {
+            return;
+        }
+        if PROTECTED_REGISTERS.contains(&address) && self.reg(REG_MCAN_CCCR) & (CCCR_INIT | CCCR_CCE) != (CCCR_INIT | CCCR_CCE) {
+            return;
+        }
+        match address {
+            REG_INTERRUPTS | REG_MCAN_IR => {
+                self.registers.insert(address, self.reg(address) & !value);
+            },
+            REG_MCAN_INTERRUPTS => {
+                self.registers.insert(REG_MCAN_IR, self.reg(REG_MCAN_IR) & !value);
+            },
+            REG_MODES_OF_OPERATION => {
+                self.registers.insert(address, value);
+                self.process_tx();
+            },
+            REG_MCAN_CCCR => self.write_cccr(value),
+            REG_MCAN_TSCV => {
+                self.registers.insert(REG_MCAN_TSCV, 0);
+            },
+            REG_MCAN_RXF0A => self.acknowledge_rx_fifo(0, (value & RXFA_INDEX_MASK) as usize),
+            REG_MCAN_RXF1A => self.acknowledge_rx_fifo(1, (value & RXFA_INDEX_MASK) as usize),
+            REG_MCAN_TXEFA => {
+                let size: usize = self.tx_event_fifo_size();
+                Self::acknowledge_fifo(&mut self.tx_event_fifo, size, (value & TXEFA_INDEX_MASK) as usize);
+            },
+            REG_MCAN_TXBAR => self.add_tx_requests(value),
+            REG_MCAN_TXBCR => {
+                let cancelled: u32 = value & self.reg(REG_MCAN_TXBRP);
+                self.registers.insert(REG_MCAN_TXBRP, self.reg(REG_MCAN_TXBRP) & !cancelled);
+                self.registers.insert(REG_MCAN_TXBCF, self.reg(REG_MCAN_TXBCF) | cancelled);
+                if cancelled & self.reg(REG_MCAN_TXBCIE) != 0 {
+                    self.set_ir(IR_TCF);
+                }
+            },
+            _ => {
+                self.registers.insert(address, value);
+            },
+        }
+    }
+
+    pub fn mode(&self) -> Tcan4550Mode {
+        match (self.reg(REG_MODES_OF_OPERATION) & MODES_MODE_SEL_MASK) >> MODES_MODE_SEL_SHIFT {
+            MODES_MODE_SLEEP => Tcan4550Mode::Sleep,
+            MODES_MODE_NORMAL => Tcan4550Mode::Normal,
+            _ => Tcan4550Mode::Standby,
+        }
+    }
+
+    /// True while the nINT pin is asserted.
+    pub fn interrupt_pending(&self) -> bool {
+        self.mcan_interrupt_pending() || self.reg(REG_INTERRUPTS) & self.reg(REG_INTERRUPT_ENABLE) != 0
+    }
+
+    /// The MRAM as the host sees it: 32-bit words stored little-endian.
+    pub fn mram(&self) -> &[u8] {
+        &self.mram
+    }
+
+    /// Returns the element at the get index of an RX FIFO in MRAM byte order, i.e. the bytes `try_read` parses
+    /// when the data field size equals the MTU. The element stays in the FIFO until acknowledged.
+    pub fn rx_fifo_element(&self, fifo: usize) -> Option<&[u8]> {
+        let state: &FifoState = &self.rx_fifos[fifo];
+        if state.fill == 0 {
+            return None;
+        }
+        let (start, _, element_size) = self.rx_fifo_layout(fifo);
+        let offset: usize = start + state.get * element_size;
+        self.mram.get(offset..offset + element_size)
+    }
+
+    /// Presents a frame from the bus to the controller. Returns true if it was stored in an RX FIFO.
+    pub fn receive_frame(&mut self, frame: &Tcan4550Frame) -> bool {
+        if !self.is_bus_active() || (frame.fd && self.reg(REG_MCAN_CCCR) & CCCR_FDOE == 0) {
+            return false;
+        }
+        let result: FilterResult = match self.filter(frame) {
+            Some(x) => x,
+            None => return false,
+        };
+        self.store_rx_element(frame, result)
+    }
+
+    /// Returns the frames put on the bus since the last call, in transmission order.
+    pub fn take_transmitted(&mut self) -> Vec<Tcan4550Frame> {
+        self.transmitted.drain(..).collect()
+    }
+
+    /// Advances the internal timestamp counter by `ticks` counts; a wrap-around sets IR.TSW.
+    pub fn advance_timestamp(&mut self, ticks: u32) {
+        if self.reg(REG_MCAN_TSCC) & TSCC_TSS_MASK != TSCC_TSS_INTERNAL {
+            return;
+        }
+        let value: u32 = self.reg(REG_MCAN_TSCV) + ticks;
+        if value > ELEMENT_TIMESTAMP_MASK {
+            self.set_ir(IR_TSW);
+        }
+        self.registers.insert(REG_MCAN_TSCV, value & ELEMENT_TIMESTAMP_MASK);
+    }
+
+    /// Sets the error counters as bus errors would, updating ECR, PSR and the error interrupt flags.
+    /// A transmit error count above 255 takes the controller bus-off and sets CCCR.INIT.
+    pub fn set_error_counters(&mut self, tec: u16, rec: u8) {
+        let ecr: u32 = (self.reg(REG_MCAN_ECR) & ECR_CEL_MASK)
+            | (tec.min(255) as u32)
+            | ((rec as u32 & 0x7F) << ECR_REC_SHIFT)
+            | if rec > 127 { ECR_RP } else { 0 };
+        self.registers.insert(REG_MCAN_ECR, ecr);
+
+        let warning: bool = tec >= 96 || rec >= 96;
+        let passive: bool = tec >= 128 || rec >= 128;
+        let bus_off: bool = tec > 255;
+        let psr: u32 = self.reg(REG_MCAN_PSR);
+        for (flag, ir, state) in [(PSR_EW, IR_EW, warning), (PSR_EP, IR_EP, passive), (PSR_BO, IR_BO, bus_off)] {
+            if (psr & flag != 0) != state {
+                self.set_ir(ir);
+            }
+        }
+        let psr: u32 = (psr & !(PSR_EW | PSR_EP | PSR_BO))
+            | if warning { PSR_EW } else { 0 }
+            | if passive { PSR_EP } else { 0 }
+            | if bus_off { PSR_BO } else { 0 };
+        self.registers.insert(REG_MCAN_PSR, psr);
+        if bus_off {
+            self.registers.insert(REG_MCAN_CCCR, self.reg(REG_MCAN_CCCR) | CCCR_INIT);
+        }
+    }
+
+    /// Records a protocol error of the arbitration or data phase in PSR.LEC or PSR.DLEC.
+    pub fn set_last_error_code(&mut self, code: u8, data_phase: bool) {
+        let code: u32 = code as u32 & PSR_LEC_MASK;
+        let psr: u32 = self.reg(REG_MCAN_PSR);
+        let psr: u32 = match data_phase {
+            true => (psr & !PSR_DLEC_MASK) | (code << PSR_DLEC_SHIFT),
+            false => (psr & !PSR_LEC_MASK) | code,
+        };
+        self.registers.insert(REG_MCAN_PSR, psr);
+        if code != 0 && code != PSR_LEC_NO_CHANGE {
+            self.set_ir(if data_phase { IR_PED } else { IR_PEA });
+        }
+    }
+
+    fn reg(&self, address: u16) -> u32 {
+        *self.registers.get(&address).unwrap_or(&0)
+    }
+
+    fn set_ir(&mut self, flags: u32) {
+        self.registers.insert(REG_MCAN_IR, self.reg(REG_MCAN_IR) | flags);
+    }
+
+    fn spi_error(&mut self, message: &str) -> Box<dyn std::error::Error> {
+        self.registers.insert(REG_INTERRUPTS, self.reg(REG_INTERRUPTS) | INTERRUPTS_SPIERR);
+        message.into()
+    }
+
+    fn mram_offset(address: u16) -> Option<usize> {
+        let offset: usize = address.checked_sub(MRAM_BASE)? as usize;
+        if offset + 4 > MRAM_SIZE {
+            return None;
+        }
+        Some(offset & !0b11)
+    }
+
+    fn mcan_interrupt_pending(&self) -> bool {
+        self.reg(REG_MCAN_IR) & self.reg(REG_MCAN_IE) != 0 && self.reg(REG_MCAN_ILE) & (ILE_EINT0 | ILE_EINT1) != 0
+    }
+
+    fn is_bus_active(&self) -> bool {
+        self.mode() == Tcan4550Mode::Normal && self.reg(REG_MCAN_CCCR) & CCCR_INIT == 0
+    }
+
+    fn write_cccr(&mut self, value: u32) {
+        let current: u32 = self.reg(REG_MCAN_CCCR);
+        let mut value: u32 = value;
+        // CCE can only be set while INIT is set, and clearing INIT clears CCE.
+        if value & CCCR_INIT == 0 {
+            value &= !CCCR_CCE;
+        }
+        // All other configuration bits are protected.
+        if current & (CCCR_INIT | CCCR_CCE) != (CCCR_INIT | CCCR_CCE) {
+            value = (current & !(CCCR_INIT | CCCR_CCE)) | (value & (CCCR_INIT | CCCR_CCE));
+        }
+        self.registers.insert(REG_MCAN_CCCR, value);
+        if current & CCCR_INIT != 0 && value & CCCR_INIT == 0 && self.reg(REG_MCAN_PSR) & PSR_BO != 0 {
+            // Bus-off recovery: the 128 occurrences of 11 recessive bits are assumed to be seen at once.
+            self.set_error_counters(0, 0);
+        }
+        self.process_tx();
+    }
+
+    fn rx_fifo_layout(&self, fifo: usize) -> (usize, usize, usize) {
+        let config: u32 = self.reg(if fifo == 0 { REG_MCAN_RXF0C } else { REG_MCAN_RXF1C });
+        let shift: u32 = if fifo == 0 { RXESC_F0DS_SHIFT } else { RXESC_F1DS_SHIFT };
+        let data_size: usize = DATA_SIZE_CODE_TO_BYTES[((self.reg(REG_MCAN_RXESC) >> shift) & DATA_SIZE_CODE_MASK) as usize];
+        let start: usize = (config & START_ADDRESS_MASK) as usize;
+        let size: usize = ((config & RXFC_SIZE_MASK) >> RXFC_SIZE_SHIFT) as usize;
+        (start, size, ELEMENT_HEADER_SIZE + data_size)
+    }
+
+    fn tx_event_fifo_size(&self) -> usize {
+        ((self.reg(REG_MCAN_TXEFC) & TXEFC_SIZE_MASK) >> TXEFC_SIZE_SHIFT) as usize
+    }
+
+    fn fifo_status(state: &FifoState, size: usize, get_shift: u32, put_shift: u32, full: u32, lost: u32) -> u32 {
+        (state.fill as u32)
+            | ((state.get as u32) << get_shift)
+            | ((state.put as u32) << put_shift)
+            | if size > 0 && state.fill == size { full } else { 0 }
+            | if state.lost { lost } else { 0 }
+    }
+
+    fn rx_fifo_status(&self, fifo: usize) -> u32 {
+        let (_, size, _) = self.rx_fifo_layout(fifo);
+        Self::fifo_status(&self.rx_fifos[fifo], size, RXFS_GET_SHIFT, RXFS_PUT_SHIFT, RXFS_FULL, RXFS_LOST)
+    }
+
+    /// Acknowledging index `index` releases every element from the get index up to and including it.
+    fn acknowledge_fifo(state: &mut FifoState, size: usize, index: usize) {
+        if size == 0 || state.fill == 0 || index >= size {
+            return;
+        }
+        let released: usize = ((index + size - state.get) % size) + 1;
+        if released > state.fill {
+            return;
+        }
+        state.fill -= released;
+        state.get = (index + 1) % size;
+    }
+
+    fn acknowledge_rx_fifo(&mut self, fifo: usize, index: usize) {
+        let (_, size, _) = self.rx_fifo_layout(fifo);
+        Self::acknowledge_fifo(&mut self.rx_fifos[fifo], size, index);
+    }
+
+    fn filter(&self, frame: &Tcan4550Frame) -> Option<FilterResult> {
+        let gfc: u32 = self.reg(REG_MCAN_GFC);
+        if frame.remote && gfc & if frame.extended { GFC_RRFE } else { GFC_RRFS } != 0 {
+            return None;
+        }
+
+        let (config, element_size, count_mask, count_shift) = match frame.extended {
+            true => (self.reg(REG_MCAN_XIDFC), EXT_FILTER_ELEMENT_SIZE, XIDFC_LSE_MASK, XIDFC_LSE_SHIFT),
+            false => (self.reg(REG_MCAN_SIDFC), STD_FILTER_ELEMENT_SIZE, SIDFC_LSS_MASK, SIDFC_LSS_SHIFT),
+        };
+        let start: usize = (config & START_ADDRESS_MASK) as usize;
+        let count: usize = ((config & count_mask) >> count_shift) as usize;
+
+        for i in 0..count {
+            let offset: usize = start + i * element_size;
+            if offset + element_size > MRAM_SIZE {
+                break;
+            }
+            let word = |n: usize| self.mram_word(offset + n * 4);
+            let (filter_config, matched) = match frame.extended {
+                true => {
+                    let (f0, f1) = (word(0), word(1));
+                    let (id1, id2) = (f0 & XFE_EFID_MASK, f1 & XFE_EFID_MASK);
+                    let masked: u32 = frame.id & self.reg(REG_MCAN_XIDAM);
+                    let matched: bool = match f1 >> XFE_EFT_SHIFT {
+                        FILTER_TYPE_RANGE => id1 <= masked && masked <= id2,
+                        FILTER_TYPE_DUAL => masked == id1 || masked == id2,
+                        FILTER_TYPE_CLASSIC => masked & id2 == id1 & id2,
+                        _ => id1 <= frame.id && frame.id <= id2,
+                    };
+                    (f0 >> XFE_EFEC_SHIFT, matched)
+                },
+                false => {
+                    let s0: u32 = word(0);
+                    let (id1, id2) = ((s0 >> SFE_SFID1_SHIFT) & SFE_SFID_MASK, s0 & SFE_SFID_MASK);
+                    let matched: bool = match s0 >> SFE_SFT_SHIFT {
+                        FILTER_TYPE_RANGE => id1 <= frame.id && frame.id <= id2,
+                        FILTER_TYPE_DUAL => frame.id == id1 || frame.id == id2,
+                        FILTER_TYPE_CLASSIC => frame.id & id2 == id1 & id2,
+                        _ => false,
+                    };
+                    ((s0 >> SFE_SFEC_SHIFT) & 0b111, matched)
+                },
+            };
+            if filter_config == FILTER_CONFIG_DISABLE || !matched {
+                continue;
+            }
+            let fifo: usize = match filter_config {
+                FILTER_CONFIG_FIFO0 | FILTER_CONFIG_PRIORITY | FILTER_CONFIG_PRIORITY_FIFO0 => 0,
+                FILTER_CONFIG_FIFO1 | FILTER_CONFIG_PRIORITY_FIFO1 => 1,
+                // Rejected, or meant for a dedicated RX buffer which is not modelled.
+                _ => return None,
+            };
+            return Some(FilterResult {
+                fifo,
+                filter_index: i as u32,
+                non_matching: false,
+                high_priority: matches!(filter_config, FILTER_CONFIG_PRIORITY | FILTER_CONFIG_PRIORITY_FIFO0 | FILTER_CONFIG_PRIORITY_FIFO1),
+            });
+        }
+
+        let shift: u32 = if frame.extended { GFC_ANFE_SHIFT } else { GFC_ANFS_SHIFT };
+        match (gfc >> shift) & 0b11 {
+            GFC_ACCEPT_FIFO0 => Some(FilterResult { fifo: 0, filter_index: 0, non_matching: true, high_priority: false }),
+            GFC_ACCEPT_FIFO1 => Some(FilterResult { fifo: 1, filter_index: 0, non_matching: true, high_priority: false }),
+            _ => None,
+        }
+    }
+
+    fn store_rx_element(&mut self, frame: &Tcan4550Frame, result: FilterResult) -> bool {
+        let fifo: usize = result.fifo;
+        let (start, size, element_size) = self.rx_fifo_layout(fifo);
+        let (ir_new, ir_watermark, ir_full, ir_lost) = match fifo {
+            0 => (IR_RF0N, IR_RF0W, IR_RF0F, IR_RF0L),
+            _ => (IR_RF1N, IR_RF1W, IR_RF1F, IR_RF1L),
+        };
+        if size == 0 {
+            return false;
+        }
+        if start + size * element_size > MRAM_SIZE {
+            self.set_ir(IR_MRAF);
+            return false;
+        }
+        let config: u32 = self.reg(if fifo == 0 { REG_MCAN_RXF0C } else { REG_MCAN_RXF1C });
+        if self.rx_fifos[fifo].fill == size {
+            if config & RXFC_OM == 0 {
+                self.rx_fifos[fifo].lost = true;
+                self.set_ir(ir_lost);
+                return false;
+            }
+            // Overwrite mode drops the oldest element.
+            let state: &mut FifoState = &mut self.rx_fifos[fifo];
+            state.get = (state.get + 1) % size;
+            state.fill -= 1;
+        }
+
+        let data_size: usize = element_size - ELEMENT_HEADER_SIZE;
+        let length: usize = frame.data.len().min(data_size).min(CAN_DLEN_TO_DLC.len() - 1);
+        let dlc: u32 = CAN_DLEN_TO_DLC[length] as u32;
+        let r0: u32 = Self::id_word(frame);
+        let r1: u32 = (self.reg(REG_MCAN_TSCV) & ELEMENT_TIMESTAMP_MASK)
+            | (dlc << ELEMENT_DLC_SHIFT)
+            | if frame.brs { ELEMENT_BRS } else { 0 }
+            | if frame.fd { ELEMENT_FDF } else { 0 }
+            | (result.filter_index << RX_ELEMENT_FIDX_SHIFT) & RX_ELEMENT_FIDX_MASK
+            | if result.non_matching { RX_ELEMENT_ANMF } else { 0 };
+
+        let put: usize = self.rx_fifos[fifo].put;
+        let offset: usize = start + put * element_size;
+        let element: &mut [u8] = &mut self.mram[offset..offset + element_size];
+        element.fill(0);
+        element[0..4].copy_from_slice(&r0.to_le_bytes());
+        element[4..8].copy_from_slice(&r1.to_le_bytes());
+        element[ELEMENT_HEADER_SIZE..ELEMENT_HEADER_SIZE + length].copy_from_slice(&frame.data[..length]);
+
+        let state: &mut FifoState = &mut self.rx_fifos[fifo];
+        state.put = (put + 1) % size;
+        state.fill += 1;
+        let fill: usize = state.fill;
+        let watermark: usize = ((config & RXFC_WM_MASK) >> RXFC_WM_SHIFT) as usize;
+        self.set_ir(ir_new);
+        if watermark > 0 && fill == watermark {
+            self.set_ir(ir_watermark);
+        }
+        if fill == size {
+            self.set_ir(ir_full);
+        }
+        if result.high_priority {
+            // HPMS: buffer index, message stored in FIFO 0 (0b10) or 1 (0b11), filter index and list.
+            let hpms: u32 = (put as u32) | ((0b10 | fifo as u32) << 6) | (result.filter_index << 8) | if frame.extended { 1 << 15 } else { 0 };
+            self.registers.insert(REG_MCAN_HPMS, hpms);
+            self.set_ir(IR_HPM);
+        }
+        true
+    }
+
+    fn id_word(frame: &Tcan4550Frame) -> u32 {
+        let id: u32 = match frame.extended {
+            true => (frame.id & ELEMENT_EXT_ID_MASK) | ELEMENT_XTD,
+            false => (frame.id & ELEMENT_STD_ID_MASK) << ELEMENT_STD_ID_SHIFT,
+        };
+        id | if frame.remote { ELEMENT_RTR } else { 0 }
+    }
+
+    /// Returns (start, dedicated buffers, FIFO/queue size, element size) of the TX buffer section.
+    fn tx_layout(&self) -> (usize, usize, usize, usize) {
+        let config: u32 = self.reg(REG_MCAN_TXBC);
+        let data_size: usize = DATA_SIZE_CODE_TO_BYTES[((self.reg(REG_MCAN_TXESC) >> TXESC_TBDS_SHIFT) & DATA_SIZE_CODE_MASK) as usize];
+        let dedicated: usize = ((config & TXBC_NDTB_MASK) >> TXBC_NDTB_SHIFT) as usize;
+        let queue: usize = ((config & TXBC_TFQS_MASK) >> TXBC_TFQS_SHIFT) as usize;
+        let dedicated: usize = dedicated.min(TX_BUFFER_COUNT_MAX);
+        let queue: usize = queue.min(TX_BUFFER_COUNT_MAX - dedicated);
+        ((config & START_ADDRESS_MASK) as usize, dedicated, queue, ELEMENT_HEADER_SIZE + data_size)
+    }
+
+    fn tx_fifo_status(&self) -> u32 {
+        let (_, dedicated, queue, _) = self.tx_layout();
+        if queue == 0 {
+            return 0;
+        }
+        let pending: u32 = self.reg(REG_MCAN_TXBRP);
+        let is_pending = |i: usize| pending & (1 << (dedicated + i)) != 0;
+        let free: usize = (0..queue).filter(|i| !is_pending(*i)).count();
+        let put: usize = self.tx_fifo_put_index();
+        let get: usize = match self.reg(REG_MCAN_TXBC) & TXBC_TFQM != 0 {
+            true => (0..queue).find(|i| is_pending(*i)).unwrap_or(0),
+            false => (put + free) % queue,
+        };
+        (free as u32)
+            | (((dedicated + get) as u32) << TXFQS_GET_SHIFT)
+            | (((dedicated + put) as u32) << TXFQS_PUT_SHIFT)
+            | if free == 0 { TXFQS_FULL } else { 0 }
+    }
+
+    /// Index within the FIFO/queue section of the next buffer the host should fill.
+    fn tx_fifo_put_index(&self) -> usize {
+        let (_, dedicated, queue, _) = self.tx_layout();
+        match self.reg(REG_MCAN_TXBC) & TXBC_TFQM != 0 {
+            true => (0..queue).find(|i| self.reg(REG_MCAN_TXBRP) & (1 << (dedicated + i)) == 0).unwrap_or(0),
+            false => self.tx_fifo_put % queue.max(1),
+        }
+    }
+
+    fn add_tx_requests(&mut self, value: u32) {
+        let (_, dedicated, queue, _) = self.tx_layout();
+        let configured: u32 = ((1u64 << (dedicated + queue)) - 1) as u32;
+        let requests: u32 = value & configured & !self.reg(REG_MCAN_TXBRP);
+        if requests == 0 {
+            return;
+        }
+        if queue > 0 && self.reg(REG_MCAN_TXBC) & TXBC_TFQM == 0 {
+            // The FIFO put index moves past every buffer the host has handed over.
+            self.tx_fifo_put = (self.tx_fifo_put + (requests >> dedicated).count_ones() as usize) % queue;
+        }
+        self.registers.insert(REG_MCAN_TXBRP, self.reg(REG_MCAN_TXBRP) | requests);
+        self.registers.insert(REG_MCAN_TXBTO, self.reg(REG_MCAN_TXBTO) & !requests);
+        self.registers.insert(REG_MCAN_TXBCF, self.reg(REG_MCAN_TXBCF) & !requests);
+        self.process_tx();
+    }
+
+    /// Sends all pending TX buffers while the bus is active. Dedicated buffers and the queue arbitrate by ID,
+    /// as the MCAN does internally; in FIFO mode only the oldest FIFO buffer takes part in the arbitration.
+    fn process_tx(&mut self) {
+        if !self.is_bus_active() {
+            return;
+        }
+        while let Some(i) = self.next_tx_buffer() {
+            self.transmit_buffer(i);
+        }
+        let (_, dedicated, queue, _) = self.tx_layout();
+        if queue > 0 && (0..queue).all(|i| self.reg(REG_MCAN_TXBRP) & (1 << (dedicated + i)) == 0) {
+            self.set_ir(IR_TFE);
+        }
+    }
+
+    fn next_tx_buffer(&self) -> Option<usize> {
+        let (start, dedicated, queue, element_size) = self.tx_layout();
+        let pending: u32 = self.reg(REG_MCAN_TXBRP);
+        let fifo_mode: bool = self.reg(REG_MCAN_TXBC) & TXBC_TFQM == 0;
+        let fifo_head: Option<usize> = match (fifo_mode, queue > 0) {
+            (true, true) => {
+                let get: usize = ((self.tx_fifo_status() & TXFQS_GET_MASK) >> TXFQS_GET_SHIFT) as usize;
+                if pending & (1 << get) != 0 { Some(get) } else { None }
+            },
+            _ => None,
+        };
+        (0..dedicated + queue)
+            .filter(|i| pending & (1 << i) != 0)
+            .filter(|i| !fifo_mode || *i < dedicated || Some(*i) == fifo_head)
+            .map(|i| (self.mram_word(start + i * element_size) & ELEMENT_EXT_ID_MASK, i))
+            .min()
+            .map(|x| x.1)
+    }
+
+    fn transmit_buffer(&mut self, i: usize) {
+        let (start, _, _, element_size) = self.tx_layout();
+        let bit: u32 = 1 << i;
+        self.registers.insert(REG_MCAN_TXBRP, self.reg(REG_MCAN_TXBRP) & !bit);
+        let offset: usize = start + i * element_size;
+        if offset + element_size > MRAM_SIZE {
+            self.set_ir(IR_MRAF);
+            return;
+        }
+        let t0: u32 = self.mram_word(offset);
+        let t1: u32 = self.mram_word(offset + 4);
+        let dlc: usize = ((t1 & ELEMENT_DLC_MASK) >> ELEMENT_DLC_SHIFT) as usize;
+        let fd: bool = t1 & ELEMENT_FDF != 0 && self.reg(REG_MCAN_CCCR) & CCCR_FDOE != 0;
+        let length: usize = if fd { CAN_DLC_TO_DLEN[dlc] as usize } else { dlc.min(8) };
+        let length: usize = length.min(element_size - ELEMENT_HEADER_SIZE);
+        let extended: bool = t0 & ELEMENT_XTD != 0;
+        let frame: Tcan4550Frame = Tcan4550Frame {
+            id: if extended { t0 & ELEMENT_EXT_ID_MASK } else { (t0 >> ELEMENT_STD_ID_SHIFT) & ELEMENT_STD_ID_MASK },
+            extended,
+            remote: t0 & ELEMENT_RTR != 0,
+            fd,
+            brs: fd && t1 & ELEMENT_BRS != 0 && self.reg(REG_MCAN_CCCR) & CCCR_BRSE != 0,
+            data: self.mram[offset + ELEMENT_HEADER_SIZE..offset + ELEMENT_HEADER_SIZE + length].to_vec(),
+        };
+
+        self.registers.insert(REG_MCAN_TXBTO, self.reg(REG_MCAN_TXBTO) | bit);
+        if self.reg(REG_MCAN_TXBTIE) & bit != 0 {
+            self.set_ir(IR_TC);
+        }
+        if t1 & TX_ELEMENT_EFC != 0 {
+            let e1: u32 = (t1 & (0xFF << ELEMENT_MM_SHIFT))
+                | (TX_EVENT_ET_TRANSMITTED << TX_EVENT_ET_SHIFT)
+                | if frame.fd { ELEMENT_FDF } else { 0 }
+                | if frame.brs { ELEMENT_BRS } else { 0 }
+                | (t1 & ELEMENT_DLC_MASK)
+                | (self.reg(REG_MCAN_TSCV) & ELEMENT_TIMESTAMP_MASK);
+            self.store_tx_event(t0, e1);
+        }
+        self.transmitted.push_back(frame);
+    }
+
+    fn mram_word(&self, offset: usize) -> u32 {
+        u32::from_le_bytes([self.mram[offset], self.mram[offset + 1], self.mram[offset + 2], self.mram[offset + 3]])
+    }
+
+    fn store_tx_event(&mut self, e0: u32, e1: u32) {
+        let config: u32 = self.reg(REG_MCAN_TXEFC);
+        let size: usize = self.tx_event_fifo_size();
+        let start: usize = (config & START_ADDRESS_MASK) as usize;
+        if size == 0 {
+            return;
+        }
+        if start + size * TX_EVENT_ELEMENT_SIZE > MRAM_SIZE {
+            self.set_ir(IR_MRAF);
+            return;
+        }
+        if self.tx_event_fifo.fill == size {
+            self.tx_event_fifo.lost = true;
+            self.set_ir(IR_TEFL);
+            return;
+        }
+        let offset: usize = start + self.tx_event_fifo.put * TX_EVENT_ELEMENT_SIZE;
+        self.mram[offset..offset + 4].copy_from_slice(&e0.to_le_bytes());
+        self.mram[offset + 4..offset + 8].copy_from_slice(&e1.to_le_bytes());
+        self.tx_event_fifo.put = (self.tx_event_fifo.put + 1) % size;
+        self.tx_event_fifo.fill += 1;
+
+        let watermark: usize = ((config & TXEFC_WM_MASK) >> TXEFC_WM_SHIFT) as usize;
+        self.set_ir(IR_TEFN);
+        if watermark > 0 && self.tx_event_fifo.fill == watermark {
+            self.set_ir(IR_TEFW);
+        }
+        if self.tx_event_fifo.fill == size {
+            self.set_ir(IR_TEFF);
+        }
+    }
+}
+
+impl crate::driver::spi::SpiBus for Tcan4550Simulator {
+    fn transfer(&mut self, data: &mut [u8]) -> Result<(), Box<dyn std::error::Error>> {
+        Tcan4550Simulator::transfer(self, data)
+    }
+}
@@ -0,0 +1,182 @@
+use super::registers::*;
+use crate::driver::CanBusState;
+
+/// Type of the last error on the bus, as coded in PSR.LEC and PSR.DLEC.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize)]
+pub enum Tcan4550LastErrorCode {
+    NoError,
+    /// More than five equal bits in a row.
+    Stuff,
+    /// A fixed-format part of the frame had the wrong value.
+    Form,
+    /// No node acknowledged the transmitted frame, e.g. because the controller is alone on the bus.
+    Ack,
+    /// A recessive bit was sent but a dominant one was monitored.
+    Bit1,
+    /// A dominant bit was sent but a recessive one was monitored.
+    Bit0,
+    Crc,
+    /// No frame has been transferred since the last read of PSR.
+    NoChange,
+}
+
+impl From<u32> for Tcan4550LastErrorCode {
+    fn from(x: u32) -> Self {
+        match x & PSR_LEC_MASK {
+            0 => Self::NoError,
+            1 => Self::Stuff,
+            2 => Self::Form,
+            3 => Self::Ack,
+            4 => Self::Bit1,
+            5 => Self::Bit0,
+            6 => Self::Crc,
+            _ => Self::NoChange,
+        }
+    }
+}
+
+/// What the MCAN is doing on the bus (PSR.ACT).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize)]
+pub enum Tcan4550Activity {
+    /// Integrating to the bus, e.g. after a restart from bus-off.
+    Synchronizing,
+    Idle,
+    Receiver,
+    Transmitter,
+}
+
+/// Decoded error counter register (ECR).
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Tcan4550ErrorCounters {
+    pub tx_error_count: u8,
+    pub rx_error_count: u8,
+    /// The receive error counter has reached the error-passive level of 128.
+    pub rx_error_passive: bool,
+    /// Errors logged since ECR was last read, saturating at 255.
+    pub error_logging: u8,
+}
+
+impl Tcan4550ErrorCounters {
+    pub fn from_register(ecr: u32) -> Self {
+        Self {
+            tx_error_count: (ecr & ECR_TEC_MASK) as u8,
+            rx_error_count: ((ecr & ECR_REC_MASK) >> ECR_REC_SHIFT) as u8,
+            rx_error_passive: ecr & ECR_RP != 0,
+            error_logging: ((ecr & ECR_CEL_MASK) >> ECR_CEL_SHIFT) as u8,
+        }
+    }
+}
+
+/// Decoded protocol status register (PSR). Reading PSR resets the error codes to `NoChange`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Tcan4550ProtocolStatus {
+    pub last_error_code: Tcan4550LastErrorCode,
+    /// Last error in the data phase of a CAN FD frame with bit rate switching.
+    pub data_last_error_code: Tcan4550LastErrorCode,
+    pub activity: Tcan4550Activity,
+    pub error_passive: bool,
+    pub warning: bool,
+    pub bus_off: bool,
+    /// A CAN FD frame with the ESI flag set was received since the last read.
+    pub received_esi: bool,
+    pub received_brs: bool,
+    pub received_fdf: bool,
+    /// A protocol exception event occurred since the last read.
+    pub protocol_exception: bool,
+    /// Transmitter delay measured for the compensation, in clock periods.
+    pub tdc_value: u8,
+}
+
+impl Tcan4550ProtocolStatus {
+    pub fn from_register(psr: u32) -> Self {
+        Self {
+            last_error_code: Tcan4550LastErrorCode::from(psr & PSR_LEC_MASK),
+            data_last_error_code: Tcan4550LastErrorCode::from((psr & PSR_DLEC_MASK) >> PSR_DLEC_SHIFT),
+            activity: match (psr & PSR_ACT_MASK) >> PSR_ACT_SHIFT {
+                0 => Tcan4550Activity::Synchronizing,
+                1 => Tcan4550Activity::Idle,
+                2 => Tcan4550Activity::Receiver,
+                _ => Tcan4550Activity::Transmitter,
+            },
+            error_passive: psr & PSR_EP != 0,
+            warning: psr & PSR_EW != 0,
+            bus_off: psr & PSR_BO != 0,
+            received_esi: psr & PSR_RESI != 0,
+            received_brs: psr & PSR_RBRS != 0,
+            received_fdf: psr & PSR_RFDF != 0,
+            protocol_exception: psr & PSR_PXE != 0,
+            tdc_value: ((psr & PSR_TDCV_MASK) >> PSR_TDCV_SHIFT) as u8,
+        }
+    }
+
+    pub fn bus_state(&self) -> CanBusState {
+        if self.bus_off {
+            CanBusState::BusOff
+        } else if self.error_passive {
+            CanBusState::ErrorPassive
+        } else if self.warning {
+            CanBusState::ErrorWarning
+        } else {
+            CanBusState::ErrorActive
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_counters_are_decoded() {
+        let counters: Tcan4550ErrorCounters = Tcan4550ErrorCounters::from_register(0x0012_FF85);
+        assert_eq!(counters, Tcan4550ErrorCounters { tx_error_count: 0x85, rx_error_count: 127, rx_error_passive: true, error_logging: 0x12 });
+        assert_eq!(Tcan4550ErrorCounters::from_register(0), Tcan4550ErrorCounters::default());
+    }
+
+    #[test]
+    fn last_error_codes_are_decoded() {
+        let codes: Vec<Tcan4550LastErrorCode> = (0..8).map(Tcan4550LastErrorCode::from).collect();
+        assert_eq!(codes, vec![
+            Tcan4550LastErrorCode::NoError,
+            Tcan4550LastErrorCode::Stuff,
+            Tcan4550LastErrorCode::Form,
+            Tcan4550LastErrorCode::Ack,
+            Tcan4550LastErrorCode::Bit1,
+            Tcan4550LastErrorCode::Bit0,
+            Tcan4550LastErrorCode::Crc,
+            Tcan4550LastErrorCode::NoChange,
+        ]);
+    }
+
+    #[test]
+    fn protocol_status_is_decoded() {
+        let psr: u32 = 3 | (2 << PSR_ACT_SHIFT) | PSR_EW | (6 << PSR_DLEC_SHIFT) | PSR_RBRS | PSR_RFDF | (0x15 << PSR_TDCV_SHIFT);
+        let status: Tcan4550ProtocolStatus = Tcan4550ProtocolStatus::from_register(psr);
+        assert_eq!(status.last_error_code, Tcan4550LastErrorCode::Ack);
+        assert_eq!(status.data_last_error_code, Tcan4550LastErrorCode::Crc);
+        assert_eq!(status.activity, Tcan4550Activity::Receiver);
+        assert!(status.warning && !status.error_passive && !status.bus_off);
+        assert!(!status.received_esi && status.received_brs && status.received_fdf);
+        assert!(!status.protocol_exception);
+        assert_eq!(status.tdc_value, 0x15);
+    }
+
+    #[test]
+    fn data_phase_error_code_is_taken_from_its_own_field() {
+        let status: Tcan4550ProtocolStatus = Tcan4550ProtocolStatus::from_register(PSR_LEC_NO_CHANGE | (1 << PSR_DLEC_SHIFT));
+        assert_eq!(status.last_error_code, Tcan4550LastErrorCode::NoChange);
+        assert_eq!(status.data_last_error_code, Tcan4550LastErrorCode::Stuff);
+        let status: Tcan4550ProtocolStatus = Tcan4550ProtocolStatus::from_register(5);
+        assert_eq!(status.data_last_error_code, Tcan4550LastErrorCode::NoError);
+    }
+
+    #[test]
+    fn bus_state_reports_the_most_severe_flag() {
+        let state = |psr: u32| Tcan4550ProtocolStatus::from_register(psr).bus_state();
+        assert_eq!(state(0), CanBusState::ErrorActive);
+        assert_eq!(state(PSR_EW), CanBusState::ErrorWarning);
+        assert_eq!(state(PSR_EW | PSR_EP), CanBusState::ErrorPassive);
+        assert_eq!(state(PSR_EW | PSR_EP | PSR_BO), CanBusState::BusOff);
+        assert_eq!(state(PSR_BO), CanBusState::BusOff);
+    }
+}
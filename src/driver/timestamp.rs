@@ -0,0 +1,212 @@
+use super::*;
+
+/// Default weight of a new anchor in the clock offset estimate.
+const DEFAULT_OFFSET_GAIN: f64 = 0.05;
+
+/// Anchors whose read took longer than twice the fastest recent read, plus this margin, are left out
+/// of the offset estimate, as the host was likely preempted or the USB transfer was delayed.
+const LATENCY_TOLERANCE_USEC: f64 = 50.0;
+
+/// Converts a free-running hardware timestamp counter, such as the 16-bit counter the CAN controller stores
+/// in its RX and TX event elements, into the host time base of `monotonic_usec`.
+///
+/// The driver anchors the tracker by reading the current counter value between two host timestamps,
+/// e.g. around the USB transfer carrying the register read. Anchors unwrap the counter into a monotonic
+/// count of microseconds since the first anchor and refine the offset between that time base and the host clock.
+/// Counter values captured before an anchor are converted relative to it, so they must be less than one
+/// wrap period old. Wraps missed between anchors are restored from the host time that has passed.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CanTimestampTracker {
+    counter_mask: u64,
+    tick_usec: f64,
+    offset_gain: f64,
+    last_counter: Option<u64>,
+    last_anchor_usec: f64,
+    ticks: u64,
+    offset_usec: Option<f64>,
+    min_latency_usec: f64,
+}
+
+impl CanTimestampTracker {
+    /// Tracks a counter of `counter_bits` bits that advances once per `tick`.
+    pub fn new(counter_bits: u32, tick: std::time::Duration) -> Self {
+        Self {
+            counter_mask: if counter_bits >= u64::BITS { u64::MAX } else { (1 << counter_bits) - 1 },
+            tick_usec: tick.as_secs_f64() * 1e6,
+            offset_gain: DEFAULT_OFFSET_GAIN,
+            last_counter: None,
+            last_anchor_usec: 0.0,
+            ticks: 0,
+            offset_usec: None,
+            min_latency_usec: f64::MAX,
+        }
+    }
+
+    /// Weight of each anchor in the offset estimate, from 0 to 1. Higher values follow drift between
+    /// the clocks faster but pass more of the read latency jitter through.
+    pub fn set_offset_gain(mut self, gain: f64) -> Self {
+        self.offset_gain = gain.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn tick_usec(&self) -> f64 {
+        self.tick_usec
+    }
+
+    /// Time after which the counter wraps around.
+    pub fn wrap_period(&self) -> std::time::Duration {
+        std::time::Duration::from_secs_f64((self.counter_mask as f64 + 1.0) * self.tick_usec / 1e6)
+    }
+
+    /// Records the counter value read between the host times `before_usec` and `after_usec`.
+    pub fn anchor(&mut self, counter: u64, before_usec: CyphalMicrosecond, after_usec: CyphalMicrosecond) {
+        let counter: u64 = counter & self.counter_mask;
+        let anchor_usec: f64 = (before_usec as f64 + after_usec.max(before_usec) as f64) / 2.0;
+        if let Some(last) = self.last_counter {
+            let delta: u64 = counter.wrapping_sub(last) & self.counter_mask;
+            let elapsed_ticks: f64 = (anchor_usec - self.last_anchor_usec) / self.tick_usec;
+            let missed_wraps: f64 = ((elapsed_ticks - delta as f64) / (self.counter_mask as f64 + 1.0)).round().max(0.0);
+            self.ticks += delta + missed_wraps as u64 * (self.counter_mask + 1);
+        }
+        self.last_counter = Some(counter);
+        self.last_anchor_usec = anchor_usec;
+
+        let latency_usec: f64 = after_usec.saturating_sub(before_usec) as f64;
+        let offset_usec: f64 = anchor_usec - self.ticks as f64 * self.tick_usec;
+        match self.offset_usec {
+            None => self.offset_usec = Some(offset_usec),
+            Some(x) if latency_usec <= 2.0 * self.min_latency_usec + LATENCY_TOLERANCE_USEC => {
+                self.offset_usec = Some(x + self.offset_gain * (offset_usec - x));
+            },
+            Some(_) => {},
+        }
+        // Let the reference latency creep up so that one unusually fast read does not lock out all others.
+        self.min_latency_usec = latency_usec.min(self.min_latency_usec + 1.0);
+    }
+
+    /// Unwrapped hardware time of a counter value captured at or before the last anchor,
+    /// in microseconds since the first anchor. None before the first anchor.
+    pub fn hardware_usec(&self, counter: u64) -> Option<CyphalMicrosecond> {
+        let last: u64 = self.last_counter?;
+        let age: u64 = last.wrapping_sub(counter & self.counter_mask) & self.counter_mask;
+        Some((self.ticks.saturating_sub(age) as f64 * self.tick_usec) as CyphalMicrosecond)
+    }
+
+    /// Host time of a counter value captured at or before the last anchor, comparable with `monotonic_usec`.
+    pub fn host_usec(&self, counter: u64) -> Option<CyphalMicrosecond> {
+        let hardware_usec: CyphalMicrosecond = self.hardware_usec(counter)?;
+        Some((hardware_usec as f64 + self.offset_usec?).max(0.0) as CyphalMicrosecond)
+    }
+
+    /// Estimated host time minus hardware time, in microseconds.
+    pub fn offset_usec(&self) -> Option<f64> {
+        self.offset_usec
+    }
+
+    /// Forgets all anchors, e.g. after the counter was reset or reconfigured.
+    pub fn reset(&mut self) {
+        self.last_counter = None;
+        self.last_anchor_usec = 0.0;
+        self.ticks = 0;
+        self.offset_usec = None;
+        self.min_latency_usec = f64::MAX;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 16-bit counter of one microsecond per tick, wrapping every 65536 µs.
+    fn tracker() -> CanTimestampTracker {
+        CanTimestampTracker::new(16, std::time::Duration::from_micros(1))
+    }
+
+    #[test]
+    fn counter_is_unwrapped_across_wraps() {
+        let mut tracker: CanTimestampTracker = tracker();
+        assert_eq!(tracker.hardware_usec(0), None);
+        tracker.anchor(0, 1000, 1000);
+        tracker.anchor(65000, 66000, 66000);
+        tracker.anchor(500, 67036, 67036);
+        assert_eq!(tracker.hardware_usec(500), Some(66036));
+        assert_eq!(tracker.hardware_usec(65000), Some(65000));
+        assert_eq!(tracker.host_usec(500), Some(67036));
+
+        for wrap in 2..=5u64 {
+            tracker.anchor(40000, 1000 + wrap * 65536 - 25536, 1000 + wrap * 65536 - 25536);
+            tracker.anchor(500, 1000 + wrap * 65536 + 500, 1000 + wrap * 65536 + 500);
+            assert_eq!(tracker.hardware_usec(500), Some(wrap * 65536 + 500));
+        }
+        assert_eq!(tracker.hardware_usec(0x1_0000 + 400), Some(5 * 65536 + 400));
+    }
+
+    #[test]
+    fn missed_wraps_are_recovered_from_host_time() {
+        let mut tracker: CanTimestampTracker = tracker();
+        tracker.anchor(0, 1000, 1000);
+        tracker.anchor(100, 1000 + 3 * 65536 + 100, 1000 + 3 * 65536 + 100);
+        assert_eq!(tracker.hardware_usec(100), Some(3 * 65536 + 100));
+        assert_eq!(tracker.host_usec(100), Some(1000 + 3 * 65536 + 100));
+
+        // Host time slightly behind the counter must not invent a wrap.
+        tracker.anchor(200, 1000 + 3 * 65536 + 150, 1000 + 3 * 65536 + 150);
+        assert_eq!(tracker.hardware_usec(200), Some(3 * 65536 + 200));
+    }
+
+    #[test]
+    fn high_latency_anchors_do_not_move_the_offset() {
+        let mut tracker: CanTimestampTracker = tracker().set_offset_gain(1.0);
+        tracker.anchor(0, 1000, 1010);
+        tracker.anchor(1000, 2000, 2010);
+        assert_eq!(tracker.offset_usec(), Some(1005.0));
+
+        tracker.anchor(2000, 2900, 3500);
+        assert_eq!(tracker.offset_usec(), Some(1005.0));
+        assert_eq!(tracker.hardware_usec(2000), Some(2000));
+
+        tracker.anchor(3000, 4100, 4110);
+        assert_eq!(tracker.offset_usec(), Some(1105.0));
+        assert_eq!(tracker.host_usec(3000), Some(4105));
+    }
+
+    #[test]
+    fn reset_forgets_all_anchors() {
+        let mut tracker: CanTimestampTracker = tracker();
+        tracker.anchor(0, 1000, 1000);
+        tracker.anchor(65000, 66000, 66000);
+        tracker.reset();
+        assert_eq!(tracker.hardware_usec(65000), None);
+        assert_eq!(tracker.host_usec(65000), None);
+        assert_eq!(tracker.offset_usec(), None);
+
+        tracker.anchor(500, 10_000, 10_000);
+        assert_eq!(tracker.hardware_usec(500), Some(0));
+        assert_eq!(tracker.host_usec(500), Some(10_000));
+    }
+
+    #[test]
+    fn reassembled_transfer_carries_the_host_time_of_its_first_frame() {
+        let mut sender: CyphalMiddleware<8> = CyphalMiddleware::new(1);
+        let mut receiver: CyphalMiddleware<8> = CyphalMiddleware::new(2);
+        receiver.subscribe(CyphalTransferKind::Message, 1000).unwrap();
+        let data: Vec<u8> = (0..16).collect();
+        let packets: Vec<CyphalTxPacket<8>> = sender.create_message_data(1000, &data, data.len()).unwrap();
+        assert_eq!(packets.len(), 3);
+
+        // The frames straddle a wrap of the counter and are converted after the anchor that follows them.
+        let mut tracker: CanTimestampTracker = tracker();
+        tracker.anchor(65000, 100_000, 100_000);
+        tracker.anchor(40, 100_576, 100_576);
+        let counters: [u64; 3] = [65500, 65530, 20];
+        let mut frames: Vec<CyphalRxFrame> = vec![];
+        for (packet, counter) in packets.iter().zip(counters) {
+            let mut packet: CyphalRxPacket<8> = CyphalRxPacket::from_frame(packet.xid, &packet.payload[..packet.payload_size]).unwrap();
+            packet.timestamp_usec = tracker.host_usec(counter).unwrap();
+            frames.extend(receiver.accept(&packet, packet.timestamp_usec).unwrap());
+        }
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].payload, data);
+        assert_eq!(frames[0].timestamp_usec, 100_500);
+    }
+}
@@ -0,0 +1,172 @@
+use super::*;
+
+use std::collections::HashMap;
+
+/// Confirmation that all frames of a transfer have been transmitted on the bus.
+#[derive(Debug, Copy, Clone, serde::Serialize)]
+pub struct CanTxConfirmation {
+    pub marker: u8,
+    /// Properties of the transfer as encoded in its frames; the source is the local node.
+    pub props: CyphalRxProps,
+    pub frame_count: usize,
+    /// Transmission time of the first frame, which is what time synchronization reports for single-frame transfers.
+    pub first_frame_usec: CyphalMicrosecond,
+    pub last_frame_usec: CyphalMicrosecond,
+}
+
+struct PendingTransfer {
+    props: CyphalRxProps,
+    frame_count: usize,
+    remaining: usize,
+    first_frame_usec: Option<CyphalMicrosecond>,
+}
+
+/// Resolves per-frame transmission reports, identified by the message marker of `CyphalTxPacket`,
+/// back to the transfers they belong to. Drivers record the packets they queue and report each frame
+/// the controller has sent; a transfer is confirmed once all of its frames are reported.
+/// Transfers that never complete, e.g. because a frame missed its deadline, are forgotten when their
+/// marker is reused by a later transfer.
+#[derive(Default)]
+pub struct CanTxTracker {
+    pending: HashMap<u8, PendingTransfer>,
+    confirmations: Vec<CanTxConfirmation>,
+}
+
+impl CanTxTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records packets handed to the controller.
+    pub fn record<const MTU: usize>(&mut self, packets: &[CyphalTxPacket<MTU>]) {
+        for packet in packets {
+            let frame: CyphalRxPacket<MTU> = match CyphalRxPacket::from_frame(packet.xid, &packet.payload[..packet.payload_size]) {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+            let start_of_transfer: bool = matches!(frame.status.frame_type, CyphalRxPacketType::SignleFrame | CyphalRxPacketType::MultiFrameStart);
+            if start_of_transfer {
+                self.pending.insert(packet.marker, PendingTransfer { props: frame.props, frame_count: 0, remaining: 0, first_frame_usec: None });
+            }
+            if let Some(transfer) = self.pending.get_mut(&packet.marker) {
+                transfer.frame_count += 1;
+                transfer.remaining += 1;
+            }
+        }
+    }
+
+    /// Reports that the controller has sent one frame carrying `marker` at `timestamp_usec`.
+    pub fn confirm(&mut self, marker: u8, timestamp_usec: CyphalMicrosecond) {
+        let transfer: &mut PendingTransfer = match self.pending.get_mut(&marker) {
+            Some(x) => x,
+            None => return,
+        };
+        let first_frame_usec: CyphalMicrosecond = *transfer.first_frame_usec.get_or_insert(timestamp_usec);
+        transfer.remaining = transfer.remaining.saturating_sub(1);
+        if transfer.remaining == 0 {
+            self.confirmations.push(CanTxConfirmation {
+                marker,
+                props: transfer.props,
+                frame_count: transfer.frame_count,
+                first_frame_usec,
+                last_frame_usec: timestamp_usec,
+            });
+            self.pending.remove(&marker);
+        }
+    }
+
+    /// Transfers with frames not yet reported as sent.
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn take_confirmations(&mut self) -> Vec<CanTxConfirmation> {
+        std::mem::take(&mut self.confirmations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MTU: usize = 8;
+
+    /// A message transfer on subject 1000 of `length` bytes counting up from zero.
+    fn transfer(middleware: &mut CyphalMiddleware<MTU>, length: usize) -> Vec<CyphalTxPacket<MTU>> {
+        let data: Vec<u8> = (0..length).map(|x| x as u8).collect();
+        middleware.create_message_data(1000, &data, data.len()).unwrap()
+    }
+
+    fn transfer_id_of(packets: &[CyphalTxPacket<MTU>]) -> CyphalTransferID {
+        CyphalRxPacket::<MTU>::from_frame(packets[0].xid, &packets[0].payload[..packets[0].payload_size]).unwrap().props.transfer_id
+    }
+
+    #[test]
+    fn multi_frame_transfer_is_confirmed_after_its_last_frame() {
+        let mut middleware: CyphalMiddleware<MTU> = CyphalMiddleware::new(42);
+        let packets: Vec<CyphalTxPacket<MTU>> = transfer(&mut middleware, 20);
+        let marker: u8 = packets[0].marker;
+        assert!(packets.len() > 2 && packets.iter().all(|x| x.marker == marker));
+
+        let mut tracker: CanTxTracker = CanTxTracker::new();
+        tracker.record(&packets);
+        assert_eq!(tracker.pending_count(), 1);
+        for (index, _) in packets.iter().enumerate().skip(1) {
+            tracker.confirm(marker, 100 + index as CyphalMicrosecond);
+            assert!(tracker.take_confirmations().is_empty());
+        }
+        tracker.confirm(marker, 200);
+        let confirmations: Vec<CanTxConfirmation> = tracker.take_confirmations();
+        assert_eq!(confirmations.len(), 1);
+        assert_eq!(confirmations[0].marker, marker);
+        assert_eq!(confirmations[0].frame_count, packets.len());
+        assert_eq!((confirmations[0].first_frame_usec, confirmations[0].last_frame_usec), (101, 200));
+        assert_eq!(confirmations[0].props.port_id, 1000);
+        assert_eq!(confirmations[0].props.source_node_id, 42);
+        assert_eq!(tracker.pending_count(), 0);
+        assert!(tracker.take_confirmations().is_empty());
+    }
+
+    #[test]
+    fn reused_marker_replaces_the_pending_transfer() {
+        let mut middleware: CyphalMiddleware<MTU> = CyphalMiddleware::new(42);
+        let stale: Vec<CyphalTxPacket<MTU>> = transfer(&mut middleware, 20);
+        let mut fresh: Vec<CyphalTxPacket<MTU>> = transfer(&mut middleware, 3);
+        let marker: u8 = stale[0].marker;
+        assert_ne!(transfer_id_of(&stale), transfer_id_of(&fresh));
+        fresh[0].marker = marker;
+
+        let mut tracker: CanTxTracker = CanTxTracker::new();
+        tracker.record(&stale);
+        tracker.confirm(marker, 100);
+        tracker.record(&fresh);
+        assert_eq!(tracker.pending_count(), 1);
+        tracker.confirm(marker, 300);
+        let confirmations: Vec<CanTxConfirmation> = tracker.take_confirmations();
+        assert_eq!(confirmations.len(), 1);
+        assert_eq!(confirmations[0].frame_count, 1);
+        assert_eq!(confirmations[0].props.transfer_id, transfer_id_of(&fresh));
+        assert_eq!((confirmations[0].first_frame_usec, confirmations[0].last_frame_usec), (300, 300));
+        assert_eq!(tracker.pending_count(), 0);
+    }
+
+    #[test]
+    fn unknown_markers_are_ignored() {
+        let mut middleware: CyphalMiddleware<MTU> = CyphalMiddleware::new(42);
+        let packets: Vec<CyphalTxPacket<MTU>> = transfer(&mut middleware, 3);
+        let marker: u8 = packets[0].marker;
+
+        let mut tracker: CanTxTracker = CanTxTracker::new();
+        tracker.confirm(marker, 100);
+        tracker.record(&packets);
+        tracker.confirm(marker.wrapping_add(1), 200);
+        assert!(tracker.take_confirmations().is_empty());
+        assert_eq!(tracker.pending_count(), 1);
+
+        tracker.confirm(marker, 300);
+        tracker.confirm(marker, 400);
+        let confirmations: Vec<CanTxConfirmation> = tracker.take_confirmations();
+        assert_eq!(confirmations.len(), 1);
+        assert_eq!(confirmations[0].first_frame_usec, 300);
+    }
+}
@@ -0,0 +1,232 @@
+use super::*;
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+
+struct PendingFrame {
+    xid: u32,
+    marker: u8,
+    sequence: u64,
+    source: usize,
+    data: Vec<u8>,
+    ready_usec: CyphalMicrosecond,
+    deadline_usec: Option<CyphalMicrosecond>,
+}
+
+struct DeliveredFrame {
+    xid: u32,
+    data: Vec<u8>,
+    timestamp_usec: CyphalMicrosecond,
+}
+
+struct BusState {
+    latency_usec: CyphalMicrosecond,
+    max_payload_size: usize,
+    /// While set, no frame is put on the bus.
+    held: bool,
+    sequence: u64,
+    pending: Vec<PendingFrame>,
+    /// Receive queues of connected ports; None once a port is dropped.
+    rx_queues: Vec<Option<VecDeque<DeliveredFrame>>>,
+    statuses: Vec<CanDriverStatus>,
+    tx_trackers: Vec<CanTxTracker>,
+}
+
+impl BusState {
+    /// Puts every frame whose latency has elapsed on the bus. Frames contending for the bus are delivered
+    /// in arbitration order, lowest ID first; frames with equal IDs keep their submission order.
+    fn pump(&mut self, now_usec: CyphalMicrosecond) {
+        if self.held {
+            return;
+        }
+        let (mut ready, pending): (Vec<PendingFrame>, Vec<PendingFrame>) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|x| x.ready_usec <= now_usec);
+        self.pending = pending;
+        ready.sort_by_key(|x| (x.xid, x.sequence));
+
+        for frame in ready {
+            if frame.deadline_usec.is_some_and(|x| now_usec > x) {
+                self.statuses[frame.source].tx_deadline_expired += 1;
+                continue;
+            }
+            for (i, queue) in self.rx_queues.iter_mut().enumerate() {
+                if let (Some(queue), true) = (queue.as_mut(), i != frame.source) {
+                    queue.push_back(DeliveredFrame { xid: frame.xid, data: frame.data.clone(), timestamp_usec: now_usec });
+                }
+            }
+            self.tx_trackers[frame.source].confirm(frame.marker, now_usec);
+        }
+    }
+
+    fn next_ready_usec(&self) -> Option<CyphalMicrosecond> {
+        if self.held {
+            return None;
+        }
+        self.pending.iter().map(|x| x.ready_usec).min()
+    }
+}
+
+/// In-process CAN bus connecting any number of nodes without sockets or hardware.
+/// Every frame is delivered to all other ports after the configured latency; frames contending for the bus
+/// are ordered by CAN arbitration, lowest ID first. Limiting the payload to 8 bytes
+/// turns it into a classic CAN bus that rejects CAN FD frames. The sending port receives a `CanTxConfirmation`
+/// for each transfer once its last frame is on the bus.
+#[derive(Clone)]
+pub struct CanVirtualBus {
+    shared: Arc<(Mutex<BusState>, Condvar)>,
+}
+
+impl Default for CanVirtualBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CanVirtualBus {
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new((
+                Mutex::new(BusState {
+                    latency_usec: 0,
+                    max_payload_size: CYPHAL_MTU_CAN_FD as usize,
+                    held: false,
+                    sequence: 0,
+                    pending: vec![],
+                    rx_queues: vec![],
+                    statuses: vec![],
+                    tx_trackers: vec![],
+                }),
+                Condvar::new(),
+            )),
+        }
+    }
+
+    /// Delay between the transmission of a frame and its reception by the other ports.
+    pub fn set_latency(self, latency: std::time::Duration) -> Self {
+        self.lock().latency_usec = latency.as_micros() as CyphalMicrosecond;
+        self
+    }
+
+    /// Largest data field accepted on the bus: 8 for classic CAN, 64 for CAN FD.
+    pub fn set_max_payload_size(self, max_payload_size: usize) -> Self {
+        self.lock().max_payload_size = max_payload_size;
+        self
+    }
+
+    /// Attaches a new port to the bus. It receives every frame transmitted after this call by any other port.
+    pub fn connect<const MTU: usize>(&self) -> CanVirtualPort<MTU> {
+        let mut state = self.lock();
+        state.rx_queues.push(Some(VecDeque::new()));
+        state.statuses.push(CanDriverStatus::default());
+        state.tx_trackers.push(CanTxTracker::new());
+        CanVirtualPort {
+            bus: self.clone(),
+            index: state.rx_queues.len() - 1,
+        }
+    }
+
+    /// Number of frames transmitted but not yet delivered because of the latency or a hold.
+    pub fn pending_count(&self) -> usize {
+        self.lock().pending.len()
+    }
+
+    /// Keeps transmitted frames off the bus until `release`, as if it were busy, so that tests can make
+    /// frames contend for it without depending on timing.
+    pub fn hold(&self) {
+        self.lock().held = true;
+    }
+
+    /// Lets the held frames contend for the bus; those whose latency has elapsed are delivered before this returns.
+    pub fn release(&self) {
+        let mut state = self.lock();
+        state.held = false;
+        state.pump(monotonic_usec());
+        self.shared.1.notify_all();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BusState> {
+        // A panicking test thread must not take the bus of the others down with it.
+        self.shared.0.lock().unwrap_or_else(|x| x.into_inner())
+    }
+}
+
+/// One node's connection to a `CanVirtualBus`.
+pub struct CanVirtualPort<const MTU: usize> {
+    bus: CanVirtualBus,
+    index: usize,
+}
+
+impl <const MTU: usize> CanDriver<MTU> for CanVirtualPort<MTU> {
+    fn transmit(&mut self, packets: &[CyphalTxPacket<MTU>]) -> Result<(), Box<dyn std::error::Error>> {
+        let now_usec: CyphalMicrosecond = monotonic_usec();
+        let mut state = self.bus.lock();
+        if packets.iter().any(|x| x.payload_size > state.max_payload_size) {
+            return Err("FRAME EXCEEDS BUS PAYLOAD SIZE".into());
+        }
+        for packet in packets {
+            if packet.deadline_usec.is_some_and(|x| now_usec > x) {
+                state.statuses[self.index].tx_deadline_expired += 1;
+                continue;
+            }
+            state.sequence += 1;
+            let frame: PendingFrame = PendingFrame {
+                xid: packet.xid,
+                marker: packet.marker,
+                sequence: state.sequence,
+                source: self.index,
+                data: packet.payload[..packet.payload_size].to_vec(),
+                ready_usec: now_usec + state.latency_usec,
+                deadline_usec: packet.deadline_usec,
+            };
+            state.pending.push(frame);
+            state.tx_trackers[self.index].record(std::slice::from_ref(packet));
+        }
+        state.pump(now_usec);
+        self.bus.shared.1.notify_all();
+        Ok(())
+    }
+
+    fn receive(&mut self, timeout: std::time::Duration) -> Result<Vec<CyphalRxPacket<MTU>>, Box<dyn std::error::Error>> {
+        let until_usec: CyphalMicrosecond = monotonic_usec() + timeout.as_micros() as CyphalMicrosecond;
+        let mut state = self.bus.lock();
+        loop {
+            let now_usec: CyphalMicrosecond = monotonic_usec();
+            state.pump(now_usec);
+            let queue: &mut VecDeque<DeliveredFrame> = state.rx_queues[self.index].as_mut().ok_or(CanDriverError::Disconnected)?;
+            if !queue.is_empty() || now_usec >= until_usec {
+                let mut ret: Vec<CyphalRxPacket<MTU>> = vec![];
+                for frame in queue.drain(..) {
+                    // A classic controller cannot take part in CAN FD traffic.
+                    if frame.data.is_empty() || frame.data.len() > MTU {
+                        continue;
+                    }
+                    let mut packet: CyphalRxPacket<MTU> = CyphalRxPacket::from_frame(frame.xid, &frame.data)?;
+                    packet.timestamp_usec = frame.timestamp_usec;
+                    ret.push(packet);
+                }
+                return Ok(ret);
+            }
+            let wake_usec: CyphalMicrosecond = state.next_ready_usec().map_or(until_usec, |x| x.min(until_usec));
+            let wait: std::time::Duration = std::time::Duration::from_micros(wake_usec.saturating_sub(now_usec));
+            state = self.bus.shared.1.wait_timeout(state, wait).unwrap_or_else(|x| x.into_inner()).0;
+        }
+    }
+
+    fn status(&mut self) -> Result<CanDriverStatus, Box<dyn std::error::Error>> {
+        Ok(self.bus.lock().statuses[self.index])
+    }
+
+    fn take_tx_confirmations(&mut self) -> Vec<CanTxConfirmation> {
+        let mut state = self.bus.lock();
+        let now_usec: CyphalMicrosecond = monotonic_usec();
+        state.pump(now_usec);
+        state.tx_trackers[self.index].take_confirmations()
+    }
+}
+
+impl <const MTU: usize> Drop for CanVirtualPort<MTU> {
+    fn drop(&mut self) {
+        self.bus.lock().rx_queues[self.index] = None;
+    }
+}
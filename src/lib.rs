@@ -1 +1,2 @@
-pub mod cyphal;
\ No newline at end of file
+pub mod cyphal;
+pub mod driver;
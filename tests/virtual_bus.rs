@@ -0,0 +1,109 @@
+use cands_transport::cyphal::*;
+use cands_transport::cyphal::node::CyphalNode;
+use cands_transport::cyphal::uavcan::node::GetInfoResponse;
+use cands_transport::driver::*;
+use cands_transport::driver::virtual_bus::*;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+const MTU: usize = CYPHAL_MTU_CAN_FD as usize;
+const SUBJECT_ID: u16 = 1000;
+const SERVICE_ID: u16 = 100;
+
+fn node(node_id: u8) -> CyphalNode<MTU> {
+    CyphalNode::new(CyphalMiddleware::new(node_id), GetInfoResponse::default()).set_port_list_enabled(false)
+}
+
+#[test]
+fn published_message_reaches_the_other_node() {
+    let bus: CanVirtualBus = CanVirtualBus::new();
+    let mut port_a: CanVirtualPort<MTU> = bus.connect();
+    let mut port_b: CanVirtualPort<MTU> = bus.connect();
+    let mut node_a: CyphalNode<MTU> = node(10);
+    let mut node_b: CyphalNode<MTU> = node(11);
+
+    let received: Rc<RefCell<Vec<CyphalRxFrame>>> = Rc::new(RefCell::new(vec![]));
+    let sink: Rc<RefCell<Vec<CyphalRxFrame>>> = received.clone();
+    node_b.subscribe(SUBJECT_ID, move |frame| sink.borrow_mut().push(frame.clone())).unwrap();
+
+    // Longer than one frame, so that the transfer is reassembled from several.
+    let data: Vec<u8> = (0..100).collect();
+    node_a.publish(SUBJECT_ID, CyphalPriority::Nominal, &data).unwrap();
+    node_a.spin_once(&mut port_a, Duration::ZERO).unwrap();
+    node_b.spin_once(&mut port_b, Duration::from_millis(100)).unwrap();
+
+    let received = received.borrow();
+    assert_eq!(received.len(), 1);
+    // The last frame is padded up to the next CAN FD data length; the padding stays in the payload.
+    assert_eq!(received[0].payload[..data.len()], data[..]);
+    assert_eq!(received[0].props.source_node_id, 10);
+}
+
+#[test]
+fn client_call_is_answered_by_the_server() {
+    let bus: CanVirtualBus = CanVirtualBus::new();
+    let mut port_client: CanVirtualPort<MTU> = bus.connect();
+    let mut port_server: CanVirtualPort<MTU> = bus.connect();
+    let mut client: CyphalNode<MTU> = node(20);
+    let mut server: CyphalNode<MTU> = node(21);
+
+    server.serve(SERVICE_ID, |_, request| Some(request.iter().rev().copied().collect())).unwrap();
+    let key = client.request(21, SERVICE_ID, &[1, 2, 3], client.now_usec()).unwrap();
+
+    for _ in 0..100 {
+        client.spin_once(&mut port_client, Duration::from_millis(1)).unwrap();
+        server.spin_once(&mut port_server, Duration::from_millis(1)).unwrap();
+        if !client.is_request_pending(&key) {
+            break;
+        }
+    }
+    client.spin_once(&mut port_client, Duration::from_millis(1)).unwrap();
+
+    let response: CyphalRxFrame = client.take_response(&key).expect("no response").unwrap();
+    assert_eq!(response.payload, vec![3, 2, 1]);
+    assert_eq!(response.props.source_node_id, 21);
+}
+
+#[test]
+fn contending_frames_are_delivered_in_arbitration_order() {
+    let bus: CanVirtualBus = CanVirtualBus::new();
+    let mut port_a: CanVirtualPort<MTU> = bus.connect();
+    let mut port_b: CanVirtualPort<MTU> = bus.connect();
+    let mut port_c: CanVirtualPort<MTU> = bus.connect();
+
+    let low_priority: Vec<CyphalTxPacket<MTU>> = CyphalMiddleware::<MTU>::new(30).create_message_data(2000, &[1], 1).unwrap();
+    let high_priority: Vec<CyphalTxPacket<MTU>> = CyphalMiddleware::<MTU>::new(31).create_message_data(10, &[2], 1).unwrap();
+    assert!(high_priority[0].xid < low_priority[0].xid);
+
+    // Both frames are waiting for the bus when it becomes free.
+    bus.hold();
+    port_a.transmit(&low_priority).unwrap();
+    port_b.transmit(&high_priority).unwrap();
+    assert_eq!(bus.pending_count(), 2);
+    assert!(port_c.receive(Duration::ZERO).unwrap().is_empty());
+    bus.release();
+    assert_eq!(bus.pending_count(), 0);
+
+    let received: Vec<CyphalRxPacket<MTU>> = port_c.receive(Duration::ZERO).unwrap();
+    let xids: Vec<u32> = received.iter().map(|x| x.xid).collect();
+    assert_eq!(xids, vec![high_priority[0].xid, low_priority[0].xid]);
+}
+
+#[test]
+fn classic_bus_rejects_can_fd_frames() {
+    let bus: CanVirtualBus = CanVirtualBus::new().set_max_payload_size(CYPHAL_MTU_CAN_CLASSIC as usize);
+    let mut port_a: CanVirtualPort<MTU> = bus.connect();
+    let mut port_b: CanVirtualPort<MTU> = bus.connect();
+    let mut middleware: CyphalMiddleware<MTU> = CyphalMiddleware::new(40);
+
+    let packets: Vec<CyphalTxPacket<MTU>> = middleware.create_message_data(SUBJECT_ID, &[0; 20], 20).unwrap();
+    assert!(packets[0].payload_size > CYPHAL_MTU_CAN_CLASSIC as usize);
+    assert!(port_a.transmit(&packets).is_err());
+    assert!(port_b.receive(Duration::from_millis(10)).unwrap().is_empty());
+
+    let packets: Vec<CyphalTxPacket<MTU>> = middleware.create_message_data(SUBJECT_ID, &[0; 4], 4).unwrap();
+    port_a.transmit(&packets).unwrap();
+    assert_eq!(port_b.receive(Duration::from_millis(10)).unwrap().len(), 1);
+}